@@ -0,0 +1,147 @@
+//! Admin commands, run as `timely-server <command> [--flag value ...]`.
+//!
+//! The binary normally serves a single wcgi request. With arguments it runs a
+//! maintenance command instead, using the same configuration and database
+//! client as the server.
+
+use std::{io::Read, str::FromStr};
+
+use anyhow::{bail, Context};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    db::client_supabase::SupaDb,
+    logic::{
+        backup::{full_backup, restore_all, Backup},
+        seed::{self, SeedOptions},
+        trash::purge_expired,
+    },
+    Config,
+};
+
+const USAGE: &str = "usage:
+  timely-server seed [--seed N] [--users N] [--days N]
+  timely-server backup      (writes a backup of all users to stdout)
+  timely-server restore     (reads a backup from stdin)
+  timely-server purge-trash (deletes rows that were in the trash longer than the retention)";
+
+pub fn run(config: Config, args: &[String]) -> Result<(), anyhow::Error> {
+    let db = SupaDb::new(
+        config.supabase_endpoint.clone(),
+        config.supabase_api_key.clone(),
+        config.db_policy.clone(),
+    )?;
+
+    match args.split_first() {
+        Some((cmd, flags)) if cmd == "seed" => cmd_seed(&db, flags),
+        Some((cmd, [])) if cmd == "backup" => cmd_backup(&db),
+        Some((cmd, [])) if cmd == "restore" => cmd_restore(&db),
+        Some((cmd, [])) if cmd == "purge-trash" => {
+            cmd_purge_trash(&db, config.trash_retention_days)
+        }
+        Some((cmd, _)) => bail!("unknown command '{cmd}'\n{USAGE}"),
+        None => bail!("{USAGE}"),
+    }
+}
+
+fn cmd_seed(db: &SupaDb, flags: &[String]) -> Result<(), anyhow::Error> {
+    let flags = Flags::parse(flags, &["seed", "users", "days"])?;
+    let defaults = SeedOptions::default();
+    let opts = SeedOptions {
+        seed: flags.get("seed")?.unwrap_or(defaults.seed),
+        users: flags.get("users")?.unwrap_or(defaults.users),
+        days: flags.get("days")?.unwrap_or(defaults.days),
+    };
+
+    let summary = seed::seed(db, &opts)?;
+    println!(
+        "Seeded {} users, {} tags and {} timelogs (seed {}). Password for all users: {}",
+        summary.users,
+        summary.tags,
+        summary.timelogs,
+        opts.seed,
+        seed::DEMO_PASSWORD
+    );
+    Ok(())
+}
+
+fn cmd_backup(db: &SupaDb) -> Result<(), anyhow::Error> {
+    let backup = full_backup(db)?;
+    println!("{}", serde_json::to_string_pretty(&backup)?);
+    eprintln!("Backed up {} users", backup.users.len());
+    Ok(())
+}
+
+fn cmd_restore(db: &SupaDb) -> Result<(), anyhow::Error> {
+    let mut data = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut data)
+        .context("Could not read backup from stdin")?;
+    let backup = Backup::parse(&data)?;
+
+    for (username, summary) in restore_all(db, &backup)? {
+        println!(
+            "{username}: created {} timelogs, {} tags, {} projects and {} tag links, skipped {} existing timelogs",
+            summary.timelogs_created,
+            summary.tags_created,
+            summary.projects_created,
+            summary.links_created,
+            summary.timelogs_skipped
+        );
+    }
+    Ok(())
+}
+
+fn cmd_purge_trash(db: &SupaDb, retention_days: u32) -> Result<(), anyhow::Error> {
+    let before = OffsetDateTime::now_utc() - Duration::days(retention_days.into());
+    let summary = purge_expired(db, before)?;
+    println!(
+        "Purged {} timelogs, {} tags and {} projects deleted before {before}",
+        summary.timelogs, summary.tags, summary.projects
+    );
+    if summary.projects_kept > 0 {
+        eprintln!(
+            "Kept {} projects with invoiced or submitted timelogs",
+            summary.projects_kept
+        );
+    }
+    Ok(())
+}
+
+/// `--name value` pairs.
+struct Flags(Vec<(String, String)>);
+
+impl Flags {
+    fn parse(args: &[String], allowed: &[&str]) -> Result<Self, anyhow::Error> {
+        let mut flags = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .filter(|name| allowed.contains(name))
+                .with_context(|| format!("unexpected argument '{arg}'\n{USAGE}"))?;
+            let value = args
+                .next()
+                .with_context(|| format!("missing value for '{arg}'"))?;
+            flags.push((name.to_string(), value.clone()));
+        }
+        Ok(Self(flags))
+    }
+
+    fn get<T>(&self, name: &str) -> Result<Option<T>, anyhow::Error>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.0
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| {
+                value
+                    .parse()
+                    .map_err(|err| anyhow::anyhow!("invalid value for --{name}: {err}"))
+            })
+            .transpose()
+    }
+}
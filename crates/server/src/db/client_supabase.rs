@@ -1,23 +1,43 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Instant};
 
-use anyhow::Context;
-use anyhttp::{HttpError, Method, RequestBody};
+use anyhow::anyhow;
+use anyhttp::{Method, RequestBody};
+use http::StatusCode;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
 
 // type Request = anyhttp::Request<RequestBody>;
 type Response = anyhttp::Response<anyhttp::sync::DynResponseBody>;
 
+use crate::{logging, metrics};
+
 use super::{
+    error::DbError,
+    policy::RequestPolicy,
     types::{
-        Direction, Timelog, TimelogFilter, TimelogOrder, TimelogQuery, User, UserFilter, UserQuery,
+        AuditEvent, AuditEventFilter, AuditEventQuery, Client, ClientCreate, ClientFilter,
+        ClientQuery, Direction, Invitation, InvitationCreate, InvitationFilter, Invoice,
+        InvoiceCreate, InvoiceFilter, InvoiceQuery, Membership, MembershipCreate, MembershipFilter,
+        MembershipPatch, MetricCounter, Organization, OrganizationCreate, OrganizationId, Project,
+        ProjectCreate, ProjectFilter, ProjectPatch, ProjectQuery, Timelog, TimelogCreate,
+        TimelogFilter, TimelogId, TimelogOrder, TimelogQuery, TimelogSwitch, TimelogUserTag,
+        Timesheet, TimesheetCreate, TimesheetEvent, TimesheetEventCreate, TimesheetFilter,
+        TimesheetId, TimesheetPatch, TimesheetQuery, User, UserFilter, UserId, UserPatch,
+        UserQuery, UserTag, UserTagCreate, UserTagFilter, UserTagPatch, UserTagQuery,
     },
     Db,
 };
 
+/// Header read by the audit triggers to attribute changes to a user.
+const ACTOR_HEADER: &str = "x-timely-actor";
+
 #[derive(Clone)]
 pub struct SupaDb {
     endpoint: String,
     api_key: String,
     client: anyhttp::sync::DynClient,
+    policy: RequestPolicy,
+    /// User that changes are made for, recorded in the audit log.
+    actor: Option<UserId>,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -28,6 +48,47 @@ pub struct ApiError {
     pub hint: Option<String>,
 }
 
+impl ApiError {
+    /// Map a PostgREST error onto a [`DbError`].
+    ///
+    /// PostgREST forwards Postgres SQLSTATE codes, and uses `PGRST*` codes for
+    /// its own errors.
+    /// See https://postgrest.org/en/stable/errors.html
+    fn into_db_error(self, status: StatusCode) -> DbError {
+        match self.code.as_str() {
+            "23505" => DbError::UniqueViolation {
+                constraint: self.constraint_name().unwrap_or_default(),
+            },
+            "23514" => DbError::CheckViolation {
+                constraint: self.constraint_name().unwrap_or_default(),
+            },
+            "23503" => DbError::ForeignKey,
+            // Single object requested, but no rows returned.
+            "PGRST116" => DbError::NotFound,
+            // Connection errors, admin shutdown, insufficient resources.
+            code if code.starts_with("PGRST00")
+                || code.starts_with("08")
+                || code.starts_with("53")
+                || code.starts_with("57P") =>
+            {
+                DbError::unavailable(self)
+            }
+            _ => error_from_status(status, self.into()),
+        }
+    }
+
+    /// Extract the constraint name from the error message.
+    ///
+    /// Postgres does not report the constraint in a separate field, but always
+    /// quotes it last, e.g.:
+    /// `duplicate key value violates unique constraint "users_username_key"`
+    fn constraint_name(&self) -> Option<String> {
+        let (rest, _) = self.message.rsplit_once('"')?;
+        let (_, name) = rest.rsplit_once('"')?;
+        Some(name.to_string())
+    }
+}
+
 impl std::fmt::Display for ApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Supabase API error: {}", self.message)
@@ -36,9 +97,91 @@ impl std::fmt::Display for ApiError {
 
 impl std::error::Error for ApiError {}
 
+fn error_from_status(status: StatusCode, err: anyhow::Error) -> DbError {
+    match status {
+        StatusCode::NOT_FOUND => DbError::NotFound,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+            DbError::Unavailable(err)
+        }
+        _ => DbError::Other(err),
+    }
+}
+
+fn error_from_body(status: StatusCode, body: &[u8]) -> DbError {
+    match serde_json::from_slice::<ApiError>(body) {
+        Ok(err) => err.into_db_error(status),
+        Err(_) => error_from_status(status, anyhow!("api request failed with status {status}")),
+    }
+}
+
+/// Table (or rpc function) name of a request path, used as a metrics label.
+fn table_name(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or_default();
+    match path.split_once('/') {
+        Some(("rpc", name)) => format!("rpc/{name}"),
+        Some((table, _)) => table.to_string(),
+        None => path.to_string(),
+    }
+}
+
+fn operation_name(method: &Method) -> &'static str {
+    match *method {
+        Method::GET | Method::HEAD => "select",
+        Method::POST => "insert",
+        Method::PATCH => "update",
+        Method::DELETE => "delete",
+        _ => "other",
+    }
+}
+
+/// Read the total row count from the `Content-Range` header of a response
+/// to a request sent with `Prefer: count=exact`.
+///
+/// The header looks like `0-24/3573`, or `*/0` if no rows matched.
+fn read_count(res: Response) -> Result<u64, DbError> {
+    let status = res.status;
+    let range = res
+        .headers
+        .get(http::header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    if !status.is_success() {
+        let body = res.bytes_sync().map_err(DbError::unavailable)?;
+        return Err(error_from_body(status, &body));
+    }
+
+    range
+        .as_deref()
+        .and_then(|v| v.rsplit_once('/'))
+        .and_then(|(_, total)| total.parse().ok())
+        .ok_or_else(|| DbError::other(anyhow!("invalid Content-Range header: {range:?}")))
+}
+
+/// Read a response body, mapping non-success responses to a [`DbError`].
+fn read_json<O>(res: Response) -> Result<O, DbError>
+where
+    O: serde::de::DeserializeOwned,
+{
+    let status = res.status;
+    let body = res.bytes_sync().map_err(DbError::unavailable)?;
+
+    if !status.is_success() {
+        return Err(error_from_body(status, &body));
+    }
+
+    serde_json::from_slice(&body).map_err(|err| {
+        DbError::Other(anyhow::Error::new(err).context("could not deserialize response body"))
+    })
+}
+
 impl SupaDb {
-    pub fn new(endpoint: String, api_key: String) -> Result<Self, anyhow::Error> {
-        let client = crate::util::WasixHttpExecutor::new_dyn_client()?;
+    pub fn new(
+        endpoint: String,
+        api_key: String,
+        policy: RequestPolicy,
+    ) -> Result<Self, anyhow::Error> {
+        let client = crate::util::WasixHttpExecutor::new_dyn_client(policy.timeout)?;
         let endpoint = endpoint
             .strip_suffix('/')
             .map(|s| s.to_string())
@@ -48,71 +191,161 @@ impl SupaDb {
             endpoint,
             api_key,
             client,
+            policy,
+            actor: None,
         })
     }
 
+    /// A client that records changes as made by the given user.
+    pub fn with_actor(&self, actor: UserId) -> Self {
+        Self {
+            actor: Some(actor),
+            ..self.clone()
+        }
+    }
+
+    /// Send the request produced by `build`, applying the [`RequestPolicy`].
+    ///
+    /// Only idempotent requests are retried, and only if the database was
+    /// unavailable.
+    fn execute<O>(
+        &self,
+        idempotent: bool,
+        build: impl Fn() -> Result<anyhttp::RequestPre<RequestBody>, anyhttp::HttpError>,
+        read: impl Fn(Response) -> Result<O, DbError>,
+    ) -> Result<O, DbError> {
+        let deadline = Instant::now() + self.policy.timeout;
+        let mut retry = 0;
+        loop {
+            let pre = build().map_err(DbError::other)?;
+            let res = self.send(pre).and_then(&read);
+
+            match res {
+                Err(DbError::Unavailable(err)) => {
+                    let delay = self.policy.backoff(retry);
+                    if !idempotent
+                        || retry >= self.policy.max_retries
+                        || Instant::now() + delay >= deadline
+                    {
+                        return Err(DbError::Unavailable(err));
+                    }
+                    log::warn!(
+                        error:% = err,
+                        retry = retry + 1,
+                        delay_ms = delay.as_millis() as u64;
+                        "database unavailable, retrying"
+                    );
+                    std::thread::sleep(delay);
+                    retry += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
     fn send(
         &self,
         mut pre: anyhttp::RequestPre<anyhttp::RequestBody>,
-    ) -> Result<Response, HttpError> {
+    ) -> Result<Response, DbError> {
         let uri = pre.request.uri.to_string();
         let clean_path = uri.strip_prefix('/').map(|x| x.to_string()).unwrap_or(uri);
+        let table = table_name(&clean_path);
         pre.request.uri = format!("{}/{}", self.endpoint, clean_path).parse().unwrap();
         pre.request
             .headers
             .insert("apikey", self.api_key.parse().unwrap());
-        eprintln!(
-            "Sending http request: {} - {}",
-            pre.request.uri, pre.request.method
-        );
-        self.client.send_pre(pre)
+        if let Some(id) = logging::request_id().and_then(|id| id.parse().ok()) {
+            pre.request.headers.insert(logging::REQUEST_ID_HEADER, id);
+        }
+        if let Some(actor) = self.actor {
+            pre.request.headers.insert(ACTOR_HEADER, actor.into());
+        }
+
+        // Only log the path: the query can contain user data.
+        let method = pre.request.method.clone();
+        let path = pre.request.uri.path().to_string();
+        let start = Instant::now();
+        let res = self.client.send_pre(pre);
+        let duration = start.elapsed();
+        let duration_ms = duration.as_millis() as u64;
+
+        let success = res.as_ref().map(|r| r.status.is_success()).unwrap_or(false);
+        metrics::record_db_request(&table, operation_name(&method), success, duration);
+
+        match &res {
+            Ok(r) => log::debug!(
+                method:% = method,
+                path = path,
+                status = r.status.as_u16(),
+                duration_ms = duration_ms;
+                "supabase request"
+            ),
+            Err(err) => log::warn!(
+                method:% = method,
+                path = path,
+                error:% = err,
+                duration_ms = duration_ms;
+                "supabase request failed"
+            ),
+        }
+
+        res.map_err(DbError::unavailable)
     }
 
-    fn get_json<O>(&self, path: &str) -> Result<O, HttpError>
+    fn get_json<O>(&self, path: &str) -> Result<O, DbError>
     where
         O: serde::de::DeserializeOwned,
     {
-        let req = self
-            .client
-            .get(path)
-            .header(http::header::CONTENT_TYPE, "application/json")
-            .header(http::header::ACCEPT, "application/json")
-            .body(RequestBody::Empty)
-            .build()?;
-        let body = self.send(req)?.error_for_status()?.bytes_sync()?;
+        self.execute(
+            true,
+            || {
+                self.client
+                    .get(path)
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .header(http::header::ACCEPT, "application/json")
+                    .body(RequestBody::Empty)
+                    .build()
+            },
+            read_json,
+        )
+    }
 
-        match serde_json::from_slice(&body) {
-            Ok(o) => Ok(o),
-            Err(err) => Err(HttpError::new_custom_with_cause(
-                "could not deserialize response body",
-                err,
-            )),
-        }
+    /// Count the rows matched by the query in `path`.
+    fn count_rows(&self, path: &str) -> Result<u64, DbError> {
+        self.execute(
+            true,
+            || {
+                self.client
+                    .get(path)
+                    .header(http::header::ACCEPT, "application/json")
+                    .header("Prefer", "count=exact")
+                    .body(RequestBody::Empty)
+                    .build()
+            },
+            read_count,
+        )
     }
 
-    fn list_table<O>(&self, path: &str, limit: u64, offset: u64) -> Result<O, HttpError>
+    fn list_table<O>(&self, path: &str, limit: u64, offset: u64) -> Result<O, DbError>
     where
         O: serde::de::DeserializeOwned,
     {
-        let range = format!("{}-{}", offset, limit);
-
-        let req = self
-            .client
-            .get(path)
-            .header(http::header::CONTENT_TYPE, "application/json")
-            .header(http::header::ACCEPT, "application/json")
-            .header("Range", range)
-            .body(RequestBody::Empty)
-            .build()?;
-        let body = self.send(req)?.error_for_status()?.bytes_sync()?;
+        // Range bounds are inclusive.
+        let range = format!("{}-{}", offset, offset + limit.max(1) - 1);
 
-        match serde_json::from_slice(&body) {
-            Ok(o) => Ok(o),
-            Err(err) => Err(HttpError::new_custom_with_cause(
-                "could not deserialize response body",
-                err,
-            )),
-        }
+        self.execute(
+            true,
+            || {
+                self.client
+                    .get(path)
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .header(http::header::ACCEPT, "application/json")
+                    .header("Range", range.as_str())
+                    .body(RequestBody::Empty)
+                    .build()
+            },
+            read_json,
+        )
     }
 
     fn send_json_with_prefer_return<I, O>(
@@ -120,46 +353,30 @@ impl SupaDb {
         method: Method,
         path: &str,
         data: &I,
-    ) -> Result<O, HttpError>
+    ) -> Result<O, DbError>
     where
         I: serde::Serialize,
         O: serde::de::DeserializeOwned,
     {
-        let pre = self
-            .client
-            .request(method, path)
-            .header(http::header::CONTENT_TYPE, "application/json")
-            .header(http::header::ACCEPT, "application/json")
-            .header("Prefer", "return=representation")
-            .json(data)
-            .build()?;
-        let res = self.send(pre)?;
-        let status = res.status;
-
-        let body = res.bytes_sync()?;
-
-        if !status.is_success() {
-            if let Ok(err) = serde_json::from_slice::<ApiError>(&body) {
-                return Err(HttpError::new_custom_with_cause("api request failed ", err));
-            } else {
-                return Err(HttpError::new_custom("api request failed "));
-            }
-        }
-
-        match serde_json::from_slice(&body) {
-            Ok(o) => Ok(o),
-            Err(err) => {
-                // let body_str = String::from_utf8_lossy(&body);
-
-                Err(HttpError::new_custom_with_cause(
-                    "could not deserialize response body",
-                    err,
-                ))
-            }
-        }
+        // Creating rows is not idempotent, but patches only set fields to fixed
+        // values, so they are safe to repeat.
+        let idempotent = method != Method::POST;
+        self.execute(
+            idempotent,
+            || {
+                self.client
+                    .request(method.clone(), path)
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .header(http::header::ACCEPT, "application/json")
+                    .header("Prefer", "return=representation")
+                    .json(data)
+                    .build()
+            },
+            read_json,
+        )
     }
 
-    fn post_json_with_prefer_return<I, O>(&self, path: &str, data: &I) -> Result<O, HttpError>
+    fn post_json_with_prefer_return<I, O>(&self, path: &str, data: &I) -> Result<O, DbError>
     where
         I: serde::Serialize,
         O: serde::de::DeserializeOwned,
@@ -167,13 +384,32 @@ impl SupaDb {
         self.send_json_with_prefer_return(Method::POST, path, data)
     }
 
-    fn patch_json_with_prefer_return<I, O>(&self, path: &str, data: &I) -> Result<O, HttpError>
+    fn patch_json_with_prefer_return<I, O>(&self, path: &str, data: &I) -> Result<O, DbError>
     where
         I: serde::Serialize,
         O: serde::de::DeserializeOwned,
     {
         self.send_json_with_prefer_return(Method::PATCH, path, data)
     }
+
+    /// Delete the rows matched by the query in `path` and return them.
+    fn delete_with_prefer_return<O>(&self, path: &str) -> Result<O, DbError>
+    where
+        O: serde::de::DeserializeOwned,
+    {
+        self.execute(
+            true,
+            || {
+                self.client
+                    .request(Method::DELETE, path)
+                    .header(http::header::ACCEPT, "application/json")
+                    .header("Prefer", "return=representation")
+                    .body(RequestBody::Empty)
+                    .build()
+            },
+            read_json,
+        )
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -220,9 +456,15 @@ fn build_user_filter(f: &UserFilter) -> QueryMap {
         UserFilter::Id(id) => {
             map.add("id", format!("eq.{id}"));
         }
+        UserFilter::IdIn(ids) => {
+            map.add("id", format!("in.({})", join_ids(ids)));
+        }
         UserFilter::Name(name) => {
             map.add("username", format!("eq.{name}"));
         }
+        UserFilter::CalendarToken(token) => {
+            map.add("calendar_token", format!("eq.{token}"));
+        }
     }
 
     map
@@ -237,6 +479,7 @@ fn build_user_query(q: &UserQuery) -> QueryMap {
 fn build_timelog_filter(f: &TimelogFilter) -> QueryMap {
     let mut map = QueryMap::new();
     build_timelog_filter_rec(f, &mut map);
+    exclude_deleted(&mut map);
     map
 }
 
@@ -245,6 +488,13 @@ fn build_timelog_filter_rec(f: &TimelogFilter, map: &mut QueryMap) {
         TimelogFilter::UserId(u) => {
             map.add("user_id", format!("eq.{u}"));
         }
+        TimelogFilter::IsInvoiced(flag) => {
+            if *flag {
+                map.add("invoice_id", "not.is.null");
+            } else {
+                map.add("invoice_id", "is.null");
+            }
+        }
         TimelogFilter::IsFinished(flag) => {
             if *flag {
                 map.add("finished_at", "not.is.null");
@@ -252,6 +502,25 @@ fn build_timelog_filter_rec(f: &TimelogFilter, map: &mut QueryMap) {
                 map.add("finished_at", "is.null");
             }
         }
+        TimelogFilter::StartedSince(t) => {
+            map.add("started_at", format!("gte.{}", format_timestamp(*t)));
+        }
+        TimelogFilter::StartedBefore(t) => {
+            map.add("started_at", format!("lt.{}", format_timestamp(*t)));
+        }
+        TimelogFilter::FinishedAfter(t) => {
+            map.add("finished_at", format!("gt.{}", format_timestamp(*t)));
+        }
+        TimelogFilter::IsDeleted(flag) => {
+            if *flag {
+                map.add("deleted_at", "not.is.null");
+            } else {
+                map.add("deleted_at", "is.null");
+            }
+        }
+        TimelogFilter::DeletedBefore(t) => {
+            map.add("deleted_at", format!("lt.{}", format_timestamp(*t)));
+        }
         TimelogFilter::And(items) => {
             for item in items {
                 build_timelog_filter_rec(item, map);
@@ -260,6 +529,259 @@ fn build_timelog_filter_rec(f: &TimelogFilter, map: &mut QueryMap) {
         TimelogFilter::Id(id) => {
             map.add("id", format!("eq.{id}"));
         }
+        TimelogFilter::ProjectIdIn(ids) => {
+            map.add("project_id", format!("in.({})", join_ids(ids)));
+        }
+    }
+}
+
+/// Format a timestamp for a filter value.
+///
+/// Always uses UTC, because a `+` in an offset would need extra escaping.
+fn format_timestamp(t: OffsetDateTime) -> String {
+    t.to_offset(UtcOffset::UTC)
+        .format(&Rfc3339)
+        .expect("timestamps within the supported range can be formatted")
+}
+
+fn build_user_tag_filter(f: &UserTagFilter) -> QueryMap {
+    let mut map = QueryMap::new();
+    build_user_tag_filter_rec(f, &mut map);
+    exclude_deleted(&mut map);
+    map
+}
+
+fn build_user_tag_filter_rec(f: &UserTagFilter, map: &mut QueryMap) {
+    match f {
+        UserTagFilter::Id(id) => {
+            map.add("id", format!("eq.{id}"));
+        }
+        UserTagFilter::Personal(u) => {
+            add_visible_filter(map, *u, &[]);
+        }
+        UserTagFilter::Visible {
+            user_id,
+            organization_ids,
+        } => {
+            add_visible_filter(map, *user_id, organization_ids);
+        }
+        UserTagFilter::OrganizationId(id) => {
+            map.add("organization_id", format!("eq.{id}"));
+        }
+        UserTagFilter::IsDeleted(flag) => {
+            if *flag {
+                map.add("deleted_at", "not.is.null");
+            } else {
+                map.add("deleted_at", "is.null");
+            }
+        }
+        UserTagFilter::DeletedBefore(t) => {
+            map.add("deleted_at", format!("lt.{}", format_timestamp(*t)));
+        }
+        UserTagFilter::And(items) => {
+            for item in items {
+                build_user_tag_filter_rec(item, map);
+            }
+        }
+    }
+}
+
+fn build_client_filter(f: &ClientFilter) -> QueryMap {
+    let mut map = QueryMap::new();
+    match f {
+        ClientFilter::UserId(u) => {
+            map.add("user_id", format!("eq.{u}"));
+        }
+    }
+    map
+}
+
+fn build_project_filter(f: &ProjectFilter) -> QueryMap {
+    let mut map = QueryMap::new();
+    build_project_filter_rec(f, &mut map);
+    exclude_deleted(&mut map);
+    map
+}
+
+fn build_project_filter_rec(f: &ProjectFilter, map: &mut QueryMap) {
+    match f {
+        ProjectFilter::Id(id) => {
+            map.add("id", format!("eq.{id}"));
+        }
+        ProjectFilter::Personal(u) => {
+            add_visible_filter(map, *u, &[]);
+        }
+        ProjectFilter::Visible {
+            user_id,
+            organization_ids,
+        } => {
+            add_visible_filter(map, *user_id, organization_ids);
+        }
+        ProjectFilter::OrganizationId(id) => {
+            map.add("organization_id", format!("eq.{id}"));
+        }
+        ProjectFilter::IsArchived(flag) => {
+            map.add("archived", format!("is.{flag}"));
+        }
+        ProjectFilter::IsDeleted(flag) => {
+            if *flag {
+                map.add("deleted_at", "not.is.null");
+            } else {
+                map.add("deleted_at", "is.null");
+            }
+        }
+        ProjectFilter::DeletedBefore(t) => {
+            map.add("deleted_at", format!("lt.{}", format_timestamp(*t)));
+        }
+        ProjectFilter::And(items) => {
+            for item in items {
+                build_project_filter_rec(item, map);
+            }
+        }
+    }
+}
+
+/// Leave out rows in the trash, unless the filter asks for them.
+fn exclude_deleted(map: &mut QueryMap) {
+    if !map.0.contains_key("deleted_at") {
+        map.add("deleted_at", "is.null");
+    }
+}
+
+/// Comma separated ids for an `in.(...)` filter.
+fn join_ids(ids: &[u64]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Match personal rows of a user and rows shared in the organizations.
+fn add_visible_filter(map: &mut QueryMap, user_id: UserId, organization_ids: &[OrganizationId]) {
+    if organization_ids.is_empty() {
+        map.add("user_id", format!("eq.{user_id}"));
+        map.add("organization_id", "is.null");
+    } else {
+        map.add(
+            "or",
+            format!(
+                "(and(user_id.eq.{user_id},organization_id.is.null),organization_id.in.({}))",
+                join_ids(organization_ids)
+            ),
+        );
+    }
+}
+
+fn build_membership_filter_rec(f: &MembershipFilter, map: &mut QueryMap) {
+    match f {
+        MembershipFilter::OrganizationId(id) => {
+            map.add("organization_id", format!("eq.{id}"));
+        }
+        MembershipFilter::UserId(u) => {
+            map.add("user_id", format!("eq.{u}"));
+        }
+        MembershipFilter::And(items) => {
+            for item in items {
+                build_membership_filter_rec(item, map);
+            }
+        }
+    }
+}
+
+fn build_invitation_filter_rec(f: &InvitationFilter, map: &mut QueryMap) {
+    match f {
+        InvitationFilter::Id(id) => {
+            map.add("id", format!("eq.{id}"));
+        }
+        InvitationFilter::OrganizationId(id) => {
+            map.add("organization_id", format!("eq.{id}"));
+        }
+        InvitationFilter::UserId(id) => {
+            map.add("user_id", format!("eq.{id}"));
+        }
+        InvitationFilter::And(items) => {
+            for item in items {
+                build_invitation_filter_rec(item, map);
+            }
+        }
+    }
+}
+
+fn build_timesheet_filter_rec(f: &TimesheetFilter, map: &mut QueryMap) {
+    match f {
+        TimesheetFilter::Id(id) => {
+            map.add("id", format!("eq.{id}"));
+        }
+        TimesheetFilter::UserId(u) => {
+            map.add("user_id", format!("eq.{u}"));
+        }
+        TimesheetFilter::UserIdIn(ids) => {
+            map.add("user_id", format!("in.({})", join_ids(ids)));
+        }
+        TimesheetFilter::Status(status) => {
+            map.add("status", format!("eq.{status}"));
+        }
+        TimesheetFilter::WeekStart(date) => {
+            map.add("week_start", format!("eq.{date}"));
+        }
+        TimesheetFilter::And(items) => {
+            for item in items {
+                build_timesheet_filter_rec(item, map);
+            }
+        }
+    }
+}
+
+fn build_audit_event_filter_rec(f: &AuditEventFilter, map: &mut QueryMap) {
+    match f {
+        AuditEventFilter::ActorId(u) => {
+            map.add("actor_id", format!("eq.{u}"));
+        }
+        AuditEventFilter::Entity(entity) => {
+            map.add("entity", format!("eq.{entity}"));
+        }
+        AuditEventFilter::EntityIn(entities) => {
+            map.add("entity", format!("in.({})", entities.join(",")));
+        }
+        AuditEventFilter::EntityId(id) => {
+            map.add("entity_id", format!("eq.{id}"));
+        }
+        AuditEventFilter::Action(action) => {
+            map.add("action", format!("eq.{action}"));
+        }
+        AuditEventFilter::CreatedSince(t) => {
+            map.add("created_at", format!("gte.{}", format_timestamp(*t)));
+        }
+        AuditEventFilter::CreatedBefore(t) => {
+            map.add("created_at", format!("lt.{}", format_timestamp(*t)));
+        }
+        AuditEventFilter::And(items) => {
+            for item in items {
+                build_audit_event_filter_rec(item, map);
+            }
+        }
+    }
+}
+
+fn build_invoice_filter(f: &InvoiceFilter) -> QueryMap {
+    let mut map = QueryMap::new();
+    build_invoice_filter_rec(f, &mut map);
+    map
+}
+
+fn build_invoice_filter_rec(f: &InvoiceFilter, map: &mut QueryMap) {
+    match f {
+        InvoiceFilter::Id(id) => {
+            map.add("id", format!("eq.{id}"));
+        }
+        InvoiceFilter::UserId(u) => {
+            map.add("user_id", format!("eq.{u}"));
+        }
+        InvoiceFilter::And(items) => {
+            for item in items {
+                build_invoice_filter_rec(item, map);
+            }
+        }
     }
 }
 
@@ -269,27 +791,40 @@ fn build_timelog_query(q: &TimelogQuery) -> QueryMap {
         .as_ref()
         .map(build_timelog_filter)
         .unwrap_or_default();
+    exclude_deleted(&mut map);
 
-    for order in &q.order {
-        let dir = match order.direction {
-            Direction::Asc => "asc",
-            Direction::Desc => "desc",
-        };
-        let col = match order.expr {
-            TimelogOrder::Id => "id",
-            TimelogOrder::StartedAt => "started_at",
-        };
-        map.add("order", format!("{col}.{dir}"))
+    let order = q
+        .order
+        .iter()
+        .map(|order| {
+            let dir = match order.direction {
+                Direction::Asc => "asc",
+                Direction::Desc => "desc",
+            };
+            let col = match order.expr {
+                TimelogOrder::Id => "id",
+                TimelogOrder::StartedAt => "started_at",
+            };
+            format!("{col}.{dir}")
+        })
+        .collect::<Vec<_>>();
+    if !order.is_empty() {
+        map.set("order", order.join(","));
     }
 
     map
 }
 
 impl Db for SupaDb {
+    fn ping(&self) -> Result<(), DbError> {
+        let _: Vec<serde_json::Value> = self.get_json("/users?select=id&limit=1")?;
+        Ok(())
+    }
+
     fn user(
         &self,
         filter: super::types::UserFilter,
-    ) -> Result<Option<super::types::User>, anyhow::Error> {
+    ) -> Result<Option<super::types::User>, DbError> {
         let mut qm = build_user_filter(&filter);
         qm.set("select", "*");
 
@@ -299,56 +834,386 @@ impl Db for SupaDb {
         Ok(user)
     }
 
-    fn users(&self, query: super::types::UserQuery) -> Result<Vec<User>, anyhow::Error> {
+    fn users(&self, query: super::types::UserQuery) -> Result<Vec<User>, DbError> {
         let mut qm = build_user_query(&query);
         qm.set("select", "*");
 
         let path = format!("/users?{}", qm.to_query());
         self.list_table(&path, query.limit, query.offset)
-            .map_err(From::from)
     }
 
-    fn user_create(
-        &self,
-        user: super::types::UserCreate,
-    ) -> Result<super::types::User, anyhow::Error> {
+    fn user_create(&self, user: super::types::UserCreate) -> Result<super::types::User, DbError> {
         let users: Vec<User> = self.post_json_with_prefer_return("/users", &user)?;
         users
             .into_iter()
             .next()
-            .context("API returned invalid data")
+            .ok_or_else(|| DbError::other(anyhow!("API returned invalid data")))
+    }
+
+    fn user_update(&self, id: UserId, patch: UserPatch) -> Result<User, DbError> {
+        let mut qm = build_user_filter(&UserFilter::Id(id));
+        qm.set("select", "*");
+        let path = format!("/users?{}", qm.to_query());
+        let users: Vec<User> = self.patch_json_with_prefer_return(&path, &patch)?;
+        users.into_iter().next().ok_or(DbError::NotFound)
     }
 
     fn timelogs(
         &self,
         query: super::types::TimelogQuery,
-    ) -> Result<Vec<super::types::Timelog>, anyhow::Error> {
+    ) -> Result<Vec<super::types::Timelog>, DbError> {
         let mut qm = build_timelog_query(&query);
         qm.add("select", "*");
         let path = format!("/timelogs?{}", qm.to_query());
 
         self.list_table(&path, query.limit, query.offset)
-            .map_err(From::from)
+    }
+
+    fn timelog_count(&self, filter: TimelogFilter) -> Result<u64, DbError> {
+        let mut qm = build_timelog_filter(&filter);
+        qm.set("select", "id");
+        qm.set("limit", "1");
+        let path = format!("/timelogs?{}", qm.to_query());
+        self.count_rows(&path)
     }
 
     fn timelog_create(
         &self,
         log: super::types::TimelogCreate,
-    ) -> Result<super::types::Timelog, anyhow::Error> {
-        eprintln!("{}", serde_json::to_string(&log).unwrap());
+    ) -> Result<super::types::Timelog, DbError> {
         let logs: Vec<Timelog> = self.post_json_with_prefer_return("/timelogs", &log)?;
-        logs.into_iter().next().context("No item in response")
+        logs.into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn timelogs_create(&self, logs: Vec<TimelogCreate>) -> Result<Vec<Timelog>, DbError> {
+        if logs.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.post_json_with_prefer_return("/timelogs", &logs)
     }
 
     fn timelog_update(
         &self,
         selector: TimelogQuery,
         patch: super::types::TimelogPatch,
-    ) -> Result<Vec<Timelog>, anyhow::Error> {
+    ) -> Result<Vec<Timelog>, DbError> {
         let mut query = build_timelog_query(&selector);
         query.set("select", "*");
         let path = format!("/timelogs?{}", query.to_query());
         self.patch_json_with_prefer_return(&path, &patch)
-            .map_err(From::from)
+    }
+
+    fn timelog_delete(&self, filter: TimelogFilter) -> Result<Vec<Timelog>, DbError> {
+        let mut qm = build_timelog_filter(&filter);
+        qm.set("select", "*");
+        let path = format!("/timelogs?{}", qm.to_query());
+        self.delete_with_prefer_return(&path)
+    }
+
+    fn timelog_switch(&self, switch: TimelogSwitch) -> Result<Vec<Timelog>, DbError> {
+        self.post_json_with_prefer_return("/rpc/switch_timelog", &switch)
+    }
+
+    fn user_tags(&self, query: UserTagQuery) -> Result<Vec<UserTag>, DbError> {
+        let mut qm = query
+            .filter
+            .as_ref()
+            .map(build_user_tag_filter)
+            .unwrap_or_default();
+        exclude_deleted(&mut qm);
+        qm.set("select", "*");
+        qm.add("order", "name.asc");
+        let path = format!("/user_tags?{}", qm.to_query());
+        self.list_table(&path, query.limit, query.offset)
+    }
+
+    fn user_tag_create(&self, tag: UserTagCreate) -> Result<UserTag, DbError> {
+        let tags: Vec<UserTag> = self.post_json_with_prefer_return("/user_tags", &tag)?;
+        tags.into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn user_tag_update(
+        &self,
+        filter: UserTagFilter,
+        patch: UserTagPatch,
+    ) -> Result<Vec<UserTag>, DbError> {
+        let mut qm = build_user_tag_filter(&filter);
+        qm.set("select", "*");
+        let path = format!("/user_tags?{}", qm.to_query());
+        self.patch_json_with_prefer_return(&path, &patch)
+    }
+
+    fn user_tag_delete(&self, filter: UserTagFilter) -> Result<Vec<UserTag>, DbError> {
+        let mut qm = build_user_tag_filter(&filter);
+        qm.set("select", "*");
+        let path = format!("/user_tags?{}", qm.to_query());
+        self.delete_with_prefer_return(&path)
+    }
+
+    fn timelog_tags(&self, timelog_ids: &[TimelogId]) -> Result<Vec<TimelogUserTag>, DbError> {
+        let mut links = Vec::new();
+        // Keep the query string short.
+        for ids in timelog_ids.chunks(100) {
+            let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+            let mut qm = QueryMap::new();
+            qm.set("select", "*");
+            qm.set("timelog_id", format!("in.({})", ids.join(",")));
+            let path = format!("/timelogs_user_tags?{}", qm.to_query());
+            let mut chunk: Vec<TimelogUserTag> = self.get_json(&path)?;
+            links.append(&mut chunk);
+        }
+        Ok(links)
+    }
+
+    fn timelog_tags_add(&self, links: Vec<TimelogUserTag>) -> Result<Vec<TimelogUserTag>, DbError> {
+        if links.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.post_json_with_prefer_return("/timelogs_user_tags", &links)
+    }
+
+    fn clients(&self, query: ClientQuery) -> Result<Vec<Client>, DbError> {
+        let mut qm = query
+            .filter
+            .as_ref()
+            .map(build_client_filter)
+            .unwrap_or_default();
+        qm.set("select", "*");
+        qm.add("order", "name.asc");
+        let path = format!("/clients?{}", qm.to_query());
+        self.list_table(&path, query.limit, query.offset)
+    }
+
+    fn client_create(&self, client: ClientCreate) -> Result<Client, DbError> {
+        let clients: Vec<Client> = self.post_json_with_prefer_return("/clients", &client)?;
+        clients
+            .into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn projects(&self, query: ProjectQuery) -> Result<Vec<Project>, DbError> {
+        let mut qm = query
+            .filter
+            .as_ref()
+            .map(build_project_filter)
+            .unwrap_or_default();
+        exclude_deleted(&mut qm);
+        qm.set("select", "*");
+        qm.add("order", "name.asc");
+        let path = format!("/projects?{}", qm.to_query());
+        self.list_table(&path, query.limit, query.offset)
+    }
+
+    fn project_create(&self, project: ProjectCreate) -> Result<Project, DbError> {
+        let projects: Vec<Project> = self.post_json_with_prefer_return("/projects", &project)?;
+        projects
+            .into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn project_update(
+        &self,
+        filter: ProjectFilter,
+        patch: ProjectPatch,
+    ) -> Result<Vec<Project>, DbError> {
+        let mut qm = build_project_filter(&filter);
+        qm.set("select", "*");
+        let path = format!("/projects?{}", qm.to_query());
+        self.patch_json_with_prefer_return(&path, &patch)
+    }
+
+    fn project_delete(&self, filter: ProjectFilter) -> Result<Vec<Project>, DbError> {
+        let mut qm = build_project_filter(&filter);
+        qm.set("select", "*");
+        let path = format!("/projects?{}", qm.to_query());
+        self.delete_with_prefer_return(&path)
+    }
+
+    fn invoices(&self, query: InvoiceQuery) -> Result<Vec<Invoice>, DbError> {
+        let mut qm = query
+            .filter
+            .as_ref()
+            .map(build_invoice_filter)
+            .unwrap_or_default();
+        qm.set("select", "*");
+        qm.add("order", "number.desc");
+        let path = format!("/invoices?{}", qm.to_query());
+        self.list_table(&path, query.limit, query.offset)
+    }
+
+    fn invoice_create(&self, invoice: InvoiceCreate) -> Result<Invoice, DbError> {
+        let invoices: Vec<Invoice> = self.post_json_with_prefer_return(
+            "/rpc/create_invoice",
+            &serde_json::json!({ "invoice": invoice }),
+        )?;
+        invoices
+            .into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn organizations(&self, ids: &[OrganizationId]) -> Result<Vec<Organization>, DbError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut qm = QueryMap::new();
+        qm.set("select", "*");
+        qm.set("id", format!("in.({})", join_ids(ids)));
+        qm.add("order", "name.asc");
+        let path = format!("/organizations?{}", qm.to_query());
+        self.get_json(&path)
+    }
+
+    fn organization_create(&self, org: OrganizationCreate) -> Result<Organization, DbError> {
+        let orgs: Vec<Organization> = self.post_json_with_prefer_return("/organizations", &org)?;
+        orgs.into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn memberships(&self, filter: MembershipFilter) -> Result<Vec<Membership>, DbError> {
+        let mut qm = QueryMap::new();
+        build_membership_filter_rec(&filter, &mut qm);
+        qm.set("select", "*");
+        qm.add("order", "created_at.asc");
+        let path = format!("/memberships?{}", qm.to_query());
+        self.get_json(&path)
+    }
+
+    fn membership_create(&self, membership: MembershipCreate) -> Result<Membership, DbError> {
+        let memberships: Vec<Membership> =
+            self.post_json_with_prefer_return("/memberships", &membership)?;
+        memberships
+            .into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn membership_update(
+        &self,
+        filter: MembershipFilter,
+        patch: MembershipPatch,
+    ) -> Result<Vec<Membership>, DbError> {
+        let mut qm = QueryMap::new();
+        build_membership_filter_rec(&filter, &mut qm);
+        qm.set("select", "*");
+        let path = format!("/memberships?{}", qm.to_query());
+        self.patch_json_with_prefer_return(&path, &patch)
+    }
+
+    fn membership_delete(&self, filter: MembershipFilter) -> Result<Vec<Membership>, DbError> {
+        let mut qm = QueryMap::new();
+        build_membership_filter_rec(&filter, &mut qm);
+        qm.set("select", "*");
+        let path = format!("/memberships?{}", qm.to_query());
+        self.delete_with_prefer_return(&path)
+    }
+
+    fn invitations(&self, filter: InvitationFilter) -> Result<Vec<Invitation>, DbError> {
+        let mut qm = QueryMap::new();
+        build_invitation_filter_rec(&filter, &mut qm);
+        qm.set("select", "*");
+        qm.add("order", "created_at.asc");
+        let path = format!("/invitations?{}", qm.to_query());
+        self.get_json(&path)
+    }
+
+    fn invitation_create(&self, invitation: InvitationCreate) -> Result<Invitation, DbError> {
+        let invitations: Vec<Invitation> =
+            self.post_json_with_prefer_return("/invitations", &invitation)?;
+        invitations
+            .into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn invitation_delete(&self, filter: InvitationFilter) -> Result<Vec<Invitation>, DbError> {
+        let mut qm = QueryMap::new();
+        build_invitation_filter_rec(&filter, &mut qm);
+        qm.set("select", "*");
+        let path = format!("/invitations?{}", qm.to_query());
+        self.delete_with_prefer_return(&path)
+    }
+
+    fn timesheets(&self, query: TimesheetQuery) -> Result<Vec<Timesheet>, DbError> {
+        let mut qm = QueryMap::new();
+        if let Some(filter) = &query.filter {
+            build_timesheet_filter_rec(filter, &mut qm);
+        }
+        qm.set("select", "*");
+        qm.add("order", "week_start.desc,id.desc");
+        let path = format!("/timesheets?{}", qm.to_query());
+        self.list_table(&path, query.limit, query.offset)
+    }
+
+    fn timesheet_create(&self, timesheet: TimesheetCreate) -> Result<Timesheet, DbError> {
+        let timesheets: Vec<Timesheet> =
+            self.post_json_with_prefer_return("/timesheets", &timesheet)?;
+        timesheets
+            .into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn timesheet_update(
+        &self,
+        filter: TimesheetFilter,
+        patch: TimesheetPatch,
+    ) -> Result<Vec<Timesheet>, DbError> {
+        let mut qm = QueryMap::new();
+        build_timesheet_filter_rec(&filter, &mut qm);
+        qm.set("select", "*");
+        let path = format!("/timesheets?{}", qm.to_query());
+        self.patch_json_with_prefer_return(&path, &patch)
+    }
+
+    fn timesheet_events(&self, id: TimesheetId) -> Result<Vec<TimesheetEvent>, DbError> {
+        let mut qm = QueryMap::new();
+        qm.set("select", "*");
+        qm.set("timesheet_id", format!("eq.{id}"));
+        qm.add("order", "created_at.asc,id.asc");
+        let path = format!("/timesheet_events?{}", qm.to_query());
+        self.get_json(&path)
+    }
+
+    fn timesheet_event_create(
+        &self,
+        event: TimesheetEventCreate,
+    ) -> Result<TimesheetEvent, DbError> {
+        let events: Vec<TimesheetEvent> =
+            self.post_json_with_prefer_return("/timesheet_events", &event)?;
+        events
+            .into_iter()
+            .next()
+            .ok_or_else(|| DbError::other(anyhow!("No item in response")))
+    }
+
+    fn audit_events(&self, query: AuditEventQuery) -> Result<Vec<AuditEvent>, DbError> {
+        let mut qm = QueryMap::new();
+        if let Some(filter) = &query.filter {
+            build_audit_event_filter_rec(filter, &mut qm);
+        }
+        qm.set("select", "*");
+        qm.add("order", "created_at.desc,id.desc");
+        let path = format!("/audit_events?{}", qm.to_query());
+        self.list_table(&path, query.limit, query.offset)
+    }
+
+    fn metric_counters(&self) -> Result<Vec<MetricCounter>, DbError> {
+        self.list_table("/metric_counters?select=*", 100_000, 0)
+    }
+
+    fn metric_counters_add(&self, increments: &[MetricCounter]) -> Result<(), DbError> {
+        let _: u64 = self.post_json_with_prefer_return(
+            "/rpc/record_metrics",
+            &serde_json::json!({ "samples": increments }),
+        )?;
+        Ok(())
     }
 }
@@ -0,0 +1,68 @@
+/// Errors returned by [`super::Db`] implementations.
+///
+/// Backends map their native error codes onto these variants, so callers can
+/// react to constraint violations without inspecting error messages.
+#[derive(Debug)]
+pub enum DbError {
+    /// The requested row does not exist.
+    NotFound,
+    /// A unique constraint was violated.
+    UniqueViolation {
+        constraint: String,
+    },
+    /// A check constraint was violated.
+    CheckViolation {
+        constraint: String,
+    },
+    /// A foreign key constraint was violated.
+    ForeignKey,
+    /// The database could not be reached, or is not able to serve requests.
+    Unavailable(anyhow::Error),
+    Other(anyhow::Error),
+}
+
+impl DbError {
+    pub fn other(err: impl Into<anyhow::Error>) -> Self {
+        Self::Other(err.into())
+    }
+
+    pub fn unavailable(err: impl Into<anyhow::Error>) -> Self {
+        Self::Unavailable(err.into())
+    }
+
+    /// Returns the violated constraint name for unique and check violations.
+    pub fn constraint(&self) -> Option<&str> {
+        match self {
+            Self::UniqueViolation { constraint } | Self::CheckViolation { constraint } => {
+                Some(constraint)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "Not found"),
+            Self::UniqueViolation { constraint } => {
+                write!(f, "Unique constraint '{constraint}' violated")
+            }
+            Self::CheckViolation { constraint } => {
+                write!(f, "Check constraint '{constraint}' violated")
+            }
+            Self::ForeignKey => write!(f, "Referenced item does not exist"),
+            Self::Unavailable(err) => write!(f, "Database unavailable: {err}"),
+            Self::Other(err) => write!(f, "Database error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Unavailable(err) | Self::Other(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
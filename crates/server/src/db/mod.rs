@@ -1,23 +1,120 @@
-use self::types::{
-    Order, Timelog, TimelogCreate, TimelogFilter, TimelogId, TimelogOrder, TimelogPatch,
-    TimelogQuery, User, UserCreate, UserFilter, UserId, UserQuery,
+use time::OffsetDateTime;
+
+use self::{
+    error::DbError,
+    types::{
+        AuditEvent, AuditEventQuery, Client, ClientCreate, ClientQuery, Invitation,
+        InvitationCreate, InvitationFilter, Invoice, InvoiceCreate, InvoiceQuery, Membership,
+        MembershipCreate, MembershipFilter, MembershipPatch, MetricCounter, Order, Organization,
+        OrganizationCreate, OrganizationId, Project, ProjectCreate, ProjectFilter, ProjectId,
+        ProjectPatch, ProjectQuery, Timelog, TimelogCreate, TimelogFilter, TimelogId, TimelogOrder,
+        TimelogPatch, TimelogQuery, TimelogSwitch, TimelogUserTag, Timesheet, TimesheetCreate,
+        TimesheetEvent, TimesheetEventCreate, TimesheetFilter, TimesheetId, TimesheetPatch,
+        TimesheetQuery, User, UserCreate, UserFilter, UserId, UserPatch, UserQuery, UserTag,
+        UserTagCreate, UserTagFilter, UserTagPatch, UserTagQuery,
+    },
 };
 
 pub mod client_supabase;
+pub mod error;
+pub mod policy;
 pub mod types;
 
+/// Timelogs, tags and projects in the trash are left out of all queries,
+/// unless a filter asks for them with `IsDeleted` or `DeletedBefore`.
 pub trait Db {
-    fn user(&self, filter: UserFilter) -> Result<Option<User>, anyhow::Error>;
-    fn users(&self, query: UserQuery) -> Result<Vec<User>, anyhow::Error>;
-    fn user_create(&self, user: UserCreate) -> Result<User, anyhow::Error>;
+    /// Run a cheap query to check that the database is reachable.
+    fn ping(&self) -> Result<(), DbError>;
+
+    fn user(&self, filter: UserFilter) -> Result<Option<User>, DbError>;
+    fn users(&self, query: UserQuery) -> Result<Vec<User>, DbError>;
+    fn user_create(&self, user: UserCreate) -> Result<User, DbError>;
+    fn user_update(&self, id: UserId, patch: UserPatch) -> Result<User, DbError>;
 
-    fn timelogs(&self, query: TimelogQuery) -> Result<Vec<Timelog>, anyhow::Error>;
-    fn timelog_create(&self, log: TimelogCreate) -> Result<Timelog, anyhow::Error>;
+    fn timelogs(&self, query: TimelogQuery) -> Result<Vec<Timelog>, DbError>;
+    fn timelog_count(&self, filter: TimelogFilter) -> Result<u64, DbError>;
+    fn timelog_create(&self, log: TimelogCreate) -> Result<Timelog, DbError>;
+    /// Create multiple timelogs with a single request.
+    fn timelogs_create(&self, logs: Vec<TimelogCreate>) -> Result<Vec<Timelog>, DbError>;
     fn timelog_update(
         &self,
         selector: TimelogQuery,
         patch: TimelogPatch,
-    ) -> Result<Vec<Timelog>, anyhow::Error>;
+    ) -> Result<Vec<Timelog>, DbError>;
+    /// Delete timelogs for good. Only rows in the trash match, unless the
+    /// filter says otherwise.
+    fn timelog_delete(&self, filter: TimelogFilter) -> Result<Vec<Timelog>, DbError>;
+    /// Finish the running timelogs of a user and start a new one at the same
+    /// instant, in one transaction. Returns the finished timelogs, then the
+    /// new one.
+    fn timelog_switch(&self, switch: TimelogSwitch) -> Result<Vec<Timelog>, DbError>;
+
+    fn user_tags(&self, query: UserTagQuery) -> Result<Vec<UserTag>, DbError>;
+    fn user_tag_create(&self, tag: UserTagCreate) -> Result<UserTag, DbError>;
+    fn user_tag_update(
+        &self,
+        filter: UserTagFilter,
+        patch: UserTagPatch,
+    ) -> Result<Vec<UserTag>, DbError>;
+    fn user_tag_delete(&self, filter: UserTagFilter) -> Result<Vec<UserTag>, DbError>;
+
+    fn timelog_tags(&self, timelog_ids: &[TimelogId]) -> Result<Vec<TimelogUserTag>, DbError>;
+    fn timelog_tags_add(&self, links: Vec<TimelogUserTag>) -> Result<Vec<TimelogUserTag>, DbError>;
+
+    fn clients(&self, query: ClientQuery) -> Result<Vec<Client>, DbError>;
+    fn client_create(&self, client: ClientCreate) -> Result<Client, DbError>;
+    fn projects(&self, query: ProjectQuery) -> Result<Vec<Project>, DbError>;
+    fn project_create(&self, project: ProjectCreate) -> Result<Project, DbError>;
+    fn project_update(
+        &self,
+        filter: ProjectFilter,
+        patch: ProjectPatch,
+    ) -> Result<Vec<Project>, DbError>;
+    fn project_delete(&self, filter: ProjectFilter) -> Result<Vec<Project>, DbError>;
+
+    fn invoices(&self, query: InvoiceQuery) -> Result<Vec<Invoice>, DbError>;
+    /// Create an invoice with the next number and set it on the timelogs of
+    /// its entries, in one transaction. Fails with a check violation of
+    /// `invoice_entries_available` if an entry is already invoiced, deleted
+    /// or running.
+    fn invoice_create(&self, invoice: InvoiceCreate) -> Result<Invoice, DbError>;
+
+    fn organizations(&self, ids: &[OrganizationId]) -> Result<Vec<Organization>, DbError>;
+    fn organization_create(&self, org: OrganizationCreate) -> Result<Organization, DbError>;
+
+    fn memberships(&self, filter: MembershipFilter) -> Result<Vec<Membership>, DbError>;
+    fn membership_create(&self, membership: MembershipCreate) -> Result<Membership, DbError>;
+    fn membership_update(
+        &self,
+        filter: MembershipFilter,
+        patch: MembershipPatch,
+    ) -> Result<Vec<Membership>, DbError>;
+    fn membership_delete(&self, filter: MembershipFilter) -> Result<Vec<Membership>, DbError>;
+
+    fn invitations(&self, filter: InvitationFilter) -> Result<Vec<Invitation>, DbError>;
+    fn invitation_create(&self, invitation: InvitationCreate) -> Result<Invitation, DbError>;
+    fn invitation_delete(&self, filter: InvitationFilter) -> Result<Vec<Invitation>, DbError>;
+
+    fn timesheets(&self, query: TimesheetQuery) -> Result<Vec<Timesheet>, DbError>;
+    fn timesheet_create(&self, timesheet: TimesheetCreate) -> Result<Timesheet, DbError>;
+    fn timesheet_update(
+        &self,
+        filter: TimesheetFilter,
+        patch: TimesheetPatch,
+    ) -> Result<Vec<Timesheet>, DbError>;
+    /// Status changes of a timesheet, oldest first.
+    fn timesheet_events(&self, id: TimesheetId) -> Result<Vec<TimesheetEvent>, DbError>;
+    fn timesheet_event_create(
+        &self,
+        event: TimesheetEventCreate,
+    ) -> Result<TimesheetEvent, DbError>;
+
+    /// Changes recorded by the database. There is no way to write them.
+    fn audit_events(&self, query: AuditEventQuery) -> Result<Vec<AuditEvent>, DbError>;
+
+    fn metric_counters(&self) -> Result<Vec<MetricCounter>, DbError>;
+    /// Add the values to the counters of the same name and labels.
+    fn metric_counters_add(&self, increments: &[MetricCounter]) -> Result<(), DbError>;
 }
 
 pub fn user_active_timelogs(user_id: UserId) -> TimelogQuery {
@@ -38,11 +135,93 @@ pub fn user_finished_timelogs(user_id: UserId) -> TimelogQuery {
     }
 }
 
-pub fn timelog_by_id(id: TimelogId) -> TimelogQuery {
+/// Load all timelogs matching the query, in pages of `query.limit`.
+///
+/// The query should have a unique order, otherwise pages can overlap.
+pub fn all_timelogs(db: &impl Db, mut query: TimelogQuery) -> Result<Vec<Timelog>, DbError> {
+    query.limit = query.limit.max(1);
+    let mut logs = Vec::new();
+    loop {
+        let mut page = db.timelogs(query.clone())?;
+        let done = (page.len() as u64) < query.limit;
+        logs.append(&mut page);
+        if done {
+            return Ok(logs);
+        }
+        query.offset += query.limit;
+    }
+}
+
+/// Timelogs of a user that overlap the range `from..until`.
+pub fn user_timelogs_in_range(
+    user_id: UserId,
+    from: OffsetDateTime,
+    until: OffsetDateTime,
+) -> TimelogQuery {
+    TimelogQuery {
+        filter: Some(TimelogFilter::And(vec![
+            TimelogFilter::UserId(user_id),
+            TimelogFilter::StartedBefore(until),
+            TimelogFilter::FinishedAfter(from),
+        ])),
+        limit: 1000,
+        offset: 0,
+        order: vec![
+            Order::asc(TimelogOrder::StartedAt),
+            Order::asc(TimelogOrder::Id),
+        ],
+    }
+}
+
+/// Timelogs of all users on the projects that overlap `from..until`, oldest
+/// first.
+pub fn project_timelogs_in_range(
+    project_ids: Vec<ProjectId>,
+    from: OffsetDateTime,
+    until: OffsetDateTime,
+) -> TimelogQuery {
+    TimelogQuery {
+        filter: Some(TimelogFilter::And(vec![
+            TimelogFilter::ProjectIdIn(project_ids),
+            TimelogFilter::StartedBefore(until),
+            TimelogFilter::FinishedAfter(from),
+        ])),
+        limit: 1000,
+        offset: 0,
+        order: vec![
+            Order::asc(TimelogOrder::StartedAt),
+            Order::asc(TimelogOrder::Id),
+        ],
+    }
+}
+
+/// Timelogs of a user that started within `from..until`, oldest first.
+pub fn user_timelogs_started_in(
+    user_id: UserId,
+    from: OffsetDateTime,
+    until: OffsetDateTime,
+) -> TimelogQuery {
+    TimelogQuery {
+        filter: Some(TimelogFilter::And(vec![
+            TimelogFilter::UserId(user_id),
+            TimelogFilter::StartedSince(from),
+            TimelogFilter::StartedBefore(until),
+        ])),
+        limit: 500,
+        offset: 0,
+        order: vec![
+            Order::asc(TimelogOrder::StartedAt),
+            Order::asc(TimelogOrder::Id),
+        ],
+    }
+}
+
+/// All timelogs of a user, by id.
+pub fn user_timelogs_all(user_id: UserId) -> TimelogQuery {
     TimelogQuery {
-        filter: Some(TimelogFilter::Id(id)),
-        limit: 1,
+        filter: Some(TimelogFilter::UserId(user_id)),
+        limit: 1000,
         offset: 0,
-        order: vec![],
+        order: vec![Order::asc(TimelogOrder::Id)],
     }
 }
@@ -0,0 +1,55 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// Timeout and retry settings for database calls.
+#[derive(Clone, Debug)]
+pub struct RequestPolicy {
+    /// Timeout for a single HTTP request, and deadline for a database call
+    /// including all retries.
+    ///
+    /// Requests that take longer are abandoned by the HTTP client, and no
+    /// retry is started once the deadline has passed.
+    pub timeout: Duration,
+    /// Maximum number of retries for idempotent requests.
+    pub max_retries: u32,
+    /// Base delay for the exponential backoff between retries.
+    pub backoff_base: Duration,
+}
+
+impl RequestPolicy {
+    /// Delay before the given retry (starting at 0).
+    ///
+    /// Uses exponential backoff with "equal jitter": half of the delay is
+    /// fixed, the other half random, so concurrent clients spread out.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let max = self.backoff_base.saturating_mul(2u32.saturating_pow(retry));
+        let half = max / 2;
+        half + jitter(half)
+    }
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_retries: 2,
+            backoff_base: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Random duration in `0..=max`.
+fn jitter(max: Duration) -> Duration {
+    let nanos = max.as_nanos() as u64;
+    if nanos == 0 {
+        return Duration::ZERO;
+    }
+    // RandomState is seeded randomly, which is good enough for jitter and
+    // avoids pulling in a rng.
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(nanos);
+    Duration::from_nanos(hasher.finish() % (nanos + 1))
+}
@@ -14,6 +14,34 @@ pub struct User {
         deserialize_with = "time::serde::rfc3339::deserialize"
     )]
     pub created_at: time::OffsetDateTime,
+    /// IANA time zone name, like "Europe/Vienna".
+    pub timezone: String,
+    /// Locale code, like "en-US".
+    pub locale: String,
+    /// Secret for the calendar feed URL. The feed is disabled if not set.
+    #[serde(default)]
+    pub calendar_token: Option<String>,
+    /// Default hourly rate in cents of `currency`.
+    #[serde(default)]
+    pub hourly_rate_cents: Option<i64>,
+    /// ISO 4217 code of the currency all rates are in.
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    /// Billable time of each entry is rounded to multiples of this. 0 turns
+    /// rounding off.
+    #[serde(default)]
+    pub rounding_minutes: u32,
+    /// `up`, `nearest` or `down`.
+    #[serde(default = "default_rounding_mode")]
+    pub rounding_mode: String,
+}
+
+fn default_currency() -> String {
+    "EUR".to_string()
+}
+
+fn default_rounding_mode() -> String {
+    "up".to_string()
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -23,13 +51,209 @@ pub struct UserCreate {
     pub password_hash: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UserPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// `Some(None)` removes the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calendar_token: Option<Option<String>>,
+    /// `Some(None)` removes the rate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hourly_rate_cents: Option<Option<i64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rounding_minutes: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rounding_mode: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UserTag {
-    pub id: u64,
+    pub id: UserTagId,
+    pub user_id: UserId,
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    /// Organization the tag is shared in, `None` for personal tags.
+    #[serde(default)]
+    pub organization_id: Option<OrganizationId>,
+    /// Hourly rate in cents, overrides project and user rates.
+    #[serde(default)]
+    pub hourly_rate_cents: Option<i64>,
+    /// Estimated time, `None` for no budget.
+    #[serde(default)]
+    pub budget_minutes: Option<i64>,
+    /// `total`, `week` or `month`.
+    #[serde(default = "default_budget_period")]
+    pub budget_period: String,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub created_at: time::OffsetDateTime,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub updated_at: time::OffsetDateTime,
+    /// Set while the tag is in the trash.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<time::OffsetDateTime>,
+}
+
+pub type UserTagId = u64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UserTagCreate {
+    pub user_id: UserId,
+    pub organization_id: Option<OrganizationId>,
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    pub hourly_rate_cents: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UserTagPatch {
+    /// `Some(None)` removes the rate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hourly_rate_cents: Option<Option<i64>>,
+    /// `Some(None)` removes the budget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_minutes: Option<Option<i64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_period: Option<String>,
+    /// `Some(None)` restores from the trash.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<Option<String>>,
+}
+
+fn default_budget_period() -> String {
+    "total".to_string()
+}
+
+#[derive(Clone, Debug)]
+pub enum UserTagFilter {
+    Id(UserTagId),
+    /// Personal tags of a user.
+    Personal(UserId),
+    /// Personal tags of a user and the tags of the given organizations.
+    Visible {
+        user_id: UserId,
+        organization_ids: Vec<OrganizationId>,
+    },
+    OrganizationId(OrganizationId),
+    /// In the trash or not. Without this, deleted rows are left out.
+    IsDeleted(bool),
+    /// Moved to the trash before the given time.
+    DeletedBefore(OffsetDateTime),
+    And(Vec<Self>),
+}
+
+impl UserTagFilter {
+    pub fn and(self, other: Self) -> Self {
+        Self::And(vec![self, other])
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UserTagQuery {
+    pub filter: Option<UserTagFilter>,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+impl UserTagQuery {
+    /// Personal tags of a user.
+    pub fn new_for_user(user_id: UserId) -> Self {
+        Self {
+            filter: Some(UserTagFilter::Personal(user_id)),
+            limit: 1000,
+            offset: 0,
+        }
+    }
+}
+
+pub type ClientId = u64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Client {
+    pub id: ClientId,
+    pub user_id: UserId,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub created_at: time::OffsetDateTime,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub updated_at: time::OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClientCreate {
+    pub user_id: UserId,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ClientFilter {
+    UserId(UserId),
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientQuery {
+    pub filter: Option<ClientFilter>,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+impl ClientQuery {
+    pub fn new_for_user(user_id: UserId) -> Self {
+        Self {
+            filter: Some(ClientFilter::UserId(user_id)),
+            limit: 1000,
+            offset: 0,
+        }
+    }
+}
+
+pub type ProjectId = u64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Project {
+    pub id: ProjectId,
     pub user_id: UserId,
+    pub client_id: Option<ClientId>,
     pub name: String,
     pub description: Option<String>,
     pub color: Option<String>,
+    /// Organization the project is shared in, `None` for personal projects.
+    #[serde(default)]
+    pub organization_id: Option<OrganizationId>,
+    /// Whether time on this project is billable by default.
+    pub billable: bool,
+    /// Hourly rate in cents, overrides the user rate.
+    #[serde(default)]
+    pub hourly_rate_cents: Option<i64>,
+    /// Estimated time, `None` for no budget.
+    #[serde(default)]
+    pub budget_minutes: Option<i64>,
+    /// `total`, `week` or `month`.
+    #[serde(default = "default_budget_period")]
+    pub budget_period: String,
+    /// Archived projects are kept for reports, but can't be picked for new
+    /// timelogs.
+    pub archived: bool,
     #[serde(
         serialize_with = "time::serde::rfc3339::serialize",
         deserialize_with = "time::serde::rfc3339::deserialize"
@@ -40,6 +264,86 @@ pub struct UserTag {
         deserialize_with = "time::serde::rfc3339::deserialize"
     )]
     pub updated_at: time::OffsetDateTime,
+    /// Set while the project is in the trash.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<time::OffsetDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProjectCreate {
+    pub user_id: UserId,
+    pub organization_id: Option<OrganizationId>,
+    pub client_id: Option<ClientId>,
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    pub billable: bool,
+    pub hourly_rate_cents: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ProjectPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub billable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// `Some(None)` removes the rate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hourly_rate_cents: Option<Option<i64>>,
+    /// `Some(None)` removes the budget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_minutes: Option<Option<i64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_period: Option<String>,
+    /// `Some(None)` restores from the trash.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<Option<String>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ProjectFilter {
+    Id(ProjectId),
+    /// Personal projects of a user.
+    Personal(UserId),
+    /// Personal projects of a user and the projects of the given
+    /// organizations.
+    Visible {
+        user_id: UserId,
+        organization_ids: Vec<OrganizationId>,
+    },
+    OrganizationId(OrganizationId),
+    IsArchived(bool),
+    /// In the trash or not. Without this, deleted rows are left out.
+    IsDeleted(bool),
+    /// Moved to the trash before the given time.
+    DeletedBefore(OffsetDateTime),
+    And(Vec<Self>),
+}
+
+impl ProjectFilter {
+    pub fn and(self, other: Self) -> Self {
+        Self::And(vec![self, other])
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProjectQuery {
+    pub filter: Option<ProjectFilter>,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+impl ProjectQuery {
+    /// Personal projects of a user.
+    pub fn new_for_user(user_id: UserId) -> Self {
+        Self {
+            filter: Some(ProjectFilter::Personal(user_id)),
+            limit: 1000,
+            offset: 0,
+        }
+    }
 }
 
 pub type TimelogId = u64;
@@ -63,6 +367,17 @@ pub struct Timelog {
     pub started_at: time::OffsetDateTime,
     // TODO: better serde integration, as above.
     pub finished_at: Option<String>,
+    #[serde(default)]
+    pub project_id: Option<ProjectId>,
+    #[serde(default)]
+    pub billable: bool,
+    /// The invoice this timelog was billed on. Invoiced timelogs can't be
+    /// changed.
+    #[serde(default)]
+    pub invoice_id: Option<InvoiceId>,
+    /// Set while the timelog is in the trash.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<time::OffsetDateTime>,
 }
 
 impl Timelog {
@@ -88,23 +403,62 @@ pub struct TimelogCreate {
         deserialize_with = "time::serde::rfc3339::deserialize"
     )]
     pub started_at: time::OffsetDateTime,
+    pub finished_at: Option<String>,
+    pub project_id: Option<ProjectId>,
+    pub billable: bool,
 }
 
+/// Arguments of [`Db::timelog_switch`](super::Db::timelog_switch).
 #[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimelogSwitch {
+    pub user_id: UserId,
+    pub title: String,
+    pub project_id: Option<ProjectId>,
+    pub billable: bool,
+    /// When the running timelogs finish and the new one starts.
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub switched_at: time::OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct TimelogPatch {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// `Some(None)` reopens a finished timelog.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub finished_at: Option<String>,
+    pub finished_at: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub billable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invoice_id: Option<InvoiceId>,
+    /// `Some(None)` restores from the trash.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<Option<String>>,
 }
 
 #[derive(Clone, Debug)]
 pub enum TimelogFilter {
     Id(TimelogId),
+    /// On any of the given projects, of any user.
+    ProjectIdIn(Vec<ProjectId>),
     UserId(UserId),
     IsFinished(bool),
+    IsInvoiced(bool),
+    /// Started at or after the given time.
+    StartedSince(OffsetDateTime),
+    /// Started before the given time.
+    StartedBefore(OffsetDateTime),
+    /// Finished after the given time. Excludes running timelogs.
+    FinishedAfter(OffsetDateTime),
+    /// In the trash or not. Without this, deleted rows are left out.
+    IsDeleted(bool),
+    /// Moved to the trash before the given time.
+    DeletedBefore(OffsetDateTime),
     And(Vec<Self>),
 }
 
@@ -166,26 +520,130 @@ impl TimelogQuery {
             order: vec![],
         }
     }
+}
+
+pub type InvoiceId = u64;
+
+/// An invoice with a snapshot of its lines and entries.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Invoice {
+    pub id: InvoiceId,
+    pub user_id: UserId,
+    pub client_id: ClientId,
+    /// Sequential per user, starting at 1.
+    pub number: u32,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub issued_at: time::OffsetDateTime,
+    #[serde(flatten)]
+    pub data: InvoiceData,
+}
+
+/// A new invoice. It gets the next number of the user.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InvoiceCreate {
+    pub user_id: UserId,
+    pub client_id: ClientId,
+    #[serde(flatten)]
+    pub data: InvoiceData,
+}
+
+/// Contents of an invoice.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InvoiceData {
+    /// First day of the billed period, as `YYYY-MM-DD`.
+    pub period_from: String,
+    /// Last day of the billed period, as `YYYY-MM-DD`.
+    pub period_to: String,
+    pub client_name: String,
+    /// `project` or `task`.
+    pub grouping: String,
+    pub currency: String,
+    /// Tax rate in hundredths of a percent.
+    pub tax_rate_basis_points: i64,
+    pub subtotal_cents: i64,
+    pub tax_cents: i64,
+    pub total_cents: i64,
+    pub lines: Vec<InvoiceLine>,
+    pub entries: Vec<InvoiceEntry>,
+}
 
-    pub fn new_single_id(id: TimelogId) -> Self {
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InvoiceLine {
+    pub description: String,
+    /// Billed time, after rounding.
+    pub seconds: i64,
+    /// Hourly rate, `None` for time without a rate.
+    pub rate_cents: Option<i64>,
+    pub amount_cents: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InvoiceEntry {
+    pub timelog_id: TimelogId,
+    pub title: String,
+    pub project: Option<String>,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub started_at: time::OffsetDateTime,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub finished_at: time::OffsetDateTime,
+    pub seconds: i64,
+    pub rate_cents: Option<i64>,
+    pub amount_cents: i64,
+}
+
+#[derive(Clone, Debug)]
+pub enum InvoiceFilter {
+    Id(InvoiceId),
+    UserId(UserId),
+    And(Vec<Self>),
+}
+
+impl InvoiceFilter {
+    pub fn and(self, other: Self) -> Self {
+        Self::And(vec![self, other])
+    }
+}
+
+/// Invoices are returned by number, newest first.
+#[derive(Clone, Debug)]
+pub struct InvoiceQuery {
+    pub filter: Option<InvoiceFilter>,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+impl InvoiceQuery {
+    pub fn new_for_user(user_id: UserId) -> Self {
         Self {
-            filter: Some(TimelogFilter::Id(id)),
-            limit: 1,
-            ..Self::new()
+            filter: Some(InvoiceFilter::UserId(user_id)),
+            limit: 100,
+            offset: 0,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TimelogUserTag {
-    pub user_tag_id: u64,
+    pub user_tag_id: UserTagId,
     pub timelog_id: TimelogId,
 }
 
 #[derive(Clone, Debug)]
 pub enum UserFilter {
     Id(UserId),
+    /// Any of the given ids.
+    IdIn(Vec<UserId>),
     Name(String),
+    CalendarToken(String),
 }
 
 #[derive(Clone, Debug)]
@@ -194,3 +652,293 @@ pub struct UserQuery {
     pub limit: u64,
     pub offset: u64,
 }
+
+pub type OrganizationId = u64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Organization {
+    pub id: OrganizationId,
+    pub name: String,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub created_at: time::OffsetDateTime,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub updated_at: time::OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OrganizationCreate {
+    pub name: String,
+}
+
+/// A user in an organization.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Membership {
+    pub organization_id: OrganizationId,
+    pub user_id: UserId,
+    /// `owner`, `admin` or `member`.
+    pub role: String,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub created_at: time::OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MembershipCreate {
+    pub organization_id: OrganizationId,
+    pub user_id: UserId,
+    pub role: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MembershipPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub enum MembershipFilter {
+    OrganizationId(OrganizationId),
+    UserId(UserId),
+    And(Vec<Self>),
+}
+
+impl MembershipFilter {
+    pub fn and(self, other: Self) -> Self {
+        Self::And(vec![self, other])
+    }
+}
+
+pub type InvitationId = u64;
+
+/// An invitation to join an organization, for an existing user.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Invitation {
+    pub id: InvitationId,
+    pub organization_id: OrganizationId,
+    /// The invited user.
+    pub user_id: UserId,
+    /// `admin` or `member`.
+    pub role: String,
+    pub invited_by: UserId,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub created_at: time::OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InvitationCreate {
+    pub organization_id: OrganizationId,
+    pub user_id: UserId,
+    pub role: String,
+    pub invited_by: UserId,
+}
+
+#[derive(Clone, Debug)]
+pub enum InvitationFilter {
+    Id(InvitationId),
+    OrganizationId(OrganizationId),
+    UserId(UserId),
+    And(Vec<Self>),
+}
+
+impl InvitationFilter {
+    pub fn and(self, other: Self) -> Self {
+        Self::And(vec![self, other])
+    }
+}
+
+pub type TimesheetId = u64;
+
+/// A week of timelogs submitted for approval.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Timesheet {
+    pub id: TimesheetId,
+    pub user_id: UserId,
+    /// Monday of the week in the user's time zone, as `YYYY-MM-DD`.
+    pub week_start: String,
+    /// Start of the week in the user's time zone at submission.
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub period_start: time::OffsetDateTime,
+    /// End of the week, exclusive.
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub period_end: time::OffsetDateTime,
+    /// `submitted`, `approved` or `rejected`.
+    pub status: String,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub submitted_at: time::OffsetDateTime,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub decided_at: Option<time::OffsetDateTime>,
+    #[serde(default)]
+    pub decided_by: Option<UserId>,
+    /// Comment of the approver.
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimesheetCreate {
+    pub user_id: UserId,
+    pub week_start: String,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub period_start: time::OffsetDateTime,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub period_end: time::OffsetDateTime,
+    pub status: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TimesheetPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submitted_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decided_at: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decided_by: Option<Option<UserId>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<Option<String>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum TimesheetFilter {
+    Id(TimesheetId),
+    UserId(UserId),
+    /// Any of the given users.
+    UserIdIn(Vec<UserId>),
+    Status(String),
+    WeekStart(String),
+    And(Vec<Self>),
+}
+
+impl TimesheetFilter {
+    pub fn and(self, other: Self) -> Self {
+        Self::And(vec![self, other])
+    }
+}
+
+/// Timesheets are returned by week, newest first.
+#[derive(Clone, Debug)]
+pub struct TimesheetQuery {
+    pub filter: Option<TimesheetFilter>,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+impl TimesheetQuery {
+    pub fn new_for_user(user_id: UserId) -> Self {
+        Self {
+            filter: Some(TimesheetFilter::UserId(user_id)),
+            limit: 100,
+            offset: 0,
+        }
+    }
+}
+
+/// A status change of a timesheet.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimesheetEvent {
+    pub id: u64,
+    pub timesheet_id: TimesheetId,
+    pub actor_id: UserId,
+    pub status: String,
+    pub comment: Option<String>,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub created_at: time::OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimesheetEventCreate {
+    pub timesheet_id: TimesheetId,
+    pub actor_id: UserId,
+    pub status: String,
+    pub comment: Option<String>,
+}
+
+pub type AuditEventId = u64;
+
+/// A recorded change of a row, written by the database.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditEvent {
+    pub id: AuditEventId,
+    #[serde(
+        serialize_with = "time::serde::rfc3339::serialize",
+        deserialize_with = "time::serde::rfc3339::deserialize"
+    )]
+    pub created_at: time::OffsetDateTime,
+    /// `None` for changes without a signed in user.
+    pub actor_id: Option<UserId>,
+    /// Table name, like `timelogs`.
+    pub entity: String,
+    pub entity_id: Option<u64>,
+    /// `create`, `update` or `delete`.
+    pub action: String,
+    /// Old values of the changed columns, the whole row for deletes.
+    pub before: Option<serde_json::Value>,
+    /// New values of the changed columns, the whole row for creates.
+    pub after: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug)]
+pub enum AuditEventFilter {
+    ActorId(UserId),
+    Entity(String),
+    /// Any of the given entities.
+    EntityIn(Vec<String>),
+    EntityId(u64),
+    Action(String),
+    CreatedSince(time::OffsetDateTime),
+    CreatedBefore(time::OffsetDateTime),
+    And(Vec<Self>),
+}
+
+impl AuditEventFilter {
+    pub fn and(self, other: Self) -> Self {
+        Self::And(vec![self, other])
+    }
+}
+
+/// Audit events are returned newest first.
+#[derive(Clone, Debug)]
+pub struct AuditEventQuery {
+    pub filter: Option<AuditEventFilter>,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+/// A series of the Prometheus metrics, or an increment of it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MetricCounter {
+    pub name: String,
+    /// Labels in the exposition format, like `route="/",status="200"`.
+    pub labels: String,
+    pub value: f64,
+}
@@ -1,5 +1,8 @@
+pub mod cli;
 mod db;
+pub mod logging;
 mod logic;
+mod metrics;
 mod server;
 mod util;
 
@@ -0,0 +1,211 @@
+//! Leveled, structured logging to stderr.
+//!
+//! Uses the `log` facade with key-value support, so call sites look like:
+//! `log::info!(status = 200; "request finished")`.
+//!
+//! Every record is tagged with the current request id, and values of
+//! sensitive keys (passwords, tokens, emails, ...) are redacted.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::Write,
+    sync::Mutex,
+};
+
+use log::kv::{Key, Value, VisitSource};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => anyhow::bail!("unknown log format '{other}': expected 'text' or 'json'"),
+        }
+    }
+}
+
+/// Install the logger.
+///
+/// Must be called once at startup, later calls are ignored.
+pub fn init(level: log::LevelFilter, format: LogFormat) {
+    if log::set_boxed_logger(Box::new(Logger { format })).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Header used to pass the request id to and from other services.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+static REQUEST_ID: Mutex<Option<String>> = Mutex::new(None);
+
+/// Set the id of the request currently being handled.
+pub fn set_request_id(id: Option<String>) {
+    *REQUEST_ID.lock().unwrap() = id;
+}
+
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.lock().unwrap().clone()
+}
+
+/// Generate a new random request id.
+///
+/// Only used for correlating log lines, so it does not need to be
+/// cryptographically secure.
+pub fn new_request_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_i128(OffsetDateTime::now_utc().unix_timestamp_nanos());
+    format!("{:016x}", hasher.finish())
+}
+
+/// Check a request id received from a client.
+///
+/// Ids end up in log lines and headers, so only allow a safe charset.
+pub fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Key fragments whose values must never end up in logs.
+const SENSITIVE_KEYS: &[&str] = &[
+    "password",
+    "token",
+    "secret",
+    "email",
+    "apikey",
+    "api_key",
+    "authorization",
+    "cookie",
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// Path prefixes whose last segment is a secret, like the calendar feed
+/// token.
+const SECRET_PATH_PREFIXES: &[&str] = &["/calendar/"];
+
+/// The request path with secret segments replaced, for logging.
+///
+/// Key-based redaction can't see a secret inside a path.
+pub fn redact_path(path: &str) -> String {
+    match SECRET_PATH_PREFIXES.iter().find(|p| path.starts_with(*p)) {
+        Some(prefix) => {
+            let extension = path
+                .rsplit_once('.')
+                .filter(|(_, ext)| !ext.contains('/'))
+                .map(|(_, ext)| format!(".{ext}"))
+                .unwrap_or_default();
+            format!("{prefix}{REDACTED}{extension}")
+        }
+        None => path.to_string(),
+    }
+}
+
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SENSITIVE_KEYS.iter().any(|s| key.contains(s))
+}
+
+struct Logger {
+    format: LogFormat,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut fields = Fields(Vec::new());
+        // Visiting only fails if the visitor does.
+        record.key_values().visit(&mut fields).ok();
+
+        let line = match self.format {
+            LogFormat::Text => format_text(record, fields.0),
+            LogFormat::Json => format_json(record, fields.0),
+        };
+
+        // Stdout is the response body for wcgi, so logs must go to stderr.
+        let mut stderr = std::io::stderr().lock();
+        writeln!(stderr, "{line}").ok();
+    }
+
+    fn flush(&self) {
+        std::io::stderr().flush().ok();
+    }
+}
+
+/// Collects the key-value pairs of a record, redacting sensitive values.
+struct Fields(Vec<(String, serde_json::Value)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let key = key.as_str();
+        let value = if is_sensitive(key) {
+            serde_json::Value::from(REDACTED)
+        } else if let Some(v) = value.to_bool() {
+            v.into()
+        } else if let Some(v) = value.to_u64() {
+            v.into()
+        } else if let Some(v) = value.to_i64() {
+            v.into()
+        } else if let Some(v) = value.to_f64() {
+            v.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+fn format_text(record: &log::Record, fields: Vec<(String, serde_json::Value)>) -> String {
+    let mut line = format!("{} {:<5} {}", now(), record.level(), record.args());
+    if let Some(id) = request_id() {
+        line.push_str(&format!(" request_id={id}"));
+    }
+    for (key, value) in fields {
+        match value {
+            serde_json::Value::String(s) => line.push_str(&format!(" {key}={s:?}")),
+            other => line.push_str(&format!(" {key}={other}")),
+        }
+    }
+    line
+}
+
+fn format_json(record: &log::Record, fields: Vec<(String, serde_json::Value)>) -> String {
+    let mut map = serde_json::Map::new();
+    map.insert("ts".into(), now().into());
+    map.insert("level".into(), record.level().as_str().into());
+    map.insert("target".into(), record.target().into());
+    map.insert("msg".into(), record.args().to_string().into());
+    if let Some(id) = request_id() {
+        map.insert("request_id".into(), id.into());
+    }
+    for (key, value) in fields {
+        map.insert(key, value);
+    }
+    serde_json::Value::Object(map).to_string()
+}
@@ -0,0 +1,219 @@
+//! The audit log.
+//!
+//! Every create, update and delete of the audited tables is recorded by
+//! database triggers, together with the user the server acted for (see
+//! [`SupaDb::with_actor`]). This module only reads the log.
+
+use std::collections::{BTreeSet, HashMap};
+
+use time::OffsetDateTime;
+
+use crate::{
+    db::{
+        client_supabase::SupaDb,
+        types::{
+            AuditEvent, AuditEventFilter, AuditEventQuery, Timelog, TimelogFilter, TimelogId,
+            TimelogQuery, User, UserFilter, UserQuery,
+        },
+        Db,
+    },
+    PublicError,
+};
+
+use super::{projects::user_tags, user::usernames};
+
+/// Events per page of the audit log.
+pub const PAGE_SIZE: u64 = 50;
+
+/// Most events shown in the history of an entry.
+const MAX_HISTORY: u64 = 500;
+
+/// Tables written by the triggers, for filtering.
+pub const ENTITIES: [&str; 11] = [
+    "timelogs",
+    "timelogs_user_tags",
+    "users",
+    "user_tags",
+    "clients",
+    "projects",
+    "invoices",
+    "organizations",
+    "memberships",
+    "invitations",
+    "timesheets",
+];
+
+pub const ACTIONS: [&str; 3] = ["create", "update", "delete"];
+
+/// A changed field with its old and new value, `None` if absent or null.
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditEntry {
+    pub event: AuditEvent,
+    /// Username of the actor, `None` for changes without a signed in user.
+    pub actor: Option<String>,
+    pub changes: Vec<Change>,
+}
+
+/// Filters of the audit log page. Empty values match everything.
+#[derive(Clone, Debug, Default)]
+pub struct AuditSearch {
+    pub actor: String,
+    pub entity: String,
+    pub entity_id: Option<u64>,
+    pub action: String,
+    pub since: Option<OffsetDateTime>,
+    pub before: Option<OffsetDateTime>,
+    /// Starting at 0.
+    pub page: u64,
+}
+
+/// The fields of an event, by name.
+pub fn event_changes(event: &AuditEvent) -> Vec<Change> {
+    let object = |value: &Option<serde_json::Value>| match value {
+        Some(serde_json::Value::Object(map)) => map.clone(),
+        _ => serde_json::Map::new(),
+    };
+    let before = object(&event.before);
+    let after = object(&event.after);
+    let fields = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+    fields
+        .into_iter()
+        .map(|field| Change {
+            field: field.clone(),
+            before: before.get(field).and_then(format_value),
+            after: after.get(field).and_then(format_value),
+        })
+        .collect()
+}
+
+fn format_value(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn entries(db: &SupaDb, events: Vec<AuditEvent>) -> Result<Vec<AuditEntry>, anyhow::Error> {
+    let mut ids = events.iter().filter_map(|e| e.actor_id).collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.dedup();
+    let names = usernames(db, ids)?;
+    Ok(events
+        .into_iter()
+        .map(|event| AuditEntry {
+            actor: event
+                .actor_id
+                .map(|id| names.get(&id).cloned().unwrap_or_else(|| format!("#{id}"))),
+            changes: event_changes(&event),
+            event,
+        })
+        .collect())
+}
+
+/// Changes of a timelog of the user and its tags, newest first.
+///
+/// Tag links are shown as a `tag` field with the tag's name.
+pub fn timelog_history(
+    db: &SupaDb,
+    user: &User,
+    id: TimelogId,
+) -> Result<(Timelog, Vec<AuditEntry>), anyhow::Error> {
+    let owned = TimelogQuery {
+        filter: Some(TimelogFilter::Id(id).and(TimelogFilter::UserId(user.id))),
+        limit: 1,
+        ..TimelogQuery::new()
+    };
+    let log = db
+        .timelogs(owned)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PublicError::msg("Timelog not found"))?;
+
+    let filter = AuditEventFilter::EntityIn(vec![
+        "timelogs".to_string(),
+        "timelogs_user_tags".to_string(),
+    ])
+    .and(AuditEventFilter::EntityId(id));
+    let events = db.audit_events(AuditEventQuery {
+        filter: Some(filter),
+        limit: MAX_HISTORY,
+        offset: 0,
+    })?;
+
+    let tags = user_tags(db, user)?
+        .into_iter()
+        .map(|t| (t.id, t.name))
+        .collect::<HashMap<_, _>>();
+    let tag_name = |value: &Option<serde_json::Value>| {
+        let id = value.as_ref()?.get("user_tag_id")?.as_u64()?;
+        Some(tags.get(&id).cloned().unwrap_or_else(|| format!("#{id}")))
+    };
+
+    let mut entries = entries(db, events)?;
+    for entry in &mut entries {
+        if entry.event.entity == "timelogs_user_tags" {
+            entry.changes = vec![Change {
+                field: "tag".to_string(),
+                before: tag_name(&entry.event.before),
+                after: tag_name(&entry.event.after),
+            }];
+        }
+    }
+    Ok((log, entries))
+}
+
+/// A page of the audit log of all users, newest first.
+///
+/// Only for admins, which the caller has to check.
+pub fn audit_search(db: &SupaDb, search: &AuditSearch) -> Result<Vec<AuditEntry>, anyhow::Error> {
+    let mut filters = Vec::new();
+    let actor = search.actor.trim();
+    if !actor.is_empty() {
+        let user = db
+            .users(UserQuery {
+                filter: Some(UserFilter::Name(actor.to_string())),
+                limit: 1,
+                offset: 0,
+            })?
+            .into_iter()
+            .next()
+            .ok_or_else(|| PublicError::msg(format!("Unknown user '{actor}'")))?;
+        filters.push(AuditEventFilter::ActorId(user.id));
+    }
+    if !search.entity.is_empty() {
+        if !ENTITIES.contains(&search.entity.as_str()) {
+            return Err(PublicError::msg(format!("Unknown entity '{}'", search.entity)).into());
+        }
+        filters.push(AuditEventFilter::Entity(search.entity.clone()));
+    }
+    if let Some(id) = search.entity_id {
+        filters.push(AuditEventFilter::EntityId(id));
+    }
+    if !search.action.is_empty() {
+        if !ACTIONS.contains(&search.action.as_str()) {
+            return Err(PublicError::msg(format!("Unknown action '{}'", search.action)).into());
+        }
+        filters.push(AuditEventFilter::Action(search.action.clone()));
+    }
+    if let Some(since) = search.since {
+        filters.push(AuditEventFilter::CreatedSince(since));
+    }
+    if let Some(before) = search.before {
+        filters.push(AuditEventFilter::CreatedBefore(before));
+    }
+
+    let events = db.audit_events(AuditEventQuery {
+        filter: (!filters.is_empty()).then_some(AuditEventFilter::And(filters)),
+        limit: PAGE_SIZE,
+        offset: search.page * PAGE_SIZE,
+    })?;
+    entries(db, events)
+}
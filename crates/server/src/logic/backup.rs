@@ -0,0 +1,547 @@
+//! Versioned JSON backups of user accounts.
+//!
+//! A backup holds one or more users with their profile, tags, clients,
+//! projects, timelogs and tag links. Password hashes and calendar tokens are
+//! left out. Restoring maps the ids in the backup to new ones and skips tags
+//! and timelogs that already exist, so restoring the same backup twice
+//! changes nothing.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    db::{
+        all_timelogs,
+        client_supabase::SupaDb,
+        types::{
+            ClientCreate, ClientId, ClientQuery, ProjectCreate, ProjectFilter, ProjectId,
+            ProjectPatch, ProjectQuery, TimelogCreate, TimelogId, TimelogUserTag, User, UserCreate,
+            UserFilter, UserId, UserQuery, UserTagCreate, UserTagFilter, UserTagId, UserTagPatch,
+            UserTagQuery,
+        },
+        user_timelogs_all, Db,
+    },
+    PublicError,
+};
+
+use super::{
+    billing::{user_update_billing, Amount, Rounding},
+    timesheets::Locks,
+    user::user_update_preferences,
+};
+
+/// Version of the backup format, increased on incompatible changes.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Largest accepted backup file.
+pub const MAX_BACKUP_BYTES: usize = 50 * 1024 * 1024;
+
+/// Password hash for users created by a restore. No password hashes to it,
+/// so these users can't log in until a password is set.
+const LOCKED_PASSWORD_HASH: &str = "!locked";
+
+/// Records are created in batches of this size.
+const BATCH_SIZE: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Backup {
+    pub version: u32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub exported_at: OffsetDateTime,
+    pub users: Vec<UserBackup>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UserBackup {
+    pub profile: ProfileBackup,
+    pub tags: Vec<TagBackup>,
+    #[serde(default)]
+    pub clients: Vec<ClientBackup>,
+    #[serde(default)]
+    pub projects: Vec<ProjectBackup>,
+    pub timelogs: Vec<TimelogBackup>,
+    pub timelog_tags: Vec<TimelogUserTag>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProfileBackup {
+    pub id: UserId,
+    pub username: String,
+    pub email: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub timezone: String,
+    pub locale: String,
+    #[serde(default)]
+    pub hourly_rate_cents: Option<i64>,
+    /// Not set in backups from before billing existed.
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub rounding_minutes: u32,
+    #[serde(default)]
+    pub rounding_mode: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TagBackup {
+    pub id: UserTagId,
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    #[serde(default)]
+    pub hourly_rate_cents: Option<i64>,
+    #[serde(default)]
+    pub budget_minutes: Option<i64>,
+    #[serde(default)]
+    pub budget_period: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClientBackup {
+    pub id: ClientId,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProjectBackup {
+    pub id: ProjectId,
+    pub client_id: Option<ClientId>,
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    pub billable: bool,
+    pub archived: bool,
+    #[serde(default)]
+    pub hourly_rate_cents: Option<i64>,
+    #[serde(default)]
+    pub budget_minutes: Option<i64>,
+    #[serde(default)]
+    pub budget_period: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimelogBackup {
+    pub id: TimelogId,
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub finished_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub project_id: Option<ProjectId>,
+    #[serde(default)]
+    pub billable: bool,
+}
+
+impl Backup {
+    pub fn new(users: Vec<UserBackup>) -> Self {
+        Self {
+            version: BACKUP_VERSION,
+            exported_at: OffsetDateTime::now_utc(),
+            users,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, anyhow::Error> {
+        if data.len() > MAX_BACKUP_BYTES {
+            return Err(PublicError::msg(format!(
+                "The backup is too large, at most {} MB are supported",
+                MAX_BACKUP_BYTES / 1024 / 1024
+            ))
+            .into());
+        }
+
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let Version { version } = serde_json::from_slice(data)
+            .map_err(|_| PublicError::msg("Unsupported file: expected a Timely backup"))?;
+        if version > BACKUP_VERSION {
+            return Err(PublicError::msg(format!(
+                "Backup version {version} is not supported, at most {BACKUP_VERSION}"
+            ))
+            .into());
+        }
+        serde_json::from_slice(data)
+            .map_err(|err| PublicError::msg(format!("Invalid backup: {err}")).into())
+    }
+}
+
+/// Everything stored for a user.
+pub fn user_backup(db: &SupaDb, user: &User) -> Result<UserBackup, anyhow::Error> {
+    let tags = db
+        .user_tags(UserTagQuery::new_for_user(user.id))?
+        .into_iter()
+        .map(|t| TagBackup {
+            id: t.id,
+            name: t.name,
+            description: t.description,
+            color: t.color,
+            hourly_rate_cents: t.hourly_rate_cents,
+            budget_minutes: t.budget_minutes,
+            budget_period: t.budget_minutes.map(|_| t.budget_period),
+        })
+        .collect();
+    let clients = db
+        .clients(ClientQuery::new_for_user(user.id))?
+        .into_iter()
+        .map(|c| ClientBackup {
+            id: c.id,
+            name: c.name,
+            description: c.description,
+        })
+        .collect();
+    let projects = db
+        .projects(ProjectQuery::new_for_user(user.id))?
+        .into_iter()
+        .map(|p| ProjectBackup {
+            id: p.id,
+            client_id: p.client_id,
+            name: p.name,
+            description: p.description,
+            color: p.color,
+            billable: p.billable,
+            archived: p.archived,
+            hourly_rate_cents: p.hourly_rate_cents,
+            budget_minutes: p.budget_minutes,
+            budget_period: p.budget_minutes.map(|_| p.budget_period),
+        })
+        .collect();
+
+    let logs = all_timelogs(db, user_timelogs_all(user.id)).context("Could not load timelogs")?;
+    let ids = logs.iter().map(|l| l.id).collect::<Vec<_>>();
+    let mut timelog_tags = db.timelog_tags(&ids)?;
+    timelog_tags.sort_by_key(|l| (l.timelog_id, l.user_tag_id));
+    let timelogs = logs
+        .into_iter()
+        .map(|log| TimelogBackup {
+            finished_at: log.finished_at(),
+            id: log.id,
+            title: log.title,
+            description: log.description,
+            created_at: log.created_at,
+            started_at: log.started_at,
+            project_id: log.project_id,
+            billable: log.billable,
+        })
+        .collect();
+
+    Ok(UserBackup {
+        profile: ProfileBackup {
+            id: user.id,
+            username: user.username.clone(),
+            email: user.email.clone(),
+            created_at: user.created_at,
+            timezone: user.timezone.clone(),
+            locale: user.locale.clone(),
+            hourly_rate_cents: user.hourly_rate_cents,
+            currency: Some(user.currency.clone()),
+            rounding_minutes: user.rounding_minutes,
+            rounding_mode: Some(user.rounding_mode.clone()),
+        },
+        tags,
+        clients,
+        projects,
+        timelogs,
+        timelog_tags,
+    })
+}
+
+/// Backup of all users, for admins.
+pub fn full_backup(db: &SupaDb) -> Result<Backup, anyhow::Error> {
+    let mut query = UserQuery {
+        filter: None,
+        limit: 100,
+        offset: 0,
+    };
+    let mut users = Vec::new();
+    loop {
+        let page = db.users(query.clone()).context("Could not load users")?;
+        for user in &page {
+            users.push(user_backup(db, user)?);
+        }
+        if (page.len() as u64) < query.limit {
+            return Ok(Backup::new(users));
+        }
+        query.offset += query.limit;
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Default, Debug)]
+pub struct RestoreSummary {
+    pub tags_created: usize,
+    pub projects_created: usize,
+    pub timelogs_created: usize,
+    /// Timelogs that already existed.
+    pub timelogs_skipped: usize,
+    pub links_created: usize,
+}
+
+/// Restore a backup into the account of `user`.
+///
+/// The backup must contain exactly one user, who may be a different one.
+pub fn restore_account(
+    db: &SupaDb,
+    user: &User,
+    backup: &Backup,
+) -> Result<RestoreSummary, anyhow::Error> {
+    match backup.users.as_slice() {
+        [data] => restore_user(db, user, data),
+        users => Err(PublicError::msg(format!(
+            "The backup contains {} accounts, expected one",
+            users.len()
+        ))
+        .into()),
+    }
+}
+
+/// Restore all users of a backup, for admins.
+///
+/// Users are matched by username. Missing users are created with a locked
+/// password.
+pub fn restore_all(
+    db: &SupaDb,
+    backup: &Backup,
+) -> Result<Vec<(String, RestoreSummary)>, anyhow::Error> {
+    let mut results = Vec::new();
+    for data in &backup.users {
+        let username = &data.profile.username;
+        let user = match db.user(UserFilter::Name(username.clone()))? {
+            Some(user) => user,
+            None => db
+                .user_create(UserCreate {
+                    username: username.clone(),
+                    email: data.profile.email.clone(),
+                    password_hash: LOCKED_PASSWORD_HASH.to_string(),
+                })
+                .with_context(|| format!("Could not create user '{username}'"))?,
+        };
+        let summary = restore_user(db, &user, data)
+            .with_context(|| format!("Could not restore user '{username}'"))?;
+        results.push((username.clone(), summary));
+    }
+    Ok(results)
+}
+
+fn restore_user(
+    db: &SupaDb,
+    user: &User,
+    data: &UserBackup,
+) -> Result<RestoreSummary, anyhow::Error> {
+    let mut summary = RestoreSummary::default();
+
+    if user.timezone != data.profile.timezone || user.locale != data.profile.locale {
+        user_update_preferences(db, user, &data.profile.timezone, &data.profile.locale)?;
+    }
+    if let Some(currency) = &data.profile.currency {
+        let mode = data
+            .profile
+            .rounding_mode
+            .as_deref()
+            .unwrap_or("up")
+            .parse()?;
+        let rounding = Rounding::new(data.profile.rounding_minutes, mode)?;
+        let rate = data.profile.hourly_rate_cents.map(Amount::from_cents);
+        user_update_billing(db, user, rate, currency, rounding)?;
+    }
+
+    // Tags are matched by name.
+    let mut tag_ids = db
+        .user_tags(UserTagQuery::new_for_user(user.id))?
+        .into_iter()
+        .map(|t| (t.name, t.id))
+        .collect::<HashMap<_, _>>();
+    let mut tag_map = HashMap::new();
+    for tag in &data.tags {
+        let id = match tag_ids.get(&tag.name) {
+            Some(id) => *id,
+            None => {
+                let created = db.user_tag_create(UserTagCreate {
+                    user_id: user.id,
+                    organization_id: None,
+                    name: tag.name.clone(),
+                    description: tag.description.clone(),
+                    color: tag.color.clone(),
+                    hourly_rate_cents: tag.hourly_rate_cents,
+                })?;
+                if tag.budget_minutes.is_some() {
+                    let patch = UserTagPatch {
+                        budget_minutes: Some(tag.budget_minutes),
+                        budget_period: tag.budget_period.clone(),
+                        ..Default::default()
+                    };
+                    db.user_tag_update(UserTagFilter::Id(created.id), patch)?;
+                }
+                summary.tags_created += 1;
+                tag_ids.insert(created.name, created.id);
+                created.id
+            }
+        };
+        tag_map.insert(tag.id, id);
+    }
+
+    // Clients and projects are matched by name.
+    let mut client_ids = db
+        .clients(ClientQuery::new_for_user(user.id))?
+        .into_iter()
+        .map(|c| (c.name, c.id))
+        .collect::<HashMap<_, _>>();
+    let mut client_map = HashMap::new();
+    for client in &data.clients {
+        let id = match client_ids.get(&client.name) {
+            Some(id) => *id,
+            None => {
+                let created = db.client_create(ClientCreate {
+                    user_id: user.id,
+                    name: client.name.clone(),
+                    description: client.description.clone(),
+                })?;
+                client_ids.insert(created.name, created.id);
+                created.id
+            }
+        };
+        client_map.insert(client.id, id);
+    }
+    let mut project_ids = db
+        .projects(ProjectQuery::new_for_user(user.id))?
+        .into_iter()
+        .map(|p| (p.name, p.id))
+        .collect::<HashMap<_, _>>();
+    let mut project_map = HashMap::new();
+    for project in &data.projects {
+        let id = match project_ids.get(&project.name) {
+            Some(id) => *id,
+            None => {
+                let created = db.project_create(ProjectCreate {
+                    user_id: user.id,
+                    organization_id: None,
+                    client_id: project
+                        .client_id
+                        .and_then(|id| client_map.get(&id).copied()),
+                    name: project.name.clone(),
+                    description: project.description.clone(),
+                    color: project.color.clone(),
+                    billable: project.billable,
+                    hourly_rate_cents: project.hourly_rate_cents,
+                })?;
+                if project.archived || project.budget_minutes.is_some() {
+                    let filter = ProjectFilter::Id(created.id);
+                    let patch = ProjectPatch {
+                        archived: Some(project.archived),
+                        budget_minutes: Some(project.budget_minutes),
+                        budget_period: project.budget_period.clone(),
+                        ..Default::default()
+                    };
+                    db.project_update(filter, patch)?;
+                }
+                summary.projects_created += 1;
+                project_ids.insert(created.name, created.id);
+                created.id
+            }
+        };
+        project_map.insert(project.id, id);
+    }
+
+    // Timelogs are matched by title, start and end.
+    let key = |title: &str, started_at: OffsetDateTime, finished_at: Option<OffsetDateTime>| {
+        (
+            title.to_string(),
+            started_at.unix_timestamp(),
+            finished_at.map(|t| t.unix_timestamp()),
+        )
+    };
+    let mut log_ids = all_timelogs(db, user_timelogs_all(user.id))
+        .context("Could not load timelogs")?
+        .into_iter()
+        .map(|log| (key(&log.title, log.started_at, log.finished_at()), log.id))
+        .collect::<HashMap<_, _>>();
+    let mut log_map = HashMap::new();
+    let mut missing = Vec::new();
+    for log in &data.timelogs {
+        let key = key(&log.title, log.started_at, log.finished_at);
+        match log_ids.get(&key) {
+            Some(id) => {
+                summary.timelogs_skipped += 1;
+                log_map.insert(log.id, *id);
+            }
+            None => missing.push((key, log)),
+        }
+    }
+    let locks = Locks::for_user(db, user.id)?;
+    for (_, log) in &missing {
+        locks.ensure_open(log.started_at, log.finished_at)?;
+    }
+
+    for batch in missing.chunks(BATCH_SIZE) {
+        // The backup itself can contain the same timelog twice.
+        let mut keys = HashSet::new();
+        let batch = batch
+            .iter()
+            .filter(|(key, _)| !log_ids.contains_key(key) && keys.insert(key))
+            .collect::<Vec<_>>();
+        let creates = batch
+            .iter()
+            .map(|(_, log)| {
+                Ok(TimelogCreate {
+                    user_id: user.id,
+                    title: log.title.clone(),
+                    description: log.description.clone(),
+                    created_at: log.created_at,
+                    started_at: log.started_at,
+                    finished_at: log.finished_at.map(|t| t.format(&Rfc3339)).transpose()?,
+                    project_id: log.project_id.and_then(|id| project_map.get(&id).copied()),
+                    billable: log.billable,
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        let created = db
+            .timelogs_create(creates)
+            .context("Could not create timelogs")?;
+        summary.timelogs_created += created.len();
+        // Rows are returned in insertion order.
+        for ((key, _), log) in batch.iter().zip(&created) {
+            log_ids.insert(key.clone(), log.id);
+        }
+    }
+    for (key, log) in &missing {
+        if let Some(id) = log_ids.get(key) {
+            log_map.insert(log.id, *id);
+        }
+    }
+
+    let new_ids = log_map.values().copied().collect::<Vec<_>>();
+    let existing = db
+        .timelog_tags(&new_ids)?
+        .into_iter()
+        .map(|l| (l.timelog_id, l.user_tag_id))
+        .collect::<HashSet<_>>();
+    let mut links = data
+        .timelog_tags
+        .iter()
+        .filter_map(|l| Some((*log_map.get(&l.timelog_id)?, *tag_map.get(&l.user_tag_id)?)))
+        .filter(|link| !existing.contains(link))
+        .collect::<Vec<_>>();
+    links.sort_unstable();
+    links.dedup();
+    for batch in links.chunks(BATCH_SIZE) {
+        let batch = batch
+            .iter()
+            .map(|(timelog_id, user_tag_id)| TimelogUserTag {
+                user_tag_id: *user_tag_id,
+                timelog_id: *timelog_id,
+            })
+            .collect();
+        summary.links_created += db.timelog_tags_add(batch)?.len();
+    }
+
+    Ok(summary)
+}
@@ -0,0 +1,329 @@
+//! Hourly rates and earnings.
+//!
+//! Amounts are integer cents, so totals are exact. The rate of an entry is
+//! the highest rate of its tags, else the rate of its project, else the
+//! user's default rate. Only billable entries earn anything, and their
+//! duration is rounded per entry before the rate is applied.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use time::Duration;
+
+use crate::{
+    db::{
+        client_supabase::SupaDb,
+        types::{
+            ProjectFilter, ProjectId, ProjectPatch, Timelog, User, UserPatch, UserTagFilter,
+            UserTagId, UserTagPatch,
+        },
+        Db,
+    },
+    PublicError,
+};
+
+use super::projects::{project_for_update, tag_for_update, user_projects, user_tags};
+
+/// Currencies a user can bill in. All of them have cents.
+pub const CURRENCIES: [&str; 11] = [
+    "EUR", "USD", "GBP", "CHF", "CAD", "AUD", "SEK", "NOK", "DKK", "PLN", "CZK",
+];
+
+/// Largest accepted hourly rate, in cents.
+const MAX_RATE_CENTS: i64 = 100_000_000;
+
+/// An amount of money in cents.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Debug, Serialize)]
+#[serde(transparent)]
+pub struct Amount(i64);
+
+impl Amount {
+    pub const ZERO: Self = Self(0);
+
+    pub fn from_cents(cents: i64) -> Self {
+        Self(cents)
+    }
+
+    pub fn cents(self) -> i64 {
+        self.0
+    }
+
+    /// Parse a non-negative amount like `85`, `85.5` or `85,50`.
+    pub fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        let invalid = || PublicError::msg(format!("Invalid amount '{value}'"));
+        let value = value.trim();
+        let (units, fraction) = match value.split_once(['.', ',']) {
+            Some((units, fraction)) => (units, fraction),
+            None => (value, ""),
+        };
+        let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if units.is_empty() || fraction.len() > 2 || !digits(units) || !digits(fraction) {
+            return Err(invalid().into());
+        }
+        let units: i64 = units.parse().map_err(|_| invalid())?;
+        let cents: i64 = format!("{fraction:0<2}").parse().map_err(|_| invalid())?;
+        units
+            .checked_mul(100)
+            .and_then(|u| u.checked_add(cents))
+            .map(Self)
+            .ok_or_else(|| invalid().into())
+    }
+
+    /// Earnings for working `duration` at this hourly rate, rounded half up
+    /// to cents.
+    pub fn for_duration(self, duration: Duration) -> Self {
+        self.mul_div(duration.whole_seconds(), 3600)
+    }
+
+    /// This amount times a rate in hundredths of a percent, rounded half up
+    /// to cents.
+    pub fn percent(self, basis_points: i64) -> Self {
+        self.mul_div(basis_points, 10_000)
+    }
+
+    /// `self * factor / divisor`, rounded half up.
+    fn mul_div(self, factor: i64, divisor: i64) -> Self {
+        let product = i128::from(self.0) * i128::from(factor);
+        let divisor = i128::from(divisor);
+        let cents = (product * 2 + divisor).div_euclid(2 * divisor);
+        Self(cents.clamp(i64::MIN.into(), i64::MAX.into()) as i64)
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{sign}{}.{:02}", cents / 100, cents % 100)
+    }
+}
+
+impl std::ops::Add for Amount {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+}
+
+impl std::ops::AddAssign for Amount {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl std::iter::Sum for Amount {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, |a, b| a + b)
+    }
+}
+
+/// Parse an optional rate, where an empty value means no rate.
+pub fn parse_rate(value: &str) -> Result<Option<Amount>, anyhow::Error> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    let rate = Amount::parse(value)?;
+    if rate.cents() > MAX_RATE_CENTS {
+        return Err(PublicError::msg("The rate is too large").into());
+    }
+    Ok(Some(rate))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RoundingMode {
+    Up,
+    Nearest,
+    Down,
+}
+
+impl RoundingMode {
+    pub const ALL: [Self; 3] = [Self::Up, Self::Nearest, Self::Down];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Nearest => "nearest",
+            Self::Down => "down",
+        }
+    }
+}
+
+impl std::str::FromStr for RoundingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|m| m.as_str() == s)
+            .ok_or_else(|| PublicError::msg(format!("Unknown rounding mode '{s}'")).into())
+    }
+}
+
+/// Rounding of billable time per entry.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rounding {
+    /// 0 turns rounding off.
+    pub minutes: u32,
+    pub mode: RoundingMode,
+}
+
+impl Rounding {
+    /// Supported increments in minutes.
+    pub const INCREMENTS: [u32; 8] = [0, 1, 5, 6, 10, 15, 30, 60];
+
+    pub fn new(minutes: u32, mode: RoundingMode) -> Result<Self, anyhow::Error> {
+        if !Self::INCREMENTS.contains(&minutes) {
+            return Err(
+                PublicError::msg(format!("Unsupported rounding to {minutes} minutes")).into(),
+            );
+        }
+        Ok(Self { minutes, mode })
+    }
+
+    /// The rounding of a user, falling back to none if it is invalid.
+    pub fn for_user(user: &User) -> Self {
+        user.rounding_mode
+            .parse()
+            .and_then(|mode| Self::new(user.rounding_minutes, mode))
+            .unwrap_or(Self {
+                minutes: 0,
+                mode: RoundingMode::Up,
+            })
+    }
+
+    pub fn apply(&self, duration: Duration) -> Duration {
+        let step = i64::from(self.minutes) * 60;
+        if step == 0 {
+            return duration;
+        }
+        let seconds = duration.whole_seconds();
+        let steps = match self.mode {
+            RoundingMode::Up => (seconds + step - 1).div_euclid(step),
+            RoundingMode::Nearest => (seconds + step / 2).div_euclid(step),
+            RoundingMode::Down => seconds.div_euclid(step),
+        };
+        Duration::seconds(steps * step)
+    }
+
+    pub fn label(&self) -> String {
+        match self.minutes {
+            0 => "No rounding".to_string(),
+            minutes => format!("{} to {minutes} minutes", self.mode.as_str()),
+        }
+    }
+}
+
+/// Rates and rounding of a user.
+#[derive(Clone, Debug)]
+pub struct Billing {
+    pub currency: String,
+    pub rounding: Rounding,
+    pub user_rate: Option<Amount>,
+    pub project_rates: HashMap<ProjectId, Amount>,
+    pub tag_rates: HashMap<UserTagId, Amount>,
+}
+
+impl Billing {
+    pub fn for_user(db: &SupaDb, user: &User) -> Result<Self, anyhow::Error> {
+        let project_rates = user_projects(db, user, true)?
+            .into_iter()
+            .filter_map(|p| Some((p.id, Amount::from_cents(p.hourly_rate_cents?))))
+            .collect();
+        let tag_rates = user_tags(db, user)?
+            .into_iter()
+            .filter_map(|t| Some((t.id, Amount::from_cents(t.hourly_rate_cents?))))
+            .collect();
+        Ok(Self {
+            currency: user.currency.clone(),
+            rounding: Rounding::for_user(user),
+            user_rate: user.hourly_rate_cents.map(Amount::from_cents),
+            project_rates,
+            tag_rates,
+        })
+    }
+
+    /// The hourly rate for an entry with the given project and tags.
+    pub fn rate(&self, project_id: Option<ProjectId>, tags: &[UserTagId]) -> Option<Amount> {
+        tags.iter()
+            .filter_map(|id| self.tag_rates.get(id).copied())
+            .max()
+            .or_else(|| project_id.and_then(|id| self.project_rates.get(&id).copied()))
+            .or(self.user_rate)
+    }
+
+    /// Billable time and earnings for `duration` spent on an entry.
+    ///
+    /// Returns `None` if the entry is not billable. Billable entries without
+    /// any rate earn nothing.
+    pub fn entry(
+        &self,
+        log: &Timelog,
+        duration: Duration,
+        tags: &[UserTagId],
+    ) -> Option<(Duration, Amount)> {
+        if !log.billable {
+            return None;
+        }
+        let billed = self.rounding.apply(duration);
+        let amount = self
+            .rate(log.project_id, tags)
+            .map_or(Amount::ZERO, |rate| rate.for_duration(billed));
+        Some((billed, amount))
+    }
+}
+
+/// Change the default rate, currency and rounding of a user.
+pub fn user_update_billing(
+    db: &SupaDb,
+    user: &User,
+    rate: Option<Amount>,
+    currency: &str,
+    rounding: Rounding,
+) -> Result<User, anyhow::Error> {
+    let currency = currency.trim().to_ascii_uppercase();
+    if !CURRENCIES.contains(&currency.as_str()) {
+        return Err(PublicError::msg(format!("Unsupported currency '{currency}'")).into());
+    }
+
+    let patch = UserPatch {
+        hourly_rate_cents: Some(rate.map(Amount::cents)),
+        currency: Some(currency),
+        rounding_minutes: Some(rounding.minutes),
+        rounding_mode: Some(rounding.mode.as_str().to_string()),
+        ..Default::default()
+    };
+    db.user_update(user.id, patch).map_err(From::from)
+}
+
+/// Set or remove the rate of a project.
+pub fn project_set_rate(
+    db: &SupaDb,
+    user: &User,
+    id: ProjectId,
+    rate: Option<Amount>,
+) -> Result<(), anyhow::Error> {
+    let patch = ProjectPatch {
+        hourly_rate_cents: Some(rate.map(Amount::cents)),
+        ..Default::default()
+    };
+    let project = project_for_update(db, user, id)?;
+    db.project_update(ProjectFilter::Id(project.id), patch)?;
+    Ok(())
+}
+
+/// Set or remove the rate of a tag.
+pub fn tag_set_rate(
+    db: &SupaDb,
+    user: &User,
+    id: UserTagId,
+    rate: Option<Amount>,
+) -> Result<(), anyhow::Error> {
+    let patch = UserTagPatch {
+        hourly_rate_cents: Some(rate.map(Amount::cents)),
+        ..Default::default()
+    };
+    let tag = tag_for_update(db, user, id)?;
+    db.user_tag_update(UserTagFilter::Id(tag.id), patch)?;
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! Time budgets of projects and tags.
+//!
+//! A budget is an estimate of hours, either in total or per calendar week or
+//! month in the user's time zone. Consumed time is the duration of all
+//! timelogs of the user on the project or tag in the period, including
+//! running timers up to now. For shared projects and tags, that is only the
+//! user's own time.
+
+use anyhow::Context;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    db::{
+        all_timelogs,
+        client_supabase::SupaDb,
+        types::{
+            ProjectFilter, ProjectId, ProjectPatch, Timelog, User, UserTagFilter, UserTagId,
+            UserTagPatch,
+        },
+        user_active_timelogs, user_timelogs_all, user_timelogs_in_range, Db,
+    },
+    PublicError,
+};
+
+use super::{
+    billing::Amount,
+    locale::Zone,
+    projects::{project_for_update, tag_for_update, user_projects, user_tags},
+    reports::{timelog_tags, Grouping},
+};
+
+/// Share of a budget in percent from which a warning is shown.
+pub const WARNING_PERCENT: i64 = 80;
+
+/// Largest accepted budget, in hours.
+const MAX_BUDGET_HOURS: i64 = 100_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BudgetPeriod {
+    Total,
+    Week,
+    Month,
+}
+
+impl BudgetPeriod {
+    pub const ALL: [Self; 3] = [Self::Total, Self::Week, Self::Month];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Total => "total",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Total => "in total",
+            Self::Week => "per week",
+            Self::Month => "per month",
+        }
+    }
+
+    /// The instant the current period started, `None` for all time.
+    fn start(&self, zone: Zone) -> Option<OffsetDateTime> {
+        let grouping = match self {
+            Self::Total => return None,
+            Self::Week => Grouping::Week,
+            Self::Month => Grouping::Month,
+        };
+        Some(zone.start_of_day(grouping.period_start(zone.today())))
+    }
+}
+
+impl std::str::FromStr for BudgetPeriod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str() == s)
+            .ok_or_else(|| PublicError::msg(format!("Unknown budget period '{s}'")).into())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Budget {
+    pub minutes: i64,
+    pub period: BudgetPeriod,
+}
+
+impl Budget {
+    /// The budget stored with a project or tag, if any.
+    pub fn from_db(minutes: Option<i64>, period: &str) -> Option<Self> {
+        Some(Self {
+            minutes: minutes.filter(|m| *m > 0)?,
+            period: period.parse().unwrap_or(BudgetPeriod::Total),
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::minutes(self.minutes)
+    }
+
+    /// Hours like "12.5", as entered.
+    pub fn hours(&self) -> String {
+        let hours = Amount::from_cents(self.minutes * 100 / 60).to_string();
+        hours
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    }
+}
+
+/// Parse an optional budget of hours like `40` or `7.5`, where an empty value
+/// means no budget.
+pub fn parse_budget(hours: &str, period: &str) -> Result<Option<Budget>, anyhow::Error> {
+    if hours.trim().is_empty() {
+        return Ok(None);
+    }
+    let invalid = || PublicError::msg(format!("Invalid number of hours '{}'", hours.trim()));
+    // Hundredths of an hour.
+    let hundredths = Amount::parse(hours).map_err(|_| invalid())?.cents();
+    if hundredths > MAX_BUDGET_HOURS * 100 {
+        return Err(PublicError::msg("The budget is too large").into());
+    }
+    let minutes = (hundredths * 60 + 50) / 100;
+    if minutes == 0 {
+        return Err(PublicError::msg("The budget must be at least one minute").into());
+    }
+    Ok(Some(Budget {
+        minutes,
+        period: period.parse()?,
+    }))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BudgetTarget {
+    Project(ProjectId),
+    Tag(UserTagId),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum BudgetLevel {
+    Ok,
+    /// At least [`WARNING_PERCENT`] used.
+    Warning,
+    /// Used up.
+    Over,
+}
+
+/// Consumed and remaining time of a budget.
+#[derive(Clone, Debug)]
+pub struct BudgetStatus {
+    pub target: BudgetTarget,
+    pub name: String,
+    pub budget: Budget,
+    pub used: Duration,
+    /// Running timers counted in `used`.
+    pub running: i64,
+}
+
+impl BudgetStatus {
+    /// Time left, negative if the budget is overrun.
+    pub fn remaining(&self) -> Duration {
+        self.budget.duration() - self.used
+    }
+
+    pub fn percent(&self) -> i64 {
+        self.used.whole_seconds() * 100 / self.budget.duration().whole_seconds()
+    }
+
+    pub fn level(&self) -> BudgetLevel {
+        match self.percent() {
+            p if p >= 100 => BudgetLevel::Over,
+            p if p >= WARNING_PERCENT => BudgetLevel::Warning,
+            _ => BudgetLevel::Ok,
+        }
+    }
+
+    /// How long the running timers take to use up `percent` of the budget.
+    ///
+    /// Zero if already reached, `None` if no timer runs on the budget.
+    pub fn time_until(&self, percent: i64) -> Option<Duration> {
+        if self.running == 0 {
+            return None;
+        }
+        let target = self.budget.duration().whole_seconds() * percent / 100;
+        let left = (target - self.used.whole_seconds()).max(0);
+        // Round up, so the level has changed when the time has passed.
+        Some(Duration::seconds((left + self.running - 1) / self.running))
+    }
+}
+
+/// Status of all budgets of a user, projects first, each by name.
+///
+/// Archived projects are left out.
+pub fn user_budgets(
+    db: &SupaDb,
+    user: &User,
+    now: OffsetDateTime,
+) -> Result<Vec<BudgetStatus>, anyhow::Error> {
+    let mut budgets = user_projects(db, user, false)?
+        .into_iter()
+        .filter_map(|p| {
+            let budget = Budget::from_db(p.budget_minutes, &p.budget_period)?;
+            Some((BudgetTarget::Project(p.id), p.name, budget))
+        })
+        .collect::<Vec<_>>();
+    budgets.extend(user_tags(db, user)?.into_iter().filter_map(|t| {
+        let budget = Budget::from_db(t.budget_minutes, &t.budget_period)?;
+        Some((BudgetTarget::Tag(t.id), t.name, budget))
+    }));
+    if budgets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Load the timelogs of the longest period once. `None` is all time and
+    // sorts first.
+    let zone = Zone::for_user(user);
+    let starts = budgets
+        .iter()
+        .map(|(_, _, budget)| budget.period.start(zone))
+        .collect::<Vec<_>>();
+    let logs = match starts.iter().min().copied().flatten() {
+        Some(since) => {
+            let query = user_timelogs_in_range(user.id, since, now);
+            let mut logs = all_timelogs(db, query).context("Could not load timelogs")?;
+            logs.extend(all_timelogs(db, user_active_timelogs(user.id))?);
+            logs
+        }
+        None => all_timelogs(db, user_timelogs_all(user.id)).context("Could not load timelogs")?,
+    };
+    let tags = timelog_tags(db, user, &logs)?;
+
+    Ok(budgets
+        .into_iter()
+        .zip(starts)
+        .map(|((target, name, budget), since)| {
+            let matches = |log: &Timelog| match target {
+                BudgetTarget::Project(id) => log.project_id == Some(id),
+                BudgetTarget::Tag(id) => tags.of(log.id).contains(&id),
+            };
+            let mut used = Duration::ZERO;
+            let mut running = 0;
+            for log in logs.iter().filter(|log| matches(log)) {
+                let start = since.map_or(log.started_at, |s| log.started_at.max(s));
+                let end = log.finished_at().unwrap_or(now);
+                if end > start {
+                    used += end - start;
+                }
+                if log.finished_at.is_none() {
+                    running += 1;
+                }
+            }
+            BudgetStatus {
+                target,
+                name,
+                budget,
+                used,
+                running,
+            }
+        })
+        .collect())
+}
+
+/// Set or remove the budget of a project.
+pub fn project_set_budget(
+    db: &SupaDb,
+    user: &User,
+    id: ProjectId,
+    budget: Option<Budget>,
+) -> Result<(), anyhow::Error> {
+    let patch = ProjectPatch {
+        budget_minutes: Some(budget.map(|b| b.minutes)),
+        budget_period: budget.map(|b| b.period.as_str().to_string()),
+        ..Default::default()
+    };
+    let project = project_for_update(db, user, id)?;
+    db.project_update(ProjectFilter::Id(project.id), patch)?;
+    Ok(())
+}
+
+/// Set or remove the budget of a tag.
+pub fn tag_set_budget(
+    db: &SupaDb,
+    user: &User,
+    id: UserTagId,
+    budget: Option<Budget>,
+) -> Result<(), anyhow::Error> {
+    let patch = UserTagPatch {
+        budget_minutes: Some(budget.map(|b| b.minutes)),
+        budget_period: budget.map(|b| b.period.as_str().to_string()),
+        ..Default::default()
+    };
+    let tag = tag_for_update(db, user, id)?;
+    db.user_tag_update(UserTagFilter::Id(tag.id), patch)?;
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! iCalendar (RFC 5545) feed of finished timelogs.
+//!
+//! Each user can enable a feed with a secret token in the URL, so calendar
+//! apps can subscribe without a login. Resetting the token revokes old URLs.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context};
+use time::{Duration, OffsetDateTime};
+
+use crate::db::{
+    all_timelogs,
+    client_supabase::SupaDb,
+    types::{User, UserFilter, UserPatch},
+    user_timelogs_in_range, Db,
+};
+
+use super::projects::user_tags;
+
+/// How far back the feed goes.
+pub const FEED_DAYS: i64 = 180;
+
+/// Random bytes in a token, hex encoded in the URL.
+const TOKEN_BYTES: usize = 24;
+
+/// Lines longer than this many bytes are folded.
+const MAX_LINE_BYTES: usize = 75;
+
+/// Create a new feed token, replacing the previous one.
+pub fn user_calendar_token_reset(db: &SupaDb, user: &User) -> Result<User, anyhow::Error> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| anyhow!("Could not generate a calendar token: {err}"))?;
+    let token = bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+    let patch = UserPatch {
+        calendar_token: Some(Some(token)),
+        ..Default::default()
+    };
+    db.user_update(user.id, patch).map_err(From::from)
+}
+
+/// Disable the feed.
+pub fn user_calendar_token_revoke(db: &SupaDb, user: &User) -> Result<User, anyhow::Error> {
+    let patch = UserPatch {
+        calendar_token: Some(None),
+        ..Default::default()
+    };
+    db.user_update(user.id, patch).map_err(From::from)
+}
+
+/// The user a feed token belongs to.
+pub fn user_for_calendar_token(db: &SupaDb, token: &str) -> Result<Option<User>, anyhow::Error> {
+    // Tokens are hex, anything else can't match and must not reach the query.
+    if token.is_empty() || !token.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    db.user(UserFilter::CalendarToken(token.to_string()))
+        .map_err(From::from)
+}
+
+/// Render the feed of a user, with finished timelogs of the last
+/// [`FEED_DAYS`] days.
+///
+/// Times are in UTC, so calendar apps show them in their own zone.
+pub fn user_calendar(db: &SupaDb, user: &User) -> Result<String, anyhow::Error> {
+    let now = OffsetDateTime::now_utc();
+    let query = user_timelogs_in_range(user.id, now - Duration::days(FEED_DAYS), now);
+    let logs = all_timelogs(db, query).context("Could not load timelogs")?;
+
+    let tag_names = user_tags(db, user)?
+        .into_iter()
+        .map(|t| (t.id, t.name))
+        .collect::<HashMap<_, _>>();
+    let ids = logs.iter().map(|l| l.id).collect::<Vec<_>>();
+    let mut tags: HashMap<_, Vec<&str>> = HashMap::new();
+    for link in db.timelog_tags(&ids)? {
+        if let Some(name) = tag_names.get(&link.user_tag_id) {
+            tags.entry(link.timelog_id).or_default().push(name);
+        }
+    }
+
+    let mut cal = Calendar::default();
+    cal.line("BEGIN:VCALENDAR");
+    cal.line("VERSION:2.0");
+    cal.line("PRODID:-//Timely//Timely//EN");
+    cal.line("CALSCALE:GREGORIAN");
+    cal.line("METHOD:PUBLISH");
+    cal.line(&format!(
+        "X-WR-CALNAME:{}",
+        escape_text(&format!("Timely ({})", user.username))
+    ));
+    for log in &logs {
+        let Some(finished_at) = log.finished_at() else {
+            continue;
+        };
+        cal.line("BEGIN:VEVENT");
+        cal.line(&format!("UID:timelog-{}@timely", log.id));
+        cal.line(&format!("DTSTAMP:{}", format_utc(now)));
+        cal.line(&format!("DTSTART:{}", format_utc(log.started_at)));
+        cal.line(&format!("DTEND:{}", format_utc(finished_at)));
+        cal.line(&format!("SUMMARY:{}", escape_text(&log.title)));
+        if let Some(description) = log.description.as_deref().filter(|d| !d.is_empty()) {
+            cal.line(&format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        if let Some(names) = tags.get_mut(&log.id) {
+            names.sort_unstable();
+            let names = names.iter().map(|n| escape_text(n)).collect::<Vec<_>>();
+            cal.line(&format!("CATEGORIES:{}", names.join(",")));
+        }
+        cal.line("TRANSP:TRANSPARENT");
+        cal.line("END:VEVENT");
+    }
+    cal.line("END:VCALENDAR");
+    Ok(cal.0)
+}
+
+#[derive(Default)]
+struct Calendar(String);
+
+impl Calendar {
+    /// Append a content line, folded to at most 75 bytes per line.
+    fn line(&mut self, line: &str) {
+        let mut width = 0;
+        for c in line.chars() {
+            if width + c.len_utf8() > MAX_LINE_BYTES {
+                // Continuation lines start with a space, which counts.
+                self.0.push_str("\r\n ");
+                width = 1;
+            }
+            self.0.push(c);
+            width += c.len_utf8();
+        }
+        self.0.push_str("\r\n");
+    }
+}
+
+fn format_utc(t: OffsetDateTime) -> String {
+    let t = t.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        t.year(),
+        t.month() as u8,
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+/// Escape a TEXT value.
+fn escape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
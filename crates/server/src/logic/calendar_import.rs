@@ -0,0 +1,265 @@
+//! Import of calendar events from `.ics` files as finished timelogs.
+//!
+//! Recurring events are expanded within the chosen date range. Each
+//! occurrence becomes a row that can be picked for import, with the summary
+//! as title and categories as tags. Occurrences that overlap existing
+//! timelogs are flagged and not picked by default, exact matches are
+//! skipped as duplicates. All-day and cancelled events are ignored.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{
+    db::{
+        all_timelogs,
+        client_supabase::SupaDb,
+        types::{Timelog, User},
+        user_active_timelogs, user_timelogs_in_range, Db,
+    },
+    PublicError,
+};
+
+use super::{
+    ics::parse_events,
+    import::{create_timelogs, ImportEntry, MAX_IMPORT_BYTES},
+    locale::Zone,
+    reports::DateRange,
+};
+
+/// Most occurrences listed for one import.
+pub const MAX_EVENT_ROWS: usize = 2000;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "status", content = "message", rename_all = "lowercase")]
+pub enum EventStatus {
+    New,
+    /// Overlaps existing timelogs, the message names them.
+    Overlap(String),
+    Duplicate,
+    Error(String),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EventRow {
+    /// Identifies the occurrence when picking events.
+    pub key: String,
+    pub summary: String,
+    pub entry: Option<ImportEntry>,
+    #[serde(flatten)]
+    pub status: EventStatus,
+    /// Whether the occurrence is picked. In a preview these are the
+    /// suggested rows.
+    pub selected: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CalendarImportReport {
+    pub dry_run: bool,
+    /// Number of created timelogs. Always 0 for a dry run.
+    pub created: usize,
+    pub rows: Vec<EventRow>,
+}
+
+/// List the event occurrences of an `.ics` file that start within `range`,
+/// and import the `selected` ones.
+///
+/// Without a selection nothing is written and rows without conflicts are
+/// suggested. Occurrences that failed to parse or are duplicates are never
+/// imported.
+pub fn import_calendar(
+    db: &SupaDb,
+    user: &User,
+    data: &[u8],
+    range: DateRange,
+    selected: Option<&HashSet<String>>,
+) -> Result<CalendarImportReport, anyhow::Error> {
+    if data.len() > MAX_IMPORT_BYTES {
+        return Err(PublicError::msg(format!(
+            "The file is too large, at most {} MB are supported",
+            MAX_IMPORT_BYTES / 1024 / 1024
+        ))
+        .into());
+    }
+    let text = std::str::from_utf8(data)
+        .map_err(|_| PublicError::msg("The file must be UTF-8 encoded"))?;
+
+    let zone = Zone::for_user(user);
+    let mut rows = event_rows(text, zone, range)?;
+    mark_conflicts(db, user, &mut rows)?;
+
+    for row in &mut rows {
+        let importable = matches!(row.status, EventStatus::New | EventStatus::Overlap(_));
+        row.selected = importable
+            && match selected {
+                Some(keys) => keys.contains(&row.key),
+                None => matches!(row.status, EventStatus::New),
+            };
+    }
+
+    let mut created = 0;
+    if selected.is_some() {
+        let entries = rows
+            .iter()
+            .filter(|r| r.selected)
+            .filter_map(|r| r.entry.as_ref())
+            .collect::<Vec<_>>();
+        created = create_timelogs(db, user, &entries)?;
+    }
+
+    Ok(CalendarImportReport {
+        dry_run: selected.is_none(),
+        created,
+        rows,
+    })
+}
+
+/// One row per occurrence in the range, sorted by start, and one per
+/// invalid event.
+fn event_rows(text: &str, zone: Zone, range: DateRange) -> Result<Vec<EventRow>, anyhow::Error> {
+    let events = parse_events(text, zone)?;
+
+    // Occurrences of recurring events that are replaced by a separate event.
+    let mut overridden: HashMap<&str, Vec<OffsetDateTime>> = HashMap::new();
+    for event in events.iter().flatten() {
+        if let (Some(uid), Some(id)) = (&event.uid, event.recurrence_id) {
+            overridden.entry(uid).or_default().push(id);
+        }
+    }
+
+    let (from, until) = (range.start(zone), range.end(zone));
+    let mut rows = Vec::new();
+    let mut invalid = Vec::new();
+    for (index, event) in events.iter().enumerate() {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                invalid.push(EventRow {
+                    key: format!("invalid-{index}"),
+                    summary: err.summary.clone(),
+                    entry: None,
+                    status: EventStatus::Error(err.message.clone()),
+                    selected: false,
+                });
+                continue;
+            }
+        };
+        if event.cancelled || event.all_day {
+            continue;
+        }
+
+        let uid = event
+            .uid
+            .clone()
+            .unwrap_or_else(|| format!("event-{index}"));
+        let overridden = match event.recurrence_id {
+            Some(_) => &[][..],
+            None => overridden.get(uid.as_str()).map_or(&[][..], Vec::as_slice),
+        };
+        let starts = match event.occurrences(from, until, overridden) {
+            Ok(starts) => starts,
+            Err(message) => {
+                invalid.push(EventRow {
+                    key: format!("invalid-{index}"),
+                    summary: event.summary.clone(),
+                    entry: None,
+                    status: EventStatus::Error(message),
+                    selected: false,
+                });
+                continue;
+            }
+        };
+        for start in starts {
+            let entry = match start.checked_add(event.duration) {
+                _ if event.duration.is_zero() => Err("Event has no duration".to_string()),
+                Some(end) => ImportEntry::new(
+                    &event.summary,
+                    event.description.as_deref(),
+                    start,
+                    end,
+                    event.categories.iter().map(String::as_str),
+                ),
+                None => Err("Event ends after the supported dates".to_string()),
+            };
+            let (entry, status) = match entry {
+                Ok(entry) => (Some(entry), EventStatus::New),
+                Err(message) => (None, EventStatus::Error(message)),
+            };
+            rows.push((
+                start,
+                EventRow {
+                    key: format!("{uid}/{}", start.unix_timestamp()),
+                    summary: event.summary.clone(),
+                    entry,
+                    status,
+                    selected: false,
+                },
+            ));
+        }
+        if rows.len() > MAX_EVENT_ROWS {
+            return Err(PublicError::msg(format!(
+                "More than {MAX_EVENT_ROWS} events in this range, please choose a shorter one"
+            ))
+            .into());
+        }
+    }
+
+    rows.sort_by_key(|(start, _)| *start);
+    Ok(rows
+        .into_iter()
+        .map(|(_, row)| row)
+        .chain(invalid)
+        .collect())
+}
+
+/// Flag rows that overlap or duplicate existing timelogs, or duplicate an
+/// earlier row.
+fn mark_conflicts(db: &SupaDb, user: &User, rows: &mut [EventRow]) -> Result<(), anyhow::Error> {
+    let entries = rows.iter().filter_map(|r| r.entry.as_ref());
+    let (Some(from), Some(until)) = (
+        entries.clone().map(|e| e.started_at).min(),
+        entries.map(|e| e.finished_at).max(),
+    ) else {
+        return Ok(());
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let mut existing = all_timelogs(db, user_timelogs_in_range(user.id, from, until))
+        .context("Could not load existing timelogs")?;
+    existing.extend(
+        db.timelogs(user_active_timelogs(user.id))?
+            .into_iter()
+            .filter(|log| log.started_at < until),
+    );
+    // Running timelogs count as ending now.
+    let end_of = |log: &Timelog| log.finished_at().unwrap_or(now);
+
+    let mut seen = HashSet::new();
+    for row in rows.iter_mut() {
+        let Some(entry) = &row.entry else {
+            continue;
+        };
+        let key = (entry.title.clone(), entry.started_at, entry.finished_at);
+        let duplicate = existing.iter().any(|log| {
+            log.title == entry.title
+                && log.started_at == entry.started_at
+                && log.finished_at() == Some(entry.finished_at)
+        });
+        if duplicate || !seen.insert(key) {
+            row.status = EventStatus::Duplicate;
+            continue;
+        }
+
+        let overlapping = existing
+            .iter()
+            .filter(|log| log.started_at < entry.finished_at && entry.started_at < end_of(log))
+            .map(|log| format!("'{}'", log.title))
+            .collect::<Vec<_>>();
+        if !overlapping.is_empty() {
+            row.status = EventStatus::Overlap(format!("Overlaps {}", overlapping.join(", ")));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! CSV export of timelogs.
+
+use std::{collections::HashMap, io::Write};
+
+use anyhow::Context;
+use time::format_description::well_known::Rfc3339;
+
+use crate::db::{client_supabase::SupaDb, types::User, user_timelogs_started_in, Db};
+
+use super::{locale::Zone, projects::user_tags, reports::DateRange};
+
+pub const CSV_HEADER: [&str; 8] = [
+    "id",
+    "title",
+    "description",
+    "tags",
+    "start",
+    "end",
+    "duration",
+    "timezone",
+];
+
+/// Write the timelogs of a user that started within `range` as CSV.
+///
+/// Follows RFC 4180: fields are quoted when needed and lines end with CRLF.
+/// Times are RFC 3339 in the user's zone, durations are whole seconds, and
+/// tags are separated by `;`. Running timelogs have an empty end and
+/// duration.
+///
+/// Timelogs are loaded and written one page at a time, so only a single page
+/// is held in memory. Returns the number of exported timelogs.
+pub fn write_timelogs_csv<W: Write>(
+    db: &SupaDb,
+    user: &User,
+    range: DateRange,
+    out: W,
+) -> Result<u64, anyhow::Error> {
+    let zone = Zone::for_user(user);
+    let tag_names = user_tags(db, user)?
+        .into_iter()
+        .map(|t| (t.id, t.name))
+        .collect::<HashMap<_, _>>();
+
+    let mut writer = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(out);
+    writer.write_record(CSV_HEADER)?;
+
+    let mut query = user_timelogs_started_in(user.id, range.start(zone), range.end(zone));
+    let mut count = 0;
+    loop {
+        let page = db
+            .timelogs(query.clone())
+            .context("Could not load timelogs")?;
+
+        let ids = page.iter().map(|l| l.id).collect::<Vec<_>>();
+        let mut tags: HashMap<_, Vec<&str>> = HashMap::new();
+        for link in db.timelog_tags(&ids)? {
+            if let Some(name) = tag_names.get(&link.user_tag_id) {
+                tags.entry(link.timelog_id).or_default().push(name);
+            }
+        }
+
+        for log in &page {
+            let finished_at = log.finished_at();
+            let end = finished_at
+                .map(|t| zone.to_local(t).format(&Rfc3339))
+                .transpose()?
+                .unwrap_or_default();
+            let duration = finished_at
+                .map(|t| (t - log.started_at).whole_seconds().to_string())
+                .unwrap_or_default();
+            let mut log_tags = tags.remove(&log.id).unwrap_or_default();
+            log_tags.sort_unstable();
+
+            writer.write_record([
+                log.id.to_string().as_str(),
+                &log.title,
+                log.description.as_deref().unwrap_or_default(),
+                &log_tags.join(";"),
+                &zone.to_local(log.started_at).format(&Rfc3339)?,
+                &end,
+                &duration,
+                zone.name(),
+            ])?;
+        }
+        writer.flush()?;
+        count += page.len() as u64;
+
+        if (page.len() as u64) < query.limit {
+            return Ok(count);
+        }
+        query.offset += query.limit;
+    }
+}
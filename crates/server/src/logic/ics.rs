@@ -0,0 +1,856 @@
+//! Reading events from iCalendar (RFC 5545) files.
+//!
+//! Supports what calendar apps commonly export: timed events with a `TZID`,
+//! UTC or floating times, `DTEND` or `DURATION`, and `RRULE` recurrences
+//! with `EXDATE` exclusions and `RECURRENCE-ID` overrides. `VTIMEZONE`
+//! definitions are ignored, zone ids are looked up in the IANA database.
+
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, Weekday};
+
+use crate::PublicError;
+
+use super::locale::Zone;
+
+/// Expansion of a single rule stops after this many periods.
+const MAX_PERIODS: i64 = 50_000;
+
+/// A `VEVENT`.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub uid: Option<String>,
+    pub summary: String,
+    pub description: Option<String>,
+    pub categories: Vec<String>,
+    /// Start in the local time of `zone`.
+    start: PrimitiveDateTime,
+    zone: Zone,
+    pub all_day: bool,
+    pub duration: Duration,
+    rule: Option<Rule>,
+    exdates: Vec<OffsetDateTime>,
+    /// Set on events that replace one occurrence of a recurring event.
+    pub recurrence_id: Option<OffsetDateTime>,
+    pub cancelled: bool,
+}
+
+/// A `VEVENT` that could not be read.
+#[derive(Clone, Debug)]
+pub struct InvalidEvent {
+    pub summary: String,
+    pub message: String,
+}
+
+impl Event {
+    /// Start times of the occurrences that start within `from..until`.
+    ///
+    /// `overridden` are the `RECURRENCE-ID`s of separately defined
+    /// occurrences, which are left out. Fails if the rule reaches past the
+    /// supported dates before `until`.
+    pub fn occurrences(
+        &self,
+        from: OffsetDateTime,
+        until: OffsetDateTime,
+        overridden: &[OffsetDateTime],
+    ) -> Result<Vec<OffsetDateTime>, String> {
+        let Some(rule) = &self.rule else {
+            return Ok(self
+                .zone
+                .resolve_local(self.start)
+                .filter(|t| *t >= from && *t < until)
+                .into_iter()
+                .collect());
+        };
+
+        let mut starts = Vec::new();
+        let mut count = 0;
+        let mut period = 0;
+        'periods: while period < MAX_PERIODS && !matches!(rule.count, Some(c) if count >= c) {
+            let dates = rule
+                .period_dates(self.start.date(), period)
+                .ok_or("Recurrence rule reaches past the supported dates")?;
+            for date in dates {
+                let local = PrimitiveDateTime::new(date, self.start.time());
+                if local < self.start {
+                    continue;
+                }
+                // Occurrences in a DST gap don't exist.
+                let Some(start) = self.zone.resolve_local(local) else {
+                    continue;
+                };
+                if rule.until.is_some_and(|u| start > u) || start >= until {
+                    break 'periods;
+                }
+                count += 1;
+                if rule.count.is_some_and(|c| count > c) {
+                    break 'periods;
+                }
+                if start >= from && !self.exdates.contains(&start) && !overridden.contains(&start) {
+                    starts.push(start);
+                }
+            }
+            period += 1;
+        }
+        Ok(starts)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A parsed `RRULE`.
+#[derive(Clone, Debug)]
+struct Rule {
+    frequency: Frequency,
+    interval: i64,
+    count: Option<u32>,
+    until: Option<OffsetDateTime>,
+    /// Weekdays, with an optional position within the month, like `-1FR`.
+    by_day: Vec<(Option<i8>, Weekday)>,
+    by_month_day: Vec<i8>,
+}
+
+impl Rule {
+    fn parse(value: &str, zone: Zone) -> Result<Self, String> {
+        let mut frequency = None;
+        let mut rule = Self {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+        };
+        for part in value.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid recurrence rule part '{part}'"))?;
+            let invalid = || format!("Invalid recurrence rule value {key}={value}");
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("Unsupported recurrence frequency '{value}'")),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value.parse().ok().filter(|i| *i > 0).ok_or_else(invalid)?
+                }
+                "COUNT" => rule.count = Some(value.parse().map_err(|_| invalid())?),
+                "UNTIL" => {
+                    rule.until = Some(match parse_date_time(value, zone)? {
+                        DateTimeValue::Date(date) => {
+                            zone.start_of_day(date.next_day().ok_or_else(invalid)?)
+                                - Duration::SECOND
+                        }
+                        DateTimeValue::DateTime(t, zone) => {
+                            zone.resolve_local(t).ok_or_else(invalid)?
+                        }
+                    })
+                }
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(parse_weekday_num)
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = value
+                        .split(',')
+                        .map(|d| d.parse().ok().filter(|d: &i8| *d != 0 && d.abs() <= 31))
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?
+                }
+                // Only affects weekly rules with an interval and BYDAY, rare
+                // enough to ignore.
+                "WKST" => {}
+                _ => return Err(format!("Unsupported recurrence rule part '{key}'")),
+            }
+        }
+        rule.frequency = frequency.ok_or("Recurrence rule without FREQ")?;
+
+        let has_positions = rule.by_day.iter().any(|(pos, _)| pos.is_some());
+        let supported = match rule.frequency {
+            Frequency::Daily | Frequency::Weekly => !has_positions && rule.by_month_day.is_empty(),
+            Frequency::Monthly => rule.by_day.is_empty() || rule.by_month_day.is_empty(),
+            Frequency::Yearly => rule.by_day.is_empty() && rule.by_month_day.is_empty(),
+        };
+        if !supported {
+            return Err(format!("Unsupported recurrence rule '{value}'"));
+        }
+        Ok(rule)
+    }
+
+    /// Candidate dates of the `index`th period after the one containing
+    /// `start`, in order. `None` if the period is out of the supported
+    /// range of dates.
+    fn period_dates(&self, start: Date, index: i64) -> Option<Vec<Date>> {
+        let step = index.checked_mul(self.interval)?;
+        let dates = match self.frequency {
+            Frequency::Daily => {
+                let date = start.checked_add(days(step)?)?;
+                if self.by_day.is_empty() || self.by_day.iter().any(|(_, d)| *d == date.weekday()) {
+                    vec![date]
+                } else {
+                    Vec::new()
+                }
+            }
+            Frequency::Weekly => {
+                let monday = start
+                    .checked_sub(Duration::days(
+                        start.weekday().number_days_from_monday().into(),
+                    ))?
+                    .checked_add(days(step.checked_mul(7)?)?)?;
+                let mut days = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|(_, d)| *d).collect()
+                };
+                days.sort_by_key(|d| d.number_days_from_monday());
+                days.dedup();
+                days.into_iter()
+                    .map(|d| monday.checked_add(Duration::days(d.number_days_from_monday().into())))
+                    .collect::<Option<_>>()?
+            }
+            Frequency::Monthly => {
+                let months = (i64::from(start.year()) * 12 + i64::from(start.month() as u8 - 1))
+                    .checked_add(step)?;
+                let year = i32::try_from(months.div_euclid(12)).ok()?;
+                let month = Month::try_from((months.rem_euclid(12) + 1) as u8).ok()?;
+                // Checks that the year is supported.
+                Date::from_calendar_date(year, month, 1).ok()?;
+                let mut dates = self.month_dates(year, month, start.day());
+                dates.sort();
+                dates.dedup();
+                dates
+            }
+            Frequency::Yearly => {
+                let year = i32::try_from(i64::from(start.year()).checked_add(step)?).ok()?;
+                Date::from_calendar_date(year, Month::January, 1).ok()?;
+                // Skipped in years without the day, like Feb 29.
+                Date::from_calendar_date(year, start.month(), start.day())
+                    .ok()
+                    .into_iter()
+                    .collect()
+            }
+        };
+        Some(dates)
+    }
+
+    fn month_dates(&self, year: i32, month: Month, start_day: u8) -> Vec<Date> {
+        let days_in_month = days_in_month(year, month);
+        let date = |day: i16| {
+            let day = if day < 0 {
+                i16::from(days_in_month) + 1 + day
+            } else {
+                day
+            };
+            u8::try_from(day)
+                .ok()
+                .and_then(|day| Date::from_calendar_date(year, month, day).ok())
+        };
+
+        if !self.by_month_day.is_empty() {
+            return self
+                .by_month_day
+                .iter()
+                .filter_map(|d| date((*d).into()))
+                .collect();
+        }
+        if self.by_day.is_empty() {
+            return date(start_day.into()).into_iter().collect();
+        }
+
+        let mut dates = Vec::new();
+        for (position, weekday) in &self.by_day {
+            let matching = (1..=days_in_month)
+                .filter_map(|d| date(d.into()))
+                .filter(|d| d.weekday() == *weekday)
+                .collect::<Vec<_>>();
+            match position {
+                None => dates.extend(matching),
+                Some(pos) if *pos > 0 => dates.extend(matching.get(*pos as usize - 1)),
+                Some(pos) => {
+                    let back = pos.unsigned_abs() as usize;
+                    dates.extend(matching.len().checked_sub(back).map(|i| matching[i]))
+                }
+            }
+        }
+        dates
+    }
+}
+
+/// `n` days, `None` on overflow.
+fn days(n: i64) -> Option<Duration> {
+    n.checked_mul(86_400).map(Duration::seconds)
+}
+
+fn days_in_month(year: i32, month: Month) -> u8 {
+    (28..=31)
+        .rev()
+        .find(|day| Date::from_calendar_date(year, month, *day).is_ok())
+        .unwrap_or(28)
+}
+
+/// Parse `MO`, `2TU` or `-1FR`.
+fn parse_weekday_num(value: &str) -> Option<(Option<i8>, Weekday)> {
+    let value = value.trim();
+    let split = value.len().checked_sub(2)?;
+    let (position, day) = value.split_at(split);
+    let weekday = match day.to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Monday,
+        "TU" => Weekday::Tuesday,
+        "WE" => Weekday::Wednesday,
+        "TH" => Weekday::Thursday,
+        "FR" => Weekday::Friday,
+        "SA" => Weekday::Saturday,
+        "SU" => Weekday::Sunday,
+        _ => return None,
+    };
+    let position = match position {
+        "" => None,
+        p => Some(
+            p.trim_start_matches('+')
+                .parse()
+                .ok()
+                .filter(|p: &i8| *p != 0)?,
+        ),
+    };
+    Some((position, weekday))
+}
+
+/// A content line: name, parameters and value.
+struct Property<'a> {
+    name: String,
+    params: Vec<(String, &'a str)>,
+    value: &'a str,
+}
+
+impl<'a> Property<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        // The value starts at the first colon outside of quoted parameters.
+        let mut quoted = false;
+        let colon = line.char_indices().find_map(|(i, c)| match c {
+            '"' => {
+                quoted = !quoted;
+                None
+            }
+            ':' if !quoted => Some(i),
+            _ => None,
+        })?;
+        let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+        let mut parts = head.split(';');
+        let name = parts.next()?.trim().to_ascii_uppercase();
+        let params = parts
+            .filter_map(|p| p.split_once('='))
+            .map(|(k, v)| (k.trim().to_ascii_uppercase(), v.trim().trim_matches('"')))
+            .collect();
+        Some(Self {
+            name,
+            params,
+            value,
+        })
+    }
+
+    fn param(&self, name: &str) -> Option<&'a str> {
+        self.params.iter().find(|(k, _)| k == name).map(|(_, v)| *v)
+    }
+}
+
+/// Parse all events of a calendar file.
+///
+/// Floating times, without a zone, are read in `default_zone`.
+pub fn parse_events(
+    text: &str,
+    default_zone: Zone,
+) -> Result<Vec<Result<Event, InvalidEvent>>, anyhow::Error> {
+    let text = text
+        .replace("\r\n ", "")
+        .replace("\r\n\t", "")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+    let mut lines = text.lines().map(|l| l.trim_end_matches('\r'));
+    if !lines
+        .by_ref()
+        .find(|l| !l.trim().is_empty())
+        .is_some_and(|l| l.trim().eq_ignore_ascii_case("BEGIN:VCALENDAR"))
+    {
+        return Err(PublicError::msg("Unsupported file: expected an iCalendar (.ics) file").into());
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<Vec<Property>> = None;
+    // Components nested in an event, like alarms, are skipped.
+    let mut nested = 0;
+    for line in lines {
+        let Some(prop) = Property::parse(line) else {
+            continue;
+        };
+        match (prop.name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if prop.value.eq_ignore_ascii_case("VEVENT") => {
+                current = Some(Vec::new())
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) if prop.value.eq_ignore_ascii_case("VEVENT") => {
+                let props = current.take().unwrap_or_default();
+                events.push(build_event(&props, default_zone));
+            }
+            (_, Some(props)) if nested == 0 => props.push(prop),
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+fn build_event(props: &[Property], default_zone: Zone) -> Result<Event, InvalidEvent> {
+    let get = |name: &str| props.iter().find(|p| p.name == name);
+    let summary = get("SUMMARY")
+        .map(|p| unescape_text(p.value))
+        .unwrap_or_default();
+    let invalid = |message: String| InvalidEvent {
+        summary: summary.clone(),
+        message,
+    };
+
+    let dtstart = get("DTSTART").ok_or_else(|| invalid("Event without start".to_string()))?;
+    let (start, zone, all_day) =
+        match parse_property_time(dtstart, default_zone).map_err(invalid)? {
+            DateTimeValue::Date(date) => (
+                PrimitiveDateTime::new(date, Time::MIDNIGHT),
+                default_zone,
+                true,
+            ),
+            DateTimeValue::DateTime(t, zone) => (t, zone, false),
+        };
+
+    let duration = if let Some(dtend) = get("DTEND") {
+        let end = match parse_property_time(dtend, default_zone).map_err(invalid)? {
+            DateTimeValue::Date(date) => {
+                zone.resolve_local(PrimitiveDateTime::new(date, Time::MIDNIGHT))
+            }
+            DateTimeValue::DateTime(t, zone) => zone.resolve_local(t),
+        };
+        let start = zone.resolve_local(start);
+        match (start, end) {
+            (Some(start), Some(end)) => end - start,
+            _ => {
+                return Err(invalid(
+                    "Start or end does not exist in its time zone".to_string(),
+                ))
+            }
+        }
+    } else if let Some(duration) = get("DURATION") {
+        parse_duration(duration.value)
+            .ok_or_else(|| invalid(format!("Invalid duration '{}'", duration.value)))?
+    } else if all_day {
+        Duration::DAY
+    } else {
+        Duration::ZERO
+    };
+    if duration.is_negative() {
+        return Err(invalid("End is before start".to_string()));
+    }
+
+    let rule = get("RRULE")
+        .map(|p| Rule::parse(p.value, zone))
+        .transpose()
+        .map_err(invalid)?;
+
+    let mut exdates = Vec::new();
+    for prop in props.iter().filter(|p| p.name == "EXDATE") {
+        for value in prop.value.split(',') {
+            let exdate =
+                match parse_date_time_with(value, prop.param("TZID"), prop.param("VALUE"), zone)
+                    .map_err(invalid)?
+                {
+                    DateTimeValue::Date(date) => {
+                        zone.resolve_local(PrimitiveDateTime::new(date, start.time()))
+                    }
+                    DateTimeValue::DateTime(t, zone) => zone.resolve_local(t),
+                };
+            exdates.extend(exdate);
+        }
+    }
+
+    let recurrence_id = get("RECURRENCE-ID")
+        .map(|p| match parse_property_time(p, zone)? {
+            DateTimeValue::Date(date) => {
+                Ok(zone.resolve_local(PrimitiveDateTime::new(date, start.time())))
+            }
+            DateTimeValue::DateTime(t, zone) => Ok(zone.resolve_local(t)),
+        })
+        .transpose()
+        .map_err(invalid)?
+        .flatten();
+
+    Ok(Event {
+        uid: get("UID").map(|p| p.value.trim().to_string()),
+        description: get("DESCRIPTION").map(|p| unescape_text(p.value)),
+        categories: props
+            .iter()
+            .filter(|p| p.name == "CATEGORIES")
+            .flat_map(|p| split_text_list(p.value))
+            .collect(),
+        cancelled: get("STATUS").is_some_and(|p| p.value.trim().eq_ignore_ascii_case("CANCELLED")),
+        summary,
+        start,
+        zone,
+        all_day,
+        duration,
+        rule,
+        exdates,
+        recurrence_id,
+    })
+}
+
+enum DateTimeValue {
+    Date(Date),
+    DateTime(PrimitiveDateTime, Zone),
+}
+
+fn parse_property_time(prop: &Property, default_zone: Zone) -> Result<DateTimeValue, String> {
+    parse_date_time_with(
+        prop.value,
+        prop.param("TZID"),
+        prop.param("VALUE"),
+        default_zone,
+    )
+}
+
+fn parse_date_time_with(
+    value: &str,
+    tzid: Option<&str>,
+    value_type: Option<&str>,
+    default_zone: Zone,
+) -> Result<DateTimeValue, String> {
+    let zone = match tzid {
+        Some(tzid) => lookup_zone(tzid).ok_or_else(|| format!("Unknown time zone '{tzid}'"))?,
+        None => default_zone,
+    };
+    match parse_date_time(value, zone)? {
+        DateTimeValue::DateTime(..)
+            if value_type.is_some_and(|v| v.eq_ignore_ascii_case("DATE")) =>
+        {
+            Err(format!("Invalid date '{value}'"))
+        }
+        v => Ok(v),
+    }
+}
+
+/// Find an IANA zone, also for ids with a vendor prefix like
+/// `/mozilla.org/20050126_1/Europe/Berlin`.
+fn lookup_zone(tzid: &str) -> Option<Zone> {
+    let tzid = tzid.trim();
+    std::iter::once(tzid)
+        .chain(tzid.match_indices('/').map(|(i, _)| &tzid[i + 1..]))
+        .find_map(|name| Zone::from_name(name).ok())
+}
+
+/// Parse `YYYYMMDD`, `YYYYMMDDTHHMMSS` or `YYYYMMDDTHHMMSSZ`.
+fn parse_date_time(value: &str, zone: Zone) -> Result<DateTimeValue, String> {
+    let value = value.trim();
+    let invalid = || format!("Invalid date or time '{value}'");
+    let number = |range: std::ops::Range<usize>| -> Result<u16, String> {
+        value
+            .get(range)
+            .filter(|v| v.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(invalid)
+    };
+
+    let month = Month::try_from(number(4..6)? as u8).map_err(|_| invalid())?;
+    let date = Date::from_calendar_date(number(0..4)?.into(), month, number(6..8)? as u8)
+        .map_err(|_| invalid())?;
+    match value.len() {
+        8 => Ok(DateTimeValue::Date(date)),
+        15 | 16 if value.as_bytes()[8] == b'T' => {
+            let time = Time::from_hms(
+                number(9..11)? as u8,
+                number(11..13)? as u8,
+                number(13..15)? as u8,
+            )
+            .map_err(|_| invalid())?;
+            let zone = match value.as_bytes().get(15) {
+                Some(b'Z' | b'z') => Zone::utc(),
+                Some(_) => return Err(invalid()),
+                None => zone,
+            };
+            Ok(DateTimeValue::DateTime(
+                PrimitiveDateTime::new(date, time),
+                zone,
+            ))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse a duration like `PT1H30M`, `P1D` or `P2W`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (negative, value) = match value.as_bytes().first()? {
+        b'-' => (true, &value[1..]),
+        b'+' => (false, &value[1..]),
+        _ => (false, value),
+    };
+    let mut rest = value.strip_prefix(['P', 'p'])?;
+    let mut total = Duration::ZERO;
+    let mut in_time = false;
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix(['T', 't']) {
+            in_time = true;
+            rest = r;
+            continue;
+        }
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let n: i64 = rest[..digits].parse().ok()?;
+        let unit = rest[digits..].chars().next()?.to_ascii_uppercase();
+        let seconds = match (unit, in_time) {
+            ('W', false) => 7 * 86_400,
+            ('D', false) => 86_400,
+            ('H', true) => 3_600,
+            ('M', true) => 60,
+            ('S', true) => 1,
+            _ => return None,
+        };
+        total = total.checked_add(Duration::seconds(n.checked_mul(seconds)?))?;
+        rest = &rest[digits + 1..];
+    }
+    Some(if negative { -total } else { total })
+}
+
+/// Split a comma separated TEXT list, like `CATEGORIES`.
+fn split_text_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => items.last_mut().expect("not empty").push('\n'),
+                Some(c) => items.last_mut().expect("not empty").push(c),
+                None => {}
+            },
+            ',' => items.push(String::new()),
+            c => items.last_mut().expect("not empty").push(c),
+        }
+    }
+    items
+        .into_iter()
+        .map(|i| i.trim().to_string())
+        .filter(|i| !i.is_empty())
+        .collect()
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use time::format_description::well_known::Rfc3339;
+
+    use super::*;
+
+    fn utc(value: &str) -> OffsetDateTime {
+        OffsetDateTime::parse(value, &Rfc3339).unwrap()
+    }
+
+    fn calendar(events: &[&str]) -> String {
+        let mut text = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n".to_string();
+        for event in events {
+            text += &format!("BEGIN:VEVENT\r\n{}\r\nEND:VEVENT\r\n", event.trim());
+        }
+        text + "END:VCALENDAR\r\n"
+    }
+
+    fn event(lines: &str) -> Event {
+        parse_events(&calendar(&[lines]), Zone::utc())
+            .unwrap()
+            .remove(0)
+            .unwrap()
+    }
+
+    /// Occurrences in 2024.
+    fn occurrences(event: &Event, overridden: &[OffsetDateTime]) -> Vec<OffsetDateTime> {
+        event
+            .occurrences(
+                utc("2024-01-01T00:00:00Z"),
+                utc("2025-01-01T00:00:00Z"),
+                overridden,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn daily_with_count() {
+        let event = event(
+            "SUMMARY:Standup\r\nDTSTART:20240101T090000Z\r\nDURATION:PT15M\r\n\
+             RRULE:FREQ=DAILY;COUNT=3",
+        );
+        assert_eq!(event.duration, Duration::minutes(15));
+        assert_eq!(
+            occurrences(&event, &[]),
+            [
+                utc("2024-01-01T09:00:00Z"),
+                utc("2024-01-02T09:00:00Z"),
+                utc("2024-01-03T09:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn weekly_by_day_with_interval_and_until() {
+        let event = event(
+            "DTSTART:20240101T090000Z\r\nDTEND:20240101T100000Z\r\n\
+             RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=WE,MO;UNTIL=20240117T235959Z",
+        );
+        assert_eq!(event.duration, Duration::HOUR);
+        assert_eq!(
+            occurrences(&event, &[]),
+            [
+                utc("2024-01-01T09:00:00Z"),
+                utc("2024-01-03T09:00:00Z"),
+                utc("2024-01-15T09:00:00Z"),
+                utc("2024-01-17T09:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_last_friday() {
+        let event = event("DTSTART:20240126T100000Z\r\nRRULE:FREQ=MONTHLY;BYDAY=-1FR;COUNT=3");
+        assert_eq!(
+            occurrences(&event, &[]),
+            [
+                utc("2024-01-26T10:00:00Z"),
+                utc("2024-02-23T10:00:00Z"),
+                utc("2024-03-29T10:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_skips_missing_days() {
+        let event = event("DTSTART:20240131T100000Z\r\nRRULE:FREQ=MONTHLY;COUNT=3");
+        assert_eq!(
+            occurrences(&event, &[]),
+            [
+                utc("2024-01-31T10:00:00Z"),
+                utc("2024-03-31T10:00:00Z"),
+                utc("2024-05-31T10:00:00Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_time_is_kept_across_dst() {
+        let event = event("DTSTART;TZID=Europe/Berlin:20240330T090000\r\nRRULE:FREQ=DAILY;COUNT=2");
+        assert_eq!(
+            occurrences(&event, &[]),
+            [utc("2024-03-30T08:00:00Z"), utc("2024-03-31T07:00:00Z")]
+        );
+    }
+
+    #[test]
+    fn exdates_are_left_out() {
+        let event = event(
+            "DTSTART:20240101T090000Z\r\nRRULE:FREQ=DAILY;COUNT=4\r\n\
+             EXDATE:20240102T090000Z,20240103T090000Z",
+        );
+        assert_eq!(
+            occurrences(&event, &[]),
+            [utc("2024-01-01T09:00:00Z"), utc("2024-01-04T09:00:00Z")]
+        );
+    }
+
+    #[test]
+    fn overridden_occurrences_are_left_out() {
+        let text = calendar(&[
+            "UID:abc\r\nSUMMARY:Sync\r\nDTSTART:20240101T090000Z\r\nRRULE:FREQ=DAILY;COUNT=3",
+            "UID:abc\r\nSUMMARY:Sync (moved)\r\nRECURRENCE-ID:20240102T090000Z\r\n\
+             DTSTART:20240102T140000Z",
+        ]);
+        let events = parse_events(&text, Zone::utc())
+            .unwrap()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let moved = &events[1];
+        assert_eq!(moved.recurrence_id, Some(utc("2024-01-02T09:00:00Z")));
+        assert_eq!(occurrences(moved, &[]), [utc("2024-01-02T14:00:00Z")]);
+
+        let overridden = [moved.recurrence_id.unwrap()];
+        assert_eq!(
+            occurrences(&events[0], &overridden),
+            [utc("2024-01-01T09:00:00Z"), utc("2024-01-03T09:00:00Z")]
+        );
+    }
+
+    #[test]
+    fn huge_intervals_are_an_error() {
+        for freq in ["DAILY", "WEEKLY", "MONTHLY", "YEARLY"] {
+            for interval in ["10000000", "9223372036854775807"] {
+                let event = event(&format!(
+                    "DTSTART:20240101T090000Z\r\nRRULE:FREQ={freq};INTERVAL={interval}"
+                ));
+                let res = event.occurrences(
+                    utc("2024-01-01T00:00:00Z"),
+                    utc("2025-01-01T00:00:00Z"),
+                    &[],
+                );
+                assert!(res.is_err(), "{freq} {interval}: {res:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn huge_interval_with_count() {
+        let event = event("DTSTART:20240101T090000Z\r\nRRULE:FREQ=DAILY;INTERVAL=10000000;COUNT=1");
+        assert_eq!(occurrences(&event, &[]), [utc("2024-01-01T09:00:00Z")]);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1W2D"), Some(Duration::days(9)));
+        assert_eq!(parse_duration("-PT5S"), Some(Duration::seconds(-5)));
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("P99999999999999W"), None);
+        assert_eq!(parse_duration("P15250284452471W15250284452471W"), None);
+    }
+
+    #[test]
+    fn huge_duration_is_an_invalid_event() {
+        let events = parse_events(
+            &calendar(&[
+                "SUMMARY:Forever\r\nDTSTART:20240101T090000Z\r\nDURATION:P99999999999999W",
+            ]),
+            Zone::utc(),
+        )
+        .unwrap();
+        let err = events[0].as_ref().unwrap_err();
+        assert_eq!(err.summary, "Forever");
+    }
+}
@@ -0,0 +1,501 @@
+//! Import of timelogs from Toggl and Clockify CSV exports.
+//!
+//! Both tools export one entry per row with a description, project, tags and
+//! local start and end times. Descriptions become titles, projects and tags
+//! become user tags. Rows that fail to parse are reported and skipped, and
+//! rows matching an existing timelog (same title, start and end) are
+//! skipped as duplicates.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use serde::Serialize;
+use time::{
+    format_description::well_known::Rfc3339, Date, Month, OffsetDateTime, PrimitiveDateTime, Time,
+};
+
+use crate::{
+    db::{
+        all_timelogs,
+        client_supabase::SupaDb,
+        types::{TimelogCreate, TimelogUserTag, User, UserTagCreate, UserTagQuery},
+        user_timelogs_in_range, Db,
+    },
+    PublicError,
+};
+
+use super::{locale::Zone, timesheets::Locks};
+
+/// Maximum title length, enforced by the `title_length` constraint.
+pub const TITLE_MAX_CHARS: usize = 150;
+/// Maximum description length, enforced by the `description_length` constraint.
+pub const DESCRIPTION_MAX_CHARS: usize = 4999;
+/// Maximum tag name length, enforced by the `name_length` constraint.
+pub const TAG_MAX_CHARS: usize = 100;
+/// Title used for entries without one.
+pub const NO_TITLE: &str = "<no title>";
+/// Largest accepted file.
+pub const MAX_IMPORT_BYTES: usize = 5 * 1024 * 1024;
+
+/// Timelogs are inserted in batches of this size.
+const BATCH_SIZE: usize = 200;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Toggl,
+    Clockify,
+}
+
+impl ImportFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Toggl => "Toggl",
+            Self::Clockify => "Clockify",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ImportEntry {
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub finished_at: OffsetDateTime,
+    pub tags: Vec<String>,
+}
+
+impl ImportEntry {
+    /// Build an entry, checking the database constraints.
+    ///
+    /// Tag names are trimmed, empty and repeated ones are dropped.
+    pub(super) fn new<'a>(
+        title: &str,
+        description: Option<&str>,
+        started_at: OffsetDateTime,
+        finished_at: OffsetDateTime,
+        tag_names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, String> {
+        let title = title.trim();
+        if title.chars().count() > TITLE_MAX_CHARS {
+            return Err(format!("Title is longer than {TITLE_MAX_CHARS} characters"));
+        }
+        let description = description.map(str::trim).filter(|d| !d.is_empty());
+        if description.is_some_and(|d| d.chars().count() > DESCRIPTION_MAX_CHARS) {
+            return Err(format!(
+                "Description is longer than {DESCRIPTION_MAX_CHARS} characters"
+            ));
+        }
+        if finished_at < started_at {
+            return Err("End is before start".to_string());
+        }
+
+        let mut tags: Vec<String> = Vec::new();
+        for name in tag_names
+            .into_iter()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            if name.chars().count() > TAG_MAX_CHARS {
+                return Err(format!(
+                    "Tag '{name}' is longer than {TAG_MAX_CHARS} characters"
+                ));
+            }
+            if !tags.iter().any(|t| t == name) {
+                tags.push(name.to_string());
+            }
+        }
+
+        Ok(Self {
+            title: if title.is_empty() { NO_TITLE } else { title }.to_string(),
+            description: description.map(str::to_string),
+            started_at,
+            finished_at,
+            tags,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "status", content = "message", rename_all = "lowercase")]
+pub enum RowStatus {
+    /// Will be, or was, imported.
+    New,
+    Duplicate,
+    Error(String),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RowResult {
+    /// Line number in the file, starting at 1 for the header.
+    pub line: u64,
+    pub entry: Option<ImportEntry>,
+    #[serde(flatten)]
+    pub status: RowStatus,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ImportReport {
+    pub format: ImportFormat,
+    pub dry_run: bool,
+    /// Number of created timelogs. Always 0 for a dry run.
+    pub created: usize,
+    pub rows: Vec<RowResult>,
+}
+
+impl ImportReport {
+    pub fn count(&self, f: impl Fn(&RowStatus) -> bool) -> usize {
+        self.rows.iter().filter(|r| f(&r.status)).count()
+    }
+}
+
+/// Parse a Toggl or Clockify CSV export and create timelogs for new rows.
+///
+/// With `dry_run` nothing is written, the report shows what would happen.
+/// Times in the file are interpreted in the user's time zone.
+pub fn import_csv(
+    db: &SupaDb,
+    user: &User,
+    data: &[u8],
+    dry_run: bool,
+) -> Result<ImportReport, anyhow::Error> {
+    if data.len() > MAX_IMPORT_BYTES {
+        return Err(PublicError::msg(format!(
+            "The file is too large, at most {} MB are supported",
+            MAX_IMPORT_BYTES / 1024 / 1024
+        ))
+        .into());
+    }
+
+    let zone = Zone::for_user(user);
+    let (format, parsed) = parse_csv(data, zone)?;
+    let mut rows = mark_duplicates(db, user, parsed)?;
+
+    let mut report = ImportReport {
+        format,
+        dry_run,
+        created: 0,
+        rows: Vec::new(),
+    };
+    if !dry_run {
+        let entries = rows
+            .iter()
+            .filter(|r| matches!(r.status, RowStatus::New))
+            .filter_map(|r| r.entry.as_ref())
+            .collect::<Vec<_>>();
+        report.created = create_timelogs(db, user, &entries)?;
+    }
+    report.rows.append(&mut rows);
+    Ok(report)
+}
+
+/// Check rows against existing timelogs and earlier rows of the file.
+fn mark_duplicates(
+    db: &SupaDb,
+    user: &User,
+    parsed: Vec<ParsedRow>,
+) -> Result<Vec<RowResult>, anyhow::Error> {
+    let entries = parsed.iter().filter_map(|(_, r)| r.as_ref().ok());
+    let from = entries.clone().map(|e| e.started_at).min();
+    let until = entries.map(|e| e.finished_at).max();
+
+    let mut seen = HashSet::new();
+    if let (Some(from), Some(until)) = (from, until) {
+        // Widen the range a bit, so entries exactly at the edges are found.
+        let query = user_timelogs_in_range(
+            user.id,
+            from - time::Duration::SECOND,
+            until + time::Duration::SECOND,
+        );
+        for log in all_timelogs(db, query).context("Could not load existing timelogs")? {
+            if let Some(finished_at) = log.finished_at() {
+                seen.insert(dedup_key(&log.title, log.started_at, finished_at));
+            }
+        }
+    }
+
+    let rows = parsed
+        .into_iter()
+        .map(|(line, res)| match res {
+            Ok(entry) => {
+                let key = dedup_key(&entry.title, entry.started_at, entry.finished_at);
+                let status = if seen.insert(key) {
+                    RowStatus::New
+                } else {
+                    RowStatus::Duplicate
+                };
+                RowResult {
+                    line,
+                    entry: Some(entry),
+                    status,
+                }
+            }
+            Err(message) => RowResult {
+                line,
+                entry: None,
+                status: RowStatus::Error(message),
+            },
+        })
+        .collect();
+    Ok(rows)
+}
+
+fn dedup_key(
+    title: &str,
+    started_at: OffsetDateTime,
+    finished_at: OffsetDateTime,
+) -> (String, i64, i64) {
+    (
+        title.to_string(),
+        started_at.unix_timestamp(),
+        finished_at.unix_timestamp(),
+    )
+}
+
+/// Create finished timelogs with their tags, returns the number created.
+///
+/// Missing tags are created. Nothing is created if an entry falls into a
+/// submitted week.
+pub(super) fn create_timelogs(
+    db: &SupaDb,
+    user: &User,
+    entries: &[&ImportEntry],
+) -> Result<usize, anyhow::Error> {
+    let locks = Locks::for_user(db, user.id)?;
+    for entry in entries {
+        locks.ensure_open(entry.started_at, Some(entry.finished_at))?;
+    }
+
+    let mut tag_ids = db
+        .user_tags(UserTagQuery::new_for_user(user.id))?
+        .into_iter()
+        .map(|t| (t.name, t.id))
+        .collect::<HashMap<_, _>>();
+    for name in entries.iter().flat_map(|e| &e.tags) {
+        if !tag_ids.contains_key(name) {
+            let tag = db.user_tag_create(UserTagCreate {
+                user_id: user.id,
+                organization_id: None,
+                name: name.clone(),
+                description: None,
+                color: None,
+                hourly_rate_cents: None,
+            })?;
+            tag_ids.insert(tag.name, tag.id);
+        }
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let mut created = 0;
+    for batch in entries.chunks(BATCH_SIZE) {
+        let logs = batch
+            .iter()
+            .map(|e| {
+                Ok(TimelogCreate {
+                    user_id: user.id,
+                    title: e.title.clone(),
+                    description: e.description.clone(),
+                    created_at: now,
+                    started_at: e.started_at,
+                    finished_at: Some(e.finished_at.format(&Rfc3339)?),
+                    project_id: None,
+                    billable: false,
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        let logs = db
+            .timelogs_create(logs)
+            .context("Could not create timelogs")?;
+
+        // Rows are returned in insertion order.
+        let links = logs
+            .iter()
+            .zip(batch)
+            .flat_map(|(log, entry)| {
+                entry.tags.iter().filter_map(|name| {
+                    tag_ids.get(name).map(|id| TimelogUserTag {
+                        user_tag_id: *id,
+                        timelog_id: log.id,
+                    })
+                })
+            })
+            .collect();
+        db.timelog_tags_add(links)?;
+        created += logs.len();
+    }
+    Ok(created)
+}
+
+/// Columns of a supported export, by lower-cased header name.
+struct Columns {
+    format: ImportFormat,
+    description: usize,
+    project: Option<usize>,
+    task: Option<usize>,
+    tags: Option<usize>,
+    start_date: usize,
+    start_time: usize,
+    end_date: usize,
+    end_time: usize,
+}
+
+impl Columns {
+    fn detect(headers: &csv::StringRecord) -> Result<Self, anyhow::Error> {
+        let names = headers
+            .iter()
+            .map(|h| h.trim().trim_start_matches('\u{feff}').to_lowercase())
+            .collect::<Vec<_>>();
+        let find = |name: &str| names.iter().position(|n| n == name);
+        let require = |name: &str| {
+            find(name).ok_or_else(|| {
+                PublicError::msg(format!("Unsupported file: missing column '{name}'"))
+            })
+        };
+
+        // Clockify has "Duration (h)", Toggl a plain "Duration".
+        let format = if find("duration (h)").is_some() || find("duration (decimal)").is_some() {
+            ImportFormat::Clockify
+        } else if find("duration").is_some() {
+            ImportFormat::Toggl
+        } else {
+            return Err(PublicError::msg(
+                "Unsupported file: expected a Toggl or Clockify detailed CSV export",
+            )
+            .into());
+        };
+
+        Ok(Self {
+            format,
+            description: require("description")?,
+            project: find("project"),
+            task: find("task"),
+            tags: find("tags"),
+            start_date: require("start date")?,
+            start_time: require("start time")?,
+            end_date: require("end date")?,
+            end_time: require("end time")?,
+        })
+    }
+}
+
+/// Line number and entry or error of a row.
+type ParsedRow = (u64, Result<ImportEntry, String>);
+
+/// Parse all rows of a file.
+fn parse_csv(data: &[u8], zone: Zone) -> Result<(ImportFormat, Vec<ParsedRow>), anyhow::Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let headers = reader
+        .headers()
+        .map_err(|err| PublicError::msg(format!("Could not read the CSV header: {err}")))?
+        .clone();
+    let columns = Columns::detect(&headers)?;
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        // The header is line 1. Quoted fields with line breaks are rare in
+        // these exports, so records are counted instead of physical lines.
+        let line = index as u64 + 2;
+        let res = match record {
+            Ok(record) => parse_row(&columns, &record, zone),
+            Err(err) => Err(format!("Invalid CSV: {err}")),
+        };
+        rows.push((line, res));
+    }
+    Ok((columns.format, rows))
+}
+
+fn parse_row(
+    columns: &Columns,
+    record: &csv::StringRecord,
+    zone: Zone,
+) -> Result<ImportEntry, String> {
+    let get = |index: usize| record.get(index).unwrap_or_default().trim();
+    let get_opt = |index: Option<usize>| index.map(get).filter(|v| !v.is_empty());
+
+    let project = get_opt(columns.project);
+    let title = get(columns.description);
+    let title = if title.is_empty() {
+        project.unwrap_or(NO_TITLE)
+    } else {
+        title
+    };
+    let started_at = parse_local(get(columns.start_date), get(columns.start_time), zone)
+        .map_err(|err| format!("Invalid start: {err}"))?;
+    let finished_at = parse_local(get(columns.end_date), get(columns.end_time), zone)
+        .map_err(|err| format!("Invalid end: {err}"))?;
+    let tags = project
+        .into_iter()
+        .chain(get_opt(columns.tags).into_iter().flat_map(|t| t.split(',')));
+
+    ImportEntry::new(title, get_opt(columns.task), started_at, finished_at, tags)
+}
+
+fn parse_local(date: &str, time: &str, zone: Zone) -> Result<OffsetDateTime, String> {
+    let date = parse_date(date).ok_or_else(|| format!("unrecognized date '{date}'"))?;
+    let time = parse_time(time).ok_or_else(|| format!("unrecognized time '{time}'"))?;
+    zone.resolve_local(PrimitiveDateTime::new(date, time))
+        .ok_or_else(|| format!("{date} {time} does not exist in {}", zone.name()))
+}
+
+/// Parse `YYYY-MM-DD`, `MM/DD/YYYY` or `DD.MM.YYYY`.
+///
+/// Slashed dates are read as month first, unless the first number can only
+/// be a day.
+fn parse_date(value: &str) -> Option<Date> {
+    let numbers = |sep: char| -> Option<Vec<u16>> {
+        value.split(sep).map(|p| p.trim().parse().ok()).collect()
+    };
+    let (year, month, day) = if value.contains('-') {
+        match numbers('-')?.as_slice() {
+            [y, m, d] => (*y, *m, *d),
+            _ => return None,
+        }
+    } else if value.contains('/') {
+        match numbers('/')?.as_slice() {
+            [d, m, y] if *d > 12 => (*y, *m, *d),
+            [m, d, y] => (*y, *m, *d),
+            _ => return None,
+        }
+    } else if value.contains('.') {
+        match numbers('.')?.as_slice() {
+            [d, m, y] => (*y, *m, *d),
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+
+    let month = Month::try_from(u8::try_from(month).ok()?).ok()?;
+    Date::from_calendar_date(year.into(), month, u8::try_from(day).ok()?).ok()
+}
+
+/// Parse `HH:MM[:SS]` with an optional `AM`/`PM` suffix.
+fn parse_time(value: &str) -> Option<Time> {
+    let value = value.trim().to_uppercase();
+    let (clock, pm) = match value.strip_suffix("PM") {
+        Some(clock) => (clock.trim(), Some(true)),
+        None => match value.strip_suffix("AM") {
+            Some(clock) => (clock.trim(), Some(false)),
+            None => (value.as_str(), None),
+        },
+    };
+
+    let parts = clock
+        .split(':')
+        .map(|p| p.parse::<u8>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let (hour, minute, second) = match parts.as_slice() {
+        [h, m] => (*h, *m, 0),
+        [h, m, s] => (*h, *m, *s),
+        _ => return None,
+    };
+    let hour = match pm {
+        Some(_) if hour == 0 || hour > 12 => return None,
+        Some(true) => hour % 12 + 12,
+        Some(false) => hour % 12,
+        None => hour,
+    };
+    Time::from_hms(hour, minute, second).ok()
+}
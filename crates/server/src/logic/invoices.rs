@@ -0,0 +1,374 @@
+//! Invoices for the billable time of a client.
+//!
+//! An invoice covers the finished, billable entries of a client's projects
+//! that started in a date range and are not on another invoice yet. Lines
+//! and entries are stored as a snapshot, so later changes to rates or
+//! projects don't change issued invoices. The database rejects changes to
+//! invoices and to the timelogs on them.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Context;
+
+use crate::{
+    db::{
+        all_timelogs,
+        client_supabase::SupaDb,
+        types::{
+            ClientId, Invoice, InvoiceCreate, InvoiceData, InvoiceEntry, InvoiceFilter, InvoiceId,
+            InvoiceLine, InvoiceQuery, TimelogFilter, User,
+        },
+        user_timelogs_started_in, Db,
+    },
+    PublicError,
+};
+
+use super::{
+    billing::{Amount, Billing},
+    locale::{DisplayPrefs, Zone},
+    pdf::{Document, Font, Page, A4},
+    projects::{user_clients, user_projects},
+    reports::{parse_date, timelog_tags, DateRange},
+};
+
+/// What invoice lines are grouped by.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InvoiceGrouping {
+    Project,
+    /// By entry title.
+    Task,
+}
+
+impl InvoiceGrouping {
+    pub const ALL: [Self; 2] = [Self::Project, Self::Task];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Project => "project",
+            Self::Task => "task",
+        }
+    }
+}
+
+impl std::str::FromStr for InvoiceGrouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|g| g.as_str() == s)
+            .ok_or_else(|| PublicError::msg(format!("Unknown grouping '{s}'")).into())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct InvoiceRequest {
+    pub client_id: ClientId,
+    pub range: DateRange,
+    pub grouping: InvoiceGrouping,
+    /// Tax rate in hundredths of a percent.
+    pub tax_rate_basis_points: i64,
+}
+
+/// Parse a tax rate in percent, like `19` or `7.5`, into basis points.
+pub fn parse_tax_rate(value: &str) -> Result<i64, anyhow::Error> {
+    if value.trim().is_empty() {
+        return Ok(0);
+    }
+    let basis_points = Amount::parse(value)?.cents();
+    if basis_points > 10_000 {
+        return Err(PublicError::msg("The tax rate may be at most 100%").into());
+    }
+    Ok(basis_points)
+}
+
+/// Invoices of a user, newest first.
+pub fn user_invoices(db: &SupaDb, user: &User) -> Result<Vec<Invoice>, anyhow::Error> {
+    db.invoices(InvoiceQuery::new_for_user(user.id))
+        .map_err(From::from)
+}
+
+/// Load an invoice, if it belongs to the user.
+pub fn user_invoice(
+    db: &SupaDb,
+    user: &User,
+    id: InvoiceId,
+) -> Result<Option<Invoice>, anyhow::Error> {
+    let query = InvoiceQuery {
+        filter: Some(InvoiceFilter::Id(id).and(InvoiceFilter::UserId(user.id))),
+        limit: 1,
+        offset: 0,
+    };
+    Ok(db.invoices(query)?.into_iter().next())
+}
+
+/// Compute the contents of an invoice without creating it.
+pub fn invoice_draft(
+    db: &SupaDb,
+    user: &User,
+    request: &InvoiceRequest,
+) -> Result<InvoiceData, anyhow::Error> {
+    let client = user_clients(db, user)?
+        .into_iter()
+        .find(|c| c.id == request.client_id)
+        .ok_or_else(|| PublicError::msg("Unknown client"))?;
+    let projects = user_projects(db, user, true)?
+        .into_iter()
+        .filter(|p| p.client_id == Some(client.id))
+        .map(|p| (p.id, p.name))
+        .collect::<HashMap<_, _>>();
+
+    let zone = Zone::for_user(user);
+    let mut query =
+        user_timelogs_started_in(user.id, request.range.start(zone), request.range.end(zone));
+    query.filter = query.filter.map(|f| {
+        f.and(TimelogFilter::IsFinished(true))
+            .and(TimelogFilter::IsInvoiced(false))
+    });
+    let logs = all_timelogs(db, query)
+        .context("Could not load timelogs")?
+        .into_iter()
+        .filter(|log| log.billable && log.project_id.is_some_and(|id| projects.contains_key(&id)))
+        .collect::<Vec<_>>();
+    if logs.is_empty() {
+        return Err(PublicError::msg(format!(
+            "No billable time of {} left to invoice in this period",
+            client.name
+        ))
+        .into());
+    }
+
+    let tags = timelog_tags(db, user, &logs)?;
+    let billing = Billing::for_user(db, user)?;
+    let mut entries = Vec::with_capacity(logs.len());
+    for log in &logs {
+        let Some(finished_at) = log.finished_at() else {
+            continue;
+        };
+        let tags = tags.of(log.id);
+        let Some((billed, amount)) = billing.entry(log, finished_at - log.started_at, tags) else {
+            continue;
+        };
+        entries.push(InvoiceEntry {
+            timelog_id: log.id,
+            title: log.title.clone(),
+            project: log.project_id.and_then(|id| projects.get(&id).cloned()),
+            started_at: log.started_at,
+            finished_at,
+            seconds: billed.whole_seconds(),
+            rate_cents: billing.rate(log.project_id, tags).map(Amount::cents),
+            amount_cents: amount.cents(),
+        });
+    }
+
+    let lines = invoice_lines(&entries, request.grouping);
+    let subtotal = entries
+        .iter()
+        .map(|e| Amount::from_cents(e.amount_cents))
+        .sum::<Amount>();
+    let tax = subtotal.percent(request.tax_rate_basis_points);
+    Ok(InvoiceData {
+        period_from: request.range.from.to_string(),
+        period_to: request.range.to.to_string(),
+        client_name: client.name,
+        grouping: request.grouping.as_str().to_string(),
+        currency: billing.currency,
+        tax_rate_basis_points: request.tax_rate_basis_points,
+        subtotal_cents: subtotal.cents(),
+        tax_cents: tax.cents(),
+        total_cents: (subtotal + tax).cents(),
+        lines,
+        entries,
+    })
+}
+
+/// Sum up entries by project or title, and rate.
+///
+/// Line amounts are sums of the rounded entry amounts, so they add up to the
+/// subtotal exactly.
+fn invoice_lines(entries: &[InvoiceEntry], grouping: InvoiceGrouping) -> Vec<InvoiceLine> {
+    let mut lines: BTreeMap<(&str, Option<i64>), (i64, Amount)> = BTreeMap::new();
+    for entry in entries {
+        let description = match grouping {
+            InvoiceGrouping::Project => entry.project.as_deref().unwrap_or_default(),
+            InvoiceGrouping::Task => entry.title.as_str(),
+        };
+        let line = lines
+            .entry((description, entry.rate_cents))
+            .or_insert((0, Amount::ZERO));
+        line.0 += entry.seconds;
+        line.1 += Amount::from_cents(entry.amount_cents);
+    }
+    lines
+        .into_iter()
+        .map(
+            |((description, rate_cents), (seconds, amount))| InvoiceLine {
+                description: description.to_string(),
+                seconds,
+                rate_cents,
+                amount_cents: amount.cents(),
+            },
+        )
+        .collect()
+}
+
+/// Create an invoice with the next number and lock its entries.
+///
+/// Fails if an entry was invoiced or deleted since the draft was computed,
+/// then nothing is created.
+pub fn invoice_create(
+    db: &SupaDb,
+    user: &User,
+    request: &InvoiceRequest,
+) -> Result<Invoice, anyhow::Error> {
+    let data = invoice_draft(db, user, request)?;
+    let create = InvoiceCreate {
+        user_id: user.id,
+        client_id: request.client_id,
+        data,
+    };
+    db.invoice_create(create)
+        .map_err(|err| match err.constraint() {
+            Some("invoice_entries_available") => PublicError::msg(
+                "Some entries were invoiced or deleted in the meantime, please try again",
+            )
+            .into(),
+            _ => anyhow::Error::from(err),
+        })
+}
+
+/// Format a number of seconds as decimal hours, like "1.50".
+pub fn format_hours(seconds: i64) -> String {
+    // Hundredths of an hour, rounded half up.
+    let hundredths = (seconds * 2 + 36).div_euclid(72);
+    format!("{}.{:02}", hundredths / 100, hundredths % 100)
+}
+
+/// Format a tax rate in basis points as percent, like "7.5".
+pub fn format_tax_rate(basis_points: i64) -> String {
+    let rate = Amount::from_cents(basis_points).to_string();
+    rate.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// The title of an invoice, like "Invoice 12".
+pub fn invoice_title(invoice: &Invoice) -> String {
+    format!("Invoice {}", invoice.number)
+}
+
+/// Render an invoice as PDF, with lines and totals, followed by its entries.
+pub fn invoice_pdf(invoice: &Invoice, user: &User) -> Vec<u8> {
+    const MARGIN: f32 = 56.0;
+    const LINE: f32 = 16.0;
+    let right = A4.0 - MARGIN;
+    let prefs = DisplayPrefs::for_user(user);
+    let data = &invoice.data;
+    let currency = data.currency.as_str();
+    let money = |cents: i64| format!("{} {currency}", Amount::from_cents(cents));
+    let date = |value: &str| {
+        parse_date(value)
+            .map(|d| prefs.locale.format_date(d))
+            .unwrap_or_else(|_| value.to_string())
+    };
+
+    let mut doc = Document::new();
+    let mut page = Page::new();
+    let mut y = A4.1 - MARGIN;
+
+    page.text(MARGIN, y, Font::Bold, 20.0, &invoice_title(invoice));
+    y -= 2.0 * LINE;
+    let header = [
+        ("From", user.username.clone()),
+        ("To", data.client_name.clone()),
+        (
+            "Date",
+            prefs
+                .locale
+                .format_date(prefs.zone.to_local(invoice.issued_at).date()),
+        ),
+        (
+            "Period",
+            format!("{} to {}", date(&data.period_from), date(&data.period_to)),
+        ),
+    ];
+    for (label, value) in header {
+        page.text(MARGIN, y, Font::Bold, 10.0, label);
+        page.text(MARGIN + 60.0, y, Font::Regular, 10.0, &value);
+        y -= LINE;
+    }
+    y -= LINE;
+
+    // Columns: description, hours, rate and amount, right aligned.
+    let (hours_x, rate_x) = (right - 220.0, right - 110.0);
+    let description_width = hours_x - MARGIN - 60.0;
+    page.text(MARGIN, y, Font::Bold, 10.0, "Description");
+    page.text_right(hours_x, y, Font::Bold, 10.0, "Hours");
+    page.text_right(rate_x, y, Font::Bold, 10.0, "Rate");
+    page.text_right(right, y, Font::Bold, 10.0, "Amount");
+    page.hline(MARGIN, right, y - 5.0);
+    y -= LINE + 4.0;
+    for line in &data.lines {
+        if y < MARGIN + 6.0 * LINE {
+            doc.add_page(std::mem::take(&mut page));
+            y = A4.1 - MARGIN;
+        }
+        let description = Font::Regular.truncate(&line.description, 10.0, description_width);
+        page.text(MARGIN, y, Font::Regular, 10.0, &description);
+        page.text_right(hours_x, y, Font::Regular, 10.0, &format_hours(line.seconds));
+        if let Some(rate) = line.rate_cents {
+            page.text_right(rate_x, y, Font::Regular, 10.0, &money(rate));
+        }
+        page.text_right(right, y, Font::Regular, 10.0, &money(line.amount_cents));
+        y -= LINE;
+    }
+    page.hline(MARGIN, right, y + LINE - 5.0);
+    y -= 4.0;
+    let totals = [
+        (Font::Regular, "Subtotal".to_string(), data.subtotal_cents),
+        (
+            Font::Regular,
+            format!("Tax ({}%)", format_tax_rate(data.tax_rate_basis_points)),
+            data.tax_cents,
+        ),
+        (Font::Bold, "Total".to_string(), data.total_cents),
+    ];
+    for (font, label, cents) in totals {
+        page.text_right(rate_x, y, font, 10.0, &label);
+        page.text_right(right, y, font, 10.0, &money(cents));
+        y -= LINE;
+    }
+
+    y -= 2.0 * LINE;
+    let entry_header = |page: &mut Page, y: f32| {
+        page.text(MARGIN, y, Font::Bold, 12.0, "Entries");
+        y - LINE - 4.0
+    };
+    if y < MARGIN + 3.0 * LINE {
+        doc.add_page(std::mem::take(&mut page));
+        y = A4.1 - MARGIN;
+    }
+    y = entry_header(&mut page, y);
+    for entry in &data.entries {
+        if y < MARGIN {
+            doc.add_page(std::mem::take(&mut page));
+            y = entry_header(&mut page, A4.1 - MARGIN);
+        }
+        let title = match &entry.project {
+            Some(project) => format!("{project}: {}", entry.title),
+            None => entry.title.clone(),
+        };
+        page.text(
+            MARGIN,
+            y,
+            Font::Regular,
+            8.0,
+            &prefs.datetime(entry.started_at),
+        );
+        let title = Font::Regular.truncate(&title, 8.0, rate_x - MARGIN - 150.0);
+        page.text(MARGIN + 90.0, y, Font::Regular, 8.0, &title);
+        page.text_right(rate_x, y, Font::Regular, 8.0, &format_hours(entry.seconds));
+        page.text_right(right, y, Font::Regular, 8.0, &money(entry.amount_cents));
+        y -= LINE * 0.75;
+    }
+    doc.add_page(page);
+    doc.into_bytes()
+}
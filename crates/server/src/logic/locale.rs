@@ -0,0 +1,202 @@
+//! Per-user time zone and locale preferences.
+//!
+//! Timestamps are stored in UTC and only converted for display and for
+//! finding day boundaries.
+
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time, Weekday};
+use time_tz::{timezones, OffsetDateTimeExt, OffsetResult, PrimitiveDateTimeExt, TimeZone, Tz};
+
+use crate::{db::types::User, PublicError};
+
+/// An IANA time zone.
+#[derive(Clone, Copy)]
+pub struct Zone(&'static Tz);
+
+impl Zone {
+    pub fn utc() -> Self {
+        Self(timezones::db::UTC)
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, anyhow::Error> {
+        timezones::get_by_name(name)
+            .map(Self)
+            .ok_or_else(|| PublicError::msg(format!("Unknown time zone '{name}'")).into())
+    }
+
+    /// The zone of a user, falling back to UTC if it is unknown.
+    pub fn for_user(user: &User) -> Self {
+        Self::from_name(&user.timezone).unwrap_or_else(|_| Self::utc())
+    }
+
+    /// All known zone names, sorted.
+    pub fn names() -> Vec<&'static str> {
+        let mut names = timezones::iter().map(|tz| tz.name()).collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    pub fn name(self) -> &'static str {
+        self.0.name()
+    }
+
+    pub fn to_local(self, t: OffsetDateTime) -> OffsetDateTime {
+        t.to_timezone(self.0)
+    }
+
+    pub fn today(self) -> Date {
+        self.to_local(OffsetDateTime::now_utc()).date()
+    }
+
+    /// The instant of a local date and time.
+    ///
+    /// Returns `None` for times skipped by a DST change. Times that occur
+    /// twice resolve to the first occurrence.
+    pub fn resolve_local(self, t: PrimitiveDateTime) -> Option<OffsetDateTime> {
+        match t.assume_timezone(self.0) {
+            OffsetResult::Some(t) | OffsetResult::Ambiguous(t, _) => Some(t),
+            OffsetResult::None => None,
+        }
+    }
+
+    /// The instant the given local day starts.
+    ///
+    /// Some zones skip midnight on DST changes, then the day starts with the
+    /// first valid local time. If midnight occurs twice, the first one counts.
+    pub fn start_of_day(self, date: Date) -> OffsetDateTime {
+        let midnight = PrimitiveDateTime::new(date, Time::MIDNIGHT);
+        // Gaps are at most a few hours long.
+        for minutes in (0..=180).step_by(15) {
+            match (midnight + Duration::minutes(minutes)).assume_timezone(self.0) {
+                OffsetResult::Some(t) | OffsetResult::Ambiguous(t, _) => return t,
+                OffsetResult::None => {}
+            }
+        }
+        midnight.assume_timezone_utc(self.0)
+    }
+}
+
+impl std::fmt::Debug for Zone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Zone").field(&self.name()).finish()
+    }
+}
+
+/// Supported display locales.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Locale {
+    EnUs,
+    EnGb,
+    DeDe,
+    FrFr,
+    JaJp,
+}
+
+impl Locale {
+    pub const ALL: [Self; 5] = [Self::EnUs, Self::EnGb, Self::DeDe, Self::FrFr, Self::JaJp];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::EnUs => "en-US",
+            Self::EnGb => "en-GB",
+            Self::DeDe => "de-DE",
+            Self::FrFr => "fr-FR",
+            Self::JaJp => "ja-JP",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::EnUs => "English (US)",
+            Self::EnGb => "English (UK)",
+            Self::DeDe => "Deutsch",
+            Self::FrFr => "Français",
+            Self::JaJp => "日本語",
+        }
+    }
+
+    /// The locale of a user, falling back to en-US if it is unknown.
+    pub fn for_user(user: &User) -> Self {
+        user.locale.parse().unwrap_or(Self::EnUs)
+    }
+
+    pub fn format_date(&self, d: Date) -> String {
+        let (y, m, d) = (d.year(), d.month() as u8, d.day());
+        match self {
+            Self::EnUs => format!("{m}/{d}/{y}"),
+            Self::EnGb | Self::FrFr => format!("{d:02}/{m:02}/{y}"),
+            Self::DeDe => format!("{d:02}.{m:02}.{y}"),
+            Self::JaJp => format!("{y}/{m:02}/{d:02}"),
+        }
+    }
+
+    /// Abbreviated name of a weekday.
+    pub fn format_weekday(&self, w: Weekday) -> &'static str {
+        let names = match self {
+            Self::EnUs | Self::EnGb => ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+            Self::DeDe => ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+            Self::FrFr => ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."],
+            Self::JaJp => ["月", "火", "水", "木", "金", "土", "日"],
+        };
+        names[usize::from(w.number_days_from_monday())]
+    }
+
+    pub fn format_time(&self, t: Time) -> String {
+        match self {
+            Self::EnUs => {
+                let suffix = if t.hour() < 12 { "AM" } else { "PM" };
+                let hour = match t.hour() % 12 {
+                    0 => 12,
+                    h => h,
+                };
+                format!("{hour}:{:02} {suffix}", t.minute())
+            }
+            _ => format!("{:02}:{:02}", t.hour(), t.minute()),
+        }
+    }
+
+    pub fn format_datetime(&self, t: OffsetDateTime) -> String {
+        format!(
+            "{} {}",
+            self.format_date(t.date()),
+            self.format_time(t.time())
+        )
+    }
+}
+
+impl std::str::FromStr for Locale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|l| l.code().eq_ignore_ascii_case(s))
+            .ok_or_else(|| PublicError::msg(format!("Unsupported locale '{s}'")).into())
+    }
+}
+
+/// How times are shown to a user.
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayPrefs {
+    pub zone: Zone,
+    pub locale: Locale,
+}
+
+impl DisplayPrefs {
+    pub fn for_user(user: &User) -> Self {
+        Self {
+            zone: Zone::for_user(user),
+            locale: Locale::for_user(user),
+        }
+    }
+
+    /// Format an instant as local date and time.
+    pub fn datetime(&self, t: OffsetDateTime) -> String {
+        self.locale.format_datetime(self.zone.to_local(t))
+    }
+
+    /// Format an instant as local date.
+    pub fn date(&self, t: OffsetDateTime) -> String {
+        self.locale.format_date(self.zone.to_local(t).date())
+    }
+}
@@ -1 +1,19 @@
+pub mod audit;
+pub mod backup;
+pub mod billing;
+pub mod budgets;
+pub mod calendar;
+pub mod calendar_import;
+pub mod export;
+pub mod ics;
+pub mod import;
+pub mod invoices;
+pub mod locale;
+pub mod orgs;
+pub mod pdf;
+pub mod projects;
+pub mod reports;
+pub mod seed;
+pub mod timesheets;
+pub mod trash;
 pub mod user;
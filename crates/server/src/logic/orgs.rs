@@ -0,0 +1,544 @@
+//! Organizations, their members and invitations.
+//!
+//! Projects and tags are either personal or shared in an organization.
+//! Every member can see and track time on shared projects, admins and owners
+//! manage them and the members. Timelogs always stay with the user who
+//! tracked them.
+//!
+//! All access checks happen here, routes only pass the current user on.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use time::Duration;
+
+use crate::{
+    db::{
+        all_timelogs,
+        client_supabase::SupaDb,
+        project_timelogs_in_range,
+        types::{
+            Invitation, InvitationCreate, InvitationFilter, InvitationId, Membership,
+            MembershipCreate, MembershipFilter, MembershipPatch, Organization, OrganizationCreate,
+            OrganizationId, Project, ProjectFilter, ProjectQuery, Timelog, User, UserFilter,
+            UserId, UserTag, UserTagCreate, UserTagFilter, UserTagQuery,
+        },
+        Db,
+    },
+    PublicError,
+};
+
+use super::{
+    billing::{Billing, Rounding},
+    locale::{Locale, Zone},
+    projects::{validate_color, validate_name, ProjectNames},
+    reports::{summarize, DateRange, Details, Grouping, NamedTotal, Report, TimelogTags},
+    user::usernames,
+};
+
+/// Name used in the member totals for users who left the organization.
+pub const FORMER_MEMBER: &str = "Former member";
+
+/// Role of a member, ordered by what it allows.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Role {
+    /// Tracks time on the projects of the organization.
+    Member,
+    /// Also manages projects, tags and members.
+    Admin,
+    /// Also manages admins and owners.
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Self; 3] = [Self::Owner, Self::Admin, Self::Member];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Admin => "admin",
+            Self::Member => "member",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|r| r.as_str() == s)
+            .ok_or_else(|| PublicError::msg(format!("Unknown role '{s}'")).into())
+    }
+}
+
+/// A member of an organization with their username.
+#[derive(Clone, Debug)]
+pub struct Member {
+    pub user_id: UserId,
+    pub username: String,
+    pub role: Role,
+}
+
+/// Everything an organization page shows.
+#[derive(Clone, Debug)]
+pub struct OrganizationDetails {
+    pub organization: Organization,
+    /// Role of the current user.
+    pub role: Role,
+    /// Owners first, then by username.
+    pub members: Vec<Member>,
+    /// With the username of the invited user, only loaded for admins and
+    /// owners.
+    pub invitations: Vec<(Invitation, String)>,
+    pub projects: Vec<Project>,
+    pub tags: Vec<UserTag>,
+}
+
+/// An org-level report.
+#[derive(Clone, Debug)]
+pub struct OrganizationReport {
+    /// Time of all members on the projects of the organization.
+    pub report: Report,
+    /// Sorted by total, largest first. Members without time are left out,
+    /// users who left are counted as [`FORMER_MEMBER`].
+    pub by_member: Vec<NamedTotal>,
+}
+
+fn role_of(membership: &Membership) -> Role {
+    // The database only allows known roles.
+    membership.role.parse().unwrap_or(Role::Member)
+}
+
+/// Ids of the organizations the user is a member of.
+pub fn user_organization_ids(
+    db: &SupaDb,
+    user: &User,
+) -> Result<Vec<OrganizationId>, anyhow::Error> {
+    Ok(db
+        .memberships(MembershipFilter::UserId(user.id))?
+        .into_iter()
+        .map(|m| m.organization_id)
+        .collect())
+}
+
+/// Organizations of the user with their role, by name.
+pub fn user_organizations(
+    db: &SupaDb,
+    user: &User,
+) -> Result<Vec<(Organization, Role)>, anyhow::Error> {
+    let roles = db
+        .memberships(MembershipFilter::UserId(user.id))?
+        .into_iter()
+        .map(|m| (m.organization_id, role_of(&m)))
+        .collect::<HashMap<_, _>>();
+    let ids = roles.keys().copied().collect::<Vec<_>>();
+    Ok(db
+        .organizations(&ids)?
+        .into_iter()
+        .filter_map(|org| {
+            let role = *roles.get(&org.id)?;
+            Some((org, role))
+        })
+        .collect())
+}
+
+/// Load an organization, if the user has at least the role `min` in it.
+///
+/// Non-members get the same error whether or not the organization exists.
+pub fn require_role(
+    db: &SupaDb,
+    user: &User,
+    id: OrganizationId,
+    min: Role,
+) -> Result<(Organization, Role), anyhow::Error> {
+    let membership = db
+        .memberships(MembershipFilter::OrganizationId(id).and(MembershipFilter::UserId(user.id)))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PublicError::msg("Organization not found"))?;
+    let role = role_of(&membership);
+    if role < min {
+        return Err(PublicError::msg(format!(
+            "Only {}s can do this in the organization",
+            min.as_str()
+        ))
+        .into());
+    }
+    let org = db
+        .organizations(&[id])?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PublicError::msg("Organization not found"))?;
+    Ok((org, role))
+}
+
+/// Create an organization with the user as its owner.
+pub fn organization_create(
+    db: &SupaDb,
+    user: &User,
+    name: &str,
+) -> Result<Organization, anyhow::Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(PublicError::msg("Name may not be empty").into());
+    }
+    if name.chars().count() > 100 {
+        return Err(PublicError::msg("Name may be at most 100 characters long").into());
+    }
+    let org = db.organization_create(OrganizationCreate {
+        name: name.to_string(),
+    })?;
+    db.membership_create(MembershipCreate {
+        organization_id: org.id,
+        user_id: user.id,
+        role: Role::Owner.as_str().to_string(),
+    })
+    .context("Could not add the owner to the new organization")?;
+    Ok(org)
+}
+
+/// An organization with its members, projects and tags, for any member.
+pub fn organization_details(
+    db: &SupaDb,
+    user: &User,
+    id: OrganizationId,
+) -> Result<OrganizationDetails, anyhow::Error> {
+    let (organization, role) = require_role(db, user, id, Role::Member)?;
+    let invitations = if role >= Role::Admin {
+        let invitations = db.invitations(InvitationFilter::OrganizationId(id))?;
+        let names = usernames(db, invitations.iter().map(|i| i.user_id).collect())?;
+        invitations
+            .into_iter()
+            .map(|i| {
+                let name = names.get(&i.user_id).cloned().unwrap_or_default();
+                (i, name)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let projects = db.projects(ProjectQuery {
+        filter: Some(ProjectFilter::OrganizationId(id)),
+        limit: 1000,
+        offset: 0,
+    })?;
+    let tags = db.user_tags(UserTagQuery {
+        filter: Some(UserTagFilter::OrganizationId(id)),
+        limit: 1000,
+        offset: 0,
+    })?;
+    Ok(OrganizationDetails {
+        organization,
+        role,
+        members: organization_members(db, id)?,
+        invitations,
+        projects,
+        tags,
+    })
+}
+
+fn organization_members(db: &SupaDb, id: OrganizationId) -> Result<Vec<Member>, anyhow::Error> {
+    let memberships = db.memberships(MembershipFilter::OrganizationId(id))?;
+    let names = usernames(db, memberships.iter().map(|m| m.user_id).collect())?;
+    let mut members = memberships
+        .iter()
+        .map(|m| Member {
+            user_id: m.user_id,
+            username: names.get(&m.user_id).cloned().unwrap_or_default(),
+            role: role_of(m),
+        })
+        .collect::<Vec<_>>();
+    members.sort_by(|a, b| {
+        b.role
+            .cmp(&a.role)
+            .then_with(|| a.username.cmp(&b.username))
+    });
+    Ok(members)
+}
+
+/// Invite a user by username, as admin or member.
+///
+/// Invitations are bound to the account, not to an email address: emails
+/// are not verified, so anyone could sign up with an invited one.
+pub fn invitation_create(
+    db: &SupaDb,
+    user: &User,
+    id: OrganizationId,
+    username: &str,
+    role: Role,
+) -> Result<(Invitation, User), anyhow::Error> {
+    require_role(db, user, id, Role::Admin)?;
+    if role == Role::Owner {
+        return Err(PublicError::msg("Invite as admin or member, then make them an owner").into());
+    }
+    let username = username.trim();
+    let invited = db
+        .user(UserFilter::Name(username.to_string()))?
+        .ok_or_else(|| PublicError::msg(format!("No user named '{username}'")))?;
+    if !db
+        .memberships(membership_filter(id, invited.id))?
+        .is_empty()
+    {
+        return Err(PublicError::msg(format!("{username} is already a member")).into());
+    }
+    let create = InvitationCreate {
+        organization_id: id,
+        user_id: invited.id,
+        role: role.as_str().to_string(),
+        invited_by: user.id,
+    };
+    let invitation = db
+        .invitation_create(create)
+        .map_err(|err| match err.constraint() {
+            Some("unique_invitation_per_user") => {
+                PublicError::msg(format!("{username} has already been invited")).into()
+            }
+            _ => anyhow::Error::from(err),
+        })?;
+    Ok((invitation, invited))
+}
+
+/// Pending invitations of the user, with their organization.
+pub fn user_invitations(
+    db: &SupaDb,
+    user: &User,
+) -> Result<Vec<(Invitation, Organization)>, anyhow::Error> {
+    let invitations = db.invitations(InvitationFilter::UserId(user.id))?;
+    let ids = invitations
+        .iter()
+        .map(|i| i.organization_id)
+        .collect::<Vec<_>>();
+    let orgs = db
+        .organizations(&ids)?
+        .into_iter()
+        .map(|o| (o.id, o))
+        .collect::<HashMap<_, _>>();
+    Ok(invitations
+        .into_iter()
+        .filter_map(|i| {
+            let org = orgs.get(&i.organization_id)?.clone();
+            Some((i, org))
+        })
+        .collect())
+}
+
+/// An invitation of the user.
+fn user_invitation(
+    db: &SupaDb,
+    user: &User,
+    id: InvitationId,
+) -> Result<Invitation, anyhow::Error> {
+    let filter = InvitationFilter::Id(id).and(InvitationFilter::UserId(user.id));
+    db.invitations(filter)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PublicError::msg("Invitation not found").into())
+}
+
+/// Join the organization of an invitation.
+pub fn invitation_accept(
+    db: &SupaDb,
+    user: &User,
+    id: InvitationId,
+) -> Result<OrganizationId, anyhow::Error> {
+    let invitation = user_invitation(db, user, id)?;
+    let create = MembershipCreate {
+        organization_id: invitation.organization_id,
+        user_id: user.id,
+        role: invitation.role.clone(),
+    };
+    match db.membership_create(create) {
+        Ok(_) => {}
+        // Already a member, only the invitation is left to clean up.
+        Err(err) if err.constraint() == Some("memberships_pkey") => {}
+        Err(err) => return Err(err.into()),
+    }
+    db.invitation_delete(InvitationFilter::Id(invitation.id))?;
+    Ok(invitation.organization_id)
+}
+
+/// Decline an invitation of the user.
+pub fn invitation_decline(db: &SupaDb, user: &User, id: InvitationId) -> Result<(), anyhow::Error> {
+    let invitation = user_invitation(db, user, id)?;
+    db.invitation_delete(InvitationFilter::Id(invitation.id))?;
+    Ok(())
+}
+
+/// Withdraw an invitation of an organization.
+pub fn invitation_cancel(
+    db: &SupaDb,
+    user: &User,
+    org_id: OrganizationId,
+    id: InvitationId,
+) -> Result<(), anyhow::Error> {
+    require_role(db, user, org_id, Role::Admin)?;
+    let filter = InvitationFilter::Id(id).and(InvitationFilter::OrganizationId(org_id));
+    if db.invitation_delete(filter)?.is_empty() {
+        return Err(PublicError::msg("Invitation not found").into());
+    }
+    Ok(())
+}
+
+fn membership_filter(org_id: OrganizationId, user_id: UserId) -> MembershipFilter {
+    MembershipFilter::OrganizationId(org_id).and(MembershipFilter::UserId(user_id))
+}
+
+fn member(db: &SupaDb, org_id: OrganizationId, user_id: UserId) -> Result<Role, anyhow::Error> {
+    db.memberships(membership_filter(org_id, user_id))?
+        .first()
+        .map(role_of)
+        .ok_or_else(|| PublicError::msg("Member not found").into())
+}
+
+fn owner_count(db: &SupaDb, org_id: OrganizationId) -> Result<usize, anyhow::Error> {
+    Ok(db
+        .memberships(MembershipFilter::OrganizationId(org_id))?
+        .iter()
+        .filter(|m| role_of(m) == Role::Owner)
+        .count())
+}
+
+/// Remove a member, or leave the organization if `member_id` is the user.
+///
+/// Admins remove members, owners anyone. The last owner can't leave.
+pub fn membership_remove(
+    db: &SupaDb,
+    user: &User,
+    org_id: OrganizationId,
+    member_id: UserId,
+) -> Result<(), anyhow::Error> {
+    let (_, role) = require_role(db, user, org_id, Role::Member)?;
+    let target = member(db, org_id, member_id)?;
+    if member_id != user.id {
+        let allowed = match target {
+            Role::Member => role >= Role::Admin,
+            Role::Admin | Role::Owner => role == Role::Owner,
+        };
+        if !allowed {
+            return Err(PublicError::msg("You are not allowed to remove this member").into());
+        }
+    }
+    if target == Role::Owner && owner_count(db, org_id)? <= 1 {
+        return Err(PublicError::msg("An organization needs at least one owner").into());
+    }
+    db.membership_delete(membership_filter(org_id, member_id))?;
+    Ok(())
+}
+
+/// Change the role of a member. Only owners can do this.
+pub fn membership_set_role(
+    db: &SupaDb,
+    user: &User,
+    org_id: OrganizationId,
+    member_id: UserId,
+    role: Role,
+) -> Result<(), anyhow::Error> {
+    require_role(db, user, org_id, Role::Owner)?;
+    let current = member(db, org_id, member_id)?;
+    if current == Role::Owner && role != Role::Owner && owner_count(db, org_id)? <= 1 {
+        return Err(PublicError::msg("An organization needs at least one owner").into());
+    }
+    let patch = MembershipPatch {
+        role: Some(role.as_str().to_string()),
+    };
+    db.membership_update(membership_filter(org_id, member_id), patch)?;
+    Ok(())
+}
+
+/// Create a tag shared in an organization. Admins and owners can do this.
+pub fn organization_tag_create(
+    db: &SupaDb,
+    user: &User,
+    id: OrganizationId,
+    name: &str,
+    color: Option<&str>,
+) -> Result<UserTag, anyhow::Error> {
+    require_role(db, user, id, Role::Admin)?;
+    let create = UserTagCreate {
+        user_id: user.id,
+        organization_id: Some(id),
+        name: validate_name(name)?,
+        description: None,
+        color: color.map(validate_color).transpose()?,
+        hourly_rate_cents: None,
+    };
+    db.user_tag_create(create)
+        .map_err(|err| match err.constraint() {
+            Some("unique_tag_name_per_organization") => {
+                PublicError::msg("A tag with this name already exists").into()
+            }
+            _ => anyhow::Error::from(err),
+        })
+}
+
+/// Time of all members on the projects of an organization.
+///
+/// Days follow the time zone of the user viewing the report. Only time on
+/// shared projects is counted, personal time of members stays private.
+pub fn organization_report(
+    db: &SupaDb,
+    user: &User,
+    id: OrganizationId,
+    range: DateRange,
+    grouping: Grouping,
+) -> Result<OrganizationReport, anyhow::Error> {
+    let details = organization_details(db, user, id)?;
+    let zone = Zone::for_user(user);
+    let project_ids = details.projects.iter().map(|p| p.id).collect::<Vec<_>>();
+    let logs = if project_ids.is_empty() {
+        Vec::new()
+    } else {
+        let query = project_timelogs_in_range(project_ids, range.start(zone), range.end(zone));
+        all_timelogs(db, query).context("Could not load timelogs")?
+    };
+
+    let report_details = Details {
+        tags: TimelogTags::load(db, &details.tags, &logs)?,
+        projects: details
+            .projects
+            .iter()
+            .map(|p| {
+                let names = ProjectNames {
+                    project: p.name.clone(),
+                    client_id: None,
+                    client: None,
+                };
+                (p.id, names)
+            })
+            .collect(),
+        // Rates are personal, so the report has no earnings.
+        billing: Billing {
+            currency: user.currency.clone(),
+            rounding: Rounding::for_user(user),
+            user_rate: None,
+            project_rates: HashMap::new(),
+            tag_rates: HashMap::new(),
+        },
+    };
+    let locale = Locale::for_user(user);
+    let report = summarize(&logs, &report_details, range, grouping, zone, locale);
+
+    let names = details
+        .members
+        .iter()
+        .map(|m| (m.user_id, m.username.as_str()))
+        .collect::<HashMap<_, _>>();
+    let mut by_name: HashMap<&str, Vec<Timelog>> = HashMap::new();
+    for log in &logs {
+        let name = names.get(&log.user_id).copied().unwrap_or(FORMER_MEMBER);
+        by_name.entry(name).or_default().push(log.clone());
+    }
+    let mut by_member = by_name
+        .into_iter()
+        .map(|(name, member_logs)| NamedTotal {
+            name: name.to_string(),
+            total: summarize(&member_logs, &report_details, range, grouping, zone, locale).total,
+        })
+        .filter(|t| t.total > Duration::ZERO)
+        .collect::<Vec<_>>();
+    by_member.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(OrganizationReport { report, by_member })
+}
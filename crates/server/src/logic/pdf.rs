@@ -0,0 +1,225 @@
+//! Minimal PDF writer for simple text documents.
+//!
+//! Only the standard Helvetica fonts are used, so nothing has to be
+//! embedded. Text is encoded as WinAnsi, characters outside of it are
+//! replaced by `?`. Coordinates are in points from the bottom left corner.
+
+use std::fmt::Write as _;
+
+/// Width and height of an A4 page in points.
+pub const A4: (f32, f32) = (595.0, 842.0);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Self::Regular => "F1",
+            Self::Bold => "F2",
+        }
+    }
+
+    fn widths(self) -> &'static [u16; 95] {
+        match self {
+            Self::Regular => &HELVETICA_WIDTHS,
+            Self::Bold => &HELVETICA_BOLD_WIDTHS,
+        }
+    }
+
+    /// Width of `text` in points.
+    ///
+    /// Exact for ASCII, an estimate for other characters.
+    pub fn width(self, text: &str, size: f32) -> f32 {
+        let units: u32 = text
+            .chars()
+            .map(|c| match c {
+                ' '..='~' => u32::from(self.widths()[c as usize - 32]),
+                _ => 556,
+            })
+            .sum();
+        units as f32 * size / 1000.0
+    }
+
+    /// Shorten `text` with an ellipsis so it fits into `max_width` points.
+    pub fn truncate(self, text: &str, size: f32, max_width: f32) -> String {
+        if self.width(text, size) <= max_width {
+            return text.to_string();
+        }
+        let mut out = text.to_string();
+        while !out.is_empty() && self.width(&out, size) + self.width("...", size) > max_width {
+            out.pop();
+        }
+        out.truncate(out.trim_end().len());
+        out + "..."
+    }
+}
+
+/// A page under construction.
+#[derive(Clone, Debug, Default)]
+pub struct Page {
+    content: String,
+}
+
+impl Page {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Draw text with its baseline starting at `x`, `y`.
+    pub fn text(&mut self, x: f32, y: f32, font: Font, size: f32, text: &str) {
+        let _ = writeln!(
+            self.content,
+            "BT /{} {size:.1} Tf {x:.2} {y:.2} Td ({}) Tj ET",
+            font.resource(),
+            encode_text(text)
+        );
+    }
+
+    /// Draw text ending at `x`.
+    pub fn text_right(&mut self, x: f32, y: f32, font: Font, size: f32, text: &str) {
+        self.text(x - font.width(text, size), y, font, size, text);
+    }
+
+    /// Draw a thin horizontal line.
+    pub fn hline(&mut self, x1: f32, x2: f32, y: f32) {
+        let _ = writeln!(self.content, "0.5 w {x1:.2} {y:.2} m {x2:.2} {y:.2} l S");
+    }
+}
+
+/// A document of A4 pages.
+#[derive(Clone, Debug, Default)]
+pub struct Document {
+    pages: Vec<Page>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_page(&mut self, page: Page) {
+        self.pages.push(page);
+    }
+
+    /// Serialize the document. An empty document gets one blank page.
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        if self.pages.is_empty() {
+            self.pages.push(Page::new());
+        }
+
+        // Objects 1 to 4 are fixed, then a page and its content per page.
+        let page_ids = (0..self.pages.len()).map(|i| 5 + 2 * i).collect::<Vec<_>>();
+        let kids = page_ids
+            .iter()
+            .map(|id| format!("{id} 0 R"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut objects = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            format!(
+                "<< /Type /Pages /Kids [{kids}] /Count {} >>",
+                self.pages.len()
+            )
+            .into_bytes(),
+            font_object("Helvetica"),
+            font_object("Helvetica-Bold"),
+        ];
+        for (page, id) in self.pages.into_iter().zip(page_ids) {
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                     /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                    A4.0,
+                    A4.1,
+                    id + 1
+                )
+                .into_bytes(),
+            );
+            let content = latin_bytes(&page.content);
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend_from_slice(&content);
+            stream.extend_from_slice(b"\nendstream");
+            objects.push(stream);
+        }
+
+        let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(object);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(table, "{offset:010} 00000 n ");
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        );
+        out.extend_from_slice(table.as_bytes());
+        out
+    }
+}
+
+fn font_object(name: &str) -> Vec<u8> {
+    format!("<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>")
+        .into_bytes()
+}
+
+/// Escape text for a PDF string literal.
+///
+/// The result still holds one `char` per WinAnsi byte, see [`latin_bytes`].
+fn encode_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' | '\u{a0}'..='\u{ff}' => out.push(c),
+            // WinAnsi puts the euro sign at 0x80.
+            '€' => out.push('\u{80}'),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+/// Content streams are built as strings of chars up to U+00FF, one byte each.
+fn latin_bytes(content: &str) -> Vec<u8> {
+    content
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+/// Widths of the ASCII characters 32 to 126 in Helvetica, per 1000 units.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278,
+    278, // space to /
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // 0 to ?
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // @ to O
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // P to _
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // ` to o
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, // p to ~
+];
+
+/// Widths of the ASCII characters 32 to 126 in Helvetica-Bold, per 1000 units.
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278,
+    278, // space to /
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, // 0 to ?
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778, // @ to O
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556, // P to _
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611, // ` to o
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584, // p to ~
+];
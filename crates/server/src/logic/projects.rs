@@ -0,0 +1,259 @@
+//! Projects and the clients they belong to.
+//!
+//! A timelog can belong to one project, and a project to one client.
+//! Archived projects stay in reports but can't be picked for new timelogs.
+//!
+//! Projects and tags are personal or shared in an organization. Users see
+//! both, but only admins of the organization may change shared ones.
+
+use std::collections::HashMap;
+
+use crate::{
+    db::{
+        client_supabase::SupaDb,
+        types::{
+            Client, ClientCreate, ClientId, ClientQuery, OrganizationId, Project, ProjectCreate,
+            ProjectFilter, ProjectId, ProjectPatch, ProjectQuery, User, UserTag, UserTagFilter,
+            UserTagId, UserTagQuery,
+        },
+        Db,
+    },
+    PublicError,
+};
+
+use super::{
+    billing::Amount,
+    orgs::{require_role, user_organization_ids, Role},
+};
+
+#[derive(Clone, Debug)]
+pub struct ProjectInput {
+    pub name: String,
+    pub client_id: Option<ClientId>,
+    /// A `#rrggbb` color.
+    pub color: Option<String>,
+    pub billable: bool,
+    pub hourly_rate: Option<Amount>,
+    /// Share the project in an organization instead of keeping it personal.
+    pub organization_id: Option<OrganizationId>,
+}
+
+/// Names of a project and its client, as shown in reports.
+#[derive(Clone, Debug)]
+pub struct ProjectNames {
+    pub project: String,
+    pub client_id: Option<ClientId>,
+    pub client: Option<String>,
+}
+
+pub fn user_clients(db: &SupaDb, user: &User) -> Result<Vec<Client>, anyhow::Error> {
+    db.clients(ClientQuery::new_for_user(user.id))
+        .map_err(From::from)
+}
+
+/// Projects the user can see: their personal ones and those of their
+/// organizations.
+fn visible_projects(db: &SupaDb, user: &User) -> Result<ProjectFilter, anyhow::Error> {
+    Ok(ProjectFilter::Visible {
+        user_id: user.id,
+        organization_ids: user_organization_ids(db, user)?,
+    })
+}
+
+/// Projects of a user by name, optionally including archived ones.
+///
+/// Includes the projects of the user's organizations.
+pub fn user_projects(
+    db: &SupaDb,
+    user: &User,
+    include_archived: bool,
+) -> Result<Vec<Project>, anyhow::Error> {
+    let mut filter = visible_projects(db, user)?;
+    if !include_archived {
+        filter = filter.and(ProjectFilter::IsArchived(false));
+    }
+    let query = ProjectQuery {
+        filter: Some(filter),
+        ..ProjectQuery::new_for_user(user.id)
+    };
+    db.projects(query).map_err(From::from)
+}
+
+/// Load a project, if the user can see it.
+pub fn user_project(
+    db: &SupaDb,
+    user: &User,
+    id: ProjectId,
+) -> Result<Option<Project>, anyhow::Error> {
+    let query = ProjectQuery {
+        filter: Some(ProjectFilter::Id(id).and(visible_projects(db, user)?)),
+        limit: 1,
+        offset: 0,
+    };
+    Ok(db.projects(query)?.into_iter().next())
+}
+
+/// Load a project the user may change.
+///
+/// Personal projects can be changed by their user, shared ones by admins
+/// and owners of the organization.
+pub fn project_for_update(
+    db: &SupaDb,
+    user: &User,
+    id: ProjectId,
+) -> Result<Project, anyhow::Error> {
+    let project =
+        user_project(db, user, id)?.ok_or_else(|| PublicError::msg("Project not found"))?;
+    if let Some(org_id) = project.organization_id {
+        require_role(db, user, org_id, Role::Admin)?;
+    }
+    Ok(project)
+}
+
+/// Tags the user can see: their personal ones and those of their
+/// organizations.
+pub fn user_tags(db: &SupaDb, user: &User) -> Result<Vec<UserTag>, anyhow::Error> {
+    let query = UserTagQuery {
+        filter: Some(UserTagFilter::Visible {
+            user_id: user.id,
+            organization_ids: user_organization_ids(db, user)?,
+        }),
+        ..UserTagQuery::new_for_user(user.id)
+    };
+    db.user_tags(query).map_err(From::from)
+}
+
+/// Load a tag the user may change, see [`project_for_update`].
+pub fn tag_for_update(db: &SupaDb, user: &User, id: UserTagId) -> Result<UserTag, anyhow::Error> {
+    let tag = user_tags(db, user)?
+        .into_iter()
+        .find(|t| t.id == id)
+        .ok_or_else(|| PublicError::msg("Tag not found"))?;
+    if let Some(org_id) = tag.organization_id {
+        require_role(db, user, org_id, Role::Admin)?;
+    }
+    Ok(tag)
+}
+
+/// Project and client names of all projects of a user, by project id.
+pub fn user_project_names(
+    db: &SupaDb,
+    user: &User,
+) -> Result<HashMap<ProjectId, ProjectNames>, anyhow::Error> {
+    let clients = user_clients(db, user)?
+        .into_iter()
+        .map(|c| (c.id, c.name))
+        .collect::<HashMap<_, _>>();
+    Ok(user_projects(db, user, true)?
+        .into_iter()
+        .map(|p| {
+            let names = ProjectNames {
+                client_id: p.client_id,
+                client: p.client_id.and_then(|id| clients.get(&id).cloned()),
+                project: p.name,
+            };
+            (p.id, names)
+        })
+        .collect())
+}
+
+pub fn client_create(
+    db: &SupaDb,
+    user: &User,
+    name: &str,
+    description: Option<&str>,
+) -> Result<Client, anyhow::Error> {
+    let name = validate_name(name)?;
+    let create = ClientCreate {
+        user_id: user.id,
+        name,
+        description: description
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(str::to_string),
+    };
+    db.client_create(create)
+        .map_err(|err| match err.constraint() {
+            Some("unique_client_name_per_user") => {
+                PublicError::msg("A client with this name already exists").into()
+            }
+            _ => anyhow::Error::from(err),
+        })
+}
+
+pub fn project_create(
+    db: &SupaDb,
+    user: &User,
+    input: ProjectInput,
+) -> Result<Project, anyhow::Error> {
+    let name = validate_name(&input.name)?;
+    let color = input.color.as_deref().map(validate_color).transpose()?;
+    if let Some(org_id) = input.organization_id {
+        require_role(db, user, org_id, Role::Admin)?;
+    }
+    if let Some(client_id) = input.client_id {
+        let owned = user_clients(db, user)?.iter().any(|c| c.id == client_id);
+        if !owned {
+            return Err(PublicError::msg("Unknown client").into());
+        }
+    }
+
+    let create = ProjectCreate {
+        user_id: user.id,
+        organization_id: input.organization_id,
+        client_id: input.client_id,
+        name,
+        description: None,
+        color,
+        billable: input.billable,
+        hourly_rate_cents: input.hourly_rate.map(Amount::cents),
+    };
+    db.project_create(create)
+        .map_err(|err| match err.constraint() {
+            Some("unique_project_name_per_user" | "unique_project_name_per_organization") => {
+                PublicError::msg("A project with this name already exists").into()
+            }
+            _ => anyhow::Error::from(err),
+        })
+}
+
+/// Archive or restore a project.
+pub fn project_set_archived(
+    db: &SupaDb,
+    user: &User,
+    id: ProjectId,
+    archived: bool,
+) -> Result<Project, anyhow::Error> {
+    let project = project_for_update(db, user, id)?;
+    let patch = ProjectPatch {
+        archived: Some(archived),
+        ..Default::default()
+    };
+    db.project_update(ProjectFilter::Id(project.id), patch)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PublicError::msg("Project not found").into())
+}
+
+pub(crate) fn validate_name(name: &str) -> Result<String, anyhow::Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(PublicError::msg("Name may not be empty").into());
+    }
+    if name.chars().count() > 100 {
+        return Err(PublicError::msg("Name may be at most 100 characters long").into());
+    }
+    Ok(name.to_string())
+}
+
+/// Accept `#rrggbb` colors, as sent by color inputs.
+pub(crate) fn validate_color(color: &str) -> Result<String, anyhow::Error> {
+    let color = color.trim().to_ascii_lowercase();
+    let valid = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !valid {
+        return Err(PublicError::msg(format!("Invalid color '{color}'")).into());
+    }
+    Ok(color)
+}
@@ -0,0 +1,490 @@
+//! Time summaries grouped by day, ISO week or month.
+//!
+//! Only finished timelogs are counted. Entries are clipped to the report
+//! range and split at midnight, so time is attributed to the day it was
+//! actually spent on. Day boundaries follow the user's time zone, so days
+//! with a DST change are 23 or 25 hours long.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Context;
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::{
+    db::{
+        all_timelogs,
+        client_supabase::SupaDb,
+        types::{ClientId, ProjectId, Timelog, TimelogId, User, UserTag, UserTagId},
+        user_timelogs_in_range, Db,
+    },
+    PublicError,
+};
+
+use super::{
+    billing::{Amount, Billing, Rounding},
+    locale::{Locale, Zone},
+    projects::{user_project_names, user_tags, ProjectNames},
+};
+
+/// Longest range a report can cover.
+pub const MAX_RANGE_DAYS: i64 = 366;
+
+/// Name used in the tag totals for entries without tags.
+pub const UNTAGGED: &str = "Untagged";
+
+/// Name used in the project totals for entries without a project.
+pub const NO_PROJECT: &str = "No project";
+
+/// Name used in the client totals for entries without a client.
+pub const NO_CLIENT: &str = "No client";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Grouping {
+    Day,
+    Week,
+    Month,
+}
+
+impl Grouping {
+    pub const ALL: [Self; 3] = [Self::Day, Self::Week, Self::Month];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    /// First day of the period that contains `date`.
+    pub fn period_start(&self, date: Date) -> Date {
+        match self {
+            Self::Day => date,
+            Self::Week => date - Duration::days(date.weekday().number_days_from_monday().into()),
+            Self::Month => date.replace_day(1).expect("day 1 exists in every month"),
+        }
+    }
+
+    /// Label for the period starting at `start`.
+    pub fn period_label(&self, start: Date, locale: Locale) -> String {
+        match self {
+            Self::Day => format!(
+                "{} ({})",
+                locale.format_date(start),
+                locale.format_weekday(start.weekday())
+            ),
+            Self::Week => {
+                let (year, week, _) = start.to_iso_week_date();
+                format!("{year}-W{week:02}")
+            }
+            Self::Month => format!("{}-{:02}", start.year(), start.month() as u8),
+        }
+    }
+}
+
+impl std::str::FromStr for Grouping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|g| g.as_str() == s)
+            .ok_or_else(|| PublicError::msg(format!("Unknown grouping '{s}'")).into())
+    }
+}
+
+/// Inclusive range of days.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DateRange {
+    pub from: Date,
+    pub to: Date,
+}
+
+impl DateRange {
+    pub fn new(from: Date, to: Date) -> Result<Self, anyhow::Error> {
+        if from > to {
+            return Err(PublicError::msg("The start date must not be after the end date").into());
+        }
+        if (to - from).whole_days() >= MAX_RANGE_DAYS {
+            return Err(PublicError::msg(format!(
+                "Reports can cover at most {MAX_RANGE_DAYS} days"
+            ))
+            .into());
+        }
+        Ok(Self { from, to })
+    }
+
+    /// Parse a range from optional `YYYY-MM-DD` values.
+    ///
+    /// Without `to` the range ends `today`, without `from` it covers
+    /// `default_days` days.
+    pub fn parse(
+        from: Option<&str>,
+        to: Option<&str>,
+        today: Date,
+        default_days: i64,
+    ) -> Result<Self, anyhow::Error> {
+        let to = match to.map(str::trim) {
+            Some(to) if !to.is_empty() => parse_date(to)?,
+            _ => today,
+        };
+        match from.map(str::trim) {
+            Some(from) if !from.is_empty() => Self::new(parse_date(from)?, to),
+            _ => Ok(Self::last_days(to, default_days)),
+        }
+    }
+
+    /// The `days` days up to and including `to`.
+    pub fn last_days(to: Date, days: i64) -> Self {
+        Self {
+            from: to - Duration::days(days - 1),
+            to,
+        }
+    }
+
+    /// The instant the range starts in the given zone.
+    pub fn start(&self, zone: Zone) -> OffsetDateTime {
+        zone.start_of_day(self.from)
+    }
+
+    /// The instant after the last day of the range in the given zone.
+    pub fn end(&self, zone: Zone) -> OffsetDateTime {
+        zone.start_of_day(self.to + Duration::days(1))
+    }
+
+    fn days(&self) -> impl Iterator<Item = Date> {
+        let to = self.to;
+        std::iter::successors(Some(self.from), move |d| d.next_day().filter(|d| *d <= to))
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date, as sent by date inputs.
+pub fn parse_date(value: &str) -> Result<Date, anyhow::Error> {
+    let invalid = || PublicError::msg(format!("Invalid date '{value}': expected YYYY-MM-DD"));
+    let mut parts = value.trim().splitn(3, '-').map(|p| p.parse::<u16>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid().into());
+    };
+    let month = u8::try_from(month)
+        .ok()
+        .and_then(|m| time::Month::try_from(m).ok())
+        .ok_or_else(invalid)?;
+    let day = u8::try_from(day).map_err(|_| invalid())?;
+    Date::from_calendar_date(year.into(), month, day).map_err(|_| invalid().into())
+}
+
+#[derive(Clone, Debug)]
+pub struct PeriodTotal {
+    pub label: String,
+    pub total: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct NamedTotal {
+    pub name: String,
+    pub total: Duration,
+}
+
+/// Billable time and earnings of a group.
+#[derive(Clone, Debug)]
+pub struct NamedEarnings {
+    pub name: String,
+    pub billable: Duration,
+    pub earnings: Amount,
+}
+
+/// Billable time and earnings.
+///
+/// Entries are clipped to the report range before their time is rounded.
+#[derive(Clone, Debug)]
+pub struct Earnings {
+    pub currency: String,
+    pub rounding: Rounding,
+    pub billable: Duration,
+    pub total: Amount,
+    /// Sorted by earnings, largest first.
+    pub by_client: Vec<NamedEarnings>,
+    /// Sorted by earnings, largest first.
+    pub by_project: Vec<NamedEarnings>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub range: DateRange,
+    pub grouping: Grouping,
+    pub zone: Zone,
+    pub total: Duration,
+    /// One entry per period in the range, including empty ones.
+    pub periods: Vec<PeriodTotal>,
+    /// Sorted by total, largest first.
+    pub by_title: Vec<NamedTotal>,
+    /// Sorted by total, largest first.
+    ///
+    /// Entries with multiple tags count towards each of them, so these can
+    /// add up to more than the overall total.
+    pub by_tag: Vec<NamedTotal>,
+    /// Sorted by total, largest first.
+    pub by_client: Vec<NamedTotal>,
+    /// Sorted by total, largest first.
+    pub by_project: Vec<NamedTotal>,
+    pub earnings: Earnings,
+}
+
+pub fn user_report(
+    db: &SupaDb,
+    user: &User,
+    range: DateRange,
+    grouping: Grouping,
+) -> Result<Report, anyhow::Error> {
+    let zone = Zone::for_user(user);
+    let query = user_timelogs_in_range(user.id, range.start(zone), range.end(zone));
+    let logs = all_timelogs(db, query).context("Could not load timelogs")?;
+
+    let details = Details {
+        tags: timelog_tags(db, user, &logs)?,
+        projects: user_project_names(db, user)?,
+        billing: Billing::for_user(db, user)?,
+    };
+
+    Ok(summarize(
+        &logs,
+        &details,
+        range,
+        grouping,
+        zone,
+        Locale::for_user(user),
+    ))
+}
+
+/// The tags of some timelogs.
+///
+/// Tags are told apart by id, a personal and a shared tag can have the same
+/// name.
+#[derive(Clone, Debug, Default)]
+pub struct TimelogTags {
+    ids: HashMap<TimelogId, Vec<UserTagId>>,
+    names: HashMap<UserTagId, String>,
+}
+
+impl TimelogTags {
+    /// Load the links of the timelogs to the given tags. Links to other tags
+    /// are left out.
+    pub fn load(db: &SupaDb, tags: &[UserTag], logs: &[Timelog]) -> Result<Self, anyhow::Error> {
+        let names = tags
+            .iter()
+            .map(|t| (t.id, t.name.clone()))
+            .collect::<HashMap<_, _>>();
+        let log_ids = logs.iter().map(|l| l.id).collect::<Vec<_>>();
+        let mut ids: HashMap<TimelogId, Vec<UserTagId>> = HashMap::new();
+        if !log_ids.is_empty() {
+            for link in db.timelog_tags(&log_ids)? {
+                if names.contains_key(&link.user_tag_id) {
+                    ids.entry(link.timelog_id)
+                        .or_default()
+                        .push(link.user_tag_id);
+                }
+            }
+        }
+        Ok(Self { ids, names })
+    }
+
+    /// Tag ids of a timelog.
+    pub fn of(&self, log: TimelogId) -> &[UserTagId] {
+        self.ids.get(&log).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn name(&self, id: UserTagId) -> &str {
+        self.names.get(&id).map_or("", String::as_str)
+    }
+}
+
+/// Tags of the given timelogs of a user, only those the user can see.
+pub fn timelog_tags(
+    db: &SupaDb,
+    user: &User,
+    logs: &[Timelog],
+) -> Result<TimelogTags, anyhow::Error> {
+    TimelogTags::load(db, &user_tags(db, user)?, logs)
+}
+
+/// What a report needs to know about timelogs, besides the timelogs.
+#[derive(Clone, Debug)]
+pub struct Details {
+    pub tags: TimelogTags,
+    pub projects: HashMap<ProjectId, ProjectNames>,
+    pub billing: Billing,
+}
+
+/// Build a report from already loaded timelogs.
+pub fn summarize(
+    logs: &[Timelog],
+    details: &Details,
+    range: DateRange,
+    grouping: Grouping,
+    zone: Zone,
+    locale: Locale,
+) -> Report {
+    let Details {
+        tags,
+        projects,
+        billing,
+    } = details;
+    let (range_start, range_end) = (range.start(zone), range.end(zone));
+    let mut periods: BTreeMap<Date, Duration> = range
+        .days()
+        .map(|day| (grouping.period_start(day), Duration::ZERO))
+        .collect();
+    let mut by_title: HashMap<&str, Duration> = HashMap::new();
+    // Grouped by id, so that equally named tags, projects and clients of a
+    // user and an organization stay apart. Names are only looked up at the end.
+    let mut by_tag: HashMap<Option<UserTagId>, Duration> = HashMap::new();
+    let mut by_client: HashMap<Option<ClientId>, Duration> = HashMap::new();
+    let mut by_project: HashMap<Option<ProjectId>, Duration> = HashMap::new();
+    let mut earned_by_client: HashMap<Option<ClientId>, (Duration, Amount)> = HashMap::new();
+    let mut earned_by_project: HashMap<Option<ProjectId>, (Duration, Amount)> = HashMap::new();
+    let (mut billable, mut earnings) = (Duration::ZERO, Amount::ZERO);
+    let mut total = Duration::ZERO;
+
+    for log in logs {
+        let Some(finished_at) = log.finished_at() else {
+            continue;
+        };
+        let start = log.started_at.max(range_start);
+        let end = finished_at.min(range_end);
+        if end <= start {
+            continue;
+        }
+
+        for (day, duration) in split_at_midnight(start, end, zone) {
+            *periods.entry(grouping.period_start(day)).or_default() += duration;
+        }
+
+        let duration = end - start;
+        total += duration;
+        *by_title.entry(log.title.as_str()).or_default() += duration;
+        let tag_ids = tags.of(log.id);
+        for id in tag_ids {
+            *by_tag.entry(Some(*id)).or_default() += duration;
+        }
+        if tag_ids.is_empty() {
+            *by_tag.entry(None).or_default() += duration;
+        }
+        let project_id = log.project_id.filter(|id| projects.contains_key(id));
+        let client_id = project_id
+            .and_then(|id| projects.get(&id))
+            .and_then(|p| p.client_id);
+        *by_project.entry(project_id).or_default() += duration;
+        *by_client.entry(client_id).or_default() += duration;
+
+        if let Some((billed, amount)) = billing.entry(log, duration, tag_ids) {
+            billable += billed;
+            earnings += amount;
+            for group in [
+                earned_by_client.entry(client_id).or_default(),
+                earned_by_project.entry(project_id).or_default(),
+            ] {
+                group.0 += billed;
+                group.1 += amount;
+            }
+        }
+    }
+
+    let tag_name = |id: Option<UserTagId>| id.map_or(UNTAGGED, |id| tags.name(id));
+    let project_name = |id: Option<ProjectId>| {
+        id.and_then(|id| projects.get(&id))
+            .map_or(NO_PROJECT, |p| p.project.as_str())
+    };
+    let client_names = projects
+        .values()
+        .filter_map(|p| Some((p.client_id?, p.client.as_deref()?)))
+        .collect::<HashMap<_, _>>();
+    let client_name = |id: Option<ClientId>| {
+        id.and_then(|id| client_names.get(&id).copied())
+            .unwrap_or(NO_CLIENT)
+    };
+
+    Report {
+        range,
+        grouping,
+        zone,
+        total,
+        periods: periods
+            .into_iter()
+            .map(|(start, total)| PeriodTotal {
+                label: grouping.period_label(start, locale),
+                total,
+            })
+            .collect(),
+        by_title: sorted_totals(by_title, |title| title),
+        by_tag: sorted_totals(by_tag, tag_name),
+        by_client: sorted_totals(by_client, client_name),
+        by_project: sorted_totals(by_project, project_name),
+        earnings: Earnings {
+            currency: billing.currency.clone(),
+            rounding: billing.rounding,
+            billable,
+            total: earnings,
+            by_client: sorted_earnings(earned_by_client, client_name),
+            by_project: sorted_earnings(earned_by_project, project_name),
+        },
+    }
+}
+
+/// Split `start..end` into the parts that fall on each local day.
+fn split_at_midnight(
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    zone: Zone,
+) -> Vec<(Date, Duration)> {
+    let mut parts = Vec::new();
+    let mut cursor = start;
+    while cursor < end {
+        let day = zone.to_local(cursor).date();
+        let next_midnight = match day.next_day() {
+            Some(next) => zone.start_of_day(next),
+            None => end,
+        };
+        let part_end = next_midnight.min(end);
+        parts.push((day, part_end - cursor));
+        cursor = part_end;
+    }
+    parts
+}
+
+fn sorted_totals<'a, K>(
+    totals: HashMap<K, Duration>,
+    name: impl Fn(K) -> &'a str,
+) -> Vec<NamedTotal> {
+    let mut items = totals
+        .into_iter()
+        .map(|(key, total)| NamedTotal {
+            name: name(key).to_string(),
+            total,
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+    items
+}
+
+fn sorted_earnings<'a, K>(
+    totals: HashMap<K, (Duration, Amount)>,
+    name: impl Fn(K) -> &'a str,
+) -> Vec<NamedEarnings> {
+    let mut items = totals
+        .into_iter()
+        .map(|(key, (billable, earnings))| NamedEarnings {
+            name: name(key).to_string(),
+            billable,
+            earnings,
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| {
+        b.earnings
+            .cmp(&a.earnings)
+            .then_with(|| b.billable.cmp(&a.billable))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    items
+}
@@ -0,0 +1,313 @@
+//! Development fixtures: users, tags and timelogs with realistic variation.
+//!
+//! Output is fully determined by [`SeedOptions::seed`] and the current date,
+//! so a given seed always produces the same shape of data.
+
+use anyhow::Context;
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime, Weekday};
+
+use crate::db::{
+    client_supabase::SupaDb,
+    types::{
+        TimelogCreate, TimelogUserTag, User, UserFilter, UserPatch, UserTag, UserTagCreate,
+        UserTagQuery,
+    },
+    Db,
+};
+
+use super::{
+    locale::Zone,
+    user::{user_signup, Signup},
+};
+
+/// Password of all generated users.
+pub const DEMO_PASSWORD: &str = "password";
+
+/// Timelogs are inserted in batches of this size.
+const BATCH_SIZE: usize = 200;
+
+/// Time zones the generated users work in.
+const TIMEZONES: &[&str] = &[
+    "UTC",
+    "Europe/Berlin",
+    "America/New_York",
+    "America/Los_Angeles",
+    "Asia/Kolkata",
+    "Asia/Tokyo",
+];
+
+const TAGS: &[(&str, &str)] = &[
+    ("development", "#3273dc"),
+    ("meeting", "#f14668"),
+    ("review", "#48c78e"),
+    ("support", "#ffe08a"),
+    ("admin", "#b5b5b5"),
+];
+
+const TITLES: &[&str] = &[
+    "Standup",
+    "Code review",
+    "Bug triage",
+    "Sprint planning",
+    "Customer call",
+    "Writing docs",
+    "Refactoring",
+    "Deploy",
+    "Email",
+    "Research",
+    "1:1",
+    "Fix flaky tests",
+];
+
+#[derive(Clone, Debug)]
+pub struct SeedOptions {
+    pub seed: u64,
+    /// Number of users to create.
+    pub users: u32,
+    /// Number of days of history per user, ending today.
+    pub days: u32,
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            users: 3,
+            days: 90,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SeedSummary {
+    pub users: usize,
+    pub tags: usize,
+    pub timelogs: usize,
+}
+
+/// Populate the database with generated users, tags and timelogs.
+///
+/// Users are named `demo1`, `demo2`, ... and reused if they already exist,
+/// but timelogs are always added, so running twice doubles the history.
+pub fn seed(db: &SupaDb, opts: &SeedOptions) -> Result<SeedSummary, anyhow::Error> {
+    let mut rng = Rng::new(opts.seed);
+    let now = OffsetDateTime::now_utc();
+    let mut summary = SeedSummary::default();
+
+    for index in 1..=opts.users {
+        let timezone = *rng.pick(TIMEZONES);
+        let zone = Zone::from_name(timezone)?;
+        let user = ensure_user(db, index, zone)?;
+        let tags = ensure_tags(db, &user)?;
+
+        let logs = generate_timelogs(&mut rng, &user, zone, opts.days, now)?;
+        log::info!(
+            user = user.username.as_str(),
+            timezone = zone.name(),
+            timelogs = logs.len();
+            "seeding user"
+        );
+
+        for batch in logs.chunks(BATCH_SIZE) {
+            let created = db
+                .timelogs_create(batch.to_vec())
+                .context("Could not create timelogs")?;
+            let links = created
+                .iter()
+                .flat_map(|log| {
+                    let count = rng.below(3) as usize;
+                    rng.sample(&tags, count)
+                        .into_iter()
+                        .map(|tag| TimelogUserTag {
+                            user_tag_id: tag.id,
+                            timelog_id: log.id,
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            db.timelog_tags_add(links)
+                .context("Could not tag timelogs")?;
+            summary.timelogs += created.len();
+        }
+
+        summary.users += 1;
+        summary.tags += tags.len();
+    }
+
+    Ok(summary)
+}
+
+fn ensure_user(db: &SupaDb, index: u32, zone: Zone) -> Result<User, anyhow::Error> {
+    let username = format!("demo{index}");
+    let user = match db.user(UserFilter::Name(username.clone()))? {
+        Some(user) => user,
+        None => user_signup(
+            db,
+            Signup {
+                email: format!("{username}@example.com"),
+                username,
+                password: DEMO_PASSWORD.to_string(),
+            },
+        )?,
+    };
+    if user.timezone == zone.name() {
+        return Ok(user);
+    }
+    let patch = UserPatch {
+        timezone: Some(zone.name().to_string()),
+        ..Default::default()
+    };
+    db.user_update(user.id, patch).map_err(From::from)
+}
+
+fn ensure_tags(db: &SupaDb, user: &User) -> Result<Vec<UserTag>, anyhow::Error> {
+    let mut tags = db.user_tags(UserTagQuery::new_for_user(user.id))?;
+    for (name, color) in TAGS {
+        if tags.iter().any(|t| t.name == *name) {
+            continue;
+        }
+        let tag = db.user_tag_create(UserTagCreate {
+            user_id: user.id,
+            organization_id: None,
+            name: name.to_string(),
+            description: None,
+            color: Some(color.to_string()),
+            hourly_rate_cents: None,
+        })?;
+        tags.push(tag);
+    }
+    Ok(tags)
+}
+
+/// Generate a working history for one user.
+///
+/// Weekdays have several entries between roughly 8:00 and 20:00 local time,
+/// weekends only occasional ones. A few entries overlap their predecessor,
+/// and the most recent one may still be running.
+fn generate_timelogs(
+    rng: &mut Rng,
+    user: &User,
+    zone: Zone,
+    days: u32,
+    now: OffsetDateTime,
+) -> Result<Vec<TimelogCreate>, anyhow::Error> {
+    let today = zone.to_local(now).date();
+    let mut logs = Vec::new();
+
+    for days_ago in (0..days).rev() {
+        let date = today - Duration::days(days_ago.into());
+        let entries = match date.weekday() {
+            Weekday::Saturday | Weekday::Sunday if rng.chance(0.8) => 0,
+            Weekday::Saturday | Weekday::Sunday => 1 + rng.below(2),
+            _ => 2 + rng.below(6),
+        };
+
+        let mut cursor =
+            zone.start_of_day(date) + Duration::minutes(8 * 60 + rng.below(120) as i64);
+
+        for _ in 0..entries {
+            let duration = random_duration(rng);
+            let started_at = cursor;
+            let finished_at = started_at + duration;
+            if finished_at > now || zone.to_local(started_at).hour() >= 20 {
+                break;
+            }
+
+            logs.push(TimelogCreate {
+                user_id: user.id,
+                title: rng.pick(TITLES).to_string(),
+                description: rng
+                    .chance(0.2)
+                    .then(|| "Generated by the seed command.".to_string()),
+                created_at: started_at,
+                started_at,
+                finished_at: Some(finished_at.format(&Rfc3339)?),
+                project_id: None,
+                billable: false,
+            });
+
+            cursor = if rng.chance(0.08) {
+                // Overlap: the next entry starts before this one ended.
+                started_at + duration / 2
+            } else {
+                finished_at + Duration::minutes(rng.below(45) as i64)
+            };
+        }
+    }
+
+    if rng.chance(0.5) {
+        let started_at = now - Duration::minutes(5 + rng.below(90) as i64);
+        logs.push(TimelogCreate {
+            user_id: user.id,
+            title: rng.pick(TITLES).to_string(),
+            description: None,
+            created_at: started_at,
+            started_at,
+            finished_at: None,
+            project_id: None,
+            billable: false,
+        });
+    }
+
+    Ok(logs)
+}
+
+/// Mostly short to medium entries, with a tail of long focus sessions and
+/// some very short interruptions.
+fn random_duration(rng: &mut Rng) -> Duration {
+    let minutes = match rng.below(10) {
+        0 => 1 + rng.below(15),
+        1 | 2 => 90 + rng.below(150),
+        _ => 15 + rng.below(75),
+    };
+    Duration::minutes(minutes as i64)
+}
+
+/// Small deterministic PRNG (SplitMix64).
+///
+/// Not suitable for anything security related.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in `0..max`.
+    pub fn below(&mut self, max: u64) -> u64 {
+        if max == 0 {
+            0
+        } else {
+            self.next_u64() % max
+        }
+    }
+
+    /// Returns true with the given probability.
+    pub fn chance(&mut self, probability: f64) -> bool {
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        unit < probability
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+
+    /// Pick up to `count` distinct items.
+    pub fn sample<'a, T>(&mut self, items: &'a [T], count: usize) -> Vec<&'a T> {
+        let mut indices: Vec<usize> = (0..items.len()).collect();
+        let count = count.min(items.len());
+        for i in 0..count {
+            let j = i + self.below((items.len() - i) as u64) as usize;
+            indices.swap(i, j);
+        }
+        indices[..count].iter().map(|i| &items[*i]).collect()
+    }
+}
@@ -0,0 +1,469 @@
+//! Weekly timesheets and their approval.
+//!
+//! A user submits a finished week, which locks all timelogs overlapping it.
+//! Admins and owners of an organization the user is a member of approve or
+//! reject it. A rejection reopens the week, so it can be fixed and submitted
+//! again. Every status change is kept as an event.
+//!
+//! Weeks start on Monday in the user's time zone. The instants a week covers
+//! are stored with the timesheet, so the database can enforce the lock.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use time::{format_description::well_known::Rfc3339, Date, Duration, OffsetDateTime};
+
+use crate::{
+    db::{
+        all_timelogs,
+        client_supabase::SupaDb,
+        types::{
+            MembershipFilter, Timelog, Timesheet, TimesheetCreate, TimesheetEvent,
+            TimesheetEventCreate, TimesheetFilter, TimesheetId, TimesheetPatch, TimesheetQuery,
+            User, UserId,
+        },
+        user_active_timelogs, user_timelogs_in_range, Db,
+    },
+    PublicError,
+};
+
+use super::{
+    locale::Zone,
+    orgs::{user_organizations, Role},
+    reports::Grouping,
+    user::usernames,
+};
+
+/// Weeks listed for submission.
+pub const RECENT_WEEKS: i64 = 8;
+
+/// Longest accepted comment, in characters.
+const MAX_COMMENT_CHARS: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimesheetStatus {
+    Submitted,
+    Approved,
+    /// Reopened for changes.
+    Rejected,
+}
+
+impl TimesheetStatus {
+    pub const ALL: [Self; 3] = [Self::Submitted, Self::Approved, Self::Rejected];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Submitted => "submitted",
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+        }
+    }
+
+    /// Whether the timelogs of the week may not change.
+    pub fn is_locked(&self) -> bool {
+        matches!(self, Self::Submitted | Self::Approved)
+    }
+}
+
+impl std::str::FromStr for TimesheetStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|st| st.as_str() == s)
+            .ok_or_else(|| PublicError::msg(format!("Unknown timesheet status '{s}'")).into())
+    }
+}
+
+/// Status of a timesheet as stored.
+pub fn status_of(timesheet: &Timesheet) -> TimesheetStatus {
+    // The database only allows known statuses.
+    timesheet
+        .status
+        .parse()
+        .unwrap_or(TimesheetStatus::Submitted)
+}
+
+/// A week of a user with its tracked time.
+#[derive(Clone, Debug)]
+pub struct WeekSummary {
+    /// The Monday.
+    pub start: Date,
+    pub total: Duration,
+    pub timesheet: Option<Timesheet>,
+}
+
+/// A timesheet with everything needed to review it.
+#[derive(Clone, Debug)]
+pub struct TimesheetReview {
+    pub timesheet: Timesheet,
+    pub username: String,
+    /// Timelogs overlapping the week, oldest first.
+    pub logs: Vec<Timelog>,
+    /// Time within the week.
+    pub total: Duration,
+    /// With the username of the actor, oldest first.
+    pub events: Vec<(TimesheetEvent, String)>,
+    /// Whether the viewing user may approve or reject it now.
+    pub can_decide: bool,
+}
+
+/// Periods of a user that are locked by submitted or approved timesheets.
+#[derive(Clone, Debug, Default)]
+pub struct Locks {
+    periods: Vec<(OffsetDateTime, OffsetDateTime)>,
+}
+
+impl Locks {
+    pub fn for_user(db: &SupaDb, user_id: UserId) -> Result<Self, anyhow::Error> {
+        let query = TimesheetQuery {
+            limit: 1000,
+            ..TimesheetQuery::new_for_user(user_id)
+        };
+        Ok(Self {
+            periods: db
+                .timesheets(query)?
+                .into_iter()
+                .filter(|t| status_of(t).is_locked())
+                .map(|t| (t.period_start, t.period_end))
+                .collect(),
+        })
+    }
+
+    /// Whether the timelog overlaps a locked week. Running timelogs extend
+    /// to the future.
+    pub fn is_locked(&self, log: &Timelog) -> bool {
+        self.overlaps(log.started_at, log.finished_at())
+    }
+
+    /// Whether a timelog from `started_at` to `finished_at` would overlap a
+    /// locked week.
+    pub fn overlaps(
+        &self,
+        started_at: OffsetDateTime,
+        finished_at: Option<OffsetDateTime>,
+    ) -> bool {
+        self.periods.iter().any(|(start, stop)| {
+            let before_end = started_at < *stop;
+            match finished_at {
+                Some(end) => before_end && end > *start,
+                None => before_end,
+            }
+        })
+    }
+
+    /// Fail with a public error if the timelog is locked.
+    pub fn ensure_unlocked(&self, log: &Timelog) -> Result<(), anyhow::Error> {
+        self.ensure_open(log.started_at, log.finished_at())
+    }
+
+    /// Fail with a public error if a timelog from `started_at` to
+    /// `finished_at` would be locked.
+    pub fn ensure_open(
+        &self,
+        started_at: OffsetDateTime,
+        finished_at: Option<OffsetDateTime>,
+    ) -> Result<(), anyhow::Error> {
+        if self.overlaps(started_at, finished_at) {
+            return Err(PublicError::msg(
+                "This entry is part of a submitted timesheet and can't be changed",
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Time of the timelog within `start..end`.
+fn time_within(log: &Timelog, start: OffsetDateTime, end: OffsetDateTime) -> Duration {
+    let Some(finished_at) = log.finished_at() else {
+        return Duration::ZERO;
+    };
+    let (from, to) = (log.started_at.max(start), finished_at.min(end));
+    if to > from {
+        to - from
+    } else {
+        Duration::ZERO
+    }
+}
+
+/// The instants a week covers in the zone.
+fn week_period(zone: Zone, start: Date) -> (OffsetDateTime, OffsetDateTime) {
+    (
+        zone.start_of_day(start),
+        zone.start_of_day(start + Duration::weeks(1)),
+    )
+}
+
+/// The last [`RECENT_WEEKS`] weeks that are over, newest first.
+pub fn user_recent_weeks(
+    db: &SupaDb,
+    user: &User,
+    now: OffsetDateTime,
+) -> Result<Vec<WeekSummary>, anyhow::Error> {
+    let zone = Zone::for_user(user);
+    let this_week = Grouping::Week.period_start(zone.to_local(now).date());
+    let weeks = (1..=RECENT_WEEKS)
+        .map(|i| this_week - Duration::weeks(i))
+        .collect::<Vec<_>>();
+    let (from, _) = week_period(zone, weeks[weeks.len() - 1]);
+    let (until, _) = week_period(zone, this_week);
+    let logs = all_timelogs(db, user_timelogs_in_range(user.id, from, until))
+        .context("Could not load timelogs")?;
+    let mut timesheets = db
+        .timesheets(TimesheetQuery {
+            limit: RECENT_WEEKS as u64,
+            ..TimesheetQuery::new_for_user(user.id)
+        })?
+        .into_iter()
+        .map(|t| (t.week_start.clone(), t))
+        .collect::<HashMap<_, _>>();
+
+    Ok(weeks
+        .into_iter()
+        .map(|start| {
+            let (period_start, period_end) = week_period(zone, start);
+            WeekSummary {
+                start,
+                total: logs
+                    .iter()
+                    .map(|log| time_within(log, period_start, period_end))
+                    .sum(),
+                timesheet: timesheets.remove(&start.to_string()),
+            }
+        })
+        .collect())
+}
+
+/// Submit the week containing `date` for approval.
+///
+/// Only weeks that are over and have no running timer can be submitted. A
+/// rejected week is submitted again.
+pub fn timesheet_submit(
+    db: &SupaDb,
+    user: &User,
+    date: Date,
+    now: OffsetDateTime,
+) -> Result<Timesheet, anyhow::Error> {
+    let zone = Zone::for_user(user);
+    let week_start = Grouping::Week.period_start(date);
+    let (period_start, period_end) = week_period(zone, week_start);
+    if period_end > now {
+        return Err(PublicError::msg("Only weeks that are over can be submitted").into());
+    }
+    let running = db.timelogs(user_active_timelogs(user.id))?;
+    if running.iter().any(|log| log.started_at < period_end) {
+        return Err(PublicError::msg("Finish the running timer before submitting the week").into());
+    }
+
+    let filter =
+        TimesheetFilter::UserId(user.id).and(TimesheetFilter::WeekStart(week_start.to_string()));
+    let existing = db
+        .timesheets(TimesheetQuery {
+            filter: Some(filter),
+            limit: 1,
+            offset: 0,
+        })?
+        .into_iter()
+        .next();
+    let already_submitted = || PublicError::msg("This week has already been submitted");
+    let timesheet = match existing {
+        Some(t) if status_of(&t).is_locked() => return Err(already_submitted().into()),
+        Some(t) => {
+            let patch = TimesheetPatch {
+                status: Some(TimesheetStatus::Submitted.as_str().to_string()),
+                submitted_at: Some(now.format(&Rfc3339)?),
+                decided_at: Some(None),
+                decided_by: Some(None),
+                comment: Some(None),
+            };
+            let filter = TimesheetFilter::Id(t.id).and(TimesheetFilter::Status(
+                TimesheetStatus::Rejected.as_str().to_string(),
+            ));
+            db.timesheet_update(filter, patch)?
+                .into_iter()
+                .next()
+                .ok_or_else(already_submitted)?
+        }
+        None => {
+            let create = TimesheetCreate {
+                user_id: user.id,
+                week_start: week_start.to_string(),
+                period_start,
+                period_end,
+                status: TimesheetStatus::Submitted.as_str().to_string(),
+            };
+            db.timesheet_create(create)
+                .map_err(|err| match err.constraint() {
+                    Some("unique_timesheet_per_week") => already_submitted().into(),
+                    _ => anyhow::Error::from(err),
+                })?
+        }
+    };
+    db.timesheet_event_create(TimesheetEventCreate {
+        timesheet_id: timesheet.id,
+        actor_id: user.id,
+        status: timesheet.status.clone(),
+        comment: None,
+    })?;
+    Ok(timesheet)
+}
+
+/// Ids of the users whose timesheets the user approves: the other members of
+/// all organizations the user is an admin or owner of.
+fn approved_user_ids(db: &SupaDb, user: &User) -> Result<Vec<UserId>, anyhow::Error> {
+    let mut ids = Vec::new();
+    for (org, role) in user_organizations(db, user)? {
+        if role < Role::Admin {
+            continue;
+        }
+        for membership in db.memberships(MembershipFilter::OrganizationId(org.id))? {
+            if membership.user_id != user.id && !ids.contains(&membership.user_id) {
+                ids.push(membership.user_id);
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// Submitted timesheets waiting for the user's approval, oldest week first,
+/// with the username of the submitter.
+pub fn pending_approvals(
+    db: &SupaDb,
+    user: &User,
+) -> Result<Vec<(Timesheet, String)>, anyhow::Error> {
+    let ids = approved_user_ids(db, user)?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let filter = TimesheetFilter::UserIdIn(ids.clone()).and(TimesheetFilter::Status(
+        TimesheetStatus::Submitted.as_str().to_string(),
+    ));
+    let mut timesheets = db.timesheets(TimesheetQuery {
+        filter: Some(filter),
+        limit: 1000,
+        offset: 0,
+    })?;
+    timesheets.reverse();
+    let names = usernames(db, ids)?;
+    Ok(timesheets
+        .into_iter()
+        .map(|t| {
+            let name = names.get(&t.user_id).cloned().unwrap_or_default();
+            (t, name)
+        })
+        .collect())
+}
+
+/// Load a timesheet for its user or one of their approvers.
+pub fn timesheet_review(
+    db: &SupaDb,
+    user: &User,
+    id: TimesheetId,
+) -> Result<TimesheetReview, anyhow::Error> {
+    let not_found = || PublicError::msg("Timesheet not found");
+    let timesheet = db
+        .timesheets(TimesheetQuery {
+            filter: Some(TimesheetFilter::Id(id)),
+            limit: 1,
+            offset: 0,
+        })?
+        .into_iter()
+        .next()
+        .ok_or_else(not_found)?;
+    let is_approver = approved_user_ids(db, user)?.contains(&timesheet.user_id);
+    if timesheet.user_id != user.id && !is_approver {
+        return Err(not_found().into());
+    }
+
+    let query = user_timelogs_in_range(
+        timesheet.user_id,
+        timesheet.period_start,
+        timesheet.period_end,
+    );
+    let logs = all_timelogs(db, query).context("Could not load timelogs")?;
+    let total = logs
+        .iter()
+        .map(|log| time_within(log, timesheet.period_start, timesheet.period_end))
+        .sum();
+    let events = db.timesheet_events(timesheet.id)?;
+    let mut ids = events.iter().map(|e| e.actor_id).collect::<Vec<_>>();
+    ids.push(timesheet.user_id);
+    ids.sort_unstable();
+    ids.dedup();
+    let names = usernames(db, ids)?;
+    Ok(TimesheetReview {
+        username: names.get(&timesheet.user_id).cloned().unwrap_or_default(),
+        can_decide: is_approver && status_of(&timesheet) == TimesheetStatus::Submitted,
+        logs,
+        total,
+        events: events
+            .into_iter()
+            .map(|e| {
+                let name = names.get(&e.actor_id).cloned().unwrap_or_default();
+                (e, name)
+            })
+            .collect(),
+        timesheet,
+    })
+}
+
+/// Approve or reject a submitted timesheet. Rejections need a comment.
+pub fn timesheet_decide(
+    db: &SupaDb,
+    user: &User,
+    id: TimesheetId,
+    approve: bool,
+    comment: &str,
+    now: OffsetDateTime,
+) -> Result<Timesheet, anyhow::Error> {
+    let review = timesheet_review(db, user, id)?;
+    if !review.can_decide {
+        return Err(PublicError::msg("This timesheet is not waiting for your approval").into());
+    }
+    let comment = Some(comment.trim())
+        .filter(|c| !c.is_empty())
+        .map(str::to_string);
+    if comment
+        .as_ref()
+        .is_some_and(|c| c.chars().count() > MAX_COMMENT_CHARS)
+    {
+        return Err(PublicError::msg(format!(
+            "The comment may be at most {MAX_COMMENT_CHARS} characters long"
+        ))
+        .into());
+    }
+    if !approve && comment.is_none() {
+        return Err(PublicError::msg("Please give a reason for the rejection").into());
+    }
+
+    let status = if approve {
+        TimesheetStatus::Approved
+    } else {
+        TimesheetStatus::Rejected
+    };
+    let patch = TimesheetPatch {
+        status: Some(status.as_str().to_string()),
+        decided_at: Some(Some(now.format(&Rfc3339)?)),
+        decided_by: Some(Some(user.id)),
+        comment: Some(comment.clone()),
+        ..Default::default()
+    };
+    // Only change it if nobody else decided in the meantime.
+    let filter = TimesheetFilter::Id(id).and(TimesheetFilter::Status(
+        TimesheetStatus::Submitted.as_str().to_string(),
+    ));
+    let timesheet = db
+        .timesheet_update(filter, patch)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PublicError::msg("This timesheet is not waiting for your approval"))?;
+    db.timesheet_event_create(TimesheetEventCreate {
+        timesheet_id: timesheet.id,
+        actor_id: user.id,
+        status: timesheet.status.clone(),
+        comment,
+    })?;
+    Ok(timesheet)
+}
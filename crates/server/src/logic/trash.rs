@@ -0,0 +1,412 @@
+//! Soft deletion of timelogs, tags and projects.
+//!
+//! Deleting moves a row to the trash by setting `deleted_at`, which hides it
+//! from all other queries. From the trash it can be restored, or purged for
+//! good. Rows that stay in the trash longer than the retention period are
+//! purged when their user opens the trash, and for all users by the
+//! `purge-trash` command.
+//!
+//! Invoiced timelogs and those of submitted timesheets can't be deleted.
+
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    db::{
+        client_supabase::SupaDb,
+        error::DbError,
+        types::{
+            OrganizationId, Project, ProjectFilter, ProjectId, ProjectPatch, ProjectQuery, Timelog,
+            TimelogFilter, TimelogId, TimelogPatch, TimelogQuery, User, UserTag, UserTagFilter,
+            UserTagId, UserTagPatch, UserTagQuery,
+        },
+        Db,
+    },
+    PublicError,
+};
+
+use super::{
+    orgs::{user_organizations, Role},
+    projects::{project_for_update, tag_for_update},
+    timesheets::Locks,
+};
+
+/// Days deleted rows are kept, unless configured otherwise.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrashKind {
+    Timelog,
+    Tag,
+    Project,
+}
+
+impl TrashKind {
+    pub const ALL: [Self; 3] = [Self::Timelog, Self::Tag, Self::Project];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Timelog => "timelog",
+            Self::Tag => "tag",
+            Self::Project => "project",
+        }
+    }
+}
+
+impl std::str::FromStr for TrashKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == s)
+            .ok_or_else(|| PublicError::msg(format!("Unknown kind '{s}'")).into())
+    }
+}
+
+/// The deleted rows a user can restore or purge.
+#[derive(Clone, Debug, Default)]
+pub struct Trash {
+    pub timelogs: Vec<Timelog>,
+    pub tags: Vec<UserTag>,
+    pub projects: Vec<Project>,
+}
+
+impl Trash {
+    pub fn is_empty(&self) -> bool {
+        self.timelogs.is_empty() && self.tags.is_empty() && self.projects.is_empty()
+    }
+}
+
+/// Rows removed by [`purge_expired`] and [`purge_expired_for_user`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PurgeSummary {
+    pub timelogs: usize,
+    pub tags: usize,
+    pub projects: usize,
+    /// Projects that could not be purged, because invoiced or submitted
+    /// timelogs are on them.
+    pub projects_kept: usize,
+}
+
+fn timestamp(t: OffsetDateTime) -> String {
+    t.format(&Rfc3339)
+        .expect("timestamps within the supported range can be formatted")
+}
+
+/// Load a timelog of the user, in the trash or not.
+fn owned_timelog(
+    db: &SupaDb,
+    user: &User,
+    id: TimelogId,
+    deleted: bool,
+) -> Result<Timelog, anyhow::Error> {
+    let query = TimelogQuery {
+        filter: Some(TimelogFilter::And(vec![
+            TimelogFilter::Id(id),
+            TimelogFilter::UserId(user.id),
+            TimelogFilter::IsDeleted(deleted),
+        ])),
+        limit: 1,
+        ..TimelogQuery::new()
+    };
+    db.timelogs(query)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PublicError::msg("Timelog not found").into())
+}
+
+/// Move a timelog of the user to the trash.
+pub fn timelog_delete(
+    db: &SupaDb,
+    user: &User,
+    id: TimelogId,
+    now: OffsetDateTime,
+) -> Result<Timelog, anyhow::Error> {
+    let log = owned_timelog(db, user, id, false)?;
+    if log.invoice_id.is_some() {
+        return Err(PublicError::msg("Invoiced entries can't be deleted").into());
+    }
+    Locks::for_user(db, user.id)?.ensure_unlocked(&log)?;
+    let patch = TimelogPatch {
+        deleted_at: Some(Some(timestamp(now))),
+        ..Default::default()
+    };
+    update_timelog(db, log, patch)
+}
+
+fn update_timelog(
+    db: &SupaDb,
+    log: Timelog,
+    patch: TimelogPatch,
+) -> Result<Timelog, anyhow::Error> {
+    let selector = TimelogQuery {
+        filter: Some(
+            TimelogFilter::Id(log.id).and(TimelogFilter::IsDeleted(log.deleted_at.is_some())),
+        ),
+        ..TimelogQuery::new()
+    };
+    db.timelog_update(selector, patch)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PublicError::msg("Timelog not found").into())
+}
+
+/// Move a tag to the trash. Its timelogs keep their other tags.
+pub fn tag_delete(
+    db: &SupaDb,
+    user: &User,
+    id: UserTagId,
+    now: OffsetDateTime,
+) -> Result<(), anyhow::Error> {
+    let tag = tag_for_update(db, user, id)?;
+    let patch = UserTagPatch {
+        deleted_at: Some(Some(timestamp(now))),
+        ..Default::default()
+    };
+    db.user_tag_update(UserTagFilter::Id(tag.id), patch)?;
+    Ok(())
+}
+
+/// Move a project to the trash. Its timelogs stay, without a project.
+pub fn project_delete(
+    db: &SupaDb,
+    user: &User,
+    id: ProjectId,
+    now: OffsetDateTime,
+) -> Result<(), anyhow::Error> {
+    let project = project_for_update(db, user, id)?;
+    let patch = ProjectPatch {
+        deleted_at: Some(Some(timestamp(now))),
+        ..Default::default()
+    };
+    db.project_update(ProjectFilter::Id(project.id), patch)?;
+    Ok(())
+}
+
+/// The trash of a user: their deleted timelogs, and the deleted tags and
+/// projects they may change.
+pub fn user_trash(db: &SupaDb, user: &User) -> Result<Trash, anyhow::Error> {
+    let mut timelogs = db.timelogs(TimelogQuery {
+        filter: Some(TimelogFilter::UserId(user.id).and(TimelogFilter::IsDeleted(true))),
+        limit: 1000,
+        ..TimelogQuery::new()
+    })?;
+
+    let organization_ids = managed_organization_ids(db, user)?;
+    let tags = db.user_tags(UserTagQuery {
+        filter: Some(
+            UserTagFilter::Visible {
+                user_id: user.id,
+                organization_ids: organization_ids.clone(),
+            }
+            .and(UserTagFilter::IsDeleted(true)),
+        ),
+        ..UserTagQuery::new_for_user(user.id)
+    })?;
+    let projects = db.projects(ProjectQuery {
+        filter: Some(
+            ProjectFilter::Visible {
+                user_id: user.id,
+                organization_ids,
+            }
+            .and(ProjectFilter::IsDeleted(true)),
+        ),
+        ..ProjectQuery::new_for_user(user.id)
+    })?;
+
+    timelogs.sort_by_key(|log| std::cmp::Reverse(log.deleted_at));
+    Ok(Trash {
+        timelogs,
+        tags,
+        projects,
+    })
+}
+
+/// Organizations whose shared tags and projects the user may change.
+fn managed_organization_ids(
+    db: &SupaDb,
+    user: &User,
+) -> Result<Vec<OrganizationId>, anyhow::Error> {
+    Ok(user_organizations(db, user)?
+        .into_iter()
+        .filter(|(_, role)| *role >= Role::Admin)
+        .map(|(org, _)| org.id)
+        .collect())
+}
+
+/// Take a row out of the trash.
+pub fn trash_restore(
+    db: &SupaDb,
+    user: &User,
+    kind: TrashKind,
+    id: u64,
+) -> Result<(), anyhow::Error> {
+    match kind {
+        TrashKind::Timelog => {
+            let log = owned_timelog(db, user, id, true)?;
+            Locks::for_user(db, user.id)?.ensure_unlocked(&log)?;
+            let patch = TimelogPatch {
+                deleted_at: Some(None),
+                ..Default::default()
+            };
+            update_timelog(db, log, patch)?;
+        }
+        TrashKind::Tag => {
+            let tag = trashed_tag(db, user, id)?;
+            let patch = UserTagPatch {
+                deleted_at: Some(None),
+                ..Default::default()
+            };
+            let filter = UserTagFilter::Id(tag.id).and(UserTagFilter::IsDeleted(true));
+            db.user_tag_update(filter, patch)
+                .map_err(|err| match err.constraint() {
+                    Some("unique_name_per_user" | "unique_tag_name_per_organization") => {
+                        PublicError::msg(format!("A tag named '{}' already exists", tag.name))
+                            .into()
+                    }
+                    _ => anyhow::Error::from(err),
+                })?;
+        }
+        TrashKind::Project => {
+            let project = trashed_project(db, user, id)?;
+            let patch = ProjectPatch {
+                deleted_at: Some(None),
+                ..Default::default()
+            };
+            let filter = ProjectFilter::Id(project.id).and(ProjectFilter::IsDeleted(true));
+            db.project_update(filter, patch)
+                .map_err(|err| match err.constraint() {
+                    Some(
+                        "unique_project_name_per_user" | "unique_project_name_per_organization",
+                    ) => PublicError::msg(format!(
+                        "A project named '{}' already exists",
+                        project.name
+                    ))
+                    .into(),
+                    _ => anyhow::Error::from(err),
+                })?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete a row in the trash for good.
+pub fn trash_purge(
+    db: &SupaDb,
+    user: &User,
+    kind: TrashKind,
+    id: u64,
+) -> Result<(), anyhow::Error> {
+    match kind {
+        TrashKind::Timelog => {
+            let log = owned_timelog(db, user, id, true)?;
+            db.timelog_delete(TimelogFilter::Id(log.id).and(TimelogFilter::IsDeleted(true)))?;
+        }
+        TrashKind::Tag => {
+            let tag = trashed_tag(db, user, id)?;
+            db.user_tag_delete(UserTagFilter::Id(tag.id).and(UserTagFilter::IsDeleted(true)))?;
+        }
+        TrashKind::Project => {
+            let project = trashed_project(db, user, id)?;
+            let filter = ProjectFilter::Id(project.id).and(ProjectFilter::IsDeleted(true));
+            db.project_delete(filter).map_err(|err| match err {
+                DbError::CheckViolation { .. } => {
+                    PublicError::msg("Projects with invoiced or submitted entries can't be purged")
+                        .into()
+                }
+                err => anyhow::Error::from(err),
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn trashed_tag(db: &SupaDb, user: &User, id: UserTagId) -> Result<UserTag, anyhow::Error> {
+    user_trash(db, user)?
+        .tags
+        .into_iter()
+        .find(|t| t.id == id)
+        .ok_or_else(|| PublicError::msg("Tag not found").into())
+}
+
+fn trashed_project(db: &SupaDb, user: &User, id: ProjectId) -> Result<Project, anyhow::Error> {
+    user_trash(db, user)?
+        .projects
+        .into_iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| PublicError::msg("Project not found").into())
+}
+
+/// Purge the rows of all users that were deleted before `before`.
+pub fn purge_expired(db: &SupaDb, before: OffsetDateTime) -> Result<PurgeSummary, anyhow::Error> {
+    let mut summary = PurgeSummary {
+        timelogs: db
+            .timelog_delete(TimelogFilter::DeletedBefore(before))?
+            .len(),
+        tags: db
+            .user_tag_delete(UserTagFilter::DeletedBefore(before))?
+            .len(),
+        ..Default::default()
+    };
+    purge_projects(
+        db,
+        ProjectFilter::DeletedBefore(before),
+        before,
+        &mut summary,
+    )?;
+    Ok(summary)
+}
+
+/// Purge the rows in the trash of a user that were deleted before `before`,
+/// the same rows [`user_trash`] lists.
+pub fn purge_expired_for_user(
+    db: &SupaDb,
+    user: &User,
+    before: OffsetDateTime,
+) -> Result<PurgeSummary, anyhow::Error> {
+    let organization_ids = managed_organization_ids(db, user)?;
+    let timelogs = TimelogFilter::UserId(user.id).and(TimelogFilter::DeletedBefore(before));
+    let tags = UserTagFilter::Visible {
+        user_id: user.id,
+        organization_ids: organization_ids.clone(),
+    }
+    .and(UserTagFilter::DeletedBefore(before));
+    let mut summary = PurgeSummary {
+        timelogs: db.timelog_delete(timelogs)?.len(),
+        tags: db.user_tag_delete(tags)?.len(),
+        ..Default::default()
+    };
+    let projects = ProjectFilter::Visible {
+        user_id: user.id,
+        organization_ids,
+    }
+    .and(ProjectFilter::DeletedBefore(before));
+    purge_projects(db, projects, before, &mut summary)?;
+    Ok(summary)
+}
+
+fn purge_projects(
+    db: &SupaDb,
+    filter: ProjectFilter,
+    before: OffsetDateTime,
+    summary: &mut PurgeSummary,
+) -> Result<(), anyhow::Error> {
+    // One by one, so a project that can't be purged doesn't keep the others.
+    let projects = db.projects(ProjectQuery {
+        filter: Some(filter),
+        limit: 1000,
+        offset: 0,
+    })?;
+    for project in projects {
+        let filter = ProjectFilter::Id(project.id).and(ProjectFilter::DeletedBefore(before));
+        match db.project_delete(filter) {
+            Ok(deleted) => summary.projects += deleted.len(),
+            Err(DbError::CheckViolation { .. }) => {
+                log::warn!(project_id = project.id; "could not purge project");
+                summary.projects_kept += 1;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
@@ -1,15 +1,19 @@
+use std::collections::HashMap;
+
 use anyhow::{bail, Context};
 use time::OffsetDateTime;
 
 use crate::{
     db::{
         client_supabase::SupaDb,
-        types::{User, UserCreate, UserFilter},
+        types::{User, UserCreate, UserFilter, UserId, UserPatch, UserQuery},
         Db,
     },
     PublicError,
 };
 
+use super::locale::{Locale, Zone};
+
 pub fn validate_password_hash(hash: &str, password: &str) -> Result<bool, anyhow::Error> {
     let pw_hash = hash_password(password)?;
     Ok(hash == pw_hash)
@@ -121,7 +125,53 @@ pub fn user_signup(db: &SupaDb, signup: Signup) -> Result<User, anyhow::Error> {
         password_hash,
     };
 
-    db.user_create(pre)
+    db.user_create(pre).map_err(|err| match err.constraint() {
+        Some("users_username_key") => PublicError::msg("Username is already taken").into(),
+        Some("users_email_key") => {
+            PublicError::msg("An account with this email address already exists").into()
+        }
+        Some("username_length") => {
+            PublicError::msg("Username must be between 3 and 20 characters long").into()
+        }
+        _ => anyhow::Error::from(err),
+    })
+}
+
+/// Usernames of the given users. Unknown ids are left out.
+pub(crate) fn usernames(
+    db: &SupaDb,
+    ids: Vec<UserId>,
+) -> Result<HashMap<UserId, String>, anyhow::Error> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    Ok(db
+        .users(UserQuery {
+            filter: Some(UserFilter::IdIn(ids)),
+            limit: 1000,
+            offset: 0,
+        })?
+        .into_iter()
+        .map(|u| (u.id, u.username))
+        .collect())
+}
+
+/// Change the time zone and locale of a user.
+pub fn user_update_preferences(
+    db: &SupaDb,
+    user: &User,
+    timezone: &str,
+    locale: &str,
+) -> Result<User, anyhow::Error> {
+    let zone = Zone::from_name(timezone.trim())?;
+    let locale: Locale = locale.trim().parse()?;
+
+    let patch = UserPatch {
+        timezone: Some(zone.name().to_string()),
+        locale: Some(locale.code().to_string()),
+        ..Default::default()
+    };
+    db.user_update(user.id, patch).map_err(From::from)
 }
 
 fn validate_email_address(val: &str) -> Result<(), anyhow::Error> {
@@ -2,6 +2,17 @@ use timely_server::Config;
 
 fn main() {
     let config = Config::from_env().expect("invalid configuration");
+    timely_server::logging::init(config.log_level, config.log_format);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(err) = timely_server::cli::run(config, &args) {
+            eprintln!("Command failed: {err:?}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let ctx = timely_server::Context::new(config).expect("could not build server context");
     wcgi::serve_once(move |req| timely_server::handler(&ctx, req));
 }
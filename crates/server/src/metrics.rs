@@ -0,0 +1,195 @@
+//! Metrics in the Prometheus text format.
+//!
+//! Every request is served by a fresh process, so counters can't live in
+//! memory. A request collects its increments and [`flush`]es them to the
+//! `metric_counters` table when it is done, and `/metrics` renders the
+//! totals from there. The database call of the flush itself is not counted.
+
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+use crate::db::{client_supabase::SupaDb, types::MetricCounter, Db};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Metric families: name, type and help text.
+const FAMILIES: [(&str, &str, &str); 6] = [
+    (
+        "timely_http_requests_total",
+        "counter",
+        "Handled HTTP requests.",
+    ),
+    (
+        "timely_http_request_duration_seconds",
+        "histogram",
+        "HTTP request latency.",
+    ),
+    ("timely_db_requests_total", "counter", "Supabase API calls."),
+    (
+        "timely_db_request_duration_seconds",
+        "histogram",
+        "Supabase API call latency.",
+    ),
+    ("timely_logins_total", "counter", "Login attempts."),
+    (
+        "timely_active_timers",
+        "gauge",
+        "Currently running timelogs across all users.",
+    ),
+];
+
+/// Increments of this process that are not flushed yet, by series name and
+/// labels.
+static PENDING: Mutex<BTreeMap<(String, String), f64>> = Mutex::new(BTreeMap::new());
+
+fn add(name: String, labels: String, value: f64) {
+    *PENDING.lock().unwrap().entry((name, labels)).or_default() += value;
+}
+
+/// Add an observation to the cumulative buckets, sum and count of a
+/// histogram.
+fn observe(family: &str, labels: String, value: f64) {
+    for le in BUCKETS.iter().filter(|le| value <= **le) {
+        add(
+            format!("{family}_bucket"),
+            format!("{labels},le=\"{le}\""),
+            1.0,
+        );
+    }
+    add(
+        format!("{family}_bucket"),
+        format!("{labels},le=\"+Inf\""),
+        1.0,
+    );
+    add(format!("{family}_sum"), labels.clone(), value);
+    add(format!("{family}_count"), labels, 1.0);
+}
+
+pub fn record_http_request(route: &str, method: &str, status: u16, duration: Duration) {
+    add(
+        "timely_http_requests_total".to_string(),
+        format!(
+            "route=\"{}\",method=\"{}\",status=\"{status}\"",
+            escape(route),
+            escape(method)
+        ),
+        1.0,
+    );
+    observe(
+        "timely_http_request_duration_seconds",
+        format!("route=\"{}\"", escape(route)),
+        duration.as_secs_f64(),
+    );
+}
+
+pub fn record_db_request(table: &str, operation: &str, success: bool, duration: Duration) {
+    let outcome = if success { "success" } else { "error" };
+    add(
+        "timely_db_requests_total".to_string(),
+        format!(
+            "table=\"{}\",operation=\"{}\",outcome=\"{outcome}\"",
+            escape(table),
+            escape(operation)
+        ),
+        1.0,
+    );
+    observe(
+        "timely_db_request_duration_seconds",
+        format!(
+            "table=\"{}\",operation=\"{}\"",
+            escape(table),
+            escape(operation)
+        ),
+        duration.as_secs_f64(),
+    );
+}
+
+pub fn record_login(success: bool) {
+    let outcome = if success { "success" } else { "failure" };
+    add(
+        "timely_logins_total".to_string(),
+        format!("outcome=\"{outcome}\""),
+        1.0,
+    );
+}
+
+/// Add the increments of this process to the stored counters.
+///
+/// Failures are only logged, metrics must not break requests.
+pub fn flush(db: &SupaDb) {
+    let increments = std::mem::take(&mut *PENDING.lock().unwrap())
+        .into_iter()
+        .map(|((name, labels), value)| MetricCounter {
+            name,
+            labels,
+            value,
+        })
+        .collect::<Vec<_>>();
+    if increments.is_empty() {
+        return;
+    }
+    if let Err(err) = db.metric_counters_add(&increments) {
+        log::warn!(error:% = err; "could not record metrics");
+    }
+}
+
+/// Values that are computed at scrape time.
+pub struct Gauges {
+    pub active_timers: u64,
+}
+
+/// Render the stored counters and the gauges in the Prometheus text
+/// exposition format.
+pub fn render(counters: &[MetricCounter], gauges: &Gauges) -> String {
+    let mut counters = counters.iter().collect::<Vec<_>>();
+    counters.sort_by(|a, b| {
+        let (a, b) = (sort_key(a), sort_key(b));
+        (a.0, a.1).cmp(&(b.0, b.1)).then(a.2.total_cmp(&b.2))
+    });
+
+    let mut out = String::new();
+    for (family, kind, help) in FAMILIES {
+        writeln!(out, "# HELP {family} {help}").unwrap();
+        writeln!(out, "# TYPE {family} {kind}").unwrap();
+        if family == "timely_active_timers" {
+            writeln!(out, "{family} {}", gauges.active_timers).unwrap();
+            continue;
+        }
+        let series = counters.iter().filter(|c| match kind {
+            "histogram" => c
+                .name
+                .strip_prefix(family)
+                .is_some_and(|suffix| ["_bucket", "_sum", "_count"].contains(&suffix)),
+            _ => c.name == family,
+        });
+        for counter in series {
+            writeln!(
+                out,
+                "{}{{{}}} {}",
+                counter.name, counter.labels, counter.value
+            )
+            .unwrap();
+        }
+    }
+    out
+}
+
+/// Order series by name and labels, and buckets by their upper bound.
+fn sort_key(counter: &MetricCounter) -> (&str, &str, f64) {
+    match counter.labels.rsplit_once(",le=\"") {
+        Some((labels, le)) => {
+            let le = le.trim_end_matches('"').parse().unwrap_or(f64::INFINITY);
+            (&counter.name, labels, le)
+        }
+        None => (&counter.name, &counter.labels, 0.0),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
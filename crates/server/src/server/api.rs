@@ -0,0 +1,83 @@
+//! API routes under `/api/`.
+//!
+//! Requests authenticate with `Authorization: Bearer <token>`, using the same
+//! token as the session cookie. Errors are returned as JSON.
+
+use http::StatusCode;
+use serde_json::json;
+use wcgi::{Request, Response};
+
+use crate::{logic::user::load_user_for_token, PublicError};
+
+use super::{bearer_token, is_db_unavailable, response_json, routes, Context, Method};
+
+/// Handle a request for `/api/<parts>`.
+///
+/// Returns the matched route for metrics, like `handle_request`.
+pub(super) fn handle_api_request(
+    ctx: &Context,
+    req: Request,
+    parts: &[&str],
+) -> (&'static str, Response) {
+    let Some(token) = bearer_token(&req) else {
+        return (
+            "/api/auth",
+            api_error(StatusCode::UNAUTHORIZED, "missing bearer token"),
+        );
+    };
+    let user = match load_user_for_token(&ctx.db, &ctx.config.jwt_token_secret, token) {
+        Ok(user) => user,
+        Err(err) if is_db_unavailable(&err) => {
+            log::error!(error:% = err; "could not load user");
+            return (
+                "/api/auth",
+                api_error(StatusCode::SERVICE_UNAVAILABLE, "service unavailable"),
+            );
+        }
+        Err(err) => {
+            log::warn!(error:% = err; "invalid api token");
+            return (
+                "/api/auth",
+                api_error(StatusCode::UNAUTHORIZED, "invalid token"),
+            );
+        }
+    };
+    let ctx = ctx.with_user(Some(user));
+
+    let (route, res) = match (parts, req.method().clone()) {
+        (["export.csv"], Method::GET) => ("/api/export.csv", routes::export::handler(req, &ctx)),
+        (["import"], Method::POST) => ("/api/import", routes::import::api_handler(req, &ctx)),
+        (["timelog", "switch"], Method::POST) => (
+            "/api/timelog/switch",
+            routes::timelog_switch::api_handler(req, &ctx),
+        ),
+        _ => return ("not_found", api_error(StatusCode::NOT_FOUND, "not found")),
+    };
+
+    let res = match res {
+        Ok(res) => res,
+        Err(err) if is_db_unavailable(&err) => {
+            log::error!(error:% = format!("{err:#}"); "database unavailable");
+            api_error(StatusCode::SERVICE_UNAVAILABLE, "service unavailable")
+        }
+        Err(err) => match err.downcast_ref::<PublicError>() {
+            Some(public) => api_error(StatusCode::BAD_REQUEST, &public.to_string()),
+            None => {
+                log::error!(error:% = format!("{err:#}"); "request failed");
+                api_error(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+            }
+        },
+    };
+    (route, res)
+}
+
+fn api_error(status: StatusCode, message: &str) -> Response {
+    let mut res = response_json(status, &json!({ "error": message }));
+    if status == StatusCode::UNAUTHORIZED {
+        res.headers_mut().insert(
+            http::header::WWW_AUTHENTICATE,
+            http::HeaderValue::from_static("Bearer"),
+        );
+    }
+    res
+}
@@ -1,13 +1,24 @@
+use std::time::{Duration, Instant};
+
 use anyhow::Context as _;
 use cookie::{Cookie, CookieJar};
 use http::{Method, StatusCode};
 use time::OffsetDateTime;
 use wcgi::{Body, Request, Response, ResponseBuilder, WcgiError};
 
-use crate::db::{client_supabase::SupaDb, types::User};
+use crate::{
+    db::{client_supabase::SupaDb, error::DbError, policy::RequestPolicy, types::User},
+    logging::{self, LogFormat},
+    metrics,
+};
 
-use self::{routes::login::build_auth_cookie, ui::error_page};
+use self::{
+    routes::login::build_auth_cookie,
+    ui::{error_page, maintenance_page},
+};
 
+mod api;
+mod multipart;
 mod routes;
 pub mod ui;
 
@@ -31,6 +42,12 @@ pub mod prelude {
         let data: T = serde_urlencoded::from_bytes(&body)?;
         Ok(data)
     }
+
+    pub fn parse_query<T: serde::de::DeserializeOwned>(req: &Request) -> Result<T, anyhow::Error> {
+        let query = req.uri().query().unwrap_or_default();
+        let data: T = serde_urlencoded::from_str(query)?;
+        Ok(data)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -39,6 +56,17 @@ pub struct Config {
     pub supabase_api_key: String,
     /// JWT token secret for encoding and decoding.
     pub jwt_token_secret: String,
+    /// Timeout and retry settings for Supabase calls.
+    pub db_policy: RequestPolicy,
+    pub log_level: log::LevelFilter,
+    pub log_format: LogFormat,
+    /// Bearer token for scraping `/metrics`.
+    /// The endpoint is disabled if not set.
+    pub metrics_token: Option<String>,
+    /// Usernames of the users allowed to query the audit log.
+    pub admins: Vec<String>,
+    /// Days deleted timelogs, tags and projects are kept in the trash.
+    pub trash_retention_days: u32,
 }
 
 impl Config {
@@ -60,14 +88,82 @@ impl Config {
             .filter(|x| !x.is_empty())
             .context("Missing required env var TIMELY_TOKEN_SECRET")?;
 
+        let defaults = RequestPolicy::default();
+        let db_policy = RequestPolicy {
+            timeout: env_parse("TIMELY_DB_TIMEOUT_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.timeout),
+            max_retries: env_parse("TIMELY_DB_MAX_RETRIES")?.unwrap_or(defaults.max_retries),
+            backoff_base: env_parse("TIMELY_DB_BACKOFF_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.backoff_base),
+        };
+
+        let log_level = env_parse("TIMELY_LOG_LEVEL")?.unwrap_or(log::LevelFilter::Info);
+        let log_format = env_parse("TIMELY_LOG_FORMAT")?.unwrap_or(LogFormat::Text);
+
+        let metrics_token = env_parse("TIMELY_METRICS_TOKEN")?;
+        let admins = std::env::var("TIMELY_ADMINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+
+        let trash_retention_days = env_parse("TIMELY_TRASH_RETENTION_DAYS")?
+            .unwrap_or(crate::logic::trash::DEFAULT_RETENTION_DAYS);
+
         Ok(Self {
             supabase_endpoint,
             supabase_api_key,
             jwt_token_secret,
+            db_policy,
+            log_level,
+            log_format,
+            metrics_token,
+            admins,
+            trash_retention_days,
         })
     }
 }
 
+impl Config {
+    /// Check that the configuration is complete and well-formed.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let uri: http::Uri = self
+            .supabase_endpoint
+            .parse()
+            .context("SUPABASE_ENDPOINT is not a valid URL")?;
+        if !matches!(uri.scheme_str(), Some("http" | "https")) || uri.host().is_none() {
+            anyhow::bail!("SUPABASE_ENDPOINT must be an absolute http(s) URL");
+        }
+        if self.supabase_api_key.is_empty() {
+            anyhow::bail!("SUPABASE_KEY is empty");
+        }
+        if self.jwt_token_secret.len() < 16 {
+            anyhow::bail!("TIMELY_TOKEN_SECRET must be at least 16 characters long");
+        }
+        Ok(())
+    }
+}
+
+/// Parse an optional env var.
+fn env_parse<T>(name: &str) -> Result<Option<T>, anyhow::Error>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    std::env::var(name)
+        .ok()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.parse()
+                .map_err(|err| anyhow::anyhow!("Invalid env var {name}: {err}"))
+        })
+        .transpose()
+}
+
 #[derive(Clone)]
 pub struct Context {
     config: Config,
@@ -80,6 +176,7 @@ impl Context {
         let db = SupaDb::new(
             config.supabase_endpoint.clone(),
             config.supabase_api_key.clone(),
+            config.db_policy.clone(),
         )
         .expect("Invalid configuration");
 
@@ -92,24 +189,111 @@ impl Context {
         Ok(c)
     }
 
+    /// A context for requests of the given user, whose changes are recorded
+    /// as made by them.
+    pub fn with_user(&self, user: Option<User>) -> Self {
+        let db = match &user {
+            Some(user) => self.db.with_actor(user.id),
+            None => self.db.clone(),
+        };
+        Self {
+            config: self.config.clone(),
+            db,
+            user,
+        }
+    }
+
     pub fn require_user(&self) -> Result<&User, anyhow::Error> {
         self.user.as_ref().context("expected a user in the context")
     }
+
+    /// Whether the signed in user is configured as an admin.
+    pub fn is_admin(&self) -> bool {
+        self.user
+            .as_ref()
+            .is_some_and(|user| self.config.admins.contains(&user.username))
+    }
 }
 
 pub type HandlerResult = Result<Response, anyhow::Error>;
+type Handler = fn(Request, &Context) -> HandlerResult;
 
 const AUTH_COOKIE_NAME: &str = "timelytoken";
 
+/// Routes whose requests are not flushed to the metric counters.
+const UNMETERED_ROUTES: [&str; 3] = ["/healthz", "/readyz", "/metrics"];
+
 pub fn handler(ctx: &Context, req: Request) -> Result<Response, WcgiError> {
+    let start = Instant::now();
+    let request_id = req
+        .headers()
+        .get(logging::REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| logging::is_valid_request_id(v))
+        .map(|v| v.to_string())
+        .unwrap_or_else(logging::new_request_id);
+    logging::set_request_id(Some(request_id.clone()));
+
+    let method = req.method().clone();
+    let path = logging::redact_path(req.uri().path());
+
+    let (route, mut res) = handle_request(ctx, req);
+    let duration = start.elapsed();
+    metrics::record_http_request(route, method.as_str(), res.status().as_u16(), duration);
+    // Probes and scrapes would add a write to every poll, and a 503 means the
+    // database is unavailable, so the write would only fail as well.
+    let flush =
+        !UNMETERED_ROUTES.contains(&route) && res.status() != StatusCode::SERVICE_UNAVAILABLE;
+    if ctx.config.metrics_token.is_some() && flush {
+        metrics::flush(&ctx.db);
+    }
+
+    if let Ok(value) = request_id.parse() {
+        res.headers_mut().insert(logging::REQUEST_ID_HEADER, value);
+    }
+    log::info!(
+        method:% = method,
+        path = path,
+        route = route,
+        status = res.status().as_u16(),
+        duration_ms = duration.as_millis() as u64;
+        "request finished"
+    );
+    logging::set_request_id(None);
+
+    Ok(res)
+}
+
+/// Dispatch a request.
+///
+/// Also returns the matched route, which is used as the metrics label, so it
+/// must not contain user input.
+fn handle_request(ctx: &Context, req: Request) -> (&'static str, Response) {
     let uri = req.uri();
     let path = uri.path().to_string();
     let path_parts = path
         .strip_prefix('/')
-        .unwrap_or(uri.path())
+        .unwrap_or(&path)
         .split('/')
         .collect::<Vec<_>>();
 
+    // Routes that don't use the session cookie.
+    let public: Option<(&str, Handler)> = match (path_parts.as_slice(), req.method().clone()) {
+        (["healthz"], Method::GET) => Some(("/healthz", routes::health::handler_healthz)),
+        (["readyz"], Method::GET) => Some(("/readyz", routes::health::handler_readyz)),
+        (["metrics"], Method::GET) => Some(("/metrics", routes::metrics::handler)),
+        (["calendar", _], Method::GET) => Some(("/calendar", routes::calendar::handler)),
+        _ => None,
+    };
+    if let Some((route, handler)) = public {
+        let res = handler(req, ctx);
+        return (route, response_from_result(ctx, res));
+    }
+
+    if let ["api", rest @ ..] = path_parts.as_slice() {
+        return api::handle_api_request(ctx, req, rest);
+    }
+
     let cookies = get_cookies(&req);
     let user = match cookies.get(AUTH_COOKIE_NAME) {
         Some(c) => {
@@ -119,53 +303,223 @@ pub fn handler(ctx: &Context, req: Request) -> Result<Response, WcgiError> {
                 c.value(),
             ) {
                 Ok(u) => Some(u),
+                // The token can't be checked while the database is down, so
+                // keep the cookie around.
+                Err(err) if is_db_unavailable(&err) => {
+                    log::error!(error:% = err; "could not load user");
+                    return ("auth", response_maintenance(ctx));
+                }
                 Err(err) => {
-                    eprintln!("invalid token: {err}");
+                    log::warn!(error:% = err; "invalid auth token");
 
                     // Invalid token - must reset the cookie.
                     let res = response_reset_auth_cookies();
-                    return Ok(res);
+                    return ("auth", res);
                 }
             }
         }
         None => None,
     };
 
-    let ctx = Context {
-        user,
-        ..ctx.clone()
-    };
+    let ctx = ctx.with_user(user);
 
-    let res = if ctx.user.is_none() {
+    let (route, res) = if ctx.user.is_none() {
         match (path_parts.as_slice(), req.method().clone()) {
-            (["signup"], _) => routes::signup::handler_signup(req, &ctx),
-            _ => routes::login::handler_login(req, &ctx),
+            (["signup"], _) => ("/signup", routes::signup::handler_signup(req, &ctx)),
+            _ => ("/login", routes::login::handler_login(req, &ctx)),
         }
     } else {
         match (path_parts.as_slice(), req.method().clone()) {
-            ([], Method::GET) => routes::dashboard::handler_dashboard(req, &ctx),
-            (["timelog", "start"], Method::POST) => routes::timelog_start::handler(req, &ctx),
-            (["timelog", "finish"], Method::POST) => routes::timelog_finish::handler(req, &ctx),
-            (["user", "logout"], Method::POST) => Ok(response_reset_auth_cookies()),
-            (_, Method::GET) => routes::dashboard::handler_dashboard(req, &ctx),
+            ([], Method::GET) => ("/", routes::dashboard::handler_dashboard(req, &ctx)),
+            (["reports"], Method::GET) => ("/reports", routes::reports::handler(req, &ctx)),
+            (["export.csv"], Method::GET) => ("/export.csv", routes::export::handler(req, &ctx)),
+            (["import"], Method::GET | Method::POST) => {
+                ("/import", routes::import::handler(req, &ctx))
+            }
+            (["import", "calendar"], Method::GET | Method::POST) => (
+                "/import/calendar",
+                routes::import_calendar::handler(req, &ctx),
+            ),
+            (["account", "export.json"], Method::GET) => (
+                "/account/export.json",
+                routes::account::handler_export(req, &ctx),
+            ),
+            (["account", "import"], Method::POST) => (
+                "/account/import",
+                routes::account::handler_import(req, &ctx),
+            ),
+            (["projects"], Method::GET | Method::POST) => {
+                ("/projects", routes::projects::handler(req, &ctx))
+            }
+            (["projects", "archive"], Method::POST) => (
+                "/projects/archive",
+                routes::projects::handler_archive(req, &ctx),
+            ),
+            (["projects", "delete"], Method::POST) => (
+                "/projects/delete",
+                routes::projects::handler_project_delete(req, &ctx),
+            ),
+            (["tags", "delete"], Method::POST) => (
+                "/tags/delete",
+                routes::projects::handler_tag_delete(req, &ctx),
+            ),
+            (["projects", "rate"], Method::POST) => (
+                "/projects/rate",
+                routes::projects::handler_project_rate(req, &ctx),
+            ),
+            (["projects", "budget"], Method::POST) => (
+                "/projects/budget",
+                routes::projects::handler_project_budget(req, &ctx),
+            ),
+            (["tags", "budget"], Method::POST) => (
+                "/tags/budget",
+                routes::projects::handler_tag_budget(req, &ctx),
+            ),
+            (["tags", "rate"], Method::POST) => {
+                ("/tags/rate", routes::projects::handler_tag_rate(req, &ctx))
+            }
+            (["invoices"], Method::GET | Method::POST) => {
+                ("/invoices", routes::invoices::handler(req, &ctx))
+            }
+            (["invoices", _], Method::GET) => (
+                "/invoices/<id>",
+                routes::invoices::handler_invoice(req, &ctx),
+            ),
+            (["orgs"], Method::GET | Method::POST) => ("/orgs", routes::orgs::handler(req, &ctx)),
+            (["orgs", "invitations", "accept"], Method::POST) => (
+                "/orgs/invitations/accept",
+                routes::orgs::handler_invitation_accept(req, &ctx),
+            ),
+            (["orgs", "invitations", "decline"], Method::POST) => (
+                "/orgs/invitations/decline",
+                routes::orgs::handler_invitation_decline(req, &ctx),
+            ),
+            (["orgs", _], Method::GET) => {
+                ("/orgs/<id>", routes::orgs::handler_organization(req, &ctx))
+            }
+            (["orgs", _, "report"], Method::GET) => {
+                ("/orgs/<id>/report", routes::orgs::handler_report(req, &ctx))
+            }
+            (["orgs", _, "invite"], Method::POST) => {
+                ("/orgs/<id>/invite", routes::orgs::handler_invite(req, &ctx))
+            }
+            (["orgs", _, "invitations", "cancel"], Method::POST) => (
+                "/orgs/<id>/invitations/cancel",
+                routes::orgs::handler_invitation_cancel(req, &ctx),
+            ),
+            (["orgs", _, "members", "role"], Method::POST) => (
+                "/orgs/<id>/members/role",
+                routes::orgs::handler_member_role(req, &ctx),
+            ),
+            (["orgs", _, "members", "remove"], Method::POST) => (
+                "/orgs/<id>/members/remove",
+                routes::orgs::handler_member_remove(req, &ctx),
+            ),
+            (["orgs", _, "tags"], Method::POST) => {
+                ("/orgs/<id>/tags", routes::orgs::handler_tag(req, &ctx))
+            }
+            (["timelogs", _, "history"], Method::GET) => (
+                "/timelogs/<id>/history",
+                routes::audit::handler_history(req, &ctx),
+            ),
+            (["audit"], Method::GET) => ("/audit", routes::audit::handler(req, &ctx)),
+            (["timesheets"], Method::GET | Method::POST) => {
+                ("/timesheets", routes::timesheets::handler(req, &ctx))
+            }
+            (["timesheets", "decide"], Method::POST) => (
+                "/timesheets/decide",
+                routes::timesheets::handler_decide(req, &ctx),
+            ),
+            (["timesheets", _], Method::GET) => (
+                "/timesheets/<id>",
+                routes::timesheets::handler_timesheet(req, &ctx),
+            ),
+            (["clients"], Method::POST) => {
+                ("/clients", routes::projects::handler_client(req, &ctx))
+            }
+            (["settings"], Method::GET | Method::POST) => {
+                ("/settings", routes::settings::handler(req, &ctx))
+            }
+            (["settings", "calendar"], Method::POST) => (
+                "/settings/calendar",
+                routes::settings::handler_calendar(req, &ctx),
+            ),
+            (["settings", "billing"], Method::POST) => (
+                "/settings/billing",
+                routes::settings::handler_billing(req, &ctx),
+            ),
+            (["timelog", "start"], Method::POST) => {
+                ("/timelog/start", routes::timelog_start::handler(req, &ctx))
+            }
+            (["timelog", "finish"], Method::POST) => (
+                "/timelog/finish",
+                routes::timelog_finish::handler(req, &ctx),
+            ),
+            (["timelog", "continue"], Method::POST) => (
+                "/timelog/continue",
+                routes::timelog_continue::handler(req, &ctx),
+            ),
+            (["timelog", "extend"], Method::POST) => (
+                "/timelog/extend",
+                routes::timelog_continue::handler_extend(req, &ctx),
+            ),
+            (["timelog", "switch"], Method::POST) => (
+                "/timelog/switch",
+                routes::timelog_switch::handler(req, &ctx),
+            ),
+            (["timelog", "delete"], Method::POST) => (
+                "/timelog/delete",
+                routes::timelog_delete::handler(req, &ctx),
+            ),
+            (["trash"], Method::GET) => ("/trash", routes::trash::handler(req, &ctx)),
+            (["trash", "restore"], Method::POST) => {
+                ("/trash/restore", routes::trash::handler_restore(req, &ctx))
+            }
+            (["trash", "purge"], Method::POST) => {
+                ("/trash/purge", routes::trash::handler_purge(req, &ctx))
+            }
+            (["timelog", "billable"], Method::POST) => (
+                "/timelog/billable",
+                routes::timelog_billable::handler(req, &ctx),
+            ),
+            (["user", "logout"], Method::POST) => {
+                ("/user/logout", Ok(response_reset_auth_cookies()))
+            }
+            (_, Method::GET) => ("/", routes::dashboard::handler_dashboard(req, &ctx)),
             (_, method) => {
-                eprintln!("path not found: method={} parts={:?}", method, path_parts);
-                Ok(response_not_found_html())
+                log::info!(method:% = method, parts:? = path_parts; "path not found");
+                ("not_found", Ok(response_not_found_html()))
             }
         }
     };
 
-    let res: Result<Response, WcgiError> = match res {
-        Ok(r) => Ok(r),
+    (route, response_from_result(&ctx, res))
+}
+
+fn response_from_result(ctx: &Context, res: HandlerResult) -> Response {
+    match res {
+        Ok(r) => r,
+        Err(err) if is_db_unavailable(&err) => {
+            log::error!(error:% = format!("{err:#}"); "database unavailable");
+            response_maintenance(ctx)
+        }
         Err(err) => {
-            eprintln!("ERROR: {err:?}");
-            Ok(response_html(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                error_page(&ctx, err),
-            ))
-            // }
+            log::error!(error:% = format!("{err:#}"); "request failed");
+            response_html(StatusCode::INTERNAL_SERVER_ERROR, error_page(ctx, err))
         }
-    };
+    }
+}
+
+fn is_db_unavailable(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<DbError>(), Some(DbError::Unavailable(_)))
+}
+
+fn response_maintenance(ctx: &Context) -> Response {
+    let mut res = response_html(StatusCode::SERVICE_UNAVAILABLE, maintenance_page(ctx));
+    res.headers_mut().insert(
+        http::header::RETRY_AFTER,
+        http::HeaderValue::from_static("30"),
+    );
     res
 }
 
@@ -223,6 +577,40 @@ fn response_not_found_html() -> Response {
         .unwrap()
 }
 
+/// The scheme and host the request was sent to, like `https://example.com`.
+///
+/// Honors `X-Forwarded-Proto` and defaults to https.
+fn request_origin(req: &Request) -> Option<String> {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    let host = header("host")?;
+    let scheme = header("x-forwarded-proto").unwrap_or("https");
+    Some(format!("{scheme}://{host}"))
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+fn bearer_token(req: &Request) -> Option<&str> {
+    req.headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+fn response_json(status: StatusCode, body: &serde_json::Value) -> Response {
+    ResponseBuilder::new()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .header(http::header::CACHE_CONTROL, "no-store")
+        .body(Body::new_text(body.to_string()))
+        .unwrap()
+}
+
 fn get_cookies(req: &Request) -> CookieJar {
     let mut jar = CookieJar::new();
     for header in req.headers().get_all(http::header::COOKIE) {
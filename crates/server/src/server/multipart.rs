@@ -0,0 +1,87 @@
+//! Minimal `multipart/form-data` parsing for file uploads.
+//!
+//! Only what browsers send for simple forms is supported: one level of
+//! parts with a `Content-Disposition: form-data` header each.
+
+use anyhow::{anyhow, bail};
+
+pub struct Part {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// The boundary of a `multipart/form-data` content type, if it is one.
+pub fn boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|p| p.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+        .filter(|b| !b.is_empty())
+}
+
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>, anyhow::Error> {
+    let delimiter = format!("--{boundary}");
+    let delimiter = delimiter.as_bytes();
+
+    let mut rest = match find(body, delimiter) {
+        Some(pos) => &body[pos + delimiter.len()..],
+        None => bail!("multipart body has no boundary"),
+    };
+    let mut parts = Vec::new();
+    // Every part is preceded by a delimiter, the last one is followed by `--`.
+    while !rest.starts_with(b"--") {
+        rest = rest
+            .strip_prefix(b"\r\n")
+            .ok_or_else(|| anyhow!("malformed multipart delimiter"))?;
+        let header_end =
+            find(rest, b"\r\n\r\n").ok_or_else(|| anyhow!("unterminated multipart headers"))?;
+        let headers = std::str::from_utf8(&rest[..header_end])?;
+        rest = &rest[header_end + 4..];
+
+        let data_end = find_delimiter(rest, delimiter)
+            .ok_or_else(|| anyhow!("unterminated multipart part"))?;
+        let data = rest[..data_end].to_vec();
+        rest = &rest[data_end + 2 + delimiter.len()..];
+
+        let disposition = headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("content-disposition"))
+            .map(|(_, value)| value)
+            .ok_or_else(|| anyhow!("multipart part without content disposition"))?;
+        let name = disposition_param(disposition, "name")
+            .ok_or_else(|| anyhow!("multipart part without name"))?;
+        parts.push(Part { name, data });
+    }
+    Ok(parts)
+}
+
+/// Position of `\r\n<delimiter>` in `data`.
+fn find_delimiter(data: &[u8], delimiter: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(pos) = find(&data[offset..], b"\r\n") {
+        let start = offset + pos;
+        if data[start + 2..].starts_with(delimiter) {
+            return Some(start);
+        }
+        offset = start + 2;
+    }
+    None
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+fn disposition_param(value: &str, key: &str) -> Option<String> {
+    value
+        .split(';')
+        .skip(1)
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.trim().trim_matches('"').to_string())
+}
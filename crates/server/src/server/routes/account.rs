@@ -0,0 +1,101 @@
+use anyhow::anyhow;
+use maud::html;
+use wcgi::{Body, ResponseBuilder};
+
+use crate::{
+    logic::backup::{restore_account, user_backup, Backup, MAX_BACKUP_BYTES},
+    server::{
+        multipart,
+        prelude::{h2, page, response_html_ok, Context, HandlerResult, Request, StatusCode},
+        ui::error_box,
+    },
+    PublicError,
+};
+
+/// JSON backup of the account, as a download.
+pub fn handler_export(_req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let backup = Backup::new(vec![user_backup(&ctx.db, user)?]);
+    log::info!(timelogs = backup.users[0].timelogs.len(); "exported account backup");
+
+    let filename = format!(
+        "timely-backup-{}-{}.json",
+        user.username,
+        backup.exported_at.date()
+    );
+    let res = ResponseBuilder::new()
+        .status(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .header(
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .header(http::header::CACHE_CONTROL, "no-store")
+        .body(Body::new_text(serde_json::to_string_pretty(&backup)?))
+        .unwrap();
+    Ok(res)
+}
+
+/// Restore an uploaded backup into the account.
+///
+/// Expects `multipart/form-data` with the backup as `file`.
+pub fn handler_import(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = read_backup(req).and_then(|backup| restore_account(&ctx.db, user, &backup));
+
+    let content = match res {
+        Ok(summary) => {
+            log::info!(
+                tags_created = summary.tags_created,
+                projects_created = summary.projects_created,
+                timelogs_created = summary.timelogs_created,
+                timelogs_skipped = summary.timelogs_skipped,
+                links_created = summary.links_created;
+                "restored account backup"
+            );
+            html! {
+                p class="notification is-success" {
+                    "Restored " (summary.timelogs_created) " entries, "
+                    (summary.tags_created) " tags and "
+                    (summary.projects_created) " projects. "
+                    (summary.timelogs_skipped) " entries already existed."
+                }
+            }
+        }
+        Err(err) if err.is::<PublicError>() => error_box(err.to_string()),
+        Err(err) => return Err(err),
+    };
+    let content = html! {
+        div.container {
+            (h2("Restore backup"))
+            (content)
+            a.button href="/settings" { "Back to settings" }
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+fn read_backup(req: Request) -> Result<Backup, anyhow::Error> {
+    let boundary = req
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(multipart::boundary)
+        .map(|b| b.to_string())
+        .ok_or_else(|| PublicError::msg("Expected a multipart form upload"))?;
+    let body = req
+        .into_body()
+        .read_to_vec()
+        .map_err(|err| anyhow!("Could not read request body: {err}"))?;
+    if body.len() > MAX_BACKUP_BYTES * 2 {
+        return Err(PublicError::msg("The upload is too large").into());
+    }
+
+    let parts = multipart::parse(&body, &boundary)
+        .map_err(|err| PublicError::msg(format!("Invalid upload: {err}")))?;
+    let file = parts
+        .iter()
+        .find(|p| p.name == "file" && !p.data.is_empty())
+        .ok_or_else(|| PublicError::msg("Please choose a backup file"))?;
+    Backup::parse(&file.data)
+}
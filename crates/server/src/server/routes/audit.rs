@@ -0,0 +1,263 @@
+use maud::html;
+use time::Duration;
+
+use crate::{
+    logic::{
+        audit::{
+            audit_search, timelog_history, AuditEntry, AuditSearch, ACTIONS, ENTITIES, PAGE_SIZE,
+        },
+        locale::{DisplayPrefs, Zone},
+        reports::parse_date,
+    },
+    server::{
+        prelude::{
+            h2, page, parse_query, response_html_ok, Context, Fragment, HandlerResult, Request,
+        },
+        response_not_found_html,
+        ui::error_box,
+    },
+    PublicError,
+};
+
+#[derive(serde::Deserialize, Clone, Default)]
+struct AuditParams {
+    #[serde(default)]
+    actor: String,
+    #[serde(default)]
+    entity: String,
+    #[serde(default)]
+    entity_id: String,
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+    #[serde(default)]
+    page: u64,
+}
+
+/// The changes of a timelog at `/timelogs/<id>/history`.
+pub fn handler_history(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let id = req
+        .uri()
+        .path()
+        .strip_prefix("/timelogs/")
+        .and_then(|rest| rest.strip_suffix("/history"))
+        .and_then(|id| id.parse().ok());
+    let Some(id) = id else {
+        return Ok(response_not_found_html());
+    };
+    let (log, entries) = match timelog_history(&ctx.db, user, id) {
+        Ok(history) => history,
+        Err(err) if err.is::<PublicError>() => return Ok(response_not_found_html()),
+        Err(err) => return Err(err),
+    };
+
+    let content = html! {
+        div.container {
+            (h2(format!("History of \"{}\"", log.title)))
+            (entries_table(&entries, DisplayPrefs::for_user(user), false))
+            p.block { a href="/" { "Back to timelogs" } }
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// The audit log of all users, for admins.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    if !ctx.is_admin() {
+        return Ok(response_not_found_html());
+    }
+    let user = ctx.require_user()?;
+    let params: AuditParams = parse_query(&req).unwrap_or_default();
+
+    let res = parse_search(&params, Zone::for_user(user))
+        .and_then(|search| audit_search(&ctx.db, &search));
+    let (entries, error) = match res {
+        Ok(entries) => (entries, None),
+        Err(err) if err.is::<PublicError>() => (Vec::new(), Some(err.to_string())),
+        Err(err) => return Err(err),
+    };
+
+    let content = html! {
+        div.container {
+            (h2("Audit log"))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            (search_form(&params))
+            (entries_table(&entries, DisplayPrefs::for_user(user), true))
+            nav.level {
+                div.level-left {
+                    @if params.page > 0 {
+                        a.button href=(page_url(&params, params.page - 1)) { "Newer" }
+                    }
+                }
+                div.level-right {
+                    @if entries.len() as u64 == PAGE_SIZE {
+                        a.button href=(page_url(&params, params.page + 1)) { "Older" }
+                    }
+                }
+            }
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// Read the filters, with dates in the time zone of the admin.
+fn parse_search(params: &AuditParams, zone: Zone) -> Result<AuditSearch, anyhow::Error> {
+    let entity_id = match params.entity_id.trim() {
+        "" => None,
+        id => Some(
+            id.parse()
+                .map_err(|_| PublicError::msg(format!("Invalid id '{id}'")))?,
+        ),
+    };
+    let since = match params.from.trim() {
+        "" => None,
+        from => Some(zone.start_of_day(parse_date(from)?)),
+    };
+    // The end date is included.
+    let before = match params.to.trim() {
+        "" => None,
+        to => Some(zone.start_of_day(parse_date(to)? + Duration::days(1))),
+    };
+    Ok(AuditSearch {
+        actor: params.actor.clone(),
+        entity: params.entity.clone(),
+        entity_id,
+        action: params.action.clone(),
+        since,
+        before,
+        page: params.page,
+    })
+}
+
+fn page_url(params: &AuditParams, page: u64) -> String {
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("actor", &params.actor)
+        .append_pair("entity", &params.entity)
+        .append_pair("entity_id", &params.entity_id)
+        .append_pair("action", &params.action)
+        .append_pair("from", &params.from)
+        .append_pair("to", &params.to)
+        .append_pair("page", &page.to_string())
+        .finish();
+    format!("/audit?{query}")
+}
+
+fn search_form(params: &AuditParams) -> Fragment {
+    html! {
+        form.box action="/audit" method="get" {
+            div class="field is-grouped is-grouped-multiline" {
+                div.control {
+                    label.label { "User" }
+                    input.input type="text" name="actor" value=(params.actor) {}
+                }
+                div.control {
+                    label.label { "Entity" }
+                    div.select {
+                        select name="entity" {
+                            option value="" { "any" }
+                            @for entity in ENTITIES {
+                                option value=(entity) selected[params.entity == entity] { (entity) }
+                            }
+                        }
+                    }
+                }
+                div.control {
+                    label.label { "Id" }
+                    input.input type="text" name="entity_id" size="8" value=(params.entity_id) {}
+                }
+                div.control {
+                    label.label { "Action" }
+                    div.select {
+                        select name="action" {
+                            option value="" { "any" }
+                            @for action in ACTIONS {
+                                option value=(action) selected[params.action == action] { (action) }
+                            }
+                        }
+                    }
+                }
+                div.control {
+                    label.label { "From" }
+                    input.input type="date" name="from" value=(params.from) {}
+                }
+                div.control {
+                    label.label { "To" }
+                    input.input type="date" name="to" value=(params.to) {}
+                }
+                div.control {
+                    label.label { "\u{a0}" }
+                    button.button.is-primary type="submit" { "Search" }
+                }
+            }
+        }
+    }
+}
+
+/// Events with their changed fields. `with_entity` adds the changed table
+/// and row, for logs spanning several entities.
+fn entries_table(entries: &[AuditEntry], prefs: DisplayPrefs, with_entity: bool) -> Fragment {
+    html! {
+        @if entries.is_empty() {
+            p.block { "No changes recorded." }
+        } @else {
+            table class="table is-fullwidth is-striped" {
+                thead {
+                    tr {
+                        th { "Time" }
+                        th { "User" }
+                        @if with_entity {
+                            th { "Entity" }
+                        }
+                        th { "Action" }
+                        th { "Changes" }
+                    }
+                }
+                tbody {
+                    @for entry in entries {
+                        tr {
+                            td { (prefs.datetime(entry.event.created_at)) }
+                            td {
+                                @match &entry.actor {
+                                    Some(actor) => { (actor) }
+                                    None => { span.has-text-grey { "system" } }
+                                }
+                            }
+                            @if with_entity {
+                                td {
+                                    (entry.event.entity)
+                                    @if let Some(id) = entry.event.entity_id { " #" (id) }
+                                }
+                            }
+                            td { (entry.event.action) }
+                            td {
+                                @for change in &entry.changes {
+                                    div {
+                                        b { (change.field) ": " }
+                                        @match (&change.before, &change.after) {
+                                            (Some(before), Some(after)) => {
+                                                del.has-text-grey { (before) }
+                                                " → "
+                                                (after)
+                                            }
+                                            (Some(before), None) => {
+                                                del.has-text-grey { (before) }
+                                            }
+                                            (None, Some(after)) => { (after) }
+                                            (None, None) => { "–" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
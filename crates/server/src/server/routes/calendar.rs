@@ -0,0 +1,35 @@
+use http::StatusCode;
+use wcgi::{Body, ResponseBuilder};
+
+use crate::{
+    logic::calendar::{user_calendar, user_for_calendar_token},
+    server::prelude::{Context, HandlerResult, Request},
+};
+
+/// Public iCalendar feed at `/calendar/<token>.ics`.
+///
+/// The token is the only authentication, unknown tokens get a 404.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let token = req
+        .uri()
+        .path()
+        .strip_prefix("/calendar/")
+        .and_then(|name| name.strip_suffix(".ics"))
+        .unwrap_or_default();
+    let Some(user) = user_for_calendar_token(&ctx.db, token)? else {
+        return Ok(ResponseBuilder::new()
+            .status(StatusCode::NOT_FOUND)
+            .header(http::header::CONTENT_TYPE, "text/plain")
+            .body(Body::new_text("calendar not found"))
+            .unwrap());
+    };
+
+    let calendar = user_calendar(&ctx.db, &user)?;
+    let res = ResponseBuilder::new()
+        .status(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, "text/calendar; charset=utf-8")
+        .header(http::header::CACHE_CONTROL, "private, max-age=300")
+        .body(Body::new_text(calendar))
+        .unwrap();
+    Ok(res)
+}
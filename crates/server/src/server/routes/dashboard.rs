@@ -1,31 +1,105 @@
-use maud::html;
-use time::format_description::well_known::Rfc3339;
+use std::collections::HashMap;
+
+use maud::{html, PreEscaped};
+use time::OffsetDateTime;
 
 use crate::{
-    db::{user_active_timelogs, user_finished_timelogs, Db},
+    db::{
+        types::{Project, ProjectId, Timelog},
+        user_active_timelogs, user_finished_timelogs, Db,
+    },
+    logic::{
+        budgets::{user_budgets, BudgetLevel, BudgetStatus, BudgetTarget, WARNING_PERCENT},
+        locale::DisplayPrefs,
+        projects::{user_project_names, user_projects, ProjectNames},
+        timesheets::Locks,
+    },
     server::{
-        prelude::{h2, page, response_html_ok, Context, Fragment, HandlerResult, Method, Request},
+        prelude::{h2, response_html_ok, Context, Fragment, HandlerResult, Method, Request},
         response_not_found_html,
-        ui::{error_box, util::renderiter},
+        routes::timelog_continue::can_extend,
+        ui::{
+            error_box, page_titled,
+            util::{format_clock, format_duration, renderiter},
+        },
     },
 };
 
 pub fn handler_dashboard(req: Request, ctx: &Context) -> HandlerResult {
     match *req.method() {
-        Method::GET => {
-            let content = build_dashboard(ctx, None)?;
-            Ok(response_html_ok(page(ctx, content)))
-        }
+        Method::GET => Ok(response_html_ok(dashboard_page(ctx, None)?)),
         _ => Ok(response_not_found_html()),
     }
 }
 
-pub fn build_dashboard(ctx: &Context, error: Option<String>) -> Result<Fragment, anyhow::Error> {
-    let user = ctx.require_user()?;
+/// Ticks the `[data-timer]` elements every second and shows the first one in
+/// the page title. Budget banners are shown once the running timers have
+/// used up the share of the budget given in seconds from page load.
+///
+/// Counts from the server-computed elapsed time, so a wrong client clock
+/// does not matter.
+const TIMER_SCRIPT: &str = r#"
+(function () {
+  var loaded = Date.now();
+  var timers = document.querySelectorAll("[data-timer]");
+  var budgets = document.querySelectorAll("[data-budget-warn]");
+  function pad(n) { return n < 10 ? "0" + n : "" + n; }
+  function clock(s) {
+    return Math.floor(s / 3600) + ":" + pad(Math.floor(s / 60) % 60) + ":" + pad(s % 60);
+  }
+  setInterval(function () {
+    var passed = Math.floor((Date.now() - loaded) / 1000);
+    timers.forEach(function (el, i) {
+      var text = clock(parseInt(el.dataset.timer, 10) + passed);
+      el.textContent = text;
+      if (i === 0) {
+        document.title = text + " · " + el.dataset.timerTitle + " · Timely";
+      }
+    });
+    budgets.forEach(function (el) {
+      if (passed >= parseInt(el.dataset.budgetOver, 10)) {
+        el.className = "notification is-danger";
+        el.textContent = el.dataset.budgetOverText;
+        el.hidden = false;
+      } else if (passed >= parseInt(el.dataset.budgetWarn, 10)) {
+        el.hidden = false;
+      }
+    });
+  }, 1000);
+})();
+"#;
 
+/// Render the full dashboard page.
+///
+/// While a timer is running, the elapsed time is shown in the page title.
+pub fn dashboard_page(ctx: &Context, error: Option<String>) -> Result<String, anyhow::Error> {
+    let user = ctx.require_user()?;
+    let now = OffsetDateTime::now_utc();
     let unfinished = ctx.db.timelogs(user_active_timelogs(user.id))?;
 
+    let title = match unfinished.first() {
+        Some(item) => format!(
+            "{} · {} · Timely",
+            format_clock(now - item.started_at),
+            item.title
+        ),
+        None => "Timely".to_string(),
+    };
+    let content = build_dashboard(ctx, &unfinished, now, error)?;
+    Ok(page_titled(ctx, &title, content))
+}
+
+fn build_dashboard(
+    ctx: &Context,
+    unfinished: &[Timelog],
+    now: OffsetDateTime,
+    error: Option<String>,
+) -> Result<Fragment, anyhow::Error> {
+    let user = ctx.require_user()?;
+    let prefs = DisplayPrefs::for_user(user);
+
     let errmsg = error.map(error_box).unwrap_or_else(|| html! {});
+    let projects = user_project_names(&ctx.db, user)?;
 
     let active_logs = if !unfinished.is_empty() {
         let multi_warning = if unfinished.len() > 1 {
@@ -39,17 +113,23 @@ pub fn build_dashboard(ctx: &Context, error: Option<String>) -> Result<Fragment,
         };
 
         let items = unfinished.iter().map(|item| {
+            let elapsed = now - item.started_at;
             html! {
                 div.box {
                     div {
                         b {
                             (item.title)
                         }
+                        (project_label(&projects, item.project_id))
                     }
 
                     div {
-                        "Started: "
-                        (item.started_at.format(&Rfc3339).unwrap())
+                        "Running for "
+                        b data-timer=(elapsed.whole_seconds().max(0)) data-timer-title=(item.title) {
+                            (format_clock(elapsed))
+                        }
+                        " since "
+                        (prefs.datetime(item.started_at))
                     }
 
                     div.buttons {
@@ -59,20 +139,41 @@ pub fn build_dashboard(ctx: &Context, error: Option<String>) -> Result<Fragment,
                                 "Finish"
                             }
                         }
+                        form action="/timelog/delete" method="post" {
+                            input name="timelog_id" value=(item.id) type="hidden" {}
+                            button.button.is-danger.is-light {
+                                "Delete"
+                            }
+                        }
                     }
 
                 }
             }
         });
 
+        // Budgets the running timers count towards.
+        let budgets = user_budgets(&ctx.db, user, now)?
+            .into_iter()
+            .filter(|b| b.running > 0)
+            .collect::<Vec<_>>();
+
         html! {
             (multi_warning)
+            (renderiter(budgets.iter().map(budget_banner)))
             (renderiter(items))
+            details.box {
+                summary { b { "Switch to another task" } }
+                p.help.mb-3 {
+                    "Finishes the running entry and starts the new one at the same moment."
+                }
+                (log_start_form(&user_projects(&ctx.db, user, false)?, "/timelog/switch", "Switch"))
+            }
+            script { (PreEscaped(TIMER_SCRIPT)) }
         }
     } else {
         html! {
             div.box {
-                (log_start_form())
+                (log_start_form(&user_projects(&ctx.db, user, false)?, "/timelog/start", "Start"))
             }
         }
     };
@@ -85,17 +186,22 @@ pub fn build_dashboard(ctx: &Context, error: Option<String>) -> Result<Fragment,
             }
         }
     } else {
-        let items = finished_logs.iter().map(|item| {
-            let started = item.started_at.format(&Rfc3339).unwrap();
-            let finished = item
-                .finished_at()
-                .and_then(|t| t.format(&Rfc3339).ok())
+        let locks = Locks::for_user(&ctx.db, user.id)?;
+        // Only offered while no timer runs, as both start one.
+        let idle = unfinished.is_empty();
+        let items = finished_logs.iter().enumerate().map(|(index, item)| {
+            let started = prefs.datetime(item.started_at);
+            let finished_at = item.finished_at();
+            let finished = finished_at.map(|t| prefs.datetime(t)).unwrap_or_default();
+            let duration = finished_at
+                .map(|t| format_duration(t - item.started_at))
                 .unwrap_or_default();
 
             html! {
                 div.box {
                     div {
                         (item.title)
+                        (project_label(&projects, item.project_id))
                     }
 
                     div class="is-flex" style="gap: 1rem" {
@@ -107,6 +213,53 @@ pub fn build_dashboard(ctx: &Context, error: Option<String>) -> Result<Fragment,
                             b { "Finished: " }
                             (finished)
                         }
+                        div {
+                            b { "Duration: " }
+                            (duration)
+                        }
+                        @if item.invoice_id.is_some() {
+                            span.tag.is-success.is-light { "Invoiced" }
+                        } @else if locks.is_locked(item) {
+                            span.tag.is-warning.is-light { "Submitted" }
+                        } @else {
+                            form action="/timelog/billable" method="post" {
+                                input name="timelog_id" value=(item.id) type="hidden" {}
+                                input name="billable" value=(!item.billable) type="hidden" {}
+                                button.button.is-small.is-light type="submit" {
+                                    @if item.billable { "Billable" } @else { "Not billable" }
+                                }
+                            }
+                            form action="/timelog/delete" method="post" {
+                                input name="timelog_id" value=(item.id) type="hidden" {}
+                                button.button.is-small.is-danger.is-light type="submit" { "Delete" }
+                            }
+                        }
+                        a.button.is-small.is-white href=(format!("/timelogs/{}/history", item.id)) {
+                            "History"
+                        }
+                    }
+
+                    @if idle {
+                        div.buttons.mt-2 {
+                            form action="/timelog/continue" method="post" {
+                                input name="timelog_id" value=(item.id) type="hidden" {}
+                                button.button.is-small.is-primary.is-light type="submit" { "Continue" }
+                            }
+                            @if index == 0 && can_extend(item, &locks, now) {
+                                form action="/timelog/extend" method="post" {
+                                    input name="timelog_id" value=(item.id) type="hidden" {}
+                                    button.button.is-small.is-info.is-light type="submit" {
+                                        "Extend"
+                                    }
+                                }
+                                @if let Some(end) = finished_at {
+                                    span.is-size-7.has-text-grey {
+                                        "Ended " ((now - end).whole_minutes()) " min ago"
+                                        " - extend it instead of starting a new entry."
+                                    }
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -131,16 +284,99 @@ pub fn build_dashboard(ctx: &Context, error: Option<String>) -> Result<Fragment,
     Ok(out)
 }
 
-fn log_start_form() -> Fragment {
+/// Warning about a budget that is close to or over its limit.
+///
+/// Banners of budgets below the warning level are hidden until the timer
+/// script shows them.
+fn budget_banner(status: &BudgetStatus) -> Fragment {
+    let kind = match status.target {
+        BudgetTarget::Project(_) => "Project",
+        BudgetTarget::Tag(_) => "Tag",
+    };
+    let budget = format!(
+        "{}h {}",
+        status.budget.hours(),
+        status.budget.period.label()
+    );
+    let warn_text = format!(
+        "{kind} '{}' has used over {WARNING_PERCENT}% of its budget of {budget}.",
+        status.name
+    );
+    let over_text = format!("{kind} '{}' is over its budget of {budget}.", status.name);
+    let seconds = |percent| {
+        status
+            .time_until(percent)
+            .map_or(i64::MAX, |d| d.whole_seconds())
+    };
+    let (class, text) = match status.level() {
+        BudgetLevel::Over => ("notification is-danger", &over_text),
+        _ => ("notification is-warning", &warn_text),
+    };
+    html! {
+        p class=(class)
+            hidden[status.level() == BudgetLevel::Ok]
+            data-budget-warn=(seconds(WARNING_PERCENT))
+            data-budget-over=(seconds(100))
+            data-budget-over-text=(over_text)
+        {
+            (text)
+        }
+    }
+}
+
+/// Project and client of a timelog, as a tag next to the title.
+fn project_label(projects: &HashMap<ProjectId, ProjectNames>, id: Option<ProjectId>) -> Fragment {
+    let Some(names) = id.and_then(|id| projects.get(&id)) else {
+        return html! {};
+    };
+    html! {
+        " "
+        span.tag.is-info.is-light {
+            @if let Some(client) = &names.client {
+                (client) " / "
+            }
+            (names.project)
+        }
+    }
+}
+
+/// Form for the fields of a new timer, posted to `action`.
+fn log_start_form(projects: &[Project], action: &str, submit: &str) -> Fragment {
     html! {
-        form action="/timelog/start" method="post" {
+        form action=(action) method="post" {
             div.field {
                 label.label { "Title" }
                 input.input name="title" type="text" placeholder="..." {}
             }
 
+            div class="field is-grouped" {
+                @if !projects.is_empty() {
+                    div.control {
+                        label.label { "Project" }
+                        div.select {
+                            select name="project_id" {
+                                option value="" { "No project" }
+                                @for project in projects {
+                                    option value=(project.id) { (project.name) }
+                                }
+                            }
+                        }
+                    }
+                }
+                div.control {
+                    label.label { "Billable" }
+                    div.select {
+                        select name="billable" {
+                            option value="" { "As the project" }
+                            option value="yes" { "Yes" }
+                            option value="no" { "No" }
+                        }
+                    }
+                }
+            }
+
             div.buttons {
-                button.button type="submit" { "Start" }
+                button.button type="submit" { (submit) }
             }
         }
     }
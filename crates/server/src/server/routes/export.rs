@@ -0,0 +1,49 @@
+use http::StatusCode;
+use wcgi::{Body, ResponseBuilder};
+
+use crate::{
+    logic::{export::write_timelogs_csv, locale::Zone, reports::DateRange},
+    server::prelude::{parse_query, Context, HandlerResult, Request},
+};
+
+/// Days covered when no range is given.
+const DEFAULT_DAYS: i64 = 30;
+
+#[derive(serde::Deserialize, Clone, Default)]
+struct ExportParams {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+/// CSV download of the timelogs that started in the `from`/`to` date range.
+///
+/// Database reads are paged, but wcgi needs the whole body up front, so the
+/// CSV text itself is buffered.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let params: ExportParams = parse_query(&req)?;
+    let today = Zone::for_user(user).today();
+    let range = DateRange::parse(
+        params.from.as_deref(),
+        params.to.as_deref(),
+        today,
+        DEFAULT_DAYS,
+    )?;
+
+    let mut csv = Vec::new();
+    let count = write_timelogs_csv(&ctx.db, user, range, &mut csv)?;
+    log::info!(timelogs = count; "exported timelogs");
+
+    let filename = format!("timelogs-{}-{}.csv", range.from, range.to);
+    let res = ResponseBuilder::new()
+        .status(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, "text/csv; charset=utf-8")
+        .header(
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .header(http::header::CACHE_CONTROL, "no-store")
+        .body(Body::new_text(String::from_utf8(csv)?))
+        .unwrap();
+    Ok(res)
+}
@@ -0,0 +1,71 @@
+use std::time::Instant;
+
+use http::StatusCode;
+use serde_json::json;
+
+use crate::{
+    db::Db,
+    server::{
+        prelude::{Context, HandlerResult, Request},
+        response_json,
+    },
+};
+
+/// Liveness: the process is up and able to handle requests.
+pub fn handler_healthz(_req: Request, _ctx: &Context) -> HandlerResult {
+    Ok(response_json(StatusCode::OK, &json!({ "status": "ok" })))
+}
+
+/// Readiness: the configuration is complete and the database answers.
+pub fn handler_readyz(_req: Request, ctx: &Context) -> HandlerResult {
+    let checks = [
+        run_check("config", || ctx.config.validate()),
+        run_check("database", || ctx.db.ping().map_err(From::from)),
+    ];
+
+    let ok = checks.iter().all(|c| c.ok);
+    let checks_json = checks
+        .iter()
+        .map(|c| (c.name.to_string(), c.to_json()))
+        .collect::<serde_json::Map<_, _>>();
+    let body = json!({
+        "status": if ok { "ok" } else { "error" },
+        "checks": checks_json,
+    });
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok(response_json(status, &body))
+}
+
+struct CheckResult {
+    name: &'static str,
+    ok: bool,
+    latency_ms: f64,
+}
+
+impl CheckResult {
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "status": if self.ok { "ok" } else { "error" },
+            "latency_ms": self.latency_ms,
+        })
+    }
+}
+
+fn run_check(name: &'static str, check: impl FnOnce() -> Result<(), anyhow::Error>) -> CheckResult {
+    let start = Instant::now();
+    let res = check();
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    // The endpoint is public, so the details only go to the logs.
+    if let Err(err) = &res {
+        log::warn!(check = name, error:% = err; "readiness check failed");
+    }
+    CheckResult {
+        name,
+        ok: res.is_ok(),
+        latency_ms,
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::anyhow;
+use maud::html;
+
+use crate::{
+    logic::{
+        import::{import_csv, ImportReport, RowStatus, MAX_IMPORT_BYTES},
+        locale::DisplayPrefs,
+    },
+    server::{
+        multipart,
+        prelude::{
+            h2, h4, page, parse_query, response_html_ok, Context, Fragment, HandlerResult, Method,
+            Request, StatusCode,
+        },
+        response_json, response_not_found_html,
+        ui::error_box,
+    },
+    PublicError,
+};
+
+#[derive(serde::Deserialize, Clone, Default)]
+struct ImportParams {
+    dry_run: Option<bool>,
+}
+
+/// Upload form, preview and import.
+///
+/// The form is posted as `multipart/form-data` with either an uploaded `file`
+/// or, when confirming a preview, the previewed text in `csv`. The `action`
+/// field is `preview` or `import`.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let prefs = DisplayPrefs::for_user(user);
+    let content = match *req.method() {
+        Method::GET => import_content(prefs, None, None),
+        Method::POST => {
+            let res = read_upload(req).and_then(|(csv, dry_run)| {
+                let report = import_csv(&ctx.db, user, csv.as_bytes(), dry_run)?;
+                Ok((csv, report))
+            });
+            match res {
+                Ok((csv, report)) => {
+                    log_report(&report);
+                    import_content(prefs, Some((&csv, &report)), None)
+                }
+                Err(err) if err.is::<PublicError>() => {
+                    import_content(prefs, None, Some(err.to_string()))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        _ => return Ok(response_not_found_html()),
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// API import: the request body is the CSV file.
+///
+/// With `?dry_run=true` nothing is stored. Responds with the row report.
+pub fn api_handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let params: ImportParams = parse_query(&req)?;
+    let body = req
+        .into_body()
+        .read_to_vec()
+        .map_err(|err| anyhow!("Could not read request body: {err}"))?;
+
+    let report = import_csv(&ctx.db, user, &body, params.dry_run.unwrap_or_default())?;
+    log_report(&report);
+    Ok(response_json(
+        StatusCode::OK,
+        &serde_json::to_value(&report)?,
+    ))
+}
+
+fn log_report(report: &ImportReport) {
+    log::info!(
+        format = report.format.name(),
+        dry_run = report.dry_run,
+        rows = report.rows.len(),
+        created = report.created;
+        "imported timelogs"
+    );
+}
+
+/// Read the CSV text and whether this is a dry run from the form.
+fn read_upload(req: Request) -> Result<(String, bool), anyhow::Error> {
+    let boundary = req
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(multipart::boundary)
+        .map(|b| b.to_string())
+        .ok_or_else(|| PublicError::msg("Expected a multipart form upload"))?;
+    let body = req
+        .into_body()
+        .read_to_vec()
+        .map_err(|err| anyhow!("Could not read request body: {err}"))?;
+    if body.len() > MAX_IMPORT_BYTES * 2 {
+        return Err(PublicError::msg("The upload is too large").into());
+    }
+
+    let parts = multipart::parse(&body, &boundary)
+        .map_err(|err| PublicError::msg(format!("Invalid upload: {err}")))?;
+    let field = |name: &str| parts.iter().find(|p| p.name == name && !p.data.is_empty());
+
+    let data = field("file")
+        .or_else(|| field("csv"))
+        .ok_or_else(|| PublicError::msg("Please choose a file to import"))?;
+    let csv = String::from_utf8(data.data.clone())
+        .map_err(|_| PublicError::msg("The file must be UTF-8 encoded"))?;
+    let import = field("action").is_some_and(|p| p.data == b"import");
+    Ok((csv, !import))
+}
+
+fn import_content(
+    prefs: DisplayPrefs,
+    result: Option<(&str, &ImportReport)>,
+    error: Option<String>,
+) -> Fragment {
+    html! {
+        div.container {
+            (h2("Import"))
+            (import_tabs(false))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @match result {
+                Some((csv, report)) => (report_content(prefs, csv, report)),
+                None => (upload_form()),
+            }
+        }
+    }
+}
+
+/// Tabs to switch between CSV and calendar import.
+pub(super) fn import_tabs(calendar: bool) -> Fragment {
+    html! {
+        div.tabs {
+            ul {
+                li.is-active[!calendar] { a href="/import" { "Toggl / Clockify (.csv)" } }
+                li.is-active[calendar] { a href="/import/calendar" { "Calendar (.ics)" } }
+            }
+        }
+    }
+}
+
+fn upload_form() -> Fragment {
+    html! {
+        form.box action="/import" method="post" enctype="multipart/form-data" {
+            div.field {
+                label.label { "CSV export" }
+                div.control {
+                    input.input type="file" name="file" accept=".csv,text/csv" required {}
+                }
+                p.help {
+                    "A detailed time entry export from Toggl or Clockify, at most "
+                    (MAX_IMPORT_BYTES / 1024 / 1024) " MB. "
+                    "Times are read in your time zone; projects and tags become tags."
+                }
+            }
+            input type="hidden" name="action" value="preview" {}
+            button.button.is-primary type="submit" { "Preview" }
+        }
+    }
+}
+
+fn report_content(prefs: DisplayPrefs, csv: &str, report: &ImportReport) -> Fragment {
+    let new = report.count(|s| matches!(s, RowStatus::New));
+    let duplicates = report.count(|s| matches!(s, RowStatus::Duplicate));
+    let errors = report.count(|s| matches!(s, RowStatus::Error(_)));
+
+    html! {
+        @if report.dry_run {
+            p class="notification is-info" {
+                (report.format.name()) " export: "
+                (new) " new, " (duplicates) " duplicate, " (errors) " invalid. "
+                "Nothing has been imported yet."
+            }
+            @if new > 0 {
+                form.box action="/import" method="post" enctype="multipart/form-data" {
+                    input type="hidden" name="csv" value=(csv) {}
+                    input type="hidden" name="action" value="import" {}
+                    div.buttons {
+                        button.button.is-primary type="submit" { "Import " (new) " entries" }
+                        a.button href="/import" { "Cancel" }
+                    }
+                }
+            }
+        } @else {
+            p class="notification is-success" {
+                "Imported " (report.created) " entries. Skipped "
+                (duplicates) " duplicate and " (errors) " invalid rows."
+            }
+        }
+
+        (h4("Rows"))
+        table class="table is-fullwidth is-striped" {
+            thead {
+                tr {
+                    th { "Line" }
+                    th { "Status" }
+                    th { "Title" }
+                    th { "Start" }
+                    th { "End" }
+                    th { "Tags" }
+                }
+            }
+            tbody {
+                @for row in &report.rows {
+                    tr {
+                        td { (row.line) }
+                        @match &row.status {
+                            RowStatus::New => td { span.tag.is-success { "new" } },
+                            RowStatus::Duplicate => td { span.tag.is-light { "duplicate" } },
+                            RowStatus::Error(_) => td { span.tag.is-danger { "error" } },
+                        }
+                        @match (&row.entry, &row.status) {
+                            (Some(entry), _) => {
+                                td { (entry.title) }
+                                td { (prefs.datetime(entry.started_at)) }
+                                td { (prefs.datetime(entry.finished_at)) }
+                                td { (entry.tags.join(", ")) }
+                            }
+                            (None, RowStatus::Error(message)) => {
+                                td colspan="4" { (message) }
+                            }
+                            _ => td colspan="4" {},
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use maud::html;
+
+use crate::{
+    logic::{
+        calendar_import::{import_calendar, CalendarImportReport, EventStatus},
+        import::MAX_IMPORT_BYTES,
+        locale::{DisplayPrefs, Zone},
+        reports::DateRange,
+    },
+    server::{
+        multipart::{self, Part},
+        prelude::{
+            h2, h4, page, response_html_ok, Context, Fragment, HandlerResult, Method, Request,
+        },
+        response_not_found_html,
+        ui::error_box,
+    },
+    PublicError,
+};
+
+use super::import::import_tabs;
+
+/// Days covered when no range is given.
+const DEFAULT_DAYS: i64 = 7;
+
+/// Upload form, event picker and import.
+///
+/// The form is posted as `multipart/form-data` with an uploaded `file` and
+/// the `from`/`to` range. Confirming the picker posts the previewed text as
+/// `ics`, `action=import` and one `event` field per picked occurrence.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let prefs = DisplayPrefs::for_user(user);
+    let default_range = DateRange::last_days(Zone::for_user(user).today(), DEFAULT_DAYS);
+
+    let content = match *req.method() {
+        Method::GET => import_content(prefs, default_range, None, None),
+        Method::POST => {
+            let res = read_upload(req, default_range.to).and_then(|upload| {
+                let report = import_calendar(
+                    &ctx.db,
+                    user,
+                    upload.ics.as_bytes(),
+                    upload.range,
+                    upload.selected.as_ref(),
+                )?;
+                Ok((upload, report))
+            });
+            match res {
+                Ok((upload, report)) => {
+                    log::info!(
+                        dry_run = report.dry_run,
+                        rows = report.rows.len(),
+                        created = report.created;
+                        "imported calendar events"
+                    );
+                    import_content(prefs, upload.range, Some((&upload.ics, &report)), None)
+                }
+                Err(err) if err.is::<PublicError>() => {
+                    import_content(prefs, default_range, None, Some(err.to_string()))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        _ => return Ok(response_not_found_html()),
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+struct Upload {
+    ics: String,
+    range: DateRange,
+    /// Picked occurrences, `None` for a preview.
+    selected: Option<HashSet<String>>,
+}
+
+fn read_upload(req: Request, today: time::Date) -> Result<Upload, anyhow::Error> {
+    let boundary = req
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(multipart::boundary)
+        .map(|b| b.to_string())
+        .ok_or_else(|| PublicError::msg("Expected a multipart form upload"))?;
+    let body = req
+        .into_body()
+        .read_to_vec()
+        .map_err(|err| anyhow!("Could not read request body: {err}"))?;
+    if body.len() > MAX_IMPORT_BYTES * 2 {
+        return Err(PublicError::msg("The upload is too large").into());
+    }
+
+    let parts = multipart::parse(&body, &boundary)
+        .map_err(|err| PublicError::msg(format!("Invalid upload: {err}")))?;
+    let field = |name: &str| parts.iter().find(|p| p.name == name && !p.data.is_empty());
+    let text = |part: &Part| String::from_utf8_lossy(&part.data).into_owned();
+
+    let data = field("file")
+        .or_else(|| field("ics"))
+        .ok_or_else(|| PublicError::msg("Please choose a file to import"))?;
+    let ics = String::from_utf8(data.data.clone())
+        .map_err(|_| PublicError::msg("The file must be UTF-8 encoded"))?;
+    let range = DateRange::parse(
+        field("from").map(text).as_deref(),
+        field("to").map(text).as_deref(),
+        today,
+        DEFAULT_DAYS,
+    )?;
+    let import = field("action").is_some_and(|p| p.data == b"import");
+    let selected = import.then(|| {
+        parts
+            .iter()
+            .filter(|p| p.name == "event")
+            .map(text)
+            .collect()
+    });
+    Ok(Upload {
+        ics,
+        range,
+        selected,
+    })
+}
+
+fn import_content(
+    prefs: DisplayPrefs,
+    range: DateRange,
+    result: Option<(&str, &CalendarImportReport)>,
+    error: Option<String>,
+) -> Fragment {
+    html! {
+        div.container {
+            (h2("Import"))
+            (import_tabs(true))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @match result {
+                Some((ics, report)) => (report_content(prefs, range, ics, report)),
+                None => (upload_form(range)),
+            }
+        }
+    }
+}
+
+fn upload_form(range: DateRange) -> Fragment {
+    html! {
+        form.box action="/import/calendar" method="post" enctype="multipart/form-data" {
+            div.field {
+                label.label { "Calendar file" }
+                div.control {
+                    input.input type="file" name="file" accept=".ics,text/calendar" required {}
+                }
+                p.help {
+                    "An .ics export of a calendar, at most "
+                    (MAX_IMPORT_BYTES / 1024 / 1024) " MB. "
+                    "Recurring events are expanded, all-day events are ignored."
+                }
+            }
+            div class="field is-grouped" {
+                div.control {
+                    label.label { "From" }
+                    input.input type="date" name="from" value=(range.from) {}
+                }
+                div.control {
+                    label.label { "To" }
+                    input.input type="date" name="to" value=(range.to) {}
+                }
+            }
+            input type="hidden" name="action" value="preview" {}
+            button.button.is-primary type="submit" { "Show events" }
+        }
+    }
+}
+
+fn report_content(
+    prefs: DisplayPrefs,
+    range: DateRange,
+    ics: &str,
+    report: &CalendarImportReport,
+) -> Fragment {
+    let count = |f: fn(&EventStatus) -> bool| report.rows.iter().filter(|r| f(&r.status)).count();
+    let overlaps = count(|s| matches!(s, EventStatus::Overlap(_)));
+    let duplicates = count(|s| matches!(s, EventStatus::Duplicate));
+    let errors = count(|s| matches!(s, EventStatus::Error(_)));
+
+    html! {
+        @if report.dry_run {
+            p class="notification is-info" {
+                (report.rows.len()) " events from " (range.from) " to " (range.to) ": "
+                (overlaps) " overlap existing entries, " (duplicates) " already imported, "
+                (errors) " invalid. Pick the events to import, nothing has been imported yet."
+            }
+        } @else {
+            p class="notification is-success" {
+                "Imported " (report.created) " events."
+            }
+        }
+
+        form action="/import/calendar" method="post" enctype="multipart/form-data" {
+            input type="hidden" name="ics" value=(ics) {}
+            input type="hidden" name="from" value=(range.from) {}
+            input type="hidden" name="to" value=(range.to) {}
+            input type="hidden" name="action" value="import" {}
+
+            (h4("Events"))
+            table class="table is-fullwidth is-striped" {
+                thead {
+                    tr {
+                        th {}
+                        th { "Title" }
+                        th { "Start" }
+                        th { "End" }
+                        th { "Tags" }
+                        th { "Status" }
+                    }
+                }
+                tbody {
+                    @for row in &report.rows {
+                        @let importable = matches!(row.status, EventStatus::New | EventStatus::Overlap(_));
+                        tr {
+                            td {
+                                @if report.dry_run && importable {
+                                    input type="checkbox" name="event" value=(row.key) checked[row.selected] {}
+                                }
+                            }
+                            @match &row.entry {
+                                Some(entry) => {
+                                    td { (entry.title) }
+                                    td { (prefs.datetime(entry.started_at)) }
+                                    td { (prefs.datetime(entry.finished_at)) }
+                                    td { (entry.tags.join(", ")) }
+                                }
+                                None => {
+                                    td { (row.summary) }
+                                    td colspan="3" {}
+                                }
+                            }
+                            td {
+                                @match &row.status {
+                                    _ if !report.dry_run && row.selected => {
+                                        span.tag.is-success { "imported" }
+                                    }
+                                    EventStatus::New => span.tag.is-success { "new" },
+                                    EventStatus::Overlap(message) => {
+                                        span.tag.is-warning { "overlap" } " " (message)
+                                    }
+                                    EventStatus::Duplicate => span.tag.is-light { "duplicate" },
+                                    EventStatus::Error(message) => {
+                                        span.tag.is-danger { "error" } " " (message)
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            @if report.dry_run {
+                div.buttons {
+                    button.button.is-primary type="submit" { "Import selected events" }
+                    a.button href="/import/calendar" { "Cancel" }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use http::StatusCode;
+use maud::{html, DOCTYPE};
+use wcgi::{Body, ResponseBuilder};
+
+use crate::{
+    db::types::{Client, Invoice, InvoiceData},
+    logic::{
+        billing::Amount,
+        invoices::{
+            format_hours, format_tax_rate, invoice_create, invoice_draft, invoice_pdf,
+            invoice_title, parse_tax_rate, user_invoice, user_invoices, InvoiceGrouping,
+            InvoiceRequest,
+        },
+        locale::{DisplayPrefs, Zone},
+        projects::user_clients,
+        reports::{parse_date, DateRange},
+    },
+    server::{
+        prelude::{
+            h2, h4, page, parse_form, response_html_ok, Context, Fragment, HandlerResult, Method,
+            Request,
+        },
+        response_not_found_html,
+        ui::error_box,
+    },
+    PublicError,
+};
+
+/// Days preselected for a new invoice.
+const DEFAULT_DAYS: i64 = 30;
+
+#[derive(serde::Deserialize, Clone)]
+struct InvoiceFormData {
+    client_id: u64,
+    from: String,
+    to: String,
+    grouping: String,
+    /// In percent, empty for no tax.
+    #[serde(default)]
+    tax_rate: String,
+    /// `preview` or `create`.
+    action: String,
+}
+
+/// What the page shows besides the list of invoices.
+enum Outcome {
+    Form,
+    Preview(InvoiceData),
+    Created(Invoice),
+}
+
+/// List invoices, and preview and create new ones.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let (form, res) = match *req.method() {
+        Method::GET => (None, Ok(Outcome::Form)),
+        Method::POST => {
+            let data = parse_form::<InvoiceFormData>(req)?;
+            let res = parse_request(&data).and_then(|request| match data.action.as_str() {
+                "create" => invoice_create(&ctx.db, user, &request).map(Outcome::Created),
+                _ => invoice_draft(&ctx.db, user, &request).map(Outcome::Preview),
+            });
+            (Some(data), res)
+        }
+        _ => return Ok(response_not_found_html()),
+    };
+    let (outcome, error) = match res {
+        Ok(outcome) => (outcome, None),
+        Err(err) if err.is::<PublicError>() => (Outcome::Form, Some(err.to_string())),
+        Err(err) => return Err(err),
+    };
+    if let Outcome::Created(invoice) = &outcome {
+        log::info!(
+            invoice_id = invoice.id, number = invoice.number, entries = invoice.data.entries.len();
+            "created invoice"
+        );
+    }
+
+    let clients = user_clients(&ctx.db, user)?;
+    let invoices = user_invoices(&ctx.db, user)?;
+    let today = Zone::for_user(user).today();
+    let content = html! {
+        div.container {
+            (h2("Invoices"))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @match &outcome {
+                Outcome::Form => {}
+                Outcome::Preview(draft) => {
+                    (h4(format!("Preview for {}", draft.client_name)))
+                    (invoice_table(draft))
+                }
+                Outcome::Created(invoice) => {
+                    p class="notification is-success" {
+                        "Created "
+                        a href=(format!("/invoices/{}", invoice.id)) { (invoice_title(invoice)) }
+                        "."
+                    }
+                }
+            }
+            (invoice_form(&clients, form.as_ref(), matches!(outcome, Outcome::Preview(_)), today))
+            (h4("Issued invoices"))
+            (invoices_table(&invoices, DisplayPrefs::for_user(user)))
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// A single invoice at `/invoices/<id>` as printable HTML, or as PDF at
+/// `/invoices/<id>.pdf`.
+pub fn handler_invoice(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let name = req
+        .uri()
+        .path()
+        .strip_prefix("/invoices/")
+        .unwrap_or_default();
+    let (id, pdf) = match name.strip_suffix(".pdf") {
+        Some(id) => (id, true),
+        None => (name, false),
+    };
+    let invoice = match id.parse() {
+        Ok(id) => user_invoice(&ctx.db, user, id)?,
+        Err(_) => None,
+    };
+    let Some(invoice) = invoice else {
+        return Ok(response_not_found_html());
+    };
+
+    if pdf {
+        let filename = format!("invoice-{}.pdf", invoice.number);
+        let res = ResponseBuilder::new()
+            .status(StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, "application/pdf")
+            .header(
+                http::header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            )
+            .header(http::header::CACHE_CONTROL, "no-store")
+            .body(Body::new_data(invoice_pdf(&invoice, user)))
+            .unwrap();
+        return Ok(res);
+    }
+
+    let prefs = DisplayPrefs::for_user(user);
+    let data = &invoice.data;
+    let date = |value: &str| {
+        parse_date(value)
+            .map(|d| prefs.locale.format_date(d))
+            .unwrap_or_else(|_| value.to_string())
+    };
+    let title = invoice_title(&invoice);
+    let body = html! {
+        (DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8" {}
+                title { (title) }
+                link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/bulma@0.9.4/css/bulma.min.css" { }
+                style { "@media print { .no-print { display: none; } }" }
+            }
+            body {
+                section.section {
+                    div.container {
+                        div.buttons.no-print {
+                            a.button href="/invoices" { "Back" }
+                            a.button.is-primary href=(format!("/invoices/{}.pdf", invoice.id)) { "Download PDF" }
+                        }
+                        (h2(&title))
+                        table.table {
+                            tbody {
+                                tr { th { "From" } td { (user.username) } }
+                                tr { th { "To" } td { (data.client_name) } }
+                                tr {
+                                    th { "Date" }
+                                    td { (prefs.locale.format_date(prefs.zone.to_local(invoice.issued_at).date())) }
+                                }
+                                tr {
+                                    th { "Period" }
+                                    td { (date(&data.period_from)) " to " (date(&data.period_to)) }
+                                }
+                            }
+                        }
+                        (invoice_table(data))
+                        (h4("Entries"))
+                        table class="table is-fullwidth is-narrow" {
+                            thead {
+                                tr {
+                                    th { "Start" }
+                                    th { "End" }
+                                    th { "Project" }
+                                    th { "Title" }
+                                    th.has-text-right { "Hours" }
+                                    th.has-text-right { "Amount" }
+                                }
+                            }
+                            tbody {
+                                @for entry in &data.entries {
+                                    tr {
+                                        td { (prefs.datetime(entry.started_at)) }
+                                        td { (prefs.datetime(entry.finished_at)) }
+                                        td { @if let Some(project) = &entry.project { (project) } }
+                                        td { (entry.title) }
+                                        td.has-text-right { (format_hours(entry.seconds)) }
+                                        td.has-text-right { (money(entry.amount_cents, &data.currency)) }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    Ok(response_html_ok(body.into_string()))
+}
+
+fn parse_request(data: &InvoiceFormData) -> Result<InvoiceRequest, anyhow::Error> {
+    Ok(InvoiceRequest {
+        client_id: data.client_id,
+        range: DateRange::new(parse_date(&data.from)?, parse_date(&data.to)?)?,
+        grouping: data.grouping.parse()?,
+        tax_rate_basis_points: parse_tax_rate(&data.tax_rate)?,
+    })
+}
+
+fn money(cents: i64, currency: &str) -> String {
+    format!("{} {currency}", Amount::from_cents(cents))
+}
+
+/// Lines and totals of an invoice.
+fn invoice_table(data: &InvoiceData) -> Fragment {
+    let grouping = match data.grouping.as_str() {
+        "task" => "Task",
+        _ => "Project",
+    };
+    html! {
+        table class="table is-fullwidth" {
+            thead {
+                tr {
+                    th { (grouping) }
+                    th.has-text-right { "Hours" }
+                    th.has-text-right { "Rate" }
+                    th.has-text-right { "Amount" }
+                }
+            }
+            tbody {
+                @for line in &data.lines {
+                    tr {
+                        td { (line.description) }
+                        td.has-text-right { (format_hours(line.seconds)) }
+                        td.has-text-right {
+                            @if let Some(rate) = line.rate_cents { (money(rate, &data.currency)) }
+                        }
+                        td.has-text-right { (money(line.amount_cents, &data.currency)) }
+                    }
+                }
+            }
+            tfoot {
+                tr {
+                    td colspan="3" { "Subtotal" }
+                    td.has-text-right { (money(data.subtotal_cents, &data.currency)) }
+                }
+                tr {
+                    td colspan="3" { "Tax (" (format_tax_rate(data.tax_rate_basis_points)) "%)" }
+                    td.has-text-right { (money(data.tax_cents, &data.currency)) }
+                }
+                tr {
+                    th colspan="3" { "Total" }
+                    th.has-text-right { (money(data.total_cents, &data.currency)) }
+                }
+            }
+        }
+    }
+}
+
+/// Form for a new invoice, prefilled from the last submission.
+///
+/// After a preview, the same values can be submitted again to create the
+/// invoice.
+fn invoice_form(
+    clients: &[Client],
+    data: Option<&InvoiceFormData>,
+    previewed: bool,
+    today: time::Date,
+) -> Fragment {
+    if clients.is_empty() {
+        return html! {
+            p class="notification is-warning" {
+                "Invoices are made for clients. Add a client on the "
+                a href="/projects" { "projects page" }
+                " first."
+            }
+        };
+    }
+    let default_range = DateRange::last_days(today, DEFAULT_DAYS);
+    let from = data.map_or(default_range.from.to_string(), |d| d.from.clone());
+    let to = data.map_or(default_range.to.to_string(), |d| d.to.clone());
+    html! {
+        (h4("New invoice"))
+        form.box action="/invoices" method="post" {
+            div class="field is-grouped" {
+                div.control {
+                    label.label { "Client" }
+                    div.select {
+                        select name="client_id" {
+                            @for client in clients {
+                                option value=(client.id) selected[data.is_some_and(|d| d.client_id == client.id)] {
+                                    (client.name)
+                                }
+                            }
+                        }
+                    }
+                }
+                div.control {
+                    label.label { "From" }
+                    input.input type="date" name="from" value=(from) required {}
+                }
+                div.control {
+                    label.label { "To" }
+                    input.input type="date" name="to" value=(to) required {}
+                }
+                div.control {
+                    label.label { "Group by" }
+                    div.select {
+                        select name="grouping" {
+                            @for g in InvoiceGrouping::ALL {
+                                option value=(g.as_str()) selected[data.is_some_and(|d| d.grouping == g.as_str())] {
+                                    (g.as_str())
+                                }
+                            }
+                        }
+                    }
+                }
+                div.control {
+                    label.label { "Tax (%)" }
+                    input.input name="tax_rate" type="text" inputmode="decimal" placeholder="0" value=[data.map(|d| &d.tax_rate)] {}
+                }
+            }
+            div.buttons {
+                button.button name="action" value="preview" type="submit" { "Preview" }
+                @if previewed {
+                    button.button.is-primary name="action" value="create" type="submit" { "Create invoice" }
+                }
+            }
+        }
+    }
+}
+
+fn invoices_table(invoices: &[Invoice], prefs: DisplayPrefs) -> Fragment {
+    html! {
+        @if invoices.is_empty() {
+            p.block { "No invoices created yet." }
+        } @else {
+            table class="table is-fullwidth is-striped" {
+                thead {
+                    tr {
+                        th { "Number" }
+                        th { "Date" }
+                        th { "Client" }
+                        th { "Period" }
+                        th.has-text-right { "Total" }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for invoice in invoices {
+                        tr {
+                            td { (invoice.number) }
+                            td { (prefs.locale.format_date(prefs.zone.to_local(invoice.issued_at).date())) }
+                            td { (invoice.data.client_name) }
+                            td { (invoice.data.period_from) " to " (invoice.data.period_to) }
+                            td.has-text-right { (money(invoice.data.total_cents, &invoice.data.currency)) }
+                            td.has-text-right {
+                                a href=(format!("/invoices/{}", invoice.id)) { "View" }
+                                " · "
+                                a href=(format!("/invoices/{}.pdf", invoice.id)) { "PDF" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -22,8 +22,12 @@ pub fn handler_login(req: Request, ctx: &Context) -> HandlerResult {
     match *req.method() {
         Method::GET => Ok(handler_login_get(ctx)),
         Method::POST => match handler_login_submit(req, ctx) {
-            Ok(r) => Ok(r),
+            Ok(r) => {
+                crate::metrics::record_login(true);
+                Ok(r)
+            }
             Err(err) => {
+                crate::metrics::record_login(false);
                 let content = login_page(ctx, Some(err.to_string()));
                 Ok(response_html_ok(content))
             }
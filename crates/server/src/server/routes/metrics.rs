@@ -0,0 +1,49 @@
+use http::StatusCode;
+use wcgi::{Body, ResponseBuilder};
+
+use crate::{
+    db::{types::TimelogFilter, Db},
+    metrics::{self, Gauges},
+    server::{
+        bearer_token,
+        prelude::{Context, HandlerResult, Request},
+        response_not_found_html,
+    },
+};
+
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let Some(token) = &ctx.config.metrics_token else {
+        return Ok(response_not_found_html());
+    };
+
+    let authorized = bearer_token(&req)
+        .map(|v| constant_time_eq(v.as_bytes(), token.as_bytes()))
+        .unwrap_or(false);
+    if !authorized {
+        let res = ResponseBuilder::new()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(http::header::WWW_AUTHENTICATE, "Bearer")
+            .body(Body::empty())
+            .unwrap();
+        return Ok(res);
+    }
+
+    let gauges = Gauges {
+        active_timers: ctx.db.timelog_count(TimelogFilter::IsFinished(false))?,
+    };
+
+    let res = ResponseBuilder::new()
+        .status(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::new_text(metrics::render(
+            &ctx.db.metric_counters()?,
+            &gauges,
+        )))
+        .unwrap();
+    Ok(res)
+}
+
+/// Compare without exiting early, so the token can't be guessed by timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,5 +1,24 @@
+pub mod account;
+pub mod audit;
+pub mod calendar;
 pub mod dashboard;
+pub mod export;
+pub mod health;
+pub mod import;
+pub mod import_calendar;
+pub mod invoices;
 pub mod login;
+pub mod metrics;
+pub mod orgs;
+pub mod projects;
+pub mod reports;
+pub mod settings;
 pub mod signup;
+pub mod timelog_billable;
+pub mod timelog_continue;
+pub mod timelog_delete;
 pub mod timelog_finish;
 pub mod timelog_start;
+pub mod timelog_switch;
+pub mod timesheets;
+pub mod trash;
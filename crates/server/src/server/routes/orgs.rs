@@ -0,0 +1,610 @@
+use maud::html;
+
+use crate::{
+    db::types::{Invitation, Organization, OrganizationId, UserId},
+    logic::{
+        locale::Zone,
+        orgs::{
+            invitation_accept, invitation_cancel, invitation_create, invitation_decline,
+            membership_remove, membership_set_role, organization_create, organization_details,
+            organization_report, organization_tag_create, user_invitations, user_organizations,
+            OrganizationDetails, Role,
+        },
+        reports::{DateRange, Grouping},
+    },
+    server::{
+        prelude::{
+            h2, h4, page, parse_form, parse_query, response_html_ok, Context, Fragment,
+            HandlerResult, Method, Request,
+        },
+        response_not_found_html,
+        ui::{error_box, util::format_duration},
+    },
+    PublicError,
+};
+
+use super::reports::{parse_params, totals_table, ReportParams, DEFAULT_DAYS};
+
+/// Color preselected for new tags.
+const DEFAULT_COLOR: &str = "#485fc7";
+
+#[derive(serde::Deserialize, Clone)]
+struct OrganizationFormData {
+    name: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct InvitationFormData {
+    invitation_id: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct InviteFormData {
+    username: String,
+    role: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct MemberFormData {
+    user_id: u64,
+    /// Only for role changes.
+    #[serde(default)]
+    role: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct TagFormData {
+    name: String,
+    #[serde(default)]
+    color: String,
+}
+
+/// List the organizations of the user and pending invitations, and create
+/// organizations.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    match *req.method() {
+        Method::GET => render_list(ctx, Ok(None)),
+        Method::POST => {
+            let res = parse_form::<OrganizationFormData>(req)
+                .and_then(|data| organization_create(&ctx.db, user, &data.name));
+            match res {
+                Ok(org) => {
+                    log::info!(organization_id = org.id; "created organization");
+                    let notice = format!("Created organization '{}'.", org.name);
+                    render_organization(ctx, org.id, Ok(Some(notice)))
+                }
+                Err(err) => render_list(ctx, Err(err)),
+            }
+        }
+        _ => Ok(response_not_found_html()),
+    }
+}
+
+/// Accept an invitation of the user.
+pub fn handler_invitation_accept(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<InvitationFormData>(req)
+        .and_then(|data| invitation_accept(&ctx.db, user, data.invitation_id));
+    match res {
+        Ok(id) => render_organization(
+            ctx,
+            id,
+            Ok(Some("You joined the organization.".to_string())),
+        ),
+        Err(err) => render_list(ctx, Err(err)),
+    }
+}
+
+/// Decline an invitation of the user.
+pub fn handler_invitation_decline(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<InvitationFormData>(req).and_then(|data| {
+        invitation_decline(&ctx.db, user, data.invitation_id)?;
+        Ok(Some("Invitation declined.".to_string()))
+    });
+    render_list(ctx, res)
+}
+
+/// An organization at `/orgs/<id>`.
+pub fn handler_organization(req: Request, ctx: &Context) -> HandlerResult {
+    let Some(id) = organization_id(&req) else {
+        return Ok(response_not_found_html());
+    };
+    render_organization(ctx, id, Ok(None))
+}
+
+/// Invite a user by username.
+pub fn handler_invite(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let Some(id) = organization_id(&req) else {
+        return Ok(response_not_found_html());
+    };
+    let res = parse_form::<InviteFormData>(req).and_then(|data| {
+        let (_, invited) =
+            invitation_create(&ctx.db, user, id, &data.username, data.role.parse()?)?;
+        Ok(Some(format!("Invited {}.", invited.username)))
+    });
+    render_organization(ctx, id, res)
+}
+
+/// Withdraw an invitation.
+pub fn handler_invitation_cancel(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let Some(id) = organization_id(&req) else {
+        return Ok(response_not_found_html());
+    };
+    let res = parse_form::<InvitationFormData>(req).and_then(|data| {
+        invitation_cancel(&ctx.db, user, id, data.invitation_id)?;
+        Ok(Some("Invitation withdrawn.".to_string()))
+    });
+    render_organization(ctx, id, res)
+}
+
+/// Change the role of a member.
+pub fn handler_member_role(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let Some(id) = organization_id(&req) else {
+        return Ok(response_not_found_html());
+    };
+    let res = parse_form::<MemberFormData>(req).and_then(|data| {
+        membership_set_role(&ctx.db, user, id, data.user_id, data.role.parse()?)?;
+        Ok(Some("Role saved.".to_string()))
+    });
+    render_organization(ctx, id, res)
+}
+
+/// Remove a member, or leave the organization.
+pub fn handler_member_remove(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let Some(id) = organization_id(&req) else {
+        return Ok(response_not_found_html());
+    };
+    let res = parse_form::<MemberFormData>(req)
+        .and_then(|data| membership_remove(&ctx.db, user, id, data.user_id).map(|_| data.user_id));
+    match res {
+        Ok(member_id) if member_id == user.id => {
+            render_list(ctx, Ok(Some("You left the organization.".to_string())))
+        }
+        Ok(_) => render_organization(ctx, id, Ok(Some("Member removed.".to_string()))),
+        Err(err) => render_organization(ctx, id, Err(err)),
+    }
+}
+
+/// Create a shared tag.
+pub fn handler_tag(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let Some(id) = organization_id(&req) else {
+        return Ok(response_not_found_html());
+    };
+    let res = parse_form::<TagFormData>(req).and_then(|data| {
+        let color = Some(data.color.as_str()).filter(|c| !c.trim().is_empty());
+        let tag = organization_tag_create(&ctx.db, user, id, &data.name, color)?;
+        Ok(Some(format!("Created tag '{}'.", tag.name)))
+    });
+    render_organization(ctx, id, res)
+}
+
+/// Report of the time of all members at `/orgs/<id>/report`.
+pub fn handler_report(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let Some(id) = organization_id(&req) else {
+        return Ok(response_not_found_html());
+    };
+    let params: ReportParams = parse_query(&req).unwrap_or_default();
+
+    let today = Zone::for_user(user).today();
+    let (range, grouping, error) = match parse_params(&params, today) {
+        Ok((range, grouping)) => (range, grouping, None),
+        Err(err) => (
+            DateRange::last_days(today, DEFAULT_DAYS),
+            Grouping::Day,
+            Some(err.to_string()),
+        ),
+    };
+    let res = organization_report(&ctx.db, user, id, range, grouping);
+    let org_report = match res {
+        Ok(report) => report,
+        Err(err) if err.is::<PublicError>() => return Ok(response_not_found_html()),
+        Err(err) => return Err(err),
+    };
+    let report = &org_report.report;
+
+    let content = html! {
+        div.container {
+            (h2("Organization report"))
+            p.block {
+                a href=(format!("/orgs/{id}")) { "Back to the organization" }
+            }
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            form.box action=(format!("/orgs/{id}/report")) method="get" {
+                div class="field is-grouped" {
+                    div.control {
+                        label.label { "From" }
+                        input.input type="date" name="from" value=(range.from) {}
+                    }
+                    div.control {
+                        label.label { "To" }
+                        input.input type="date" name="to" value=(range.to) {}
+                    }
+                    div.control {
+                        label.label { "Group by" }
+                        div.select {
+                            select name="group" {
+                                @for g in Grouping::ALL {
+                                    option value=(g.as_str()) selected[g == grouping] { (g.as_str()) }
+                                }
+                            }
+                        }
+                    }
+                    div.control {
+                        label.label { "\u{a0}" }
+                        button.button.is-primary type="submit" { "Show" }
+                    }
+                }
+            }
+            p.block {
+                b { "Total: " }
+                (format_duration(report.total))
+                " (" (report.range.from) " to " (report.range.to) ", " (report.zone.name()) ")"
+            }
+
+            (h4(format!("Per {}", report.grouping.as_str())))
+            table class="table is-fullwidth is-striped" {
+                tbody {
+                    @for period in &report.periods {
+                        tr {
+                            td { (period.label) }
+                            td.has-text-right { (format_duration(period.total)) }
+                        }
+                    }
+                }
+            }
+
+            div.columns {
+                div.column {
+                    (h4("Per member"))
+                    (totals_table(&org_report.by_member))
+                }
+                div.column {
+                    (h4("Per project"))
+                    (totals_table(&report.by_project))
+                }
+            }
+            div.columns {
+                div.column {
+                    (h4("Per title"))
+                    (totals_table(&report.by_title))
+                }
+                div.column {
+                    (h4("Per tag"))
+                    (totals_table(&report.by_tag))
+                }
+            }
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// The id in paths like `/orgs/<id>/...`.
+fn organization_id(req: &Request) -> Option<OrganizationId> {
+    req.uri().path().split('/').nth(2)?.parse().ok()
+}
+
+/// Split the outcome of an action into a notice and an error.
+///
+/// Public errors are shown on the page, others fail the request.
+fn outcome(
+    res: Result<Option<String>, anyhow::Error>,
+) -> Result<(Option<String>, Option<String>), anyhow::Error> {
+    match res {
+        Ok(notice) => Ok((notice, None)),
+        Err(err) if err.is::<PublicError>() => Ok((None, Some(err.to_string()))),
+        Err(err) => Err(err),
+    }
+}
+
+/// Render the list of organizations with the outcome of an action.
+fn render_list(ctx: &Context, res: Result<Option<String>, anyhow::Error>) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let (notice, error) = outcome(res)?;
+    let orgs = user_organizations(&ctx.db, user)?;
+    let invitations = user_invitations(&ctx.db, user)?;
+    let content = html! {
+        div.container {
+            (h2("Organizations"))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @if let Some(notice) = notice {
+                p class="notification is-success" { (notice) }
+            }
+            @if !invitations.is_empty() {
+                (invitations_section(&invitations))
+            }
+            @if orgs.is_empty() {
+                p.block {
+                    "Organizations share projects and tags with their members, "
+                    "and report the time everyone spent on them."
+                }
+            } @else {
+                table class="table is-fullwidth is-striped" {
+                    thead {
+                        tr {
+                            th { "Organization" }
+                            th { "Your role" }
+                        }
+                    }
+                    tbody {
+                        @for (org, role) in &orgs {
+                            tr {
+                                td { a href=(format!("/orgs/{}", org.id)) { (org.name) } }
+                                td { (role.as_str()) }
+                            }
+                        }
+                    }
+                }
+            }
+            (h4("New organization"))
+            form.box action="/orgs" method="post" {
+                div class="field has-addons" {
+                    div.control.is-expanded {
+                        input.input name="name" type="text" placeholder="Name" required {}
+                    }
+                    div.control {
+                        button.button.is-primary type="submit" { "Create organization" }
+                    }
+                }
+            }
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+fn invitations_section(invitations: &[(Invitation, Organization)]) -> Fragment {
+    html! {
+        (h4("Invitations"))
+        div.box {
+            @for (invitation, org) in invitations {
+                div class="level" {
+                    div.level-left {
+                        div.level-item {
+                            span {
+                                "Join " b { (org.name) } " as " (invitation.role)
+                            }
+                        }
+                    }
+                    div.level-right {
+                        div.level-item {
+                            div.buttons {
+                                form action="/orgs/invitations/accept" method="post" {
+                                    input type="hidden" name="invitation_id" value=(invitation.id) {}
+                                    button.button.is-primary.is-small type="submit" { "Accept" }
+                                }
+                                form action="/orgs/invitations/decline" method="post" {
+                                    input type="hidden" name="invitation_id" value=(invitation.id) {}
+                                    button.button.is-small type="submit" { "Decline" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Render an organization with the outcome of an action.
+///
+/// Users who are not a member get a not found page.
+fn render_organization(
+    ctx: &Context,
+    id: OrganizationId,
+    res: Result<Option<String>, anyhow::Error>,
+) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let (notice, error) = outcome(res)?;
+    let details = match organization_details(&ctx.db, user, id) {
+        Ok(details) => details,
+        Err(err) if err.is::<PublicError>() => return Ok(response_not_found_html()),
+        Err(err) => return Err(err),
+    };
+    let content = html! {
+        div.container {
+            (h2(&details.organization.name))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @if let Some(notice) = notice {
+                p class="notification is-success" { (notice) }
+            }
+            div.buttons {
+                a.button href=(format!("/orgs/{id}/report")) { "Report" }
+                a.button href="/orgs" { "All organizations" }
+            }
+            (members_section(&details, user.id))
+            (shared_section(&details))
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+fn members_section(details: &OrganizationDetails, user_id: UserId) -> Fragment {
+    let id = details.organization.id;
+    let role = details.role;
+    html! {
+        (h4("Members"))
+        table class="table is-fullwidth is-striped" {
+            thead {
+                tr {
+                    th { "Member" }
+                    th { "Role" }
+                    th {}
+                }
+            }
+            tbody {
+                @for member in &details.members {
+                    @let is_self = member.user_id == user_id;
+                    @let can_remove = is_self || match member.role {
+                        Role::Member => role >= Role::Admin,
+                        Role::Admin | Role::Owner => role == Role::Owner,
+                    };
+                    tr {
+                        td {
+                            (member.username)
+                            @if is_self {
+                                " " span.tag.is-light { "you" }
+                            }
+                        }
+                        td {
+                            @if role == Role::Owner {
+                                form action=(format!("/orgs/{id}/members/role")) method="post" {
+                                    input type="hidden" name="user_id" value=(member.user_id) {}
+                                    div class="field has-addons" {
+                                        div.control {
+                                            div.select.is-small {
+                                                select name="role" {
+                                                    @for r in Role::ALL {
+                                                        option value=(r.as_str()) selected[r == member.role] { (r.as_str()) }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                        div.control {
+                                            button.button.is-small type="submit" { "Save" }
+                                        }
+                                    }
+                                }
+                            } @else {
+                                (member.role.as_str())
+                            }
+                        }
+                        td.has-text-right {
+                            @if can_remove {
+                                form action=(format!("/orgs/{id}/members/remove")) method="post" {
+                                    input type="hidden" name="user_id" value=(member.user_id) {}
+                                    button.button.is-small type="submit" {
+                                        @if is_self { "Leave" } @else { "Remove" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        @if role >= Role::Admin {
+            @if !details.invitations.is_empty() {
+                table class="table is-fullwidth" {
+                    thead {
+                        tr {
+                            th { "Invited" }
+                            th { "Role" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @for (invitation, username) in &details.invitations {
+                            tr {
+                                td { (username) }
+                                td { (invitation.role) }
+                                td.has-text-right {
+                                    form action=(format!("/orgs/{id}/invitations/cancel")) method="post" {
+                                        input type="hidden" name="invitation_id" value=(invitation.id) {}
+                                        button.button.is-small type="submit" { "Withdraw" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            form.box action=(format!("/orgs/{id}/invite")) method="post" {
+                div class="field is-grouped" {
+                    div.control.is-expanded {
+                        input.input name="username" type="text" placeholder="Username" required {}
+                    }
+                    div.control {
+                        div.select {
+                            select name="role" {
+                                option value=(Role::Member.as_str()) { "member" }
+                                option value=(Role::Admin.as_str()) { "admin" }
+                            }
+                        }
+                    }
+                    div.control {
+                        button.button.is-primary type="submit" { "Invite" }
+                    }
+                }
+                p.help { "They can accept the invitation on their organizations page." }
+            }
+        }
+    }
+}
+
+fn shared_section(details: &OrganizationDetails) -> Fragment {
+    let id = details.organization.id;
+    html! {
+        div.columns {
+            div.column {
+                (h4("Projects"))
+                @if details.projects.is_empty() {
+                    p.block { "No shared projects yet." }
+                } @else {
+                    div.tags {
+                        @for project in &details.projects {
+                            span.tag {
+                                @if let Some(color) = &project.color {
+                                    span style=(format!("color: {color}")) { "● " }
+                                }
+                                (project.name)
+                            }
+                        }
+                    }
+                }
+                @if details.role >= Role::Admin {
+                    p.help {
+                        "Create shared projects on the "
+                        a href="/projects" { "projects page" }
+                        "."
+                    }
+                }
+            }
+            div.column {
+                (h4("Tags"))
+                @if details.tags.is_empty() {
+                    p.block { "No shared tags yet." }
+                } @else {
+                    div.tags {
+                        @for tag in &details.tags {
+                            span.tag {
+                                @if let Some(color) = &tag.color {
+                                    span style=(format!("color: {color}")) { "● " }
+                                }
+                                (tag.name)
+                            }
+                        }
+                    }
+                }
+                @if details.role >= Role::Admin {
+                    form action=(format!("/orgs/{id}/tags")) method="post" {
+                        div class="field has-addons" {
+                            div.control {
+                                input.input name="name" type="text" placeholder="Tag name" required {}
+                            }
+                            div.control {
+                                input.input name="color" type="color" value=(DEFAULT_COLOR) {}
+                            }
+                            div.control {
+                                button.button type="submit" { "Add tag" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,605 @@
+use std::collections::HashMap;
+
+use maud::html;
+use time::OffsetDateTime;
+
+use crate::{
+    db::types::{Client, Organization, OrganizationId, Project, UserTag},
+    logic::{
+        billing::{parse_rate, project_set_rate, tag_set_rate, Amount},
+        budgets::{
+            parse_budget, project_set_budget, tag_set_budget, user_budgets, Budget, BudgetLevel,
+            BudgetPeriod, BudgetStatus, BudgetTarget,
+        },
+        orgs::{user_organizations, Role},
+        projects::{
+            client_create, project_create, project_set_archived, user_clients, user_projects,
+            user_tags, ProjectInput,
+        },
+        trash::{project_delete, tag_delete},
+    },
+    server::{
+        prelude::{
+            h2, h4, page, parse_form, response_html_ok, Context, Fragment, HandlerResult, Method,
+            Request,
+        },
+        response_not_found_html,
+        ui::{error_box, util::format_duration},
+    },
+    PublicError,
+};
+
+/// Color preselected for new projects.
+const DEFAULT_COLOR: &str = "#485fc7";
+
+#[derive(serde::Deserialize, Clone)]
+struct ProjectFormData {
+    name: String,
+    /// Empty for no client.
+    #[serde(default)]
+    client_id: String,
+    #[serde(default)]
+    color: String,
+    /// Present if checked.
+    billable: Option<String>,
+    /// Empty for no rate.
+    #[serde(default)]
+    rate: String,
+    /// Empty for a personal project.
+    #[serde(default)]
+    organization_id: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct ClientFormData {
+    name: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct ArchiveFormData {
+    project_id: u64,
+    archived: bool,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct ProjectDeleteFormData {
+    project_id: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct TagDeleteFormData {
+    tag_id: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct ProjectRateFormData {
+    project_id: u64,
+    /// Empty to remove the rate.
+    rate: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct TagRateFormData {
+    tag_id: u64,
+    /// Empty to remove the rate.
+    rate: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct ProjectBudgetFormData {
+    project_id: u64,
+    /// Empty to remove the budget.
+    hours: String,
+    period: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct TagBudgetFormData {
+    tag_id: u64,
+    /// Empty to remove the budget.
+    hours: String,
+    period: String,
+}
+
+/// List projects and clients, and create projects.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = match *req.method() {
+        Method::GET => Ok(None),
+        Method::POST => parse_form::<ProjectFormData>(req).and_then(|data| {
+            let client_id = match data.client_id.trim() {
+                "" => None,
+                id => Some(id.parse().map_err(|_| PublicError::msg("Invalid client"))?),
+            };
+            let organization_id = match data.organization_id.trim() {
+                "" => None,
+                id => Some(
+                    id.parse()
+                        .map_err(|_| PublicError::msg("Invalid organization"))?,
+                ),
+            };
+            let input = ProjectInput {
+                name: data.name,
+                client_id,
+                color: Some(data.color).filter(|c| !c.trim().is_empty()),
+                billable: data.billable.is_some(),
+                hourly_rate: parse_rate(&data.rate)?,
+                organization_id,
+            };
+            let project = project_create(&ctx.db, user, input)?;
+            Ok(Some(format!("Created project '{}'.", project.name)))
+        }),
+        _ => return Ok(response_not_found_html()),
+    };
+    render(ctx, res)
+}
+
+/// Create a client.
+pub fn handler_client(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<ClientFormData>(req).and_then(|data| {
+        let client = client_create(&ctx.db, user, &data.name, None)?;
+        Ok(Some(format!("Created client '{}'.", client.name)))
+    });
+    render(ctx, res)
+}
+
+/// Archive or restore a project.
+pub fn handler_archive(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<ArchiveFormData>(req).and_then(|data| {
+        let project = project_set_archived(&ctx.db, user, data.project_id, data.archived)?;
+        let notice = if project.archived {
+            format!("Archived project '{}'.", project.name)
+        } else {
+            format!("Restored project '{}'.", project.name)
+        };
+        Ok(Some(notice))
+    });
+    render(ctx, res)
+}
+
+/// Move a project to the trash.
+pub fn handler_project_delete(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<ProjectDeleteFormData>(req).and_then(|data| {
+        project_delete(&ctx.db, user, data.project_id, OffsetDateTime::now_utc())?;
+        Ok(Some("Moved the project to the trash.".to_string()))
+    });
+    render(ctx, res)
+}
+
+/// Move a tag to the trash.
+pub fn handler_tag_delete(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<TagDeleteFormData>(req).and_then(|data| {
+        tag_delete(&ctx.db, user, data.tag_id, OffsetDateTime::now_utc())?;
+        Ok(Some("Moved the tag to the trash.".to_string()))
+    });
+    render(ctx, res)
+}
+
+/// Set the hourly rate of a project.
+pub fn handler_project_rate(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<ProjectRateFormData>(req).and_then(|data| {
+        project_set_rate(&ctx.db, user, data.project_id, parse_rate(&data.rate)?)?;
+        Ok(Some("Project rate saved.".to_string()))
+    });
+    render(ctx, res)
+}
+
+/// Set the hourly rate of a tag.
+pub fn handler_tag_rate(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<TagRateFormData>(req).and_then(|data| {
+        tag_set_rate(&ctx.db, user, data.tag_id, parse_rate(&data.rate)?)?;
+        Ok(Some("Tag rate saved.".to_string()))
+    });
+    render(ctx, res)
+}
+
+/// Set the time budget of a project.
+pub fn handler_project_budget(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<ProjectBudgetFormData>(req).and_then(|data| {
+        let budget = parse_budget(&data.hours, &data.period)?;
+        project_set_budget(&ctx.db, user, data.project_id, budget)?;
+        Ok(Some("Project budget saved.".to_string()))
+    });
+    render(ctx, res)
+}
+
+/// Set the time budget of a tag.
+pub fn handler_tag_budget(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<TagBudgetFormData>(req).and_then(|data| {
+        let budget = parse_budget(&data.hours, &data.period)?;
+        tag_set_budget(&ctx.db, user, data.tag_id, budget)?;
+        Ok(Some("Tag budget saved.".to_string()))
+    });
+    render(ctx, res)
+}
+
+/// Render the page with the outcome of an action.
+///
+/// Public errors are shown on the page, others fail the request.
+fn render(ctx: &Context, res: Result<Option<String>, anyhow::Error>) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let (notice, error) = match res {
+        Ok(notice) => (notice, None),
+        Err(err) if err.is::<PublicError>() => (None, Some(err.to_string())),
+        Err(err) => return Err(err),
+    };
+    let clients = user_clients(&ctx.db, user)?;
+    let projects = user_projects(&ctx.db, user, true)?;
+    let tags = user_tags(&ctx.db, user)?;
+    let orgs = user_organizations(&ctx.db, user)?;
+    let budgets = user_budgets(&ctx.db, user, OffsetDateTime::now_utc())?;
+    let content = html! {
+        (projects_content(&user.currency, &clients, &projects, &tags, &orgs, notice, error))
+        (budgets_section(&budgets))
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+fn projects_content(
+    currency: &str,
+    clients: &[Client],
+    projects: &[Project],
+    tags: &[UserTag],
+    orgs: &[(Organization, Role)],
+    notice: Option<String>,
+    error: Option<String>,
+) -> Fragment {
+    let client_names = clients
+        .iter()
+        .map(|c| (c.id, c.name.as_str()))
+        .collect::<HashMap<_, _>>();
+    let org_names = orgs
+        .iter()
+        .map(|(org, _)| (org.id, org.name.as_str()))
+        .collect::<HashMap<_, _>>();
+    // Shared projects and tags are managed by admins of the organization.
+    let can_manage = |org_id: Option<OrganizationId>| match org_id {
+        Some(id) => orgs
+            .iter()
+            .any(|(org, role)| org.id == id && *role >= Role::Admin),
+        None => true,
+    };
+
+    html! {
+        div.container {
+            (h2("Projects"))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @if let Some(notice) = notice {
+                p class="notification is-success" { (notice) }
+            }
+
+            @if projects.is_empty() {
+                p class="notification is-warning" { "No projects created yet." }
+            } @else {
+                table class="table is-fullwidth is-striped" {
+                    thead {
+                        tr {
+                            th { "Project" }
+                            th { "Client" }
+                            th { "Billable" }
+                            th { "Hourly rate (" (currency) ")" }
+                            th { "Budget (hours)" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @for project in projects {
+                            tr {
+                                td {
+                                    @if let Some(color) = &project.color {
+                                        span style=(format!("color: {color}")) { "● " }
+                                    }
+                                    (project.name)
+                                    @if let Some(name) = project.organization_id.and_then(|id| org_names.get(&id)) {
+                                        " " span.tag.is-info.is-light { (name) }
+                                    }
+                                    @if project.archived {
+                                        " " span.tag.is-light { "archived" }
+                                    }
+                                }
+                                td {
+                                    @if let Some(name) = project.client_id.and_then(|id| client_names.get(&id)) {
+                                        (name)
+                                    }
+                                }
+                                td { @if project.billable { "yes" } @else { "no" } }
+                                @let budget = Budget::from_db(project.budget_minutes, &project.budget_period);
+                                @if can_manage(project.organization_id) {
+                                    td {
+                                        form action="/projects/rate" method="post" {
+                                            input type="hidden" name="project_id" value=(project.id) {}
+                                            (rate_input(project.hourly_rate_cents))
+                                        }
+                                    }
+                                    td {
+                                        form action="/projects/budget" method="post" {
+                                            input type="hidden" name="project_id" value=(project.id) {}
+                                            (budget_input(budget))
+                                        }
+                                    }
+                                    td.has-text-right {
+                                        div.buttons.is-right {
+                                            form action="/projects/archive" method="post" {
+                                                input type="hidden" name="project_id" value=(project.id) {}
+                                                input type="hidden" name="archived" value=(!project.archived) {}
+                                                button.button.is-small type="submit" {
+                                                    @if project.archived { "Restore" } @else { "Archive" }
+                                                }
+                                            }
+                                            form action="/projects/delete" method="post" {
+                                                input type="hidden" name="project_id" value=(project.id) {}
+                                                button.button.is-small.is-danger.is-light type="submit" { "Delete" }
+                                            }
+                                        }
+                                    }
+                                } @else {
+                                    td { (rate_text(project.hourly_rate_cents)) }
+                                    td { (budget_text(budget)) }
+                                    td {}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            (h4("New project"))
+            form.box action="/projects" method="post" {
+                div class="field is-grouped" {
+                    div.control.is-expanded {
+                        label.label { "Name" }
+                        input.input name="name" type="text" required {}
+                    }
+                    @if orgs.iter().any(|(_, role)| *role >= Role::Admin) {
+                        div.control {
+                            label.label { "Owner" }
+                            div.select {
+                                select name="organization_id" {
+                                    option value="" { "Personal" }
+                                    @for (org, role) in orgs {
+                                        @if *role >= Role::Admin {
+                                            option value=(org.id) { (org.name) }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    div.control {
+                        label.label { "Client" }
+                        div.select {
+                            select name="client_id" {
+                                option value="" { "No client" }
+                                @for client in clients {
+                                    option value=(client.id) { (client.name) }
+                                }
+                            }
+                        }
+                    }
+                    div.control {
+                        label.label { "Color" }
+                        input.input name="color" type="color" value=(DEFAULT_COLOR) {}
+                    }
+                    div.control {
+                        label.label { "Hourly rate (" (currency) ")" }
+                        input.input name="rate" type="text" inputmode="decimal" placeholder="Default" {}
+                    }
+                }
+                div.field {
+                    label.checkbox {
+                        input name="billable" type="checkbox" {}
+                        " Billable by default"
+                    }
+                }
+                div.buttons {
+                    button.button.is-primary type="submit" { "Create project" }
+                }
+            }
+
+            (h4("Clients"))
+            div.box {
+                @if clients.is_empty() {
+                    p.block { "No clients created yet." }
+                } @else {
+                    div.tags {
+                        @for client in clients {
+                            span.tag { (client.name) }
+                        }
+                    }
+                }
+                form action="/clients" method="post" {
+                    div class="field has-addons" {
+                        div.control {
+                            input.input name="name" type="text" placeholder="Client name" required {}
+                        }
+                        div.control {
+                            button.button type="submit" { "Add client" }
+                        }
+                    }
+                }
+            }
+
+            (h4("Tags"))
+            div.box {
+                p.block {
+                    "Tag rates override project rates and the default rate in the settings. "
+                    "If an entry has several tags with a rate, the highest one applies."
+                }
+                @if tags.is_empty() {
+                    p.block { "No tags created yet." }
+                } @else {
+                    table.table.is-fullwidth {
+                        thead {
+                            tr {
+                                th { "Tag" }
+                                th { "Hourly rate (" (currency) ")" }
+                                th { "Budget (hours)" }
+                                th {}
+                            }
+                        }
+                        tbody {
+                            @for tag in tags {
+                                @let budget = Budget::from_db(tag.budget_minutes, &tag.budget_period);
+                                tr {
+                                    td {
+                                        (tag.name)
+                                        @if let Some(name) = tag.organization_id.and_then(|id| org_names.get(&id)) {
+                                            " " span.tag.is-info.is-light { (name) }
+                                        }
+                                    }
+                                    @if can_manage(tag.organization_id) {
+                                        td {
+                                            form action="/tags/rate" method="post" {
+                                                input type="hidden" name="tag_id" value=(tag.id) {}
+                                                (rate_input(tag.hourly_rate_cents))
+                                            }
+                                        }
+                                        td {
+                                            form action="/tags/budget" method="post" {
+                                                input type="hidden" name="tag_id" value=(tag.id) {}
+                                                (budget_input(budget))
+                                            }
+                                        }
+                                        td.has-text-right {
+                                            form action="/tags/delete" method="post" {
+                                                input type="hidden" name="tag_id" value=(tag.id) {}
+                                                button.button.is-small.is-danger.is-light type="submit" { "Delete" }
+                                            }
+                                        }
+                                    } @else {
+                                        td { (rate_text(tag.hourly_rate_cents)) }
+                                        td { (budget_text(budget)) }
+                                        td {}
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Inline rate field with a save button, for a form with a `rate` field.
+fn rate_input(cents: Option<i64>) -> Fragment {
+    let value = cents.map(|c| Amount::from_cents(c).to_string());
+    html! {
+        div class="field has-addons" {
+            div.control {
+                input.input.is-small name="rate" type="text" inputmode="decimal" placeholder="None" value=[value] {}
+            }
+            div.control {
+                button.button.is-small type="submit" { "Save" }
+            }
+        }
+    }
+}
+
+/// A rate as text, for projects and tags the user can't change.
+fn rate_text(cents: Option<i64>) -> Fragment {
+    html! {
+        @match cents {
+            Some(cents) => (Amount::from_cents(cents)),
+            None => "None",
+        }
+    }
+}
+
+/// A budget as text, for projects and tags the user can't change.
+fn budget_text(budget: Option<Budget>) -> Fragment {
+    html! {
+        @match budget {
+            Some(budget) => (budget.hours()) " " (budget.period.label()),
+            None => "None",
+        }
+    }
+}
+
+/// Inline budget fields with a save button, for a form with `hours` and
+/// `period` fields.
+fn budget_input(budget: Option<Budget>) -> Fragment {
+    let period = budget.map_or(BudgetPeriod::Total, |b| b.period);
+    html! {
+        div class="field has-addons" {
+            div.control {
+                input.input.is-small name="hours" type="text" inputmode="decimal" placeholder="None" size="6" value=[budget.map(|b| b.hours())] {}
+            }
+            div.control {
+                div.select.is-small {
+                    select name="period" {
+                        @for p in BudgetPeriod::ALL {
+                            option value=(p.as_str()) selected[p == period] { (p.label()) }
+                        }
+                    }
+                }
+            }
+            div.control {
+                button.button.is-small type="submit" { "Save" }
+            }
+        }
+    }
+}
+
+fn budgets_section(budgets: &[BudgetStatus]) -> Fragment {
+    if budgets.is_empty() {
+        return html! {};
+    }
+    html! {
+        div.container {
+            (h4("Budgets"))
+            table class="table is-fullwidth" {
+                thead {
+                    tr {
+                        th { "Budget" }
+                        th { "Estimate" }
+                        th.has-text-right { "Used" }
+                        th.has-text-right { "Remaining" }
+                        th style="width: 30%" {}
+                    }
+                }
+                tbody {
+                    @for status in budgets {
+                        @let class = match status.level() {
+                            BudgetLevel::Ok => "progress is-success",
+                            BudgetLevel::Warning => "progress is-warning",
+                            BudgetLevel::Over => "progress is-danger",
+                        };
+                        tr {
+                            td {
+                                (status.name)
+                                @if let BudgetTarget::Tag(_) = status.target {
+                                    " " span.tag.is-light { "tag" }
+                                }
+                            }
+                            td { (status.budget.hours()) "h " (status.budget.period.label()) }
+                            td.has-text-right { (format_duration(status.used)) }
+                            td.has-text-right {
+                                @if status.remaining().is_negative() {
+                                    span.has-text-danger { "-" (format_duration(-status.remaining())) }
+                                } @else {
+                                    (format_duration(status.remaining()))
+                                }
+                            }
+                            td {
+                                progress class=(class) value=(status.percent().min(100)) max="100" {
+                                    (status.percent()) "%"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use maud::html;
+use time::Date;
+
+use crate::{
+    logic::{
+        locale::Zone,
+        reports::{user_report, DateRange, Earnings, Grouping, NamedEarnings, NamedTotal, Report},
+    },
+    server::{
+        prelude::{
+            h2, h4, page, parse_query, response_html_ok, Context, Fragment, HandlerResult, Request,
+        },
+        ui::{error_box, util::format_duration},
+    },
+};
+
+/// Days covered when no range is given.
+pub(super) const DEFAULT_DAYS: i64 = 7;
+
+#[derive(serde::Deserialize, Clone, Default)]
+pub(super) struct ReportParams {
+    from: Option<String>,
+    to: Option<String>,
+    group: Option<String>,
+}
+
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let params: ReportParams = parse_query(&req).unwrap_or_default();
+
+    let today = Zone::for_user(user).today();
+    let (range, grouping, error) = match parse_params(&params, today) {
+        Ok((range, grouping)) => (range, grouping, None),
+        Err(err) => (
+            DateRange::last_days(today, DEFAULT_DAYS),
+            Grouping::Day,
+            Some(err.to_string()),
+        ),
+    };
+
+    let report = user_report(&ctx.db, user, range, grouping)?;
+    let content = html! {
+        div.container {
+            (h2("Reports"))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            (range_form(range, grouping))
+            (render_report(&report))
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+pub(super) fn parse_params(
+    params: &ReportParams,
+    today: Date,
+) -> Result<(DateRange, Grouping), anyhow::Error> {
+    let grouping = match params.group.as_deref() {
+        Some(group) if !group.is_empty() => group.parse()?,
+        _ => Grouping::Day,
+    };
+    let range = DateRange::parse(
+        params.from.as_deref(),
+        params.to.as_deref(),
+        today,
+        DEFAULT_DAYS,
+    )?;
+    Ok((range, grouping))
+}
+
+fn range_form(range: DateRange, grouping: Grouping) -> Fragment {
+    html! {
+        form.box action="/reports" method="get" {
+            div class="field is-grouped" {
+                div.control {
+                    label.label { "From" }
+                    input.input type="date" name="from" value=(range.from) {}
+                }
+                div.control {
+                    label.label { "To" }
+                    input.input type="date" name="to" value=(range.to) {}
+                }
+                div.control {
+                    label.label { "Group by" }
+                    div.select {
+                        select name="group" {
+                            @for g in Grouping::ALL {
+                                option value=(g.as_str()) selected[g == grouping] { (g.as_str()) }
+                            }
+                        }
+                    }
+                }
+                div.control {
+                    label.label { "\u{a0}" }
+                    div.buttons {
+                        button.button.is-primary type="submit" { "Show" }
+                        a.button href=(format!("/export.csv?from={}&to={}", range.from, range.to)) {
+                            "Export CSV"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn render_report(report: &Report) -> Fragment {
+    html! {
+        p.block {
+            b { "Total: " }
+            (format_duration(report.total))
+            " (" (report.range.from) " to " (report.range.to) ", " (report.zone.name()) ")"
+        }
+
+        (h4(format!("Per {}", report.grouping.as_str())))
+        table class="table is-fullwidth is-striped" {
+            tbody {
+                @for period in &report.periods {
+                    tr {
+                        td { (period.label) }
+                        td.has-text-right { (format_duration(period.total)) }
+                    }
+                }
+            }
+        }
+
+        div.columns {
+            div.column {
+                (h4("Per title"))
+                (totals_table(&report.by_title))
+            }
+            div.column {
+                (h4("Per tag"))
+                (totals_table(&report.by_tag))
+            }
+        }
+
+        div.columns {
+            div.column {
+                (h4("Per client"))
+                (totals_table(&report.by_client))
+            }
+            div.column {
+                (h4("Per project"))
+                (totals_table(&report.by_project))
+            }
+        }
+
+        (earnings_section(&report.earnings))
+    }
+}
+
+fn earnings_section(earnings: &Earnings) -> Fragment {
+    html! {
+        (h4("Earnings"))
+        p.block {
+            b { "Billable: " }
+            (format_duration(earnings.billable))
+            ", "
+            b { (earnings.total) " " (earnings.currency) }
+            " (" (earnings.rounding.label()) ")"
+        }
+        @if !earnings.by_project.is_empty() {
+            div.columns {
+                div.column {
+                    (earnings_table("Client", &earnings.by_client, &earnings.currency))
+                }
+                div.column {
+                    (earnings_table("Project", &earnings.by_project, &earnings.currency))
+                }
+            }
+        }
+    }
+}
+
+fn earnings_table(heading: &str, items: &[NamedEarnings], currency: &str) -> Fragment {
+    html! {
+        table class="table is-fullwidth is-striped" {
+            thead {
+                tr {
+                    th { (heading) }
+                    th.has-text-right { "Billable" }
+                    th.has-text-right { (currency) }
+                }
+            }
+            tbody {
+                @for item in items {
+                    tr {
+                        td { (item.name) }
+                        td.has-text-right { (format_duration(item.billable)) }
+                        td.has-text-right { (item.earnings) }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn totals_table(items: &[NamedTotal]) -> Fragment {
+    if items.is_empty() {
+        return html! {
+            p class="notification is-warning" { "No finished entries in this range." }
+        };
+    }
+    html! {
+        table class="table is-fullwidth is-striped" {
+            tbody {
+                @for item in items {
+                    tr {
+                        td { (item.name) }
+                        td.has-text-right { (format_duration(item.total)) }
+                    }
+                }
+            }
+        }
+    }
+}
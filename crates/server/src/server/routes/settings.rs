@@ -0,0 +1,272 @@
+use maud::html;
+
+use crate::{
+    db::types::User,
+    logic::{
+        billing::{parse_rate, user_update_billing, Amount, Rounding, RoundingMode, CURRENCIES},
+        calendar::{user_calendar_token_reset, user_calendar_token_revoke, FEED_DAYS},
+        locale::{DisplayPrefs, Locale, Zone},
+        user::user_update_preferences,
+    },
+    server::{
+        prelude::{
+            h2, page, parse_form, response_html_ok, Context, Fragment, HandlerResult, Method,
+            Request,
+        },
+        request_origin, response_not_found_html,
+        ui::error_box,
+    },
+};
+
+#[derive(serde::Deserialize, Clone)]
+struct SettingsFormData {
+    timezone: String,
+    locale: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct BillingFormData {
+    /// Empty for no default rate.
+    rate: String,
+    currency: String,
+    rounding_minutes: u32,
+    rounding_mode: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct CalendarFormData {
+    /// `reset` or `revoke`.
+    action: String,
+}
+
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let origin = request_origin(&req);
+    let content = match *req.method() {
+        Method::GET => settings_content(user, origin, None, None),
+        Method::POST => {
+            let res = parse_form::<SettingsFormData>(req).and_then(|data| {
+                user_update_preferences(&ctx.db, user, &data.timezone, &data.locale)
+            });
+            match res {
+                Ok(updated) => settings_content(&updated, origin, None, Some("Settings saved.")),
+                Err(err) => settings_content(user, origin, Some(err.to_string()), None),
+            }
+        }
+        _ => return Ok(response_not_found_html()),
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// Enable, reset or disable the calendar feed.
+pub fn handler_calendar(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let origin = request_origin(&req);
+    let data = parse_form::<CalendarFormData>(req)?;
+    let (updated, notice) = match data.action.as_str() {
+        "reset" => (
+            user_calendar_token_reset(&ctx.db, user)?,
+            "A new calendar URL was created. Previous URLs no longer work.",
+        ),
+        "revoke" => (
+            user_calendar_token_revoke(&ctx.db, user)?,
+            "The calendar feed was disabled.",
+        ),
+        _ => return Ok(response_not_found_html()),
+    };
+    let content = settings_content(&updated, origin, None, Some(notice));
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// Change the default rate, currency and rounding.
+pub fn handler_billing(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let origin = request_origin(&req);
+    let res = parse_form::<BillingFormData>(req).and_then(|data| {
+        let rounding = Rounding::new(data.rounding_minutes, data.rounding_mode.parse()?)?;
+        let rate = parse_rate(&data.rate)?;
+        user_update_billing(&ctx.db, user, rate, &data.currency, rounding)
+    });
+    let content = match res {
+        Ok(updated) => settings_content(&updated, origin, None, Some("Billing settings saved.")),
+        Err(err) => settings_content(user, origin, Some(err.to_string()), None),
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+fn settings_content(
+    user: &User,
+    origin: Option<String>,
+    error: Option<String>,
+    notice: Option<&str>,
+) -> Fragment {
+    let prefs = DisplayPrefs::for_user(user);
+    let now = time::OffsetDateTime::now_utc();
+
+    html! {
+        div.container {
+            (h2("Settings"))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @if let Some(notice) = notice {
+                p class="notification is-success" { (notice) }
+            }
+
+            form.box action="/settings" method="post" {
+                div.field {
+                    label.label { "Time zone" }
+                    div.select {
+                        select name="timezone" {
+                            @for name in Zone::names() {
+                                option value=(name) selected[name == prefs.zone.name()] { (name) }
+                            }
+                        }
+                    }
+                    p.help { "Used for displaying times and for day boundaries in reports." }
+                }
+
+                div.field {
+                    label.label { "Language and format" }
+                    div.select {
+                        select name="locale" {
+                            @for locale in Locale::ALL {
+                                option value=(locale.code()) selected[locale == prefs.locale] {
+                                    (locale.label())
+                                }
+                            }
+                        }
+                    }
+                    p.help { "Current time: " (prefs.datetime(now)) }
+                }
+
+                div.buttons {
+                    button.button.is-primary type="submit" { "Save" }
+                }
+            }
+
+            (billing_section(user))
+            (calendar_section(user, origin))
+            (backup_section())
+        }
+    }
+}
+
+fn billing_section(user: &User) -> Fragment {
+    let rate = user
+        .hourly_rate_cents
+        .map(|c| Amount::from_cents(c).to_string());
+    let rounding = Rounding::for_user(user);
+    html! {
+        (h2("Billing"))
+        form.box action="/settings/billing" method="post" {
+            div class="field is-grouped" {
+                div.control {
+                    label.label { "Default hourly rate" }
+                    input.input name="rate" type="text" inputmode="decimal" placeholder="None" value=[rate] {}
+                }
+                div.control {
+                    label.label { "Currency" }
+                    div.select {
+                        select name="currency" {
+                            @for code in CURRENCIES {
+                                option value=(code) selected[code == user.currency] { (code) }
+                            }
+                        }
+                    }
+                }
+            }
+            p.help.block {
+                "Used for billable entries without a tag or project rate. "
+                "Rates for projects and tags are set on the projects page."
+            }
+            div class="field is-grouped" {
+                div.control {
+                    label.label { "Round each entry" }
+                    div.select {
+                        select name="rounding_mode" {
+                            @for mode in RoundingMode::ALL {
+                                option value=(mode.as_str()) selected[mode == rounding.mode] { (mode.as_str()) }
+                            }
+                        }
+                    }
+                }
+                div.control {
+                    label.label { "to" }
+                    div.select {
+                        select name="rounding_minutes" {
+                            @for minutes in Rounding::INCREMENTS {
+                                option value=(minutes) selected[minutes == rounding.minutes] {
+                                    @if minutes == 0 { "no rounding" } @else { (minutes) " minutes" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            div.buttons {
+                button.button.is-primary type="submit" { "Save" }
+            }
+        }
+    }
+}
+
+fn calendar_section(user: &User, origin: Option<String>) -> Fragment {
+    let url = user.calendar_token.as_ref().map(|token| {
+        format!(
+            "{}/calendar/{token}.ics",
+            origin.as_deref().unwrap_or_default()
+        )
+    });
+    html! {
+        (h2("Calendar feed"))
+        form.box action="/settings/calendar" method="post" {
+            p.block {
+                "Subscribe to this URL in a calendar app to see finished entries of the last "
+                (FEED_DAYS) " days. Anyone with the URL can see them."
+            }
+            @if let Some(url) = url {
+                div.field {
+                    div.control {
+                        input.input type="text" readonly value=(url) onclick="this.select()" {}
+                    }
+                }
+                div.buttons {
+                    button.button type="submit" name="action" value="reset" { "Create new URL" }
+                    button.button.is-danger.is-light type="submit" name="action" value="revoke" {
+                        "Disable"
+                    }
+                }
+            } @else {
+                button.button.is-primary type="submit" name="action" value="reset" {
+                    "Enable calendar feed"
+                }
+            }
+        }
+    }
+}
+
+fn backup_section() -> Fragment {
+    html! {
+        (h2("Backup"))
+        div.box {
+            p.block {
+                "Download all your entries, tags and projects as a JSON file, or restore such a file. "
+                "Restoring skips entries that already exist."
+            }
+            div.buttons {
+                a.button href="/account/export.json" { "Download backup" }
+            }
+            form action="/account/import" method="post" enctype="multipart/form-data" {
+                div class="field has-addons" {
+                    div.control {
+                        input.input type="file" name="file" accept=".json,application/json" required {}
+                    }
+                    div.control {
+                        button.button type="submit" { "Restore" }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Context as _;
+
+use crate::{
+    db::{
+        types::{Timelog, TimelogFilter, TimelogPatch, TimelogQuery},
+        Db,
+    },
+    logic::timesheets::Locks,
+    server::prelude::{parse_form, response_html_ok, Context, HandlerResult, Request},
+};
+
+#[derive(serde::Deserialize, Clone)]
+struct BillableFormData {
+    timelog_id: u64,
+    billable: bool,
+}
+
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let err = match try_set_billable(req, ctx) {
+        Ok(_) => None,
+        Err(err) => Some(err.to_string()),
+    };
+    Ok(response_html_ok(super::dashboard::dashboard_page(
+        ctx, err,
+    )?))
+}
+
+/// Mark a timelog of the user as billable or not.
+///
+/// Invoiced timelogs and those of submitted timesheets can't be changed.
+pub fn try_set_billable(req: Request, ctx: &Context) -> Result<Timelog, anyhow::Error> {
+    let data: BillableFormData = parse_form(req)?;
+    let user = ctx.require_user()?;
+
+    let locks = Locks::for_user(&ctx.db, user.id)?;
+    let owned = TimelogQuery {
+        filter: Some(TimelogFilter::Id(data.timelog_id).and(TimelogFilter::UserId(user.id))),
+        limit: 1,
+        ..TimelogQuery::new()
+    };
+    if let Some(log) = ctx.db.timelogs(owned)?.first() {
+        locks.ensure_unlocked(log)?;
+    }
+
+    let selector = TimelogQuery {
+        filter: Some(TimelogFilter::And(vec![
+            TimelogFilter::Id(data.timelog_id),
+            TimelogFilter::UserId(user.id),
+            TimelogFilter::IsInvoiced(false),
+        ])),
+        ..TimelogQuery::new()
+    };
+    let patch = TimelogPatch {
+        billable: Some(data.billable),
+        ..Default::default()
+    };
+    ctx.db
+        .timelog_update(selector, patch)?
+        .into_iter()
+        .next()
+        .context("Timelog not found or already invoiced")
+}
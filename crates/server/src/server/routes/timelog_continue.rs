@@ -0,0 +1,170 @@
+use anyhow::{bail, Context as _};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    db::{
+        types::{
+            Timelog, TimelogCreate, TimelogFilter, TimelogPatch, TimelogQuery, TimelogUserTag,
+        },
+        user_active_timelogs, Db,
+    },
+    logic::{
+        projects::{user_project, user_tags},
+        timesheets::Locks,
+    },
+    server::prelude::{parse_form, response_html_ok, Context, HandlerResult, Request},
+};
+
+/// How long after it ended a timelog can be extended instead of continued.
+pub const EXTEND_WITHIN: Duration = Duration::minutes(15);
+
+#[derive(serde::Deserialize, Clone)]
+struct ContinueFormData {
+    timelog_id: u64,
+}
+
+/// Whether a finished timelog may be reopened, so its timer runs on.
+///
+/// Only the latest timelog can be extended, shortly after it ended, and
+/// only while it can still be changed.
+pub fn can_extend(log: &Timelog, locks: &Locks, now: OffsetDateTime) -> bool {
+    match log.finished_at() {
+        Some(end) => {
+            now - end <= EXTEND_WITHIN && log.invoice_id.is_none() && !locks.is_locked(log)
+        }
+        None => false,
+    }
+}
+
+/// Start a new timelog like a finished one.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let err = match try_continue(req, ctx) {
+        Ok(_) => None,
+        Err(err) => Some(err.to_string()),
+    };
+    Ok(response_html_ok(super::dashboard::dashboard_page(
+        ctx, err,
+    )?))
+}
+
+/// Reopen a timelog that ended a moment ago.
+pub fn handler_extend(req: Request, ctx: &Context) -> HandlerResult {
+    let err = match try_extend(req, ctx) {
+        Ok(_) => None,
+        Err(err) => Some(err.to_string()),
+    };
+    Ok(response_html_ok(super::dashboard::dashboard_page(
+        ctx, err,
+    )?))
+}
+
+fn finished_timelog(ctx: &Context, id: u64) -> Result<Timelog, anyhow::Error> {
+    let user = ctx.require_user()?;
+    let selector = TimelogQuery {
+        filter: Some(TimelogFilter::Id(id).and(TimelogFilter::UserId(user.id))),
+        limit: 1,
+        ..TimelogQuery::new()
+    };
+    let log = ctx
+        .db
+        .timelogs(selector)?
+        .into_iter()
+        .next()
+        .context("Timelog not found")?;
+    if log.finished_at.is_none() {
+        bail!("The timelog is still running");
+    }
+
+    let active = ctx.db.timelogs(user_active_timelogs(user.id))?;
+    if !active.is_empty() {
+        bail!("Other running tasks - finish them first!");
+    }
+    Ok(log)
+}
+
+/// Start a timelog with the title, description, project, billable flag and
+/// tags of a finished one.
+pub fn try_continue(req: Request, ctx: &Context) -> Result<Timelog, anyhow::Error> {
+    let data: ContinueFormData = parse_form(req)?;
+    let user = ctx.require_user()?;
+    let previous = finished_timelog(ctx, data.timelog_id)?;
+
+    let project_id = match previous.project_id {
+        Some(id) => match user_project(&ctx.db, user, id)? {
+            Some(project) if !project.archived => Some(project.id),
+            Some(_) => bail!("The project is archived"),
+            None => bail!("Project not found"),
+        },
+        None => None,
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let create = TimelogCreate {
+        user_id: user.id,
+        title: previous.title.clone(),
+        description: previous.description.clone(),
+        created_at: now,
+        started_at: now,
+        finished_at: None,
+        project_id,
+        billable: previous.billable,
+    };
+    let log = ctx.db.timelog_create(create)?;
+
+    // Only tags the user can still see, deleted ones stay behind.
+    let visible = user_tags(&ctx.db, user)?
+        .into_iter()
+        .map(|t| t.id)
+        .collect::<Vec<_>>();
+    let links = ctx
+        .db
+        .timelog_tags(&[previous.id])?
+        .into_iter()
+        .filter(|link| visible.contains(&link.user_tag_id))
+        .map(|link| TimelogUserTag {
+            user_tag_id: link.user_tag_id,
+            timelog_id: log.id,
+        })
+        .collect();
+    ctx.db.timelog_tags_add(links)?;
+    Ok(log)
+}
+
+/// Reopen the latest timelog, if it ended at most [`EXTEND_WITHIN`] ago.
+pub fn try_extend(req: Request, ctx: &Context) -> Result<Timelog, anyhow::Error> {
+    let data: ContinueFormData = parse_form(req)?;
+    let user = ctx.require_user()?;
+    let log = finished_timelog(ctx, data.timelog_id)?;
+
+    let now = OffsetDateTime::now_utc();
+    let locks = Locks::for_user(&ctx.db, user.id)?;
+    locks.ensure_unlocked(&log)?;
+    if !can_extend(&log, &locks, now) {
+        bail!("Only entries that ended in the last minutes can be extended");
+    }
+    let later = ctx.db.timelog_count(
+        TimelogFilter::UserId(user.id).and(TimelogFilter::StartedSince(log.started_at)),
+    )?;
+    // The count includes the timelog itself.
+    if later > 1 {
+        bail!("Only the latest entry can be extended");
+    }
+
+    let selector = TimelogQuery {
+        filter: Some(TimelogFilter::And(vec![
+            TimelogFilter::Id(log.id),
+            TimelogFilter::UserId(user.id),
+            TimelogFilter::IsInvoiced(false),
+        ])),
+        ..TimelogQuery::new()
+    };
+    let patch = TimelogPatch {
+        finished_at: Some(None),
+        ..Default::default()
+    };
+    ctx.db
+        .timelog_update(selector, patch)?
+        .into_iter()
+        .next()
+        .context("Timelog not found or already invoiced")
+}
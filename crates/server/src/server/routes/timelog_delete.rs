@@ -0,0 +1,22 @@
+use time::OffsetDateTime;
+
+use crate::{
+    logic::trash::timelog_delete,
+    server::prelude::{parse_form, response_html_ok, Context, HandlerResult, Request},
+};
+
+#[derive(serde::Deserialize, Clone)]
+struct DeleteFormData {
+    timelog_id: u64,
+}
+
+/// Move a timelog of the user to the trash.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<DeleteFormData>(req)
+        .and_then(|data| timelog_delete(&ctx.db, user, data.timelog_id, OffsetDateTime::now_utc()));
+    let err = res.err().map(|err| err.to_string());
+    Ok(response_html_ok(super::dashboard::dashboard_page(
+        ctx, err,
+    )?))
+}
@@ -3,11 +3,11 @@ use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
 use crate::{
     db::{
-        timelog_by_id,
-        types::{Timelog, TimelogPatch, TimelogQuery},
+        types::{Timelog, TimelogFilter, TimelogPatch, TimelogQuery},
         Db,
     },
-    server::prelude::{page, parse_form, response_html_ok, Context, HandlerResult, Request},
+    logic::timesheets::Locks,
+    server::prelude::{parse_form, response_html_ok, Context, HandlerResult, Request},
 };
 
 #[derive(serde::Deserialize, Clone)]
@@ -20,32 +20,41 @@ pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
         Ok(_) => None,
         Err(err) => Some(err.to_string()),
     };
-    let content = super::dashboard::build_dashboard(ctx, err)?;
-    Ok(response_html_ok(page(ctx, content)))
+    Ok(response_html_ok(super::dashboard::dashboard_page(
+        ctx, err,
+    )?))
 }
 
 pub fn try_finish(req: Request, ctx: &Context) -> Result<Timelog, anyhow::Error> {
     let data: FinishFormData = parse_form(req)?;
 
-    let _user = ctx.require_user()?;
+    let user = ctx.require_user()?;
 
+    let selector = TimelogQuery {
+        filter: Some(TimelogFilter::Id(data.timelog_id).and(TimelogFilter::UserId(user.id))),
+        limit: 1,
+        ..TimelogQuery::new()
+    };
     let log = ctx
         .db
-        .timelogs(timelog_by_id(data.timelog_id))?
+        .timelogs(selector.clone())?
         .into_iter()
         .next()
         .context("Timelog not found")?;
     if log.finished_at.is_some() {
         bail!("Log entry already closed");
     }
+    Locks::for_user(&ctx.db, user.id)?.ensure_unlocked(&log)?;
 
     let now = OffsetDateTime::now_utc();
 
-    let selector = TimelogQuery::new_single_id(data.timelog_id);
     let patch = TimelogPatch {
         title: None,
         description: None,
-        finished_at: Some(now.format(&Rfc3339).unwrap()),
+        finished_at: Some(Some(now.format(&Rfc3339).unwrap())),
+        billable: None,
+        invoice_id: None,
+        deleted_at: None,
     };
     let out = ctx
         .db
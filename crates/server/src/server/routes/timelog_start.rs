@@ -3,15 +3,78 @@ use time::OffsetDateTime;
 
 use crate::{
     db::{
-        types::{Timelog, TimelogCreate},
+        types::{ProjectId, Timelog, TimelogCreate},
         user_active_timelogs, Db,
     },
-    server::prelude::{page, parse_form, response_html_ok, Context, HandlerResult, Request},
+    logic::projects::user_project,
+    server::prelude::{parse_form, response_html_ok, Context, HandlerResult, Request},
+    PublicError,
 };
 
 #[derive(serde::Deserialize, Clone)]
-struct StartFormData {
+pub(crate) struct StartFormData {
     title: String,
+    /// Empty for no project.
+    #[serde(default)]
+    project_id: String,
+    /// `yes`, `no`, or empty for the project default.
+    #[serde(default)]
+    billable: String,
+}
+
+impl StartFormData {
+    pub(crate) fn timer_fields(&self, ctx: &Context) -> Result<TimerFields, anyhow::Error> {
+        let project_id = match self.project_id.trim() {
+            "" => None,
+            id => Some(
+                id.parse()
+                    .map_err(|_| PublicError::msg("Invalid project id"))?,
+            ),
+        };
+        let billable = match self.billable.as_str() {
+            "yes" => Some(true),
+            "no" => Some(false),
+            _ => None,
+        };
+        timer_fields(ctx, &self.title, project_id, billable)
+    }
+}
+
+/// Title, project and billable flag of a new timer.
+pub(crate) struct TimerFields {
+    pub title: String,
+    pub project_id: Option<ProjectId>,
+    pub billable: bool,
+}
+
+/// Check the fields of a new timer. Without `billable`, the project default
+/// applies.
+pub(crate) fn timer_fields(
+    ctx: &Context,
+    title: &str,
+    project_id: Option<ProjectId>,
+    billable: Option<bool>,
+) -> Result<TimerFields, anyhow::Error> {
+    let title = title.trim().to_string();
+    if title.is_empty() {
+        return Err(PublicError::msg("Title may not be empty").into());
+    }
+
+    let user = ctx.require_user()?;
+    let project = match project_id {
+        None => None,
+        Some(id) => match user_project(&ctx.db, user, id)? {
+            Some(project) if !project.archived => Some(project),
+            Some(_) => return Err(PublicError::msg("The project is archived").into()),
+            None => return Err(PublicError::msg("Project not found").into()),
+        },
+    };
+    let billable = billable.unwrap_or_else(|| project.as_ref().is_some_and(|p| p.billable));
+    Ok(TimerFields {
+        title,
+        project_id: project.map(|p| p.id),
+        billable,
+    })
 }
 
 pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
@@ -19,17 +82,14 @@ pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
         Ok(_) => None,
         Err(err) => Some(err.to_string()),
     };
-    let content = super::dashboard::build_dashboard(ctx, err)?;
-    Ok(response_html_ok(page(ctx, content)))
+    Ok(response_html_ok(super::dashboard::dashboard_page(
+        ctx, err,
+    )?))
 }
 
 pub fn try_start(req: Request, ctx: &Context) -> Result<Timelog, anyhow::Error> {
     let data: StartFormData = parse_form(req)?;
-
-    let title = data.title.trim().to_string();
-    if title.is_empty() {
-        bail!("Title may not be empty");
-    }
+    let fields = data.timer_fields(ctx)?;
 
     let user = ctx.require_user()?;
     let active = ctx.db.timelogs(user_active_timelogs(user.id))?;
@@ -41,10 +101,13 @@ pub fn try_start(req: Request, ctx: &Context) -> Result<Timelog, anyhow::Error>
 
     let create = TimelogCreate {
         user_id: user.id,
-        title,
+        title: fields.title,
         description: None,
         created_at: now.clone(),
         started_at: now,
+        finished_at: None,
+        project_id: fields.project_id,
+        billable: fields.billable,
     };
-    ctx.db.timelog_create(create)
+    ctx.db.timelog_create(create).map_err(From::from)
 }
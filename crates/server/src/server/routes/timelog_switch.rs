@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Context as _};
+use time::OffsetDateTime;
+
+use crate::{
+    db::{
+        types::{ProjectId, Timelog, TimelogSwitch},
+        user_active_timelogs, Db,
+    },
+    logic::timesheets::Locks,
+    server::{
+        prelude::{parse_form, response_html_ok, Context, HandlerResult, Request, StatusCode},
+        response_json,
+    },
+    PublicError,
+};
+
+use super::timelog_start::{timer_fields, StartFormData, TimerFields};
+
+/// JSON body of `POST /api/timelog/switch`.
+#[derive(serde::Deserialize, Clone)]
+struct SwitchRequest {
+    title: String,
+    #[serde(default)]
+    project_id: Option<ProjectId>,
+    /// Defaults to the billable flag of the project.
+    #[serde(default)]
+    billable: Option<bool>,
+}
+
+/// The timelogs that were finished, and the one that was started at the
+/// same instant.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Switched {
+    pub finished: Vec<Timelog>,
+    pub started: Timelog,
+}
+
+/// Switch tasks from the dashboard, with the fields of the start form.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let res = parse_form::<StartFormData>(req)
+        .and_then(|data| data.timer_fields(ctx))
+        .and_then(|fields| switch_task(ctx, fields));
+    let err = res.err().map(|err| err.to_string());
+    Ok(response_html_ok(super::dashboard::dashboard_page(
+        ctx, err,
+    )?))
+}
+
+/// Switch tasks from a script. Responds with the [`Switched`] timelogs.
+pub fn api_handler(req: Request, ctx: &Context) -> HandlerResult {
+    let body = req
+        .into_body()
+        .read_to_vec()
+        .map_err(|err| anyhow!("Could not read request body: {err}"))?;
+    let data: SwitchRequest = serde_json::from_slice(&body)
+        .map_err(|err| PublicError::msg(format!("Invalid request body: {err}")))?;
+
+    let fields = timer_fields(ctx, &data.title, data.project_id, data.billable)?;
+    let switched = switch_task(ctx, fields)?;
+    Ok(response_json(
+        StatusCode::OK,
+        &serde_json::to_value(&switched)?,
+    ))
+}
+
+/// Finish the running timelog and start a new one at the same instant.
+///
+/// Both happen in one transaction, so either the switch is complete or
+/// nothing changed.
+pub fn switch_task(ctx: &Context, fields: TimerFields) -> Result<Switched, anyhow::Error> {
+    let user = ctx.require_user()?;
+    let active = ctx.db.timelogs(user_active_timelogs(user.id))?;
+    if active.is_empty() {
+        return Err(PublicError::msg("No running task to switch from - start one instead").into());
+    }
+    let locks = Locks::for_user(&ctx.db, user.id)?;
+    for log in &active {
+        locks.ensure_unlocked(log)?;
+    }
+
+    let switch = TimelogSwitch {
+        user_id: user.id,
+        title: fields.title,
+        project_id: fields.project_id,
+        billable: fields.billable,
+        switched_at: OffsetDateTime::now_utc(),
+    };
+    let (finished, started): (Vec<_>, Vec<_>) = ctx
+        .db
+        .timelog_switch(switch)?
+        .into_iter()
+        .partition(|log| log.finished_at.is_some());
+    let started = started
+        .into_iter()
+        .next()
+        .context("switch did not start a timelog")?;
+    Ok(Switched { finished, started })
+}
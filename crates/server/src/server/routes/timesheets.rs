@@ -0,0 +1,317 @@
+use maud::html;
+use time::OffsetDateTime;
+
+use crate::{
+    db::types::{Timesheet, TimesheetId},
+    logic::{
+        locale::DisplayPrefs,
+        reports::{parse_date, Grouping},
+        timesheets::{
+            pending_approvals, status_of, timesheet_decide, timesheet_review, timesheet_submit,
+            user_recent_weeks, TimesheetStatus, WeekSummary,
+        },
+    },
+    server::{
+        prelude::{
+            h2, h4, page, parse_form, response_html_ok, Context, Fragment, HandlerResult, Method,
+            Request,
+        },
+        response_not_found_html,
+        ui::{error_box, util::format_duration},
+    },
+    PublicError,
+};
+
+#[derive(serde::Deserialize, Clone)]
+struct SubmitFormData {
+    /// Monday of the week, as `YYYY-MM-DD`.
+    week_start: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct DecideFormData {
+    timesheet_id: u64,
+    /// `approve` or `reject`.
+    action: String,
+    #[serde(default)]
+    comment: String,
+}
+
+/// List recent weeks with their timesheets, submit weeks, and list the
+/// timesheets waiting for the user's approval.
+pub fn handler(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let now = OffsetDateTime::now_utc();
+    let res = match *req.method() {
+        Method::GET => Ok(None),
+        Method::POST => parse_form::<SubmitFormData>(req).and_then(|data| {
+            let week_start = parse_date(&data.week_start)?;
+            let timesheet = timesheet_submit(&ctx.db, user, week_start, now)?;
+            log::info!(timesheet_id = timesheet.id; "submitted timesheet");
+            Ok(Some(format!(
+                "Submitted the week of {}.",
+                week_label(&timesheet.week_start)
+            )))
+        }),
+        _ => return Ok(response_not_found_html()),
+    };
+    let (notice, error) = match res {
+        Ok(notice) => (notice, None),
+        Err(err) if err.is::<PublicError>() => (None, Some(err.to_string())),
+        Err(err) => return Err(err),
+    };
+
+    let weeks = user_recent_weeks(&ctx.db, user, now)?;
+    let pending = pending_approvals(&ctx.db, user)?;
+    let content = html! {
+        div.container {
+            (h2("Timesheets"))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @if let Some(notice) = notice {
+                p class="notification is-success" { (notice) }
+            }
+            p.block {
+                "Submitting a week sends it to the admins of your organizations for approval. "
+                "Its entries can't be changed until it is rejected."
+            }
+            (weeks_table(&weeks))
+            @if !pending.is_empty() {
+                (h4("Waiting for your approval"))
+                (pending_table(&pending, DisplayPrefs::for_user(user)))
+            }
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// A timesheet at `/timesheets/<id>`, for its user and approvers.
+pub fn handler_timesheet(req: Request, ctx: &Context) -> HandlerResult {
+    let id = req
+        .uri()
+        .path()
+        .strip_prefix("/timesheets/")
+        .and_then(|id| id.parse().ok());
+    match id {
+        Some(id) => render_review(ctx, id, Ok(None)),
+        None => Ok(response_not_found_html()),
+    }
+}
+
+/// Approve or reject a timesheet.
+pub fn handler_decide(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let data = parse_form::<DecideFormData>(req)?;
+    let approve = data.action == "approve";
+    let res = timesheet_decide(
+        &ctx.db,
+        user,
+        data.timesheet_id,
+        approve,
+        &data.comment,
+        OffsetDateTime::now_utc(),
+    )
+    .map(|timesheet| {
+        log::info!(timesheet_id = timesheet.id, status = timesheet.status; "decided timesheet");
+        Some(if approve {
+            "Timesheet approved.".to_string()
+        } else {
+            "Timesheet rejected and reopened.".to_string()
+        })
+    });
+    render_review(ctx, data.timesheet_id, res)
+}
+
+/// Render a timesheet with the outcome of an action.
+///
+/// Users who may not see it get a not found page.
+fn render_review(
+    ctx: &Context,
+    id: TimesheetId,
+    res: Result<Option<String>, anyhow::Error>,
+) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let (notice, error) = match res {
+        Ok(notice) => (notice, None),
+        Err(err) if err.is::<PublicError>() => (None, Some(err.to_string())),
+        Err(err) => return Err(err),
+    };
+    let review = match timesheet_review(&ctx.db, user, id) {
+        Ok(review) => review,
+        Err(err) if err.is::<PublicError>() => return Ok(response_not_found_html()),
+        Err(err) => return Err(err),
+    };
+    let prefs = DisplayPrefs::for_user(user);
+    let timesheet = &review.timesheet;
+
+    let content = html! {
+        div.container {
+            (h2(format!("Week of {} by {}", week_label(&timesheet.week_start), review.username)))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @if let Some(notice) = notice {
+                p class="notification is-success" { (notice) }
+            }
+            p.block {
+                (status_tag(timesheet))
+                " "
+                b { "Total: " }
+                (format_duration(review.total))
+            }
+            @if let Some(comment) = &timesheet.comment {
+                article.message {
+                    div.message-body { (comment) }
+                }
+            }
+
+            @if review.can_decide {
+                form.box action="/timesheets/decide" method="post" {
+                    input type="hidden" name="timesheet_id" value=(timesheet.id) {}
+                    div.field {
+                        label.label { "Comment" }
+                        textarea.textarea name="comment" rows="2" placeholder="Required for rejections" {}
+                    }
+                    div.buttons {
+                        button.button.is-success name="action" value="approve" type="submit" { "Approve" }
+                        button.button.is-danger name="action" value="reject" type="submit" { "Reject" }
+                    }
+                }
+            }
+
+            (h4("Entries"))
+            @if review.logs.is_empty() {
+                p.block { "No entries in this week." }
+            } @else {
+                table class="table is-fullwidth is-striped" {
+                    thead {
+                        tr {
+                            th { "Title" }
+                            th { "Start" }
+                            th { "End" }
+                            th.has-text-right { "Duration" }
+                        }
+                    }
+                    tbody {
+                        @for log in &review.logs {
+                            tr {
+                                td { (log.title) }
+                                td { (prefs.datetime(log.started_at)) }
+                                td { @if let Some(end) = log.finished_at() { (prefs.datetime(end)) } }
+                                td.has-text-right {
+                                    @if let Some(end) = log.finished_at() { (format_duration(end - log.started_at)) }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            (h4("History"))
+            table class="table is-fullwidth" {
+                tbody {
+                    @for (event, actor) in &review.events {
+                        tr {
+                            td { (prefs.datetime(event.created_at)) }
+                            td { (actor) }
+                            td { (event.status) }
+                            td { @if let Some(comment) = &event.comment { (comment) } }
+                        }
+                    }
+                }
+            }
+            p.block { a href="/timesheets" { "Back to timesheets" } }
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+/// A week as its ISO week and first day, like `2024-W03 (2024-01-15)`.
+fn week_label(week_start: &str) -> String {
+    match parse_date(week_start) {
+        Ok(date) => {
+            let (year, week, _) = date.to_iso_week_date();
+            format!("{year}-W{week:02} ({date})")
+        }
+        Err(_) => week_start.to_string(),
+    }
+}
+
+fn status_tag(timesheet: &Timesheet) -> Fragment {
+    let class = match status_of(timesheet) {
+        TimesheetStatus::Submitted => "tag is-warning",
+        TimesheetStatus::Approved => "tag is-success",
+        TimesheetStatus::Rejected => "tag is-danger",
+    };
+    html! {
+        span class=(class) { (timesheet.status) }
+    }
+}
+
+fn weeks_table(weeks: &[WeekSummary]) -> Fragment {
+    html! {
+        table class="table is-fullwidth is-striped" {
+            thead {
+                tr {
+                    th { "Week" }
+                    th.has-text-right { "Total" }
+                    th { "Status" }
+                    th {}
+                }
+            }
+            tbody {
+                @for week in weeks {
+                    @let locked = week.timesheet.as_ref().is_some_and(|t| status_of(t).is_locked());
+                    tr {
+                        td { (week_label(&week.start.to_string())) }
+                        td.has-text-right { (format_duration(week.total)) }
+                        td {
+                            @match &week.timesheet {
+                                Some(timesheet) => {
+                                    a href=(format!("/timesheets/{}", timesheet.id)) { (status_tag(timesheet)) }
+                                }
+                                None => { span.tag.is-light { "open" } }
+                            }
+                        }
+                        td.has-text-right {
+                            @if !locked {
+                                form action="/timesheets" method="post" {
+                                    input type="hidden" name="week_start" value=(Grouping::Week.period_start(week.start)) {}
+                                    button.button.is-small type="submit" { "Submit" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn pending_table(pending: &[(Timesheet, String)], prefs: DisplayPrefs) -> Fragment {
+    html! {
+        table class="table is-fullwidth is-striped" {
+            thead {
+                tr {
+                    th { "User" }
+                    th { "Week" }
+                    th { "Submitted" }
+                    th {}
+                }
+            }
+            tbody {
+                @for (timesheet, username) in pending {
+                    tr {
+                        td { (username) }
+                        td { (week_label(&timesheet.week_start)) }
+                        td { (prefs.datetime(timesheet.submitted_at)) }
+                        td.has-text-right {
+                            a.button.is-small href=(format!("/timesheets/{}", timesheet.id)) { "Review" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
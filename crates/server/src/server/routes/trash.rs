@@ -0,0 +1,198 @@
+use maud::html;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    logic::{
+        locale::DisplayPrefs,
+        trash::{purge_expired_for_user, trash_purge, trash_restore, user_trash, TrashKind},
+    },
+    server::{
+        prelude::{
+            h2, h4, page, parse_form, response_html_ok, Context, Fragment, HandlerResult, Request,
+        },
+        ui::{error_box, util::format_duration},
+    },
+    PublicError,
+};
+
+#[derive(serde::Deserialize, Clone)]
+struct TrashFormData {
+    /// `timelog`, `tag` or `project`.
+    kind: String,
+    id: u64,
+}
+
+/// List the deleted timelogs, tags and projects of the user.
+pub fn handler(_req: Request, ctx: &Context) -> HandlerResult {
+    purge_expired_trash(ctx)?;
+    render(ctx, Ok(None))
+}
+
+/// Purge the rows of the user that stayed in the trash longer than the
+/// retention period.
+///
+/// Failures are only logged, the page loads anyway and the next visit or the
+/// `purge-trash` command tries again.
+pub fn purge_expired_trash(ctx: &Context) -> Result<(), anyhow::Error> {
+    let user = ctx.require_user()?;
+    let retention = Duration::days(ctx.config.trash_retention_days.into());
+    let before = OffsetDateTime::now_utc() - retention;
+    if let Err(err) = purge_expired_for_user(&ctx.db, user, before) {
+        log::warn!(error:% = err; "could not purge expired trash");
+    }
+    Ok(())
+}
+
+/// Take a row out of the trash.
+pub fn handler_restore(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<TrashFormData>(req).and_then(|data| {
+        let kind = data.kind.parse()?;
+        trash_restore(&ctx.db, user, kind, data.id)?;
+        Ok(Some(format!("Restored the {}.", kind.as_str())))
+    });
+    render(ctx, res)
+}
+
+/// Delete a row in the trash for good.
+pub fn handler_purge(req: Request, ctx: &Context) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let res = parse_form::<TrashFormData>(req).and_then(|data| {
+        let kind = data.kind.parse()?;
+        trash_purge(&ctx.db, user, kind, data.id)?;
+        Ok(Some(format!("Deleted the {} for good.", kind.as_str())))
+    });
+    render(ctx, res)
+}
+
+/// Render the page with the outcome of an action.
+fn render(ctx: &Context, res: Result<Option<String>, anyhow::Error>) -> HandlerResult {
+    let user = ctx.require_user()?;
+    let (notice, error) = match res {
+        Ok(notice) => (notice, None),
+        Err(err) if err.is::<PublicError>() => (None, Some(err.to_string())),
+        Err(err) => return Err(err),
+    };
+    let trash = user_trash(&ctx.db, user)?;
+    let prefs = DisplayPrefs::for_user(user);
+    let retention = Duration::days(ctx.config.trash_retention_days.into());
+    // The day a row deleted at `t` is purged.
+    let purged_on =
+        |t: Option<OffsetDateTime>| t.map(|t| prefs.date(t + retention)).unwrap_or_default();
+
+    let content = html! {
+        div.container {
+            (h2("Trash"))
+            @if let Some(error) = error {
+                (error_box(error))
+            }
+            @if let Some(notice) = notice {
+                p class="notification is-success" { (notice) }
+            }
+            p.block {
+                "Deleted entries, tags and projects are kept here for "
+                (ctx.config.trash_retention_days)
+                " days before they are deleted for good."
+            }
+            @if trash.is_empty() {
+                p class="notification is-info is-light" { "The trash is empty." }
+            }
+
+            @if !trash.timelogs.is_empty() {
+                (h4("Entries"))
+                table class="table is-fullwidth is-striped" {
+                    thead {
+                        tr {
+                            th { "Title" }
+                            th { "Started" }
+                            th.has-text-right { "Duration" }
+                            th { "Deleted" }
+                            th { "Purged on" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @for log in &trash.timelogs {
+                            tr {
+                                td { (log.title) }
+                                td { (prefs.datetime(log.started_at)) }
+                                td.has-text-right {
+                                    @if let Some(end) = log.finished_at() { (format_duration(end - log.started_at)) }
+                                }
+                                td { @if let Some(t) = log.deleted_at { (prefs.datetime(t)) } }
+                                td { (purged_on(log.deleted_at)) }
+                                td { (actions(TrashKind::Timelog, log.id)) }
+                            }
+                        }
+                    }
+                }
+            }
+
+            @if !trash.tags.is_empty() {
+                (h4("Tags"))
+                table class="table is-fullwidth is-striped" {
+                    thead {
+                        tr {
+                            th { "Tag" }
+                            th { "Deleted" }
+                            th { "Purged on" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @for tag in &trash.tags {
+                            tr {
+                                td { (tag.name) }
+                                td { @if let Some(t) = tag.deleted_at { (prefs.datetime(t)) } }
+                                td { (purged_on(tag.deleted_at)) }
+                                td { (actions(TrashKind::Tag, tag.id)) }
+                            }
+                        }
+                    }
+                }
+            }
+
+            @if !trash.projects.is_empty() {
+                (h4("Projects"))
+                table class="table is-fullwidth is-striped" {
+                    thead {
+                        tr {
+                            th { "Project" }
+                            th { "Deleted" }
+                            th { "Purged on" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @for project in &trash.projects {
+                            tr {
+                                td { (project.name) }
+                                td { @if let Some(t) = project.deleted_at { (prefs.datetime(t)) } }
+                                td { (purged_on(project.deleted_at)) }
+                                td { (actions(TrashKind::Project, project.id)) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    Ok(response_html_ok(page(ctx, content)))
+}
+
+fn actions(kind: TrashKind, id: u64) -> Fragment {
+    html! {
+        div.buttons.is-right {
+            form action="/trash/restore" method="post" {
+                input type="hidden" name="kind" value=(kind.as_str()) {}
+                input type="hidden" name="id" value=(id) {}
+                button.button.is-small type="submit" { "Restore" }
+            }
+            form action="/trash/purge" method="post" {
+                input type="hidden" name="kind" value=(kind.as_str()) {}
+                input type="hidden" name="id" value=(id) {}
+                button.button.is-small.is-danger type="submit" { "Delete for good" }
+            }
+        }
+    }
+}
@@ -41,6 +41,10 @@ pub fn error_box(e: impl maud::Render) -> Fragment {
 }
 
 pub fn page(ctx: &Context, content: Fragment) -> String {
+    page_titled(ctx, "Timely", content)
+}
+
+pub fn page_titled(ctx: &Context, title: &str, content: Fragment) -> String {
     let navbar = if let Some(user) = &ctx.user {
         navbar(ctx, user)
     } else {
@@ -50,6 +54,8 @@ pub fn page(ctx: &Context, content: Fragment) -> String {
     html!{
         html {
             head {
+                meta charset="utf-8" {}
+                title { (title) }
                 link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/bulma@0.9.4/css/bulma.min.css" { }
             }
 
@@ -69,6 +75,18 @@ pub fn error_page(ctx: &Context, error: anyhow::Error) -> String {
     page(ctx, content)
 }
 
+pub fn maintenance_page(ctx: &Context) -> String {
+    let content = html! {
+        div.container {
+            div class="notification is-warning" {
+                p { b { "Timely is temporarily unavailable." } }
+                p { "We can't reach the database right now. Please try again in a minute." }
+            }
+        }
+    };
+    page(ctx, content)
+}
+
 pub fn page_not_found() -> Fragment {
     html! {
         div {
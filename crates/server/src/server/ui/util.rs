@@ -4,7 +4,7 @@ use crate::db::types::User;
 
 use super::Fragment;
 
-pub fn navbar(_ctx: &crate::server::Context, user: &User) -> Fragment {
+pub fn navbar(ctx: &crate::server::Context, user: &User) -> Fragment {
     html! {
         nav class="navbar" role="navigation" aria-label="main navigation" {
           div class="navbar-brand" {
@@ -26,6 +26,40 @@ pub fn navbar(_ctx: &crate::server::Context, user: &User) -> Fragment {
                 "Dashboard"
               }
 
+              a class="navbar-item" href="/reports" {
+                "Reports"
+              }
+
+              a class="navbar-item" href="/projects" {
+                "Projects"
+              }
+
+              a class="navbar-item" href="/invoices" {
+                "Invoices"
+              }
+
+              a class="navbar-item" href="/orgs" {
+                "Organizations"
+              }
+
+              a class="navbar-item" href="/timesheets" {
+                "Timesheets"
+              }
+
+              a class="navbar-item" href="/import" {
+                "Import"
+              }
+
+              a class="navbar-item" href="/trash" {
+                "Trash"
+              }
+
+              @if ctx.is_admin() {
+                a class="navbar-item" href="/audit" {
+                  "Audit log"
+                }
+              }
+
               // div class="navbar-item has-dropdown is-hoverable" {
               //   a class="navbar-link" {
               //     "More"
@@ -41,7 +75,7 @@ pub fn navbar(_ctx: &crate::server::Context, user: &User) -> Fragment {
             div class="navbar-end" {
               div class="navbar-item" {
                 div class="buttons" {
-                  a.button {
+                  a.button href="/settings" {
                       (&user.username)
                   }
 
@@ -69,3 +103,23 @@ where
         }
     }
 }
+
+/// Format a duration like "1h 23m".
+pub fn format_duration(d: time::Duration) -> String {
+    let minutes = d.whole_minutes().max(0);
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{m}m"),
+        (h, m) => format!("{h}h {m}m"),
+    }
+}
+
+/// Format a duration like a stopwatch, "1:23:05".
+pub fn format_clock(d: time::Duration) -> String {
+    let seconds = d.whole_seconds().max(0);
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
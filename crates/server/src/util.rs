@@ -1,13 +1,23 @@
-use std::sync::Arc;
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use anyhttp::{HttpError, HttpExecutor, RequestBody};
 
 #[derive(Clone)]
 pub struct WasixHttpExecutor {
-    client: wasix_http_client::HttpClient,
+    /// Requests that take longer are abandoned.
+    timeout: Duration,
 }
 
-pub struct Body(wasix_http_client::Body);
+/// A request or response body, read completely.
+///
+/// Bodies are plain bytes so a request can be sent from another thread.
+pub struct Body(Vec<u8>);
 
 struct DynWrapper<E>(E);
 
@@ -91,13 +101,14 @@ where
 }
 
 impl WasixHttpExecutor {
-    pub fn new() -> Result<Self, anyhow::Error> {
-        let client = wasix_http_client::HttpClient::new()?;
-        Ok(Self { client })
+    pub fn new(timeout: Duration) -> Result<Self, anyhow::Error> {
+        // Fail early if the host has no HTTP support.
+        wasix_http_client::HttpClient::new()?;
+        Ok(Self { timeout })
     }
 
-    pub fn new_dyn_client() -> Result<anyhttp::sync::DynClient, anyhow::Error> {
-        let exec = Self::new()?;
+    pub fn new_dyn_client(timeout: Duration) -> Result<anyhttp::sync::DynClient, anyhow::Error> {
+        let exec = Self::new(timeout)?;
         let w = DynWrapper(exec);
         let e2: anyhttp::sync::DynExecutor = Arc::new(w);
         let c = anyhttp::sync::DynClient::new(e2);
@@ -112,8 +123,8 @@ impl anyhttp::HttpExecutor for WasixHttpExecutor {
 
     fn request_body_from_generic(&self, body: anyhttp::RequestBody) -> Self::RequestBody {
         match body {
-            RequestBody::Empty => Body(wasix_http_client::Body::empty()),
-            RequestBody::Bytes(bytes) => Body(wasix_http_client::Body::new_data(bytes)),
+            RequestBody::Empty => Body(Vec::new()),
+            RequestBody::Bytes(bytes) => Body(bytes),
             RequestBody::Read(_) => todo!("std::io::Read body not supported yet"),
         }
     }
@@ -123,29 +134,92 @@ impl anyhttp::HttpExecutor for WasixHttpExecutor {
     }
 
     fn execute(&self, pre: anyhttp::RequestPre<Self::RequestBody>) -> Self::Output {
-        let mut req = http::Request::builder()
-            .method(pre.request.method)
-            .uri(pre.request.uri)
-            .body(pre.request.body.0)
-            .unwrap();
-        *req.headers_mut() = pre.request.headers;
-        let res = self
-            .client
-            .send(req)
-            .map_err(|err| HttpError::new_custom(err.to_string()))?;
-        let (parts, body) = res.into_parts();
+        let job = Job {
+            method: pre.request.method,
+            uri: pre.request.uri,
+            headers: pre.request.headers,
+            body: pre.request.body.0,
+        };
+        let (status, headers, body) = send_with_timeout(job, self.timeout)?;
 
         Ok(anyhttp::Response {
             uri: None,
-            status: parts.status,
+            status,
             version: http::Version::HTTP_11,
-            headers: parts.headers,
+            headers,
             extensions: Default::default(),
             body: Body(body),
         })
     }
 }
 
+/// A request with only owned, sendable parts.
+struct Job {
+    method: http::Method,
+    uri: http::Uri,
+    headers: http::HeaderMap,
+    body: Vec<u8>,
+}
+
+type JobOutput = (http::StatusCode, http::HeaderMap, Vec<u8>);
+
+impl Job {
+    /// Send the request and read the whole response.
+    fn run(self) -> Result<JobOutput, String> {
+        let client = wasix_http_client::HttpClient::new().map_err(|err| err.to_string())?;
+        let body = if self.body.is_empty() {
+            wasix_http_client::Body::empty()
+        } else {
+            wasix_http_client::Body::new_data(self.body)
+        };
+        let mut req = http::Request::builder()
+            .method(self.method)
+            .uri(self.uri)
+            .body(body)
+            .map_err(|err| err.to_string())?;
+        *req.headers_mut() = self.headers;
+        let res = client.send(req).map_err(|err| err.to_string())?;
+        let (parts, body) = res.into_parts();
+        let body = body.read_all().map_err(|err| err.to_string())?;
+        Ok((parts.status, parts.headers, body))
+    }
+}
+
+/// Send a request from its own thread, and give up on it after `timeout`.
+///
+/// The wasix client has no timeout of its own. An abandoned request keeps
+/// its thread until the process exits at the end of the wcgi request. Where
+/// threads are not available, the request runs on the current thread
+/// without a timeout.
+fn send_with_timeout(job: Job, timeout: Duration) -> Result<JobOutput, HttpError> {
+    let job = Arc::new(Mutex::new(Some(job)));
+    let (tx, rx) = mpsc::channel();
+    let shared = job.clone();
+    let spawned = std::thread::Builder::new().spawn(move || {
+        if let Some(job) = shared.lock().unwrap().take() {
+            // The receiver is gone once the request timed out.
+            tx.send(job.run()).ok();
+        }
+    });
+    let res = match spawned {
+        Ok(_) => rx.recv_timeout(timeout).unwrap_or_else(|err| {
+            Err(match err {
+                RecvTimeoutError::Timeout => {
+                    format!("request timed out after {} ms", timeout.as_millis())
+                }
+                RecvTimeoutError::Disconnected => "request thread stopped".to_string(),
+            })
+        }),
+        Err(err) => {
+            log::debug!(error:% = err; "no thread for the request, sending it without a timeout");
+            let job = job.lock().unwrap().take();
+            job.ok_or_else(|| "request was already sent".to_string())
+                .and_then(Job::run)
+        }
+    };
+    res.map_err(HttpError::new_custom)
+}
+
 impl anyhttp::Respond for Body {
     type Chunks = BodyIter;
     type BytesOutput = Result<Vec<u8>, HttpError>;
@@ -160,9 +234,7 @@ impl anyhttp::Respond for Body {
     }
 
     fn bytes(self) -> Self::BytesOutput {
-        self.0
-            .read_all()
-            .map_err(|err| HttpError::new_custom(err.to_string()))
+        Ok(self.0)
     }
 
     fn bytes_boxed(self: Box<Self>) -> Self::BytesOutput {
@@ -170,29 +242,22 @@ impl anyhttp::Respond for Body {
     }
 
     fn reader(self) -> Self::Reader {
-        unimplemented!()
+        std::io::Cursor::new(self.0)
     }
 
     fn reader_boxed(self: Box<Self>) -> Self::Reader {
-        unimplemented!()
+        (*self).reader()
     }
 }
 
 pub struct BodyIter {
-    body: Option<wasix_http_client::Body>,
+    body: Option<Vec<u8>>,
 }
 
 impl Iterator for BodyIter {
     type Item = Result<Vec<u8>, HttpError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(b) = self.body.take() {
-            let res = b
-                .read_all()
-                .map_err(|err| anyhttp::HttpError::new_custom(err.to_string()));
-            Some(res)
-        } else {
-            None
-        }
+        self.body.take().map(Ok)
     }
 }
@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use xshell::Shell;
+
+use crate::{server::server_command, CliCommand};
+
+/// Write a JSON backup of all users to a file.
+///
+/// Uses the same format as the account backup download in the settings.
+/// Reads the server configuration (`SUPABASE_ENDPOINT`, ...) from the environment.
+#[derive(Parser)]
+pub struct CmdBackup {
+    /// File to write the backup to.
+    #[clap(long, short)]
+    output: PathBuf,
+}
+
+impl CliCommand for CmdBackup {
+    fn run(self) -> Result<(), anyhow::Error> {
+        let sh = Shell::new()?;
+        let json = server_command(&sh, &["backup".to_string()])?.read()?;
+        std::fs::write(&self.output, json + "\n")
+            .with_context(|| format!("Could not write {}", self.output.display()))?;
+        Ok(())
+    }
+}
+
+/// Restore a JSON backup of one or more users.
+///
+/// Users are matched by username, missing users are created with a locked
+/// password. Tags and timelogs that already exist are skipped.
+#[derive(Parser)]
+pub struct CmdRestore {
+    /// Backup file, from `cargo x backup` or an account download.
+    file: PathBuf,
+}
+
+impl CliCommand for CmdRestore {
+    fn run(self) -> Result<(), anyhow::Error> {
+        let json = std::fs::read(&self.file)
+            .with_context(|| format!("Could not read {}", self.file.display()))?;
+        let sh = Shell::new()?;
+        server_command(&sh, &["restore".to_string()])?
+            .stdin(json)
+            .run()?;
+        Ok(())
+    }
+}
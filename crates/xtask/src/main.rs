@@ -4,11 +4,18 @@ use anyhow::Context;
 use clap::Parser;
 use xshell::cmd;
 
+mod backup;
+mod migrate;
+mod seed;
+mod server;
+mod trash;
+
 fn main() {
     match run() {
-        Ok(_) => todo!(),
+        Ok(()) => {}
         Err(err) => {
             eprintln!("Command failed: {err:?}");
+            std::process::exit(1);
         }
     }
 }
@@ -18,6 +25,11 @@ fn run() -> Result<(), anyhow::Error> {
 
     match args.cmd {
         SubCmd::Develop(c) => c.run(),
+        SubCmd::Migrate(c) => c.run(),
+        SubCmd::Seed(c) => c.run(),
+        SubCmd::Backup(c) => c.run(),
+        SubCmd::Restore(c) => c.run(),
+        SubCmd::PurgeTrash(c) => c.run(),
     }
 }
 
@@ -41,6 +53,11 @@ struct Args {
 #[derive(clap::Subcommand)]
 enum SubCmd {
     Develop(CmdDevelop),
+    Migrate(migrate::CmdMigrate),
+    Seed(seed::CmdSeed),
+    Backup(backup::CmdBackup),
+    Restore(backup::CmdRestore),
+    PurgeTrash(trash::CmdPurgeTrash),
 }
 
 #[derive(Parser)]
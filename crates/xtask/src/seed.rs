@@ -0,0 +1,39 @@
+use clap::Parser;
+use xshell::Shell;
+
+use crate::{server::server_command, CliCommand};
+
+/// Populate the configured Supabase database with generated development data.
+///
+/// Builds the server and runs its `seed` command with `wasmer`, so data is
+/// written through the same database client as the server uses.
+/// Reads the server configuration (`SUPABASE_ENDPOINT`, ...) from the environment.
+#[derive(Parser)]
+pub struct CmdSeed {
+    /// Seed for the random generator. The same seed produces the same data.
+    #[clap(long, default_value_t = 1)]
+    seed: u64,
+    /// Number of users to create.
+    #[clap(long, default_value_t = 3)]
+    users: u32,
+    /// Days of history to generate per user.
+    #[clap(long, default_value_t = 90)]
+    days: u32,
+}
+
+impl CliCommand for CmdSeed {
+    fn run(self) -> Result<(), anyhow::Error> {
+        let sh = Shell::new()?;
+        let args = [
+            "seed".to_string(),
+            "--seed".to_string(),
+            self.seed.to_string(),
+            "--users".to_string(),
+            self.users.to_string(),
+            "--days".to_string(),
+            self.days.to_string(),
+        ];
+        server_command(&sh, &args)?.run()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+//! Running admin commands of the server binary.
+
+use xshell::{cmd, Cmd, Shell};
+
+use crate::root_dir;
+
+/// Environment variables the server reads its configuration from.
+const ENV_PREFIXES: &[&str] = &["SUPABASE_", "TIMELY_"];
+
+/// Build the server and return the `wasmer run` command for
+/// `timely-server <args>`.
+///
+/// The server configuration is passed on from the environment, so the
+/// command is quiet to keep secrets out of the output.
+pub fn server_command<'a>(sh: &'a Shell, args: &[String]) -> Result<Cmd<'a>, anyhow::Error> {
+    let root = root_dir()?;
+    sh.change_dir(&root);
+
+    cmd!(sh, "cargo build -p timely_server --target wasm32-wasi").run()?;
+    let wasm_path = root.join("target/wasm32-wasi/debug/timely_server.wasm");
+
+    let env_args = std::env::vars()
+        .filter(|(key, _)| ENV_PREFIXES.iter().any(|p| key.starts_with(p)))
+        .flat_map(|(key, value)| ["--env".to_string(), format!("{key}={value}")])
+        .collect::<Vec<_>>();
+
+    Ok(cmd!(
+        sh,
+        "wasmer run --net {env_args...} {wasm_path} -- {args...}"
+    )
+    .quiet())
+}
@@ -0,0 +1,22 @@
+use clap::Parser;
+use xshell::Shell;
+
+use crate::{server::server_command, CliCommand};
+
+/// Delete rows of all users that were in the trash longer than the retention
+/// period.
+///
+/// Users' own expired rows are also purged when they open the trash, this
+/// catches everyone else. Run it daily, e.g. from cron.
+/// Reads the server configuration (`SUPABASE_ENDPOINT`,
+/// `TIMELY_TRASH_RETENTION_DAYS`, ...) from the environment.
+#[derive(Parser)]
+pub struct CmdPurgeTrash {}
+
+impl CliCommand for CmdPurgeTrash {
+    fn run(self) -> Result<(), anyhow::Error> {
+        let sh = Shell::new()?;
+        server_command(&sh, &["purge-trash".to_string()])?.run()?;
+        Ok(())
+    }
+}
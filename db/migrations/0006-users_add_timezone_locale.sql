@@ -0,0 +1,6 @@
+ALTER TABLE users
+  ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC',
+  ADD COLUMN locale TEXT NOT NULL DEFAULT 'en-US',
+  ADD CONSTRAINT timezone_length CHECK (LENGTH(timezone) BETWEEN 1 AND 100),
+  ADD CONSTRAINT locale_length CHECK (LENGTH(locale) BETWEEN 2 AND 20)
+;
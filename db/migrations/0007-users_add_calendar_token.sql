@@ -0,0 +1,4 @@
+ALTER TABLE users
+  ADD COLUMN calendar_token TEXT UNIQUE,
+  ADD CONSTRAINT calendar_token_length CHECK (calendar_token IS NULL OR LENGTH(calendar_token) BETWEEN 32 AND 128)
+;
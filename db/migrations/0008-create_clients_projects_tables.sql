@@ -0,0 +1,32 @@
+CREATE TABLE clients (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  name TEXT NOT NULL,
+  description TEXT,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  CONSTRAINT client_name_length CHECK (LENGTH(name) BETWEEN 1 and 100),
+  CONSTRAINT client_description_length CHECK (description IS NULL OR LENGTH(description) BETWEEN 0 and 5000),
+  CONSTRAINT unique_client_name_per_user UNIQUE (user_id, name)
+);
+
+CREATE TABLE projects (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  client_id BIGINT REFERENCES clients (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  name TEXT NOT NULL,
+  description TEXT,
+  color TEXT,
+  billable BOOLEAN NOT NULL DEFAULT false,
+  archived BOOLEAN NOT NULL DEFAULT false,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  CONSTRAINT project_name_length CHECK (LENGTH(name) BETWEEN 1 and 100),
+  CONSTRAINT project_description_length CHECK (description IS NULL OR LENGTH(description) BETWEEN 0 and 5000),
+  CONSTRAINT project_color_length CHECK (color is NULL OR LENGTH(color) BETWEEN 1 and 30),
+  CONSTRAINT unique_project_name_per_user UNIQUE (user_id, name)
+);
+
+ALTER TABLE timelogs
+  ADD COLUMN project_id BIGINT REFERENCES projects (id) ON UPDATE RESTRICT ON DELETE RESTRICT
+;
@@ -0,0 +1,24 @@
+ALTER TABLE users
+  ADD COLUMN hourly_rate_cents BIGINT,
+  ADD COLUMN currency TEXT NOT NULL DEFAULT 'EUR',
+  ADD COLUMN rounding_minutes INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN rounding_mode TEXT NOT NULL DEFAULT 'up',
+  ADD CONSTRAINT hourly_rate_positive CHECK (hourly_rate_cents IS NULL OR hourly_rate_cents >= 0),
+  ADD CONSTRAINT currency_code CHECK (currency ~ '^[A-Z]{3}$'),
+  ADD CONSTRAINT rounding_minutes_range CHECK (rounding_minutes BETWEEN 0 AND 60),
+  ADD CONSTRAINT rounding_mode_valid CHECK (rounding_mode IN ('up', 'nearest', 'down'))
+;
+
+ALTER TABLE projects
+  ADD COLUMN hourly_rate_cents BIGINT,
+  ADD CONSTRAINT project_hourly_rate_positive CHECK (hourly_rate_cents IS NULL OR hourly_rate_cents >= 0)
+;
+
+ALTER TABLE user_tags
+  ADD COLUMN hourly_rate_cents BIGINT,
+  ADD CONSTRAINT tag_hourly_rate_positive CHECK (hourly_rate_cents IS NULL OR hourly_rate_cents >= 0)
+;
+
+ALTER TABLE timelogs
+  ADD COLUMN billable BOOLEAN NOT NULL DEFAULT false
+;
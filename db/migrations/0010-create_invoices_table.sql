@@ -0,0 +1,54 @@
+CREATE TABLE invoices (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  client_id BIGINT NOT NULL REFERENCES clients (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  number INTEGER NOT NULL,
+  issued_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  period_from DATE NOT NULL,
+  period_to DATE NOT NULL,
+  client_name TEXT NOT NULL,
+  grouping TEXT NOT NULL,
+  currency TEXT NOT NULL,
+  tax_rate_basis_points INTEGER NOT NULL DEFAULT 0,
+  subtotal_cents BIGINT NOT NULL,
+  tax_cents BIGINT NOT NULL,
+  total_cents BIGINT NOT NULL,
+  lines JSONB NOT NULL,
+  entries JSONB NOT NULL,
+  CONSTRAINT unique_invoice_number_per_user UNIQUE (user_id, number),
+  CONSTRAINT invoice_number_positive CHECK (number > 0),
+  CONSTRAINT invoice_period CHECK (period_from <= period_to),
+  CONSTRAINT invoice_grouping CHECK (grouping IN ('project', 'task')),
+  CONSTRAINT invoice_tax_rate_range CHECK (tax_rate_basis_points BETWEEN 0 AND 10000)
+);
+
+ALTER TABLE timelogs
+  ADD COLUMN invoice_id BIGINT REFERENCES invoices (id) ON UPDATE RESTRICT ON DELETE RESTRICT
+;
+
+-- Invoices are a snapshot, neither they nor their timelogs may change.
+CREATE FUNCTION reject_invoice_change() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'Invoices can not be changed' USING ERRCODE = 'check_violation';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER invoices_immutable
+  BEFORE UPDATE OR DELETE ON invoices
+  FOR EACH ROW EXECUTE FUNCTION reject_invoice_change();
+
+CREATE FUNCTION reject_invoiced_timelog_change() RETURNS trigger AS $$
+BEGIN
+  IF OLD.invoice_id IS NOT NULL THEN
+    RAISE EXCEPTION 'Invoiced timelogs can not be changed' USING ERRCODE = 'check_violation';
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER timelogs_invoiced_immutable
+  BEFORE UPDATE OR DELETE ON timelogs
+  FOR EACH ROW EXECUTE FUNCTION reject_invoiced_timelog_change();
@@ -0,0 +1,13 @@
+ALTER TABLE projects
+  ADD COLUMN budget_minutes INTEGER,
+  ADD COLUMN budget_period TEXT NOT NULL DEFAULT 'total',
+  ADD CONSTRAINT project_budget_positive CHECK (budget_minutes IS NULL OR budget_minutes > 0),
+  ADD CONSTRAINT project_budget_period_valid CHECK (budget_period IN ('total', 'week', 'month'))
+;
+
+ALTER TABLE user_tags
+  ADD COLUMN budget_minutes INTEGER,
+  ADD COLUMN budget_period TEXT NOT NULL DEFAULT 'total',
+  ADD CONSTRAINT tag_budget_positive CHECK (budget_minutes IS NULL OR budget_minutes > 0),
+  ADD CONSTRAINT tag_budget_period_valid CHECK (budget_period IN ('total', 'week', 'month'))
+;
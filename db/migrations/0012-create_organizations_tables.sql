@@ -0,0 +1,50 @@
+CREATE TABLE organizations (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  name TEXT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  CONSTRAINT organization_name_length CHECK (LENGTH(name) BETWEEN 1 and 100)
+);
+
+CREATE TABLE memberships (
+  organization_id BIGINT NOT NULL REFERENCES organizations (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  user_id BIGINT NOT NULL REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  role TEXT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  PRIMARY KEY (organization_id, user_id),
+  CONSTRAINT membership_role CHECK (role IN ('owner', 'admin', 'member'))
+);
+
+CREATE INDEX memberships_user_id ON memberships (user_id);
+
+CREATE TABLE invitations (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  organization_id BIGINT NOT NULL REFERENCES organizations (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  email TEXT NOT NULL,
+  role TEXT NOT NULL,
+  invited_by BIGINT NOT NULL REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  CONSTRAINT unique_invitation_per_email UNIQUE (organization_id, email),
+  CONSTRAINT invitation_role CHECK (role IN ('admin', 'member')),
+  CONSTRAINT invitation_email_length CHECK (LENGTH(email) BETWEEN 3 AND 200)
+);
+
+-- Projects and tags either belong to a user, or are shared in an
+-- organization. Names are unique within each scope.
+ALTER TABLE projects
+  ADD COLUMN organization_id BIGINT REFERENCES organizations (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  DROP CONSTRAINT unique_project_name_per_user
+;
+CREATE UNIQUE INDEX unique_project_name_per_user ON projects (user_id, name)
+  WHERE organization_id IS NULL;
+CREATE UNIQUE INDEX unique_project_name_per_organization ON projects (organization_id, name)
+  WHERE organization_id IS NOT NULL;
+
+ALTER TABLE user_tags
+  ADD COLUMN organization_id BIGINT REFERENCES organizations (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  DROP CONSTRAINT unique_name_per_user
+;
+CREATE UNIQUE INDEX unique_name_per_user ON user_tags (user_id, name)
+  WHERE organization_id IS NULL;
+CREATE UNIQUE INDEX unique_tag_name_per_organization ON user_tags (organization_id, name)
+  WHERE organization_id IS NOT NULL;
@@ -0,0 +1,62 @@
+-- A week of timelogs submitted by a user for approval. The week is stored
+-- both as the local date of its Monday and as the instants it covers in the
+-- user's time zone at submission.
+CREATE TABLE timesheets (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  week_start DATE NOT NULL,
+  period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+  period_end TIMESTAMP WITH TIME ZONE NOT NULL,
+  status TEXT NOT NULL,
+  submitted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  decided_at TIMESTAMP WITH TIME ZONE,
+  decided_by BIGINT REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  comment TEXT,
+  CONSTRAINT unique_timesheet_per_week UNIQUE (user_id, week_start),
+  CONSTRAINT timesheet_status CHECK (status IN ('submitted', 'approved', 'rejected')),
+  CONSTRAINT timesheet_period CHECK (period_start < period_end),
+  CONSTRAINT timesheet_comment_length CHECK (LENGTH(comment) <= 1000)
+);
+
+-- Every status change of a timesheet.
+CREATE TABLE timesheet_events (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  timesheet_id BIGINT NOT NULL REFERENCES timesheets (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  actor_id BIGINT NOT NULL REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  status TEXT NOT NULL,
+  comment TEXT,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  CONSTRAINT timesheet_event_status CHECK (status IN ('submitted', 'approved', 'rejected')),
+  CONSTRAINT timesheet_event_comment_length CHECK (LENGTH(comment) <= 1000)
+);
+
+CREATE INDEX timesheet_events_timesheet_id ON timesheet_events (timesheet_id);
+
+-- Timelogs overlapping a submitted or approved week may not change. Putting
+-- them on an invoice is still allowed.
+CREATE FUNCTION reject_locked_timelog_change() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'UPDATE'
+    AND NEW.invoice_id IS DISTINCT FROM OLD.invoice_id
+    AND to_jsonb(NEW) - 'invoice_id' = to_jsonb(OLD) - 'invoice_id' THEN
+    RETURN NEW;
+  END IF;
+  IF EXISTS (
+    SELECT 1 FROM timesheets
+    WHERE user_id = OLD.user_id
+      AND status IN ('submitted', 'approved')
+      AND period_start < COALESCE(OLD.finished_at, 'infinity')
+      AND period_end > OLD.started_at
+  ) THEN
+    RAISE EXCEPTION 'Timelogs of submitted timesheets can not be changed' USING ERRCODE = 'check_violation';
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER timelogs_timesheet_locked
+  BEFORE UPDATE OR DELETE ON timelogs
+  FOR EACH ROW EXECUTE FUNCTION reject_locked_timelog_change();
@@ -0,0 +1,120 @@
+-- Append-only log of every change to the audited tables.
+--
+-- `before` and `after` hold the whole row for inserts and deletes, and only
+-- the changed columns for updates. The actor is the user the server acted
+-- for, sent in the `x-timely-actor` request header, and NULL for changes
+-- made without a signed in user, like signups.
+CREATE TABLE audit_events (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  actor_id BIGINT,
+  entity TEXT NOT NULL,
+  entity_id BIGINT,
+  action TEXT NOT NULL,
+  before JSONB,
+  after JSONB,
+  CONSTRAINT audit_event_action CHECK (action IN ('create', 'update', 'delete'))
+);
+
+CREATE INDEX audit_events_entity ON audit_events (entity, entity_id);
+CREATE INDEX audit_events_actor_id ON audit_events (actor_id);
+CREATE INDEX audit_events_created_at ON audit_events (created_at);
+
+CREATE FUNCTION reject_audit_event_change() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'Audit events can not be changed' USING ERRCODE = 'check_violation';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+  BEFORE UPDATE OR DELETE ON audit_events
+  FOR EACH ROW EXECUTE FUNCTION reject_audit_event_change();
+
+-- Record a change of a row.
+--
+-- The first argument is the column identifying the entity, the others are
+-- columns whose values are replaced by "[redacted]".
+CREATE FUNCTION record_audit_event() RETURNS trigger AS $$
+DECLARE
+  old_row JSONB;
+  new_row JSONB;
+  id_column TEXT := TG_ARGV[0];
+  headers JSONB := NULLIF(current_setting('request.headers', true), '')::jsonb;
+  actor BIGINT := NULLIF(headers ->> 'x-timely-actor', '')::bigint;
+  key TEXT;
+  i INTEGER;
+BEGIN
+  IF TG_OP IN ('UPDATE', 'DELETE') THEN
+    old_row := to_jsonb(OLD);
+  END IF;
+  IF TG_OP IN ('INSERT', 'UPDATE') THEN
+    new_row := to_jsonb(NEW);
+  END IF;
+
+  IF TG_OP = 'UPDATE' THEN
+    FOR key IN SELECT jsonb_object_keys(old_row) LOOP
+      IF old_row -> key = new_row -> key THEN
+        old_row := old_row - key;
+        new_row := new_row - key;
+      END IF;
+    END LOOP;
+    IF old_row = '{}'::jsonb THEN
+      RETURN NULL;
+    END IF;
+  END IF;
+
+  FOR i IN 1 .. TG_NARGS - 1 LOOP
+    IF old_row ? TG_ARGV[i] AND old_row -> TG_ARGV[i] <> 'null'::jsonb THEN
+      old_row := old_row || jsonb_build_object(TG_ARGV[i], '[redacted]');
+    END IF;
+    IF new_row ? TG_ARGV[i] AND new_row -> TG_ARGV[i] <> 'null'::jsonb THEN
+      new_row := new_row || jsonb_build_object(TG_ARGV[i], '[redacted]');
+    END IF;
+  END LOOP;
+
+  INSERT INTO audit_events (actor_id, entity, entity_id, action, before, after)
+  VALUES (
+    actor,
+    TG_TABLE_NAME,
+    (COALESCE(to_jsonb(NEW), to_jsonb(OLD)) ->> id_column)::bigint,
+    CASE TG_OP WHEN 'INSERT' THEN 'create' WHEN 'UPDATE' THEN 'update' ELSE 'delete' END,
+    old_row,
+    new_row
+  );
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER users_audit
+  AFTER INSERT OR UPDATE OR DELETE ON users
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id', 'password_hash', 'calendar_token');
+CREATE TRIGGER timelogs_audit
+  AFTER INSERT OR UPDATE OR DELETE ON timelogs
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id');
+CREATE TRIGGER timelogs_user_tags_audit
+  AFTER INSERT OR UPDATE OR DELETE ON timelogs_user_tags
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('timelog_id');
+CREATE TRIGGER user_tags_audit
+  AFTER INSERT OR UPDATE OR DELETE ON user_tags
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id');
+CREATE TRIGGER clients_audit
+  AFTER INSERT OR UPDATE OR DELETE ON clients
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id');
+CREATE TRIGGER projects_audit
+  AFTER INSERT OR UPDATE OR DELETE ON projects
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id');
+CREATE TRIGGER invoices_audit
+  AFTER INSERT OR UPDATE OR DELETE ON invoices
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id');
+CREATE TRIGGER organizations_audit
+  AFTER INSERT OR UPDATE OR DELETE ON organizations
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id');
+CREATE TRIGGER memberships_audit
+  AFTER INSERT OR UPDATE OR DELETE ON memberships
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('organization_id');
+CREATE TRIGGER invitations_audit
+  AFTER INSERT OR UPDATE OR DELETE ON invitations
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id');
+CREATE TRIGGER timesheets_audit
+  AFTER INSERT OR UPDATE OR DELETE ON timesheets
+  FOR EACH ROW EXECUTE FUNCTION record_audit_event('id');
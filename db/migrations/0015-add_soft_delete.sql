@@ -0,0 +1,68 @@
+-- Deleted timelogs, tags and projects stay in the trash until they are
+-- restored or purged.
+ALTER TABLE timelogs ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE user_tags ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE projects ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX timelogs_deleted_at ON timelogs (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX user_tags_deleted_at ON user_tags (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX projects_deleted_at ON projects (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Names only need to be unique among rows that are not deleted.
+DROP INDEX unique_project_name_per_user;
+DROP INDEX unique_project_name_per_organization;
+CREATE UNIQUE INDEX unique_project_name_per_user ON projects (user_id, name)
+  WHERE organization_id IS NULL AND deleted_at IS NULL;
+CREATE UNIQUE INDEX unique_project_name_per_organization ON projects (organization_id, name)
+  WHERE organization_id IS NOT NULL AND deleted_at IS NULL;
+
+DROP INDEX unique_name_per_user;
+DROP INDEX unique_tag_name_per_organization;
+CREATE UNIQUE INDEX unique_name_per_user ON user_tags (user_id, name)
+  WHERE organization_id IS NULL AND deleted_at IS NULL;
+CREATE UNIQUE INDEX unique_tag_name_per_organization ON user_tags (organization_id, name)
+  WHERE organization_id IS NOT NULL AND deleted_at IS NULL;
+
+-- Purging removes the tag links of timelogs and tags, and detaches the
+-- timelogs of projects.
+ALTER TABLE timelogs_user_tags
+  DROP CONSTRAINT timelogs_user_tags_timelog_id_fkey,
+  DROP CONSTRAINT timelogs_user_tags_user_tag_id_fkey,
+  ADD CONSTRAINT timelogs_user_tags_timelog_id_fkey
+    FOREIGN KEY (timelog_id) REFERENCES timelogs (id) ON DELETE CASCADE,
+  ADD CONSTRAINT timelogs_user_tags_user_tag_id_fkey
+    FOREIGN KEY (user_tag_id) REFERENCES user_tags (id) ON DELETE CASCADE
+;
+ALTER TABLE timelogs
+  DROP CONSTRAINT timelogs_project_id_fkey,
+  ADD CONSTRAINT timelogs_project_id_fkey
+    FOREIGN KEY (project_id) REFERENCES projects (id) ON UPDATE RESTRICT ON DELETE SET NULL
+;
+
+-- Deleted timelogs are not part of a timesheet, so they can be purged.
+-- Restoring them into a locked week is still refused.
+CREATE OR REPLACE FUNCTION reject_locked_timelog_change() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'UPDATE'
+    AND NEW.invoice_id IS DISTINCT FROM OLD.invoice_id
+    AND to_jsonb(NEW) - 'invoice_id' = to_jsonb(OLD) - 'invoice_id' THEN
+    RETURN NEW;
+  END IF;
+  IF TG_OP = 'DELETE' AND OLD.deleted_at IS NOT NULL THEN
+    RETURN OLD;
+  END IF;
+  IF EXISTS (
+    SELECT 1 FROM timesheets
+    WHERE user_id = OLD.user_id
+      AND status IN ('submitted', 'approved')
+      AND period_start < COALESCE(OLD.finished_at, 'infinity')
+      AND period_end > OLD.started_at
+  ) THEN
+    RAISE EXCEPTION 'Timelogs of submitted timesheets can not be changed' USING ERRCODE = 'check_violation';
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,34 @@
+-- Finish the running timelogs of a user and start a new one at the same
+-- instant, in one transaction. Returns the finished timelogs, then the new
+-- one. Called as `POST /rpc/switch_timelog`.
+CREATE FUNCTION switch_timelog(
+  user_id BIGINT,
+  title TEXT,
+  project_id BIGINT,
+  billable BOOLEAN,
+  switched_at TIMESTAMP WITH TIME ZONE
+) RETURNS SETOF timelogs AS $$
+#variable_conflict use_column
+BEGIN
+  -- Concurrent switches of the same user would both start a timer.
+  PERFORM 1 FROM users WHERE id = switch_timelog.user_id FOR UPDATE;
+
+  RETURN QUERY
+    UPDATE timelogs SET finished_at = switch_timelog.switched_at
+    WHERE user_id = switch_timelog.user_id
+      AND finished_at IS NULL
+      AND deleted_at IS NULL
+    RETURNING *;
+  RETURN QUERY
+    INSERT INTO timelogs (user_id, title, created_at, started_at, project_id, billable)
+    VALUES (
+      switch_timelog.user_id,
+      switch_timelog.title,
+      switch_timelog.switched_at,
+      switch_timelog.switched_at,
+      switch_timelog.project_id,
+      switch_timelog.billable
+    )
+    RETURNING *;
+END;
+$$ LANGUAGE plpgsql;
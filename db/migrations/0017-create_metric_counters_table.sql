@@ -0,0 +1,24 @@
+-- Counters of the Prometheus metrics. Every request is served by a fresh
+-- process, so the counts of each request are added up here.
+CREATE TABLE metric_counters(
+  -- Series name, like `timely_http_requests_total` or
+  -- `timely_http_request_duration_seconds_bucket`.
+  name TEXT NOT NULL,
+  -- Labels in the exposition format, like `route="/",status="200"`.
+  labels TEXT NOT NULL,
+  value DOUBLE PRECISION NOT NULL,
+  PRIMARY KEY (name, labels)
+);
+
+-- Add the increments in `samples`, an array of `{name, labels, value}`.
+-- Returns the number of updated series.
+CREATE FUNCTION record_metrics(samples JSONB) RETURNS BIGINT AS $$
+  WITH added AS (
+    INSERT INTO metric_counters (name, labels, value)
+    SELECT s->>'name', s->>'labels', (s->>'value')::DOUBLE PRECISION
+    FROM jsonb_array_elements(samples) AS s
+    ON CONFLICT (name, labels) DO UPDATE SET value = metric_counters.value + EXCLUDED.value
+    RETURNING 1
+  )
+  SELECT count(*) FROM added;
+$$ LANGUAGE sql;
@@ -0,0 +1,58 @@
+-- Number an invoice, insert it and lock its timelogs in one transaction.
+-- `invoice` holds the columns of the invoice row except `id`, `number` and
+-- `issued_at`. Fails if any of its entries is already invoiced, deleted or
+-- running. Called as `POST /rpc/create_invoice`.
+CREATE FUNCTION create_invoice(invoice JSONB) RETURNS SETOF invoices AS $$
+DECLARE
+  invoice_user_id BIGINT := (invoice->>'user_id')::BIGINT;
+  timelog_ids BIGINT[];
+  available INTEGER;
+  created invoices;
+BEGIN
+  -- Concurrent invoices of the same user would get the same number.
+  PERFORM 1 FROM users WHERE id = invoice_user_id FOR UPDATE;
+
+  SELECT array_agg(DISTINCT (e->>'timelog_id')::BIGINT) INTO timelog_ids
+  FROM jsonb_array_elements(invoice->'entries') AS e;
+
+  SELECT count(*) INTO available FROM (
+    SELECT id FROM timelogs
+    WHERE id = ANY(timelog_ids)
+      AND user_id = invoice_user_id
+      AND invoice_id IS NULL
+      AND finished_at IS NOT NULL
+      AND deleted_at IS NULL
+    FOR UPDATE
+  ) AS locked;
+  IF available <> coalesce(array_length(timelog_ids, 1), 0) THEN
+    RAISE EXCEPTION 'Entries of the invoice are not available: "invoice_entries_available"'
+      USING ERRCODE = 'check_violation';
+  END IF;
+
+  INSERT INTO invoices (
+    user_id, client_id, number, period_from, period_to, client_name, grouping,
+    currency, tax_rate_basis_points, subtotal_cents, tax_cents, total_cents,
+    lines, entries
+  )
+  SELECT
+    invoice_user_id,
+    (invoice->>'client_id')::BIGINT,
+    coalesce((SELECT max(number) FROM invoices WHERE user_id = invoice_user_id), 0) + 1,
+    (invoice->>'period_from')::DATE,
+    (invoice->>'period_to')::DATE,
+    invoice->>'client_name',
+    invoice->>'grouping',
+    invoice->>'currency',
+    (invoice->>'tax_rate_basis_points')::INTEGER,
+    (invoice->>'subtotal_cents')::BIGINT,
+    (invoice->>'tax_cents')::BIGINT,
+    (invoice->>'total_cents')::BIGINT,
+    invoice->'lines',
+    invoice->'entries'
+  RETURNING * INTO created;
+
+  UPDATE timelogs SET invoice_id = created.id WHERE id = ANY(timelog_ids);
+
+  RETURN NEXT created;
+END;
+$$ LANGUAGE plpgsql;
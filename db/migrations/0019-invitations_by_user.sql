@@ -0,0 +1,15 @@
+-- Invitations name the invited user instead of an email address. Emails are
+-- not verified, so anyone could sign up with an invited address and join.
+-- Pending email invitations can't be matched to a user safely and are
+-- dropped, admins invite again by username.
+DELETE FROM invitations;
+
+ALTER TABLE invitations
+  DROP CONSTRAINT unique_invitation_per_email,
+  DROP CONSTRAINT invitation_email_length,
+  DROP COLUMN email,
+  ADD COLUMN user_id BIGINT NOT NULL REFERENCES users (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+  ADD CONSTRAINT unique_invitation_per_user UNIQUE (organization_id, user_id)
+;
+
+CREATE INDEX invitations_user_id ON invitations (user_id);
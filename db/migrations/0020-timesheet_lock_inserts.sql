@@ -0,0 +1,39 @@
+-- New timelogs may not be added to a submitted or approved week either, so
+-- imports and restores can't change a timesheet after the fact.
+CREATE OR REPLACE FUNCTION reject_locked_timelog_change() RETURNS trigger AS $$
+DECLARE
+  log timelogs;
+BEGIN
+  IF TG_OP = 'UPDATE'
+    AND NEW.invoice_id IS DISTINCT FROM OLD.invoice_id
+    AND to_jsonb(NEW) - 'invoice_id' = to_jsonb(OLD) - 'invoice_id' THEN
+    RETURN NEW;
+  END IF;
+  IF TG_OP = 'DELETE' AND OLD.deleted_at IS NOT NULL THEN
+    RETURN OLD;
+  END IF;
+  IF TG_OP = 'INSERT' THEN
+    log := NEW;
+  ELSE
+    log := OLD;
+  END IF;
+  IF EXISTS (
+    SELECT 1 FROM timesheets
+    WHERE user_id = log.user_id
+      AND status IN ('submitted', 'approved')
+      AND period_start < COALESCE(log.finished_at, 'infinity')
+      AND period_end > log.started_at
+  ) THEN
+    RAISE EXCEPTION 'Timelogs of submitted timesheets can not be changed' USING ERRCODE = 'check_violation';
+  END IF;
+  IF TG_OP = 'DELETE' THEN
+    RETURN OLD;
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER timelogs_timesheet_locked ON timelogs;
+CREATE TRIGGER timelogs_timesheet_locked
+  BEFORE INSERT OR UPDATE OR DELETE ON timelogs
+  FOR EACH ROW EXECUTE FUNCTION reject_locked_timelog_change();